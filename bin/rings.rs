@@ -4,7 +4,6 @@ use std::sync::Arc;
 use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
-use rings_core::message::CallbackFn;
 use rings_node::backend::Backend;
 use rings_node::backend::BackendConfig;
 use rings_node::cli::Client;
@@ -48,6 +47,12 @@ enum Command {
     #[clap(subcommand)]
     Pending(PendingCommand),
     Send(Send),
+    Leave(Leave),
+    Status(Status),
+    #[clap(subcommand)]
+    Subring(SubringCommand),
+    #[clap(subcommand)]
+    Inspect(InspectCommand),
     NewSecretKey,
 }
 
@@ -239,6 +244,76 @@ struct Send {
     text: String,
 }
 
+#[derive(Args, Debug)]
+#[clap(about = "Leave the ring gracefully")]
+struct Leave {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "Show status of node")]
+struct Status {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum SubringCommand {
+    Leave(SubringLeave),
+    Kick(SubringKick),
+    Members(SubringMembers),
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "Leave a subring")]
+struct SubringLeave {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+    #[clap()]
+    name: String,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "Remove a member from subring, only admin of the subring is permitted")]
+struct SubringKick {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+    #[clap()]
+    name: String,
+    #[clap()]
+    address: String,
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "List members of a subring")]
+struct SubringMembers {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+    #[clap()]
+    name: String,
+}
+
+#[derive(Subcommand, Debug)]
+#[clap(rename_all = "kebab-case")]
+enum InspectCommand {
+    Ring(InspectRing),
+}
+
+#[derive(Args, Debug)]
+#[clap(about = "Walk successors from a node, and check consistency of the ring")]
+struct InspectRing {
+    #[clap(flatten)]
+    client_args: ClientArgs,
+    /// Node to start from, it's the connected node by default
+    #[clap(long)]
+    seed: Option<String>,
+    /// Stop walking after this number of nodes
+    #[clap(long, default_value = "64")]
+    max_nodes: usize,
+}
+
 async fn daemon_run(
     http_addr: String,
     key: SecretKey,
@@ -256,25 +331,30 @@ async fn daemon_run(
             .build()?,
     );
 
-    let callback: Option<CallbackFn> = {
-        if let Some(backend) = backend {
+    let backend = match backend {
+        Some(backend) => {
             let config = BackendConfig::load(&backend).await?;
-            let backend = Backend::new(config).await;
-            Some(Box::new(backend))
-        } else {
-            None
+            Some(Backend::new(config).await)
         }
+        None => None,
     };
 
-    let listen_event = Arc::new(swarm.create_message_handler(callback, None));
+    let listen_event = Arc::new(swarm.create_message_handler(None, None));
+    let backend_swarm = swarm.clone();
+    let tunnels = async move {
+        if let Some(backend) = backend {
+            backend.serve(backend_swarm).await;
+        }
+    };
     let stabilize = Arc::new(Stabilization::new(swarm.clone(), stabilize_timeout));
     let swarm_clone = swarm.clone();
     let pubkey = Arc::new(key.pubkey());
 
-    let (_, _, _) = futures::join!(
+    let (_, _, _, _) = futures::join!(
         listen_event.listen(),
         run_service(http_addr.to_owned(), swarm_clone, stabilize.clone(), pubkey),
         stabilize.wait(),
+        tunnels,
     );
 
     Ok(())
@@ -404,6 +484,60 @@ async fn main() -> anyhow::Result<()> {
                 .display();
             Ok(())
         }
+        Command::Leave(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .leave()
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Status(args) => {
+            args.client_args
+                .new_client()
+                .await?
+                .node_status()
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Subring(SubringCommand::Leave(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .subring_leave(args.name.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Subring(SubringCommand::Kick(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .subring_kick(args.name.as_str(), args.address.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Subring(SubringCommand::Members(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .subring_members(args.name.as_str())
+                .await?
+                .display();
+            Ok(())
+        }
+        Command::Inspect(InspectCommand::Ring(args)) => {
+            args.client_args
+                .new_client()
+                .await?
+                .inspect_ring(args.seed.as_deref(), args.max_nodes)
+                .await?
+                .display();
+            Ok(())
+        }
         Command::NewSecretKey => {
             let k = SecretKey::random();
             println!("New secretKey: {}", k.to_string());
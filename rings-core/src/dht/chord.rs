@@ -1,30 +1,50 @@
 #![warn(missing_docs)]
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::MutexGuard;
 
 use async_trait::async_trait;
 use itertools::Itertools;
-use num_bigint::BigUint;
 use serde::Deserialize;
 use serde::Serialize;
 
 use super::did::BiasId;
+use super::did::RingId;
+use super::failure_detector::FailureDetector;
+use super::latency::LatencyTable;
+use super::latency::LookupHopStats;
+use super::position::derive_positions;
+use super::position::VirtualPosition;
+use super::record::Record;
+use super::scan::VNodePage;
+use super::scan::VNodeRange;
 use super::successor::Successor;
+use super::topology::DHTStatus;
 use super::types::Chord;
 use super::types::ChordStabilize;
 use super::types::ChordStorage;
+use super::vnode::VNodeType;
 use super::vnode::VirtualNode;
 use super::FingerTable;
 use crate::dht::Did;
 use crate::err::Error;
 use crate::err::Result;
+use crate::storage::CacheStats;
+use crate::storage::LruMemStorage;
 use crate::storage::MemStorage;
 use crate::storage::PersistenceStorage;
+use crate::storage::PersistenceStorageOperation;
 use crate::storage::PersistenceStorageReadAndWrite;
-// use crate::storage::PersistenceStorageOperation;
 use crate::storage::PersistenceStorageRemove;
 
+/// Max number of vnodes in cache by default
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+/// A cached vnode is expired after 5 minutes by default
+pub const DEFAULT_CACHE_TTL_MS: u128 = 5 * 60 * 1000;
+
 /// Remote actions
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
@@ -37,6 +57,12 @@ pub enum RemoteAction {
     FindAndStore(VirtualNode),
     /// Ask did_a to find virtual peer for subring joining
     FindAndJoinSubRing(Did),
+    /// Ask did_a to find virtual peer for subring leaving
+    FindAndLeaveSubRing(Did),
+    /// Ask did_a to find virtual peer for removing a member from subring
+    FindAndKickSubRing(Did),
+    /// Ask did_a to find virtual peer for listing members of subring
+    FindAndListSubRing(Did),
     /// Ask Did_a to notify(did_b)
     Notify(Did),
     /// Async data with it's successor
@@ -45,6 +71,16 @@ pub enum RemoteAction {
     FindSuccessorForFix(Did),
     /// Check predecessor
     CheckPredecessor,
+    /// Copy vnodes to did_a as replicas
+    ReplicateVNode(Vec<VirtualNode>),
+    /// Replace all replicas copied from this node on did_a with the vnodes
+    SyncReplica(Vec<VirtualNode>),
+    /// Ask did_a to find replica of virtual node did_b
+    FindVNodeReplica(Did),
+    /// Hand over replicas to did_a, which is responsible for them now
+    PromoteReplica(Vec<VirtualNode>),
+    /// Ask did_a to scan vnodes in range
+    ScanVNode(VNodeRange),
 }
 
 /// Result of PeerRing algorithm
@@ -54,6 +90,8 @@ pub enum PeerRingAction {
     None,
     /// Found some VNode
     SomeVNode(VirtualNode),
+    /// Found a page of VNodes in range
+    SomeVNodePage(VNodePage),
     /// Found some node
     Some(Did),
     /// Trigger remote action
@@ -97,6 +135,8 @@ impl PeerRingAction {
 }
 
 /// Implementation of PeerRing
+/// The ring is keyed by `Did` only, since it's the address of connected nodes and stored vnodes,
+/// the finger table and successor list beneath it are generic over `RingId`.
 #[derive(Clone)]
 pub struct PeerRing {
     /// PeerRing's id is address of Node
@@ -110,8 +150,33 @@ pub struct PeerRing {
     pub predecessor: Arc<Mutex<Option<Did>>>,
     /// LocalStorage for DHT Query
     pub storage: Arc<PersistenceStorage>,
-    /// LocalCache
-    pub cache: Arc<MemStorage<Did, VirtualNode>>,
+    /// LocalCache of vnodes fetched from remote, it's bounded and expired by TTL
+    pub cache: Arc<LruMemStorage<Did, VirtualNode>>,
+    /// Replicas of vnodes which are stored on predecessors
+    pub replica: Arc<LruMemStorage<Did, VirtualNode>>,
+    /// Number of successors that a stored vnode should be copied to
+    pub replication_factor: u8,
+    /// Replica nodes which local storage is synced to, see `PeerRing::replicate_all`
+    replicated_to: Arc<Mutex<Vec<Did>>>,
+    /// Node which copies each replica to this node
+    replica_origin: Arc<MemStorage<Did, Did>>,
+    /// Max bytes of vnode data kept by storage, cache and replicas, None means unlimited
+    pub storage_quota: Option<usize>,
+    /// Bytes of vnode data in storage, it's counted on first use, then updated by writes
+    /// via `PeerRing::put_vnode` and `PeerRing::remove_vnode`, and recounted by sweep.
+    stored_bytes: Arc<Mutex<Option<usize>>>,
+    /// Measured RTT of connected nodes, for proximity neighbour selection of fingers
+    pub latency: Arc<LatencyTable>,
+    /// Suspicion of nodes which fail liveness probes
+    pub failure_detector: Arc<FailureDetector>,
+    /// Number of positions hosted by each node of the ring, see `dht::position`
+    pub virtual_positions: u8,
+    /// Positions hosted by this node, it's empty if the node has a single position
+    pub positions: Arc<Vec<VirtualPosition>>,
+    /// Owner of known positions
+    owners: Arc<MemStorage<Did, Did>>,
+    /// Number of changes of routing tables, which is used to detect churn of ring
+    version: Arc<AtomicU64>,
 }
 
 impl PeerRing {
@@ -126,10 +191,29 @@ impl PeerRing {
             successor: Arc::new(Mutex::new(Successor::new(id, succ_max))),
             predecessor: Arc::new(Mutex::new(None)),
             // for Eth address, it's 160
-            finger: Arc::new(Mutex::new(FingerTable::new(id, 160))),
+            finger: Arc::new(Mutex::new(FingerTable::new(id, Did::BITS as usize))),
             id,
             storage: Arc::new(PersistenceStorage::new().await?),
-            cache: Arc::new(MemStorage::<Did, VirtualNode>::new()),
+            cache: Arc::new(
+                LruMemStorage::<Did, VirtualNode>::new()
+                    .with_capacity(DEFAULT_CACHE_CAPACITY)
+                    .with_ttl(DEFAULT_CACHE_TTL_MS)
+                    .with_weigher(VirtualNode::size),
+            ),
+            replica: Arc::new(
+                LruMemStorage::<Did, VirtualNode>::new().with_weigher(VirtualNode::size),
+            ),
+            replication_factor: 0,
+            replicated_to: Arc::new(Mutex::new(vec![])),
+            replica_origin: Arc::new(MemStorage::new()),
+            storage_quota: None,
+            stored_bytes: Arc::new(Mutex::new(None)),
+            latency: Arc::new(LatencyTable::new()),
+            failure_detector: Arc::new(FailureDetector::default()),
+            virtual_positions: 1,
+            positions: Arc::new(vec![]),
+            owners: Arc::new(MemStorage::new()),
+            version: Arc::new(AtomicU64::new(0)),
         })
     }
 
@@ -139,13 +223,299 @@ impl PeerRing {
             successor: Arc::new(Mutex::new(Successor::new(id, succ_max))),
             predecessor: Arc::new(Mutex::new(None)),
             // for Eth address, it's 160
-            finger: Arc::new(Mutex::new(FingerTable::new(id, 160))),
+            finger: Arc::new(Mutex::new(FingerTable::new(id, Did::BITS as usize))),
             storage: Arc::new(storage),
-            cache: Arc::new(MemStorage::<Did, VirtualNode>::new()),
+            cache: Arc::new(
+                LruMemStorage::<Did, VirtualNode>::new()
+                    .with_capacity(DEFAULT_CACHE_CAPACITY)
+                    .with_ttl(DEFAULT_CACHE_TTL_MS)
+                    .with_weigher(VirtualNode::size),
+            ),
+            replica: Arc::new(
+                LruMemStorage::<Did, VirtualNode>::new().with_weigher(VirtualNode::size),
+            ),
+            replication_factor: 0,
+            replicated_to: Arc::new(Mutex::new(vec![])),
+            replica_origin: Arc::new(MemStorage::new()),
+            storage_quota: None,
+            stored_bytes: Arc::new(Mutex::new(None)),
+            latency: Arc::new(LatencyTable::new()),
+            failure_detector: Arc::new(FailureDetector::default()),
+            virtual_positions: 1,
+            positions: Arc::new(vec![]),
+            owners: Arc::new(MemStorage::new()),
+            version: Arc::new(AtomicU64::new(0)),
             id,
         }
     }
 
+    /// Set replication factor k, vnodes stored locally will be copied to
+    /// the first k nodes of successor list
+    pub fn with_replication_factor(mut self, k: u8) -> Self {
+        self.replication_factor = k;
+        self
+    }
+
+    /// Set max number of vnodes in cache, and how long they are cached
+    pub fn with_cache(mut self, capacity: usize, ttl_ms: u128) -> Self {
+        self.cache = Arc::new(
+            LruMemStorage::<Did, VirtualNode>::new()
+                .with_capacity(capacity)
+                .with_ttl(ttl_ms)
+                .with_weigher(VirtualNode::size),
+        );
+        self
+    }
+
+    /// Set timeout of liveness probes, and number of failed probes in a row before a node is evicted
+    pub fn with_failure_detector(mut self, timeout_ms: u64, max_suspicion: u8) -> Self {
+        self.failure_detector = Arc::new(FailureDetector::new(timeout_ms, max_suspicion));
+        self
+    }
+
+    /// Host `n` positions on the ring, each with it's own finger table, successor list
+    /// and predecessor, the number should be same on all nodes of a ring.
+    pub fn with_virtual_positions(mut self, n: u8) -> Self {
+        self.virtual_positions = n.max(1);
+        if self.virtual_positions == 1 {
+            self.positions = Arc::new(vec![]);
+            return self;
+        }
+        let succ_max = self.successor.lock().map(|s| s.capacity()).unwrap_or(3);
+        let ids = derive_positions(self.id, self.virtual_positions);
+        let positions: Vec<VirtualPosition> = ids
+            .iter()
+            .map(|id| VirtualPosition::new(*id, succ_max))
+            .collect();
+        for p in positions.iter() {
+            for id in ids.iter() {
+                // the tables are empty and fresh, thus it's infallible
+                p.join(*id).ok();
+            }
+        }
+        for id in ids {
+            self.owners.set(&id, self.id);
+        }
+        self.positions = Arc::new(positions);
+        self
+    }
+
+    /// Version of routing tables, it's increased when a node joins or leaves,
+    /// or predecessor is changed. See `StabilizeScheduler`.
+    pub fn topology_version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    fn touch(&self) {
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Owner node of a position, a node is the owner of itself
+    pub fn owner_of(&self, position: Did) -> Did {
+        self.owners.get(&position).unwrap_or(position)
+    }
+
+    /// Join positions of a node to tables of local positions
+    fn join_positions(&self, id: Did) -> Result<()> {
+        if self.positions.is_empty() {
+            return Ok(());
+        }
+        for q in derive_positions(id, self.virtual_positions) {
+            self.owners.set(&q, id);
+            for p in self.positions.iter() {
+                p.join(q)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove positions of a node from tables of local positions
+    fn remove_positions(&self, id: Did) -> Result<()> {
+        if self.positions.is_empty() {
+            return Ok(());
+        }
+        for q in derive_positions(id, self.virtual_positions) {
+            self.owners.remove(&q);
+            for p in self.positions.iter() {
+                p.remove(q)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Positions of a node think they might be predecessors of local positions.
+    fn notify_positions(&self, id: Did) -> Result<()> {
+        if self.positions.is_empty() || id == self.id {
+            return Ok(());
+        }
+        let mut changed = false;
+        for q in derive_positions(id, self.virtual_positions) {
+            for p in self.positions.iter() {
+                changed |= p.notify(q)?;
+            }
+        }
+        if changed {
+            self.touch();
+        }
+        Ok(())
+    }
+
+    /// Owners of successors of local positions, which should be notified by this node,
+    /// see `ChordStabilize::notify`.
+    pub fn position_successors(&self) -> Result<Vec<Did>> {
+        let mut owners = vec![];
+        for p in self.positions.iter() {
+            for s in p.lock_successor()?.list() {
+                let owner = self.owner_of(s);
+                if owner != self.id && !owners.contains(&owner) {
+                    owners.push(owner);
+                }
+            }
+        }
+        Ok(owners)
+    }
+
+    /// Fix a finger of each local position, it's the `fix_fingers` of positions.
+    /// The finger found by remote is joined by `set_fix_finger`.
+    pub fn fix_position_fingers(&self) -> Result<Vec<PeerRingAction>> {
+        let mut actions = vec![];
+        for p in self.positions.iter() {
+            let pos = p.next_fix_finger()?;
+            // a finger found locally is joined to tables of positions already
+            if let PeerRingAction::RemoteAction(next, RemoteAction::FindSuccessor(id)) =
+                self.find_successor(pos)?
+            {
+                actions.push(PeerRingAction::RemoteAction(
+                    next,
+                    RemoteAction::FindSuccessorForFix(id),
+                ));
+            }
+        }
+        Ok(actions)
+    }
+
+    /// Owners of predecessors of local positions which should be checked,
+    /// it's the `check_predecessor` of positions.
+    pub fn check_position_predecessors(&self) -> Result<Vec<PeerRingAction>> {
+        let mut owners = vec![];
+        for p in self.positions.iter() {
+            if let Some(pre) = *p.lock_predecessor()? {
+                let owner = self.owner_of(pre);
+                if owner != self.id && !owners.contains(&owner) {
+                    owners.push(owner);
+                }
+            }
+        }
+        Ok(owners
+            .into_iter()
+            .map(|owner| PeerRingAction::RemoteAction(owner, RemoteAction::CheckPredecessor))
+            .collect())
+    }
+
+    /// Test if this node is responsible for id, it's the union of intervals of local positions.
+    /// With a single position, it's (self, successor].
+    pub fn is_responsible(&self, id: Did) -> Result<bool> {
+        if self.positions.is_empty() {
+            let successor = self.lock_successor()?;
+            return Ok(successor.is_none() || self.bias(id) <= self.bias(successor.min()));
+        }
+        for p in self.positions.iter() {
+            if p.is_responsible(id)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// End of the local interval which contains id, None if this node is responsible
+    /// for the whole ring. Id should be in the interval, see `find_successor`.
+    fn interval_end(&self, id: Did) -> Result<Option<Did>> {
+        let end = |successor: &Successor| (!successor.is_none()).then(|| successor.min());
+        if self.positions.is_empty() {
+            return Ok(end(&*self.lock_successor()?));
+        }
+        let before = id - Did::pow2(0);
+        let p = self
+            .positions
+            .iter()
+            .min_by_key(|p| p.bias(before).pos())
+            .ok_or(Error::PeerRingInvalidAction)?;
+        let successor = p.lock_successor()?;
+        Ok(end(&successor))
+    }
+
+    /// find_successor with virtual positions, it starts from the local position which
+    /// precedes id most closely, and returns owner of the position found.
+    fn find_successor_on_positions(&self, id: Did) -> Result<PeerRingAction> {
+        let before = id - Did::pow2(0);
+        let p = self
+            .positions
+            .iter()
+            .min_by_key(|p| p.bias(before).pos())
+            .ok_or(Error::PeerRingInvalidAction)?;
+        if p.is_responsible(id)? {
+            let successor = p.lock_successor()?.min();
+            return Ok(PeerRingAction::Some(self.owner_of(successor)));
+        }
+        let mut next = self.owner_of(p.lock_finger()?.closest(id)?);
+        if next == self.id {
+            // no finger precedes id, ask the successor of position
+            next = self.owner_of(p.lock_successor()?.min());
+        }
+        if next == self.id {
+            return Ok(PeerRingAction::Some(self.id));
+        }
+        self.latency.record_hop(next);
+        Ok(PeerRingAction::RemoteAction(
+            next,
+            RemoteAction::FindSuccessor(id),
+        ))
+    }
+
+    /// Hit and miss counters of cache
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Latency statistics of lookup hops forwarded by this node
+    pub fn lookup_hop_stats(&self) -> LookupHopStats {
+        self.latency.hop_stats()
+    }
+
+    /// Snapshot of predecessor, successors, fingers, storage and cache of this node
+    pub async fn status(&self) -> Result<DHTStatus> {
+        let (fingers, fix_finger_index) = {
+            let finger = self.lock_finger()?;
+            let fingers = finger
+                .list()
+                .iter()
+                .enumerate()
+                .filter_map(|(i, f)| f.map(|did| (i, did)))
+                .collect();
+            (fingers, finger.fix_finger_index)
+        };
+        let successors = self.lock_successor()?.list();
+        let predecessor = *self.lock_predecessor()?;
+        Ok(DHTStatus {
+            did: self.id,
+            predecessor,
+            successors,
+            fingers,
+            fix_finger_index,
+            storage_count: self.storage.count().await?,
+            storage_usage: self.storage_usage().await?,
+            cache: self.cache_stats(),
+            lookup_hops: self.lookup_hop_stats(),
+            stabilization: None,
+        })
+    }
+
+    /// Set quota of vnode data in bytes, see `PeerRing::reserve`
+    pub fn with_storage_quota(mut self, bytes: usize) -> Self {
+        self.storage_quota = Some(bytes);
+        self
+    }
+
     /// Lock and return MutexGuard of Successor
     pub fn lock_successor(&self) -> Result<MutexGuard<Successor>> {
         self.successor.lock().map_err(|_| Error::DHTSyncLockError)
@@ -181,11 +551,42 @@ impl PeerRing {
         }
         finger.remove(id);
         successor.remove(id);
+        self.latency.remove(&id);
+        self.failure_detector.reset(&id);
+        self.touch();
         if successor.is_none() {
             if let Some(x) = finger.first() {
                 successor.update(x);
             }
         }
+        self.remove_positions(id)
+    }
+
+    /// Record a RTT sample of a connected node, and reselect fingers by proximity,
+    /// a finger with lower RTT may take the place of the one in same finger interval.
+    /// A node which is not in finger table is never joined by it's RTT.
+    pub fn record_rtt(&self, id: Did, rtt_ms: u128) -> Result<()> {
+        if id == self.id {
+            return Ok(());
+        }
+        self.latency.record(id, rtt_ms);
+        let mut finger = self.lock_finger()?;
+        if finger.contains(&Some(id)) {
+            finger.join_with_proximity(id, |did| self.latency.get(did));
+        }
+        Ok(())
+    }
+
+    /// Set the finger which is being fixed, see `ChordStabilize::fix_fingers`,
+    /// positions of the node are joined to tables of local positions.
+    pub fn set_fix_finger(&self, id: Did) -> Result<()> {
+        {
+            let mut finger = self.lock_finger()?;
+            finger.set_fix_with_proximity(id, |did| self.latency.get(did));
+        }
+        if id != self.id {
+            self.join_positions(id)?;
+        }
         Ok(())
     }
 
@@ -199,6 +600,247 @@ impl PeerRing {
         let finger = self.lock_finger()?;
         Ok(finger.len())
     }
+
+    /// The first k nodes of successor list, which should keep replicas of local storage
+    pub fn replica_nodes(&self) -> Result<Vec<Did>> {
+        let successor = self.lock_successor()?;
+        Ok(successor
+            .list()
+            .into_iter()
+            .take(self.replication_factor.into())
+            .collect())
+    }
+
+    /// Generate actions for copying vnodes to replica nodes
+    fn replicate(&self, vnodes: Vec<VirtualNode>) -> Result<PeerRingAction> {
+        let nodes = self.replica_nodes()?;
+        if vnodes.is_empty() || nodes.is_empty() {
+            return Ok(PeerRingAction::None);
+        }
+        Ok(PeerRingAction::MultiActions(
+            nodes
+                .into_iter()
+                .map(|n| {
+                    PeerRingAction::RemoteAction(n, RemoteAction::ReplicateVNode(vnodes.clone()))
+                })
+                .collect(),
+        ))
+    }
+
+    /// Sync local storage to replica nodes when they are changed by churn.
+    /// Each replica node gets all vnodes of local storage, which replace the replicas
+    /// copied from this node before, and a node which is not a replica node anymore
+    /// gets nothing, so it drops them. Nothing is sent while successor list is not changed,
+    /// the vnodes stored since then are replicated by `store`.
+    /// This function should be called periodically, see `Stabilization`.
+    pub async fn replicate_all(&self) -> Result<PeerRingAction> {
+        let nodes = self.replica_nodes()?;
+        let dropped: Vec<Did> = {
+            let mut replicated_to = self
+                .replicated_to
+                .lock()
+                .map_err(|_| Error::DHTSyncLockError)?;
+            if *replicated_to == nodes {
+                return Ok(PeerRingAction::None);
+            }
+            let prev = std::mem::replace(&mut *replicated_to, nodes.clone());
+            prev.into_iter().filter(|n| !nodes.contains(n)).collect()
+        };
+        let data: Vec<VirtualNode> = if nodes.is_empty() {
+            vec![]
+        } else {
+            self.storage
+                .get_all()
+                .await?
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect()
+        };
+        let actions: Vec<PeerRingAction> = nodes
+            .into_iter()
+            .map(|n| PeerRingAction::RemoteAction(n, RemoteAction::SyncReplica(data.clone())))
+            .chain(
+                dropped
+                    .into_iter()
+                    .map(|n| PeerRingAction::RemoteAction(n, RemoteAction::SyncReplica(vec![]))),
+            )
+            .collect();
+        Ok(PeerRingAction::MultiActions(actions))
+    }
+
+    /// Test if a replica of id copied from origin should be kept by this node.
+    /// The vnodes of origin are in (origin, successor of origin], and they are copied to
+    /// successors of origin, so id is in (origin, self], and origin is not in
+    /// (predecessor, self), otherwise origin would be the predecessor.
+    pub fn is_replica_of(&self, origin: Did, id: Did) -> Result<bool> {
+        let range = BiasId::new(&origin, &self.id);
+        if origin == self.id || id == origin || BiasId::new(&origin, &id) > range {
+            return Ok(false);
+        }
+        Ok(match *self.lock_predecessor()? {
+            Some(p) => BiasId::new(&origin, &p) < range,
+            None => true,
+        })
+    }
+
+    /// Store a replica of vnode copied from origin, it will overwrite the previous one.
+    /// A vnode which is not in replica range of origin is refused, and a replica is
+    /// counted in storage quota, it's refused if there is no room, see `PeerRing::reserve`.
+    pub async fn store_replica(&self, origin: Did, vnode: VirtualNode) -> Result<()> {
+        let id = vnode.did();
+        if !self.is_replica_of(origin, id)? {
+            return Err(Error::ReplicaOutOfRange(id));
+        }
+        self.cache.remove(&id);
+        // the previous one is replaced, it should not be counted
+        self.replica.remove(&id);
+        self.reserve(vnode.size()).await?;
+        self.replica.set(&id, vnode);
+        self.replica_origin.set(&id, origin);
+        Ok(())
+    }
+
+    /// Drop replicas copied from origin, except the ones in `keep`
+    pub fn prune_replicas(&self, origin: Did, keep: &[Did]) {
+        for id in self.replica_origin.keys() {
+            if self.replica_origin.get(&id) == Some(origin) && !keep.contains(&id) {
+                self.replica.remove(&id);
+                self.replica_origin.remove(&id);
+            }
+        }
+    }
+
+    /// The first known node following id, which should keep a replica of id if the
+    /// node responsible for id is down. Nodes in `excluded` are skipped.
+    pub fn replica_holder(&self, id: Did, excluded: &[Did]) -> Result<Option<Did>> {
+        let mut nodes = self.lock_successor()?.list();
+        nodes.extend(self.lock_finger()?.list().iter().flatten());
+        Ok(nodes
+            .into_iter()
+            .filter(|n| *n != self.id && !excluded.contains(n))
+            .min_by_key(|n| BiasId::new(&id, n)))
+    }
+
+    /// Fetch a replica of vnode
+    pub fn fetch_replica(&self, id: &Did) -> Option<VirtualNode> {
+        self.replica.get(id).filter(|v| !v.is_expired())
+    }
+
+    /// Bytes of vnode data in storage, cache and replicas
+    pub async fn storage_usage(&self) -> Result<usize> {
+        Ok(self.stored_bytes().await? + self.cache.weight() + self.replica.weight())
+    }
+
+    fn lock_stored_bytes(&self) -> Result<MutexGuard<Option<usize>>> {
+        self.stored_bytes
+            .lock()
+            .map_err(|_| Error::DHTSyncLockError)
+    }
+
+    /// Bytes of vnode data in storage, the storage is scanned only if it's not counted.
+    async fn stored_bytes(&self) -> Result<usize> {
+        if let Some(bytes) = *self.lock_stored_bytes()? {
+            return Ok(bytes);
+        }
+        let bytes: usize = self
+            .storage
+            .get_all()
+            .await?
+            .iter()
+            .map(|(_, v): &(Did, VirtualNode)| v.size())
+            .sum();
+        *self.lock_stored_bytes()? = Some(bytes);
+        Ok(bytes)
+    }
+
+    /// Count `added` bytes are written to storage, and `removed` bytes are gone.
+    fn count_stored_bytes(&self, added: usize, removed: usize) -> Result<()> {
+        if let Some(bytes) = self.lock_stored_bytes()?.as_mut() {
+            *bytes = (*bytes + added).saturating_sub(removed);
+        }
+        Ok(())
+    }
+
+    /// Write a vnode to storage, the previous one is overwritten.
+    pub async fn put_vnode(&self, vnode: &VirtualNode) -> Result<()> {
+        let id = vnode.did();
+        let prev = self.storage.get(&id).await.map(|v: VirtualNode| v.size());
+        self.storage.put(&id, vnode).await?;
+        self.count_stored_bytes(vnode.size(), prev.unwrap_or(0))
+    }
+
+    /// Remove a vnode from storage.
+    pub async fn remove_vnode(&self, id: &Did) -> Result<()> {
+        let prev = self.storage.get(id).await.map(|v: VirtualNode| v.size());
+        self.storage.remove(id).await?;
+        self.count_stored_bytes(0, prev.unwrap_or(0))
+    }
+
+    /// Make room for `incoming` bytes under storage quota.
+    /// Cached vnodes are evicted first, then replicas, both in LRU order.
+    /// Primary data is never evicted, if there is still no room, the write should be refused.
+    pub async fn reserve(&self, incoming: usize) -> Result<()> {
+        let quota = match self.storage_quota {
+            Some(q) => q,
+            None => return Ok(()),
+        };
+        let mut usage = self.storage_usage().await? + incoming;
+        while usage > quota {
+            match self.cache.pop_lru().or_else(|| self.replica.pop_lru()) {
+                Some((_, v)) => usage -= v.size(),
+                None => return Err(Error::StorageQuotaExceeded(quota)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove expired vnodes, prune storage and evict vnodes over quota.
+    /// This function should be called periodically, see `Stabilization`.
+    pub async fn sweep(&self) -> Result<()> {
+        let mut stored_bytes = 0;
+        for (k, v) in self.storage.get_all().await? {
+            let v: VirtualNode = v;
+            if v.is_expired() {
+                self.storage.remove(&k).await?;
+            } else {
+                stored_bytes += v.size();
+            }
+        }
+        *self.lock_stored_bytes()? = Some(stored_bytes);
+        self.cache.remove_expired();
+        self.replica.retain(|_, v| !v.is_expired());
+        let replicas: HashSet<Did> = self.replica.keys().into_iter().collect();
+        self.replica_origin.retain(|id, _| replicas.contains(id));
+        self.storage.prune().await?;
+        if let Err(e) = self.reserve(0).await {
+            tracing::warn!("[sweep] primary data is over quota: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// When predecessor is changed, the new predecessor is responsible for the vnodes
+    /// in range (predecessor, self], hand over the replicas in that range to it.
+    /// Replicas may be kept without replication, when they are handed off by a leaving node.
+    pub fn promote_replicas(&self, predecessor: Did) -> Result<PeerRingAction> {
+        if predecessor == self.id {
+            return Ok(PeerRingAction::None);
+        }
+        let bias = BiasId::new(&predecessor, &self.id);
+        let data: Vec<VirtualNode> = self
+            .replica
+            .values()
+            .into_iter()
+            .filter(|v| v.did() != predecessor && BiasId::new(&predecessor, &v.did()) <= bias)
+            .collect();
+        if data.is_empty() {
+            Ok(PeerRingAction::None)
+        } else {
+            Ok(PeerRingAction::RemoteAction(
+                predecessor,
+                RemoteAction::PromoteReplica(data),
+            ))
+        }
+    }
 }
 
 impl Chord<PeerRingAction> for PeerRing {
@@ -209,7 +851,7 @@ impl Chord<PeerRingAction> for PeerRing {
         if id == self.id {
             return Ok(PeerRingAction::None);
         }
-        finger.join(id);
+        finger.join_with_proximity(id, |did| self.latency.get(did));
         if self.bias(id) < self.bias(successor.max()) || successor.is_none() {
             // 1) id should follows self.id
             // 2) #fff should follow #001 because id space is a Finate Ring
@@ -217,6 +859,8 @@ impl Chord<PeerRingAction> for PeerRing {
             successor.update(id);
             // only triger if successor is updated
         }
+        self.join_positions(id)?;
+        self.touch();
         Ok(PeerRingAction::RemoteAction(
             id,
             RemoteAction::FindSuccessor(self.id),
@@ -225,6 +869,9 @@ impl Chord<PeerRingAction> for PeerRing {
 
     /// Fig.5 n.find_successor(id)
     fn find_successor(&self, id: Did) -> Result<PeerRingAction> {
+        if !self.positions.is_empty() {
+            return self.find_successor_on_positions(id);
+        }
         let successor = self.lock_successor()?;
         let finger = self.lock_finger()?;
         // if (id \in (n; successor]); return successor
@@ -239,10 +886,13 @@ impl Chord<PeerRingAction> for PeerRing {
             // return n.find_successor(id);
             let closest = finger.closest(id);
             match closest {
-                Ok(n) => Ok(PeerRingAction::RemoteAction(
-                    n,
-                    RemoteAction::FindSuccessor(id),
-                )),
+                Ok(n) => {
+                    self.latency.record_hop(n);
+                    Ok(PeerRingAction::RemoteAction(
+                        n,
+                        RemoteAction::FindSuccessor(id),
+                    ))
+                }
                 Err(e) => Err(e),
             }
         }
@@ -251,7 +901,9 @@ impl Chord<PeerRingAction> for PeerRing {
 
 impl ChordStabilize<PeerRingAction> for PeerRing {
     /// n' thinks it might be our predecessor.
+    /// With virtual positions, positions of n' are notified to local positions too.
     fn notify(&self, id: Did) -> Result<Option<Did>> {
+        self.notify_positions(id)?;
         let mut predecessor = self.lock_predecessor()?;
         let predecessor_value = *predecessor;
         // if (predecessor is nil or n' /in (predecessor; n)); predecessor = n';
@@ -260,6 +912,7 @@ impl ChordStabilize<PeerRingAction> for PeerRing {
                 // if id <- [pre, self]
                 if self.bias(pre) < self.bias(id) {
                     *predecessor = Some(id);
+                    self.touch();
                     Ok(Some(id))
                 } else {
                     Ok(None)
@@ -267,6 +920,7 @@ impl ChordStabilize<PeerRingAction> for PeerRing {
             }
             None => {
                 *predecessor = Some(id);
+                self.touch();
                 Ok(Some(id))
             }
         }
@@ -275,28 +929,22 @@ impl ChordStabilize<PeerRingAction> for PeerRing {
     /// called periodically. refreshes finger table entries.
     /// next stores the index of the next finger to fix.
     fn fix_fingers(&self) -> Result<PeerRingAction> {
-        let mut fix_finger_index = self.lock_finger()?.fix_finger_index;
-
         // next = next + 1;
         //if (next > m) next = 1;
         // finger[next] = find_successor(n + 2^(next-1) );
         // for index start with 0
         // finger[next] = find_successor(n + 2^(next) );
-        fix_finger_index += 1;
-        if fix_finger_index >= 159 {
-            fix_finger_index = 0;
-        }
-
-        let did: BigUint = (BigUint::from(self.id)
-            + BigUint::from(2u16).pow(fix_finger_index.into()))
-            % BigUint::from(2u16).pow(160);
+        let (fix_finger_index, did) = {
+            let finger = self.lock_finger()?;
+            let index = (finger.fix_finger_index as usize + 1) % finger.size();
+            (index as u8, finger.finger_pos(index))
+        };
 
-        match self.find_successor(did.into()) {
+        match self.find_successor(did) {
             Ok(res) => match res {
                 PeerRingAction::Some(v) => {
-                    let mut finger = self.lock_finger()?;
-                    finger.fix_finger_index = fix_finger_index;
-                    finger.set_fix(v);
+                    self.lock_finger()?.fix_finger_index = fix_finger_index;
+                    self.set_fix_finger(v)?;
                     Ok(PeerRingAction::None)
                 }
                 PeerRingAction::RemoteAction(a, RemoteAction::FindSuccessor(b)) => {
@@ -346,12 +994,22 @@ impl ChordStabilize<PeerRingAction> for PeerRing {
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl ChordStorage<PeerRingAction> for PeerRing {
     /// lookup always check data via finger table
+    /// If data is missing on the responsible node, it will fall back to the replicas
     async fn lookup(&self, vid: &Did) -> Result<PeerRingAction> {
         match self.find_successor(*vid) {
             // if vid is in [self, successor]
-            Ok(PeerRingAction::Some(_)) => match self.storage.get(vid).await {
-                Ok(v) => Ok(PeerRingAction::SomeVNode(v)),
-                Err(_) => Ok(PeerRingAction::None),
+            Ok(PeerRingAction::Some(successor)) => match self.storage.get(vid).await {
+                Ok(v) if !v.is_expired() => Ok(PeerRingAction::SomeVNode(v)),
+                _ => match self.fetch_replica(vid) {
+                    Some(v) => Ok(PeerRingAction::SomeVNode(v)),
+                    None if self.replication_factor > 0 && successor != self.id => {
+                        Ok(PeerRingAction::RemoteAction(
+                            successor,
+                            RemoteAction::FindVNodeReplica(*vid),
+                        ))
+                    }
+                    None => Ok(PeerRingAction::None),
+                },
             },
             Ok(PeerRingAction::RemoteAction(n, RemoteAction::FindSuccessor(id))) => {
                 Ok(PeerRingAction::RemoteAction(n, RemoteAction::FindVNode(id)))
@@ -361,9 +1019,11 @@ impl ChordStorage<PeerRingAction> for PeerRing {
         }
     }
 
-    /// When a vnode data is fetched from remote, it should be cache at local
+    /// When a vnode data is fetched from remote, it should be cache at local,
+    /// until the vnode or the cache entry is expired
     fn cache(&self, vnode: VirtualNode) {
-        self.cache.set(&vnode.did(), vnode);
+        self.cache
+            .set_with_expiration(&vnode.did(), vnode.clone(), vnode.expires_at);
     }
 
     /// When a VNode data is fetched from remote, it should be cache at local
@@ -372,26 +1032,35 @@ impl ChordStorage<PeerRingAction> for PeerRing {
     }
 
     /// If address of VNode is in range(self, successor), it should store locally,
-    /// otherwise, it should on remote successor
+    /// otherwise, it should on remote successor.
+    /// A locally stored VNode will be replicated to the first k successors.
+    /// An expired VNode is dropped, and a write over storage quota is refused.
     async fn store(&self, peer: VirtualNode) -> Result<PeerRingAction> {
         let vid = peer.did();
+        // a newer version is written, the cached one is stale
+        self.cache.remove(&vid);
         // find VNode's closest successor
         match self.find_successor(vid) {
             // if vid is in range(self, successor)
             // self should store it
-            Ok(PeerRingAction::Some(_)) => match self.storage.get(&vid).await {
-                Ok(v) => {
-                    let _ = self
-                        .storage
-                        .put(&vid, &VirtualNode::concat(&v, &peer)?)
-                        .await?;
-                    Ok(PeerRingAction::None)
+            Ok(PeerRingAction::Some(_)) => {
+                // a record is accepted only if it's signed by it's owner
+                if peer.kind == VNodeType::Record {
+                    Record::try_from(peer.clone())?.verify()?;
                 }
-                Err(_) => {
-                    let _ = self.storage.put(&vid, &peer).await?;
-                    Ok(PeerRingAction::None)
+                if peer.is_expired() {
+                    return Ok(PeerRingAction::None);
                 }
-            },
+                let (vnode, stored_size) = match self.storage.get(&vid).await {
+                    Ok(v) if !v.is_expired() => (VirtualNode::concat(&v, &peer)?, v.size()),
+                    Ok(v) => (peer, v.size()),
+                    Err(_) => (peer, 0),
+                };
+                self.reserve(vnode.size().saturating_sub(stored_size))
+                    .await?;
+                self.put_vnode(&vnode).await?;
+                self.replicate(vec![vnode])
+            }
             Ok(PeerRingAction::RemoteAction(n, RemoteAction::FindSuccessor(_))) => Ok(
                 PeerRingAction::RemoteAction(n, RemoteAction::FindAndStore(peer)),
             ),
@@ -418,13 +1087,45 @@ impl ChordStorage<PeerRingAction> for PeerRing {
         }
     }
 
+    /// Scan vnodes in range from the node responsible for start of range.
+    /// If it's this node, a page of locally stored vnodes is returned, whose cursor
+    /// points to the rest of range, otherwise the scan should be forwarded.
+    async fn scan(&self, range: &VNodeRange) -> Result<PeerRingAction> {
+        match self.find_successor(range.start) {
+            Ok(PeerRingAction::Some(_)) => {
+                let boundary = self.interval_end(range.start)?;
+                let stored = self
+                    .storage
+                    .get_all()
+                    .await?
+                    .into_iter()
+                    .map(|(_, v)| v)
+                    .collect();
+                Ok(PeerRingAction::SomeVNodePage(range.page(stored, boundary)))
+            }
+            Ok(PeerRingAction::RemoteAction(n, RemoteAction::FindSuccessor(_))) => Ok(
+                PeerRingAction::RemoteAction(n, RemoteAction::ScanVNode(range.clone())),
+            ),
+            Ok(a) => Err(Error::PeerRingUnexpectedAction(a)),
+            Err(e) => Err(e),
+        }
+    }
+
     /// This function should call when successor is updated
     async fn sync_with_successor(&self, new_successor: Did) -> Result<PeerRingAction> {
         let mut data = Vec::<VirtualNode>::new();
         let all_items: Vec<(Did, VirtualNode)> = self.storage.get_all().await?;
         for (k, v) in all_items.iter() {
-            // k < self.successor
-            if self.bias(*k) < self.bias(new_successor) && self.storage.remove(k).await.is_ok() {
+            // a node keeps vnodes in range (self, successor],
+            // so k > new_successor is not belongs to self anymore.
+            // With virtual positions, it keeps vnodes in intervals of it's positions,
+            // and the others are handed over to new successor, which forwards them.
+            let moved = if self.positions.is_empty() {
+                self.bias(*k) > self.bias(new_successor)
+            } else {
+                !self.is_responsible(*k)?
+            };
+            if moved && self.remove_vnode(k).await.is_ok() {
                 data.push(v.clone());
             }
         }
@@ -444,8 +1145,15 @@ impl ChordStorage<PeerRingAction> for PeerRing {
 mod tests {
     use std::str::FromStr;
 
+    use num_bigint::BigUint;
+    use rand::rngs::StdRng;
+    use rand::Rng;
+    use rand::SeedableRng;
+
     use super::*;
+    use crate::dht::tests::gen_ordered_dids;
     use crate::ecc::SecretKey;
+    use crate::message::Encoder;
 
     #[tokio::test]
     async fn test_chord_finger() -> Result<()> {
@@ -668,4 +1376,453 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_sync_with_successor() -> Result<()> {
+        let dids = gen_ordered_dids(3);
+        let (a, b, c) = (dids[0], dids[1], dids[2]);
+        let db_path = PersistenceStorage::random_path("./tmp");
+        let db = PersistenceStorage::new_with_path(db_path.as_str())
+            .await
+            .unwrap();
+        let node_a = PeerRing::new_with_storage(a, 3, db);
+        node_a.join(c)?;
+        assert_eq!(node_a.lock_successor()?.min(), c);
+
+        // vnode_ab is in range (a, b], vnode_bc is in range (b, c]
+        let one = Did::from(BigUint::from(1u16));
+        let vnode_ab = VirtualNode {
+            address: a + one,
+            data: vec!["hello a".to_string().encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        let vnode_bc = VirtualNode {
+            address: b + one,
+            data: vec!["hello b".to_string().encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        assert_eq!(node_a.store(vnode_ab.clone()).await?, PeerRingAction::None);
+        assert_eq!(node_a.store(vnode_bc.clone()).await?, PeerRingAction::None);
+        assert_eq!(node_a.storage.count().await?, 2);
+
+        // nothing to sync while successor is not changed
+        assert_eq!(node_a.sync_with_successor(c).await?, PeerRingAction::None);
+
+        // b joined and become successor of a, vnode_bc should be handed over to b
+        node_a.join(b)?;
+        assert_eq!(node_a.lock_successor()?.min(), b);
+        assert_eq!(
+            node_a.sync_with_successor(b).await?,
+            PeerRingAction::RemoteAction(b, RemoteAction::SyncVNodeWithSuccessor(vec![vnode_bc]))
+        );
+        assert_eq!(node_a.storage.count().await?, 1);
+        assert_eq!(node_a.storage.get(&vnode_ab.did()).await?, vnode_ab);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replication() -> Result<()> {
+        let dids = gen_ordered_dids(3);
+        let (a, b, c) = (dids[0], dids[1], dids[2]);
+        let one = Did::from(BigUint::from(1u16));
+        let db_path_a = PersistenceStorage::random_path("./tmp");
+        let db_path_c = PersistenceStorage::random_path("./tmp");
+        let db_a = PersistenceStorage::new_with_path(db_path_a.as_str())
+            .await
+            .unwrap();
+        let db_c = PersistenceStorage::new_with_path(db_path_c.as_str())
+            .await
+            .unwrap();
+        let node_a = PeerRing::new_with_storage(a, 3, db_a).with_replication_factor(2);
+        let node_c = PeerRing::new_with_storage(c, 3, db_c).with_replication_factor(2);
+        node_a.join(b)?;
+        node_a.join(c)?;
+        assert_eq!(node_a.replica_nodes()?, vec![b, c]);
+
+        // vnode in range (a, b] is stored on a, and replicated to b and c
+        let vnode = VirtualNode {
+            address: a + one,
+            data: vec!["hello a".to_string().encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        assert_eq!(
+            node_a.store(vnode.clone()).await?,
+            PeerRingAction::MultiActions(vec![
+                PeerRingAction::RemoteAction(b, RemoteAction::ReplicateVNode(vec![vnode.clone()])),
+                PeerRingAction::RemoteAction(c, RemoteAction::ReplicateVNode(vec![vnode.clone()])),
+            ])
+        );
+
+        // local storage is synced to replica nodes only if they are changed
+        let sync = |n: Did, data: Vec<VirtualNode>| {
+            PeerRingAction::RemoteAction(n, RemoteAction::SyncReplica(data))
+        };
+        assert_eq!(
+            node_a.replicate_all().await?,
+            PeerRingAction::MultiActions(vec![
+                sync(b, vec![vnode.clone()]),
+                sync(c, vec![vnode.clone()])
+            ])
+        );
+        assert_eq!(node_a.replicate_all().await?, PeerRingAction::None);
+        // b left, c and d are replica nodes, b should drop it's replicas
+        let d = c + one;
+        node_a.remove(b)?;
+        node_a.join(d)?;
+        assert_eq!(
+            node_a.replicate_all().await?,
+            PeerRingAction::MultiActions(vec![
+                sync(c, vec![vnode.clone()]),
+                sync(d, vec![vnode.clone()]),
+                sync(b, vec![]),
+            ])
+        );
+        node_a.remove(d)?;
+        node_a.join(b)?;
+        node_a.replicate_all().await?;
+
+        // missing vnode in range (a, b] will be searched on replica nodes
+        let missing = a + one + one;
+        assert_eq!(
+            node_a.lookup(&missing).await?,
+            PeerRingAction::RemoteAction(b, RemoteAction::FindVNodeReplica(missing))
+        );
+        // local replica will be used if it exists
+        let replica = VirtualNode {
+            address: missing,
+            data: vec!["hello replica".to_string().encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        node_a.replica.set(&missing, replica.clone());
+        assert_eq!(
+            node_a.lookup(&missing).await?,
+            PeerRingAction::SomeVNode(replica.clone())
+        );
+        // b and c follow missing, b will be asked if a is down
+        assert_eq!(node_a.replica_holder(missing, &[])?, Some(b));
+        assert_eq!(node_a.replica_holder(missing, &[b])?, Some(c));
+
+        // c keeps replica of vnode, which is in range (a, c] but not in (b, c]
+        node_c.store_replica(a, vnode.clone()).await?;
+        assert_eq!(node_c.promote_replicas(b)?, PeerRingAction::None);
+        assert_eq!(
+            node_c.promote_replicas(a)?,
+            PeerRingAction::RemoteAction(a, RemoteAction::PromoteReplica(vec![vnode.clone()]))
+        );
+
+        // vnode out of range (origin, self] is refused,
+        // and origin should not be in (predecessor, self)
+        assert!(matches!(
+            node_c.store_replica(b, vnode.clone()).await,
+            Err(Error::ReplicaOutOfRange(id)) if id == vnode.did()
+        ));
+        node_c.notify(b)?;
+        assert!(node_c.store_replica(a, replica.clone()).await.is_ok());
+        let late = VirtualNode {
+            address: b + one,
+            ..replica.clone()
+        };
+        node_c.store_replica(b, late.clone()).await?;
+        let later = VirtualNode {
+            address: b + one + one,
+            ..replica.clone()
+        };
+        assert!(node_c.store_replica(b + one, later).await.is_err());
+
+        // a full copy from origin drops the replicas which are not in it
+        node_c.prune_replicas(a, &[vnode.did()]);
+        assert_eq!(node_c.fetch_replica(&vnode.did()), Some(vnode.clone()));
+        assert_eq!(node_c.fetch_replica(&replica.did()), None);
+        assert_eq!(node_c.fetch_replica(&late.did()), Some(late));
+        node_c.prune_replicas(a, &[]);
+        assert_eq!(node_c.fetch_replica(&vnode.did()), None);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expiration_and_quota() -> Result<()> {
+        let dids = gen_ordered_dids(1);
+        let db_path = PersistenceStorage::random_path("./tmp");
+        let db = PersistenceStorage::new_with_path(db_path.as_str())
+            .await
+            .unwrap();
+        // all vnodes have same size
+        let gen_vnode = |address: Did| -> Result<VirtualNode> {
+            Ok(VirtualNode {
+                address,
+                data: vec!["hello".to_string().encode()?],
+                kind: VNodeType::RelayMessage,
+                expires_at: None,
+            })
+        };
+        let vids: Vec<Did> = (1..=6u16)
+            .map(|i| dids[0] + Did::from(BigUint::from(i)))
+            .collect();
+        let cached = gen_vnode(vids[0])?;
+        let replica = gen_vnode(vids[1])?;
+        let quota = 3 * cached.size();
+        // isolated node is responsible for all vnodes
+        let node = PeerRing::new_with_storage(dids[0], 3, db).with_storage_quota(quota);
+
+        // expired vnode is dropped
+        let expired = VirtualNode {
+            expires_at: Some(0),
+            ..gen_vnode(vids[2])?
+        };
+        assert!(expired.is_expired());
+        assert_eq!(node.store(expired).await?, PeerRingAction::None);
+        assert_eq!(node.storage.count().await?, 0);
+
+        // vnode with ttl is kept until it's expired, then removed by sweep
+        let living = gen_vnode(vids[2])?.with_ttl(60 * 1000);
+        assert!(!living.is_expired());
+        node.store(living.clone()).await?;
+        assert_eq!(
+            node.lookup(&living.did()).await?,
+            PeerRingAction::SomeVNode(living.clone())
+        );
+        let dead = VirtualNode {
+            expires_at: Some(0),
+            ..living
+        };
+        node.put_vnode(&dead).await?;
+        assert_eq!(node.lookup(&dead.did()).await?, PeerRingAction::None);
+        node.sweep().await?;
+        assert_eq!(node.storage.count().await?, 0);
+
+        // cache is evicted before replica, while storage is over quota
+        node.cache(cached.clone());
+        node.replica.set(&replica.did(), replica.clone());
+        node.store(gen_vnode(vids[2])?).await?;
+        assert_eq!(node.storage_usage().await?, quota);
+        node.store(gen_vnode(vids[3])?).await?;
+        assert!(node.fetch_cache(&cached.did()).is_none());
+        assert_eq!(node.fetch_replica(&replica.did()), Some(replica.clone()));
+        node.store(gen_vnode(vids[4])?).await?;
+        assert!(node.fetch_replica(&replica.did()).is_none());
+
+        // primary data is never evicted, the write is refused
+        assert!(matches!(
+            node.store(gen_vnode(vids[5])?).await,
+            Err(Error::StorageQuotaExceeded(q)) if q == quota
+        ));
+        assert_eq!(node.storage.count().await?, 3);
+
+        // replicas are counted in quota too
+        let origin = dids[0] - Did::from(BigUint::from(10u16));
+        assert!(matches!(
+            node.store_replica(origin, gen_vnode(vids[5] - Did::from(BigUint::from(15u16)))?).await,
+            Err(Error::StorageQuotaExceeded(q)) if q == quota
+        ));
+        assert_eq!(node.storage_usage().await?, quota);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_invalidation() -> Result<()> {
+        let dids = gen_ordered_dids(1);
+        let db_path = PersistenceStorage::random_path("./tmp");
+        let db = PersistenceStorage::new_with_path(db_path.as_str())
+            .await
+            .unwrap();
+        let node = PeerRing::new_with_storage(dids[0], 3, db).with_cache(1, 60 * 1000);
+        let gen_vnode = |i: u16| -> Result<VirtualNode> {
+            Ok(VirtualNode {
+                address: dids[0] + Did::from(BigUint::from(i)),
+                data: vec![format!("hello {}", i).encode()?],
+                kind: VNodeType::RelayMessage,
+                expires_at: None,
+            })
+        };
+        let (a, b) = (gen_vnode(1)?, gen_vnode(2)?);
+
+        node.cache(a.clone());
+        assert_eq!(node.fetch_cache(&a.did()), Some(a.clone()));
+        // cache is bounded, a is evicted by b
+        node.cache(b.clone());
+        assert_eq!(node.fetch_cache(&a.did()), None);
+        assert_eq!(node.fetch_cache(&b.did()), Some(b.clone()));
+
+        // a newer version of b is stored, cached b is invalidated
+        node.store(b.clone()).await?;
+        assert_eq!(node.fetch_cache(&b.did()), None);
+
+        // cached vnode is expired with the vnode
+        node.cache(a.clone().with_ttl(0));
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        assert_eq!(node.fetch_cache(&a.did()), None);
+
+        assert_eq!(node.cache_stats(), CacheStats {
+            hits: 2,
+            misses: 3,
+            entries: 0,
+        });
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    /// Build a ring of isolated nodes which know each other, and count keys each node is
+    /// responsible for, return the variance of counts.
+    async fn key_distribution_variance(dids: &[Did], n: u8, keys: &[Did]) -> Result<f64> {
+        let mut rings = vec![];
+        for did in dids {
+            let path = PersistenceStorage::random_path("./tmp");
+            let storage = PersistenceStorage::new_with_path(path.as_str()).await?;
+            rings.push(PeerRing::new_with_storage(*did, 3, storage).with_virtual_positions(n));
+        }
+        for ring in rings.iter() {
+            for did in dids {
+                ring.join(*did)?;
+            }
+        }
+
+        let mut counts = vec![0f64; rings.len()];
+        for key in keys {
+            let mut responsible = vec![];
+            for (i, ring) in rings.iter().enumerate() {
+                if ring.is_responsible(*key)? {
+                    assert!(ring.find_successor(*key)?.is_some());
+                    responsible.push(i);
+                } else {
+                    assert!(ring.find_successor(*key)?.is_remote());
+                }
+            }
+            // the intervals of all positions are a partition of the ring
+            assert_eq!(
+                responsible.len(),
+                1,
+                "{:?} is owned by {:?}",
+                key,
+                responsible
+            );
+            counts[responsible[0]] += 1.0;
+        }
+        let mean = keys.len() as f64 / rings.len() as f64;
+        Ok(counts.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / rings.len() as f64)
+    }
+
+    #[tokio::test]
+    async fn test_virtual_positions_balance_keys() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(20221017);
+        let mut random_did = || -> Did { web3::types::H160::from(rng.gen::<[u8; 20]>()).into() };
+        let mut dids: Vec<Did> = std::iter::repeat_with(&mut random_did).take(8).collect();
+        dids.sort();
+        let keys: Vec<Did> = std::iter::repeat_with(random_did).take(4000).collect();
+
+        let without = key_distribution_variance(&dids, 1, &keys).await?;
+        let with = key_distribution_variance(&dids, 16, &keys).await?;
+        assert!(
+            with < without,
+            "variance of keys on 8 nodes: {:.1} with single position, {:.1} with 16 positions",
+            without,
+            with
+        );
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_virtual_positions_stabilization() -> Result<()> {
+        let dids = gen_ordered_dids(3);
+        let mut rings = vec![];
+        for did in dids.iter() {
+            let path = PersistenceStorage::random_path("./tmp");
+            let storage = PersistenceStorage::new_with_path(path.as_str()).await?;
+            rings.push(PeerRing::new_with_storage(*did, 3, storage).with_virtual_positions(4));
+        }
+        let (a, b) = (&rings[0], &rings[1]);
+        // an isolated node has nothing to stabilize
+        assert!(a.position_successors()?.is_empty());
+        assert!(a.check_position_predecessors()?.is_empty());
+        assert!(a.fix_position_fingers()?.is_empty());
+
+        // positions of b are notified to positions of a
+        a.notify(dids[1])?;
+        let all: Vec<Did> = [derive_positions(dids[0], 4), derive_positions(dids[1], 4)].concat();
+        for p in a.positions.iter() {
+            let closest = all
+                .iter()
+                .filter(|q| **q != p.id)
+                .max_by_key(|q| p.bias(**q).pos())
+                .copied();
+            assert_eq!(*p.lock_predecessor()?, closest);
+        }
+        if a.positions
+            .iter()
+            .any(|p| a.owner_of(p.lock_predecessor().unwrap().unwrap()) == dids[1])
+        {
+            assert!(matches!(
+                a.check_position_predecessors()?[..],
+                [PeerRingAction::RemoteAction(p, RemoteAction::CheckPredecessor)] if p == dids[1]
+            ));
+        }
+
+        // b knows c, a learns c by fixing fingers of positions through b
+        b.join(dids[2])?;
+        a.join(dids[1])?;
+        assert_eq!(a.position_successors()?, vec![dids[1]]);
+        for action in a.fix_position_fingers()? {
+            assert!(matches!(
+                action,
+                PeerRingAction::RemoteAction(next, RemoteAction::FindSuccessorForFix(_)) if next == dids[1]
+            ));
+        }
+        for p in a.positions.iter() {
+            assert_eq!(p.lock_finger()?.fix_finger_index, 1);
+        }
+        a.set_fix_finger(dids[2])?;
+        for q in derive_positions(dids[2], 4) {
+            assert_eq!(a.owner_of(q), dids[2]);
+        }
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_virtual_positions_join_and_remove() -> Result<()> {
+        let dids = gen_ordered_dids(2);
+        let path = PersistenceStorage::random_path("./tmp");
+        let storage = PersistenceStorage::new_with_path(path.as_str()).await?;
+        let ring = PeerRing::new_with_storage(dids[0], 3, storage).with_virtual_positions(4);
+        assert_eq!(ring.positions.len(), 4);
+        // an isolated node is responsible for the whole ring
+        assert!(ring.is_responsible(dids[1])?);
+
+        ring.join(dids[1])?;
+        for q in derive_positions(dids[1], 4) {
+            assert_eq!(ring.owner_of(q), dids[1]);
+            // keys right after a remote position are owned by the remote node
+            assert!(!ring.is_responsible(q + Did::pow2(0))?);
+            assert!(matches!(
+                ring.find_successor(q + Did::pow2(0))?,
+                PeerRingAction::RemoteAction(next, RemoteAction::FindSuccessor(_)) if next == dids[1]
+            ));
+        }
+
+        ring.remove(dids[1])?;
+        assert!(ring.is_responsible(dids[1] + Did::pow2(0))?);
+        for p in ring.positions.iter() {
+            assert!(p
+                .lock_successor()?
+                .list()
+                .iter()
+                .all(|s| ring.owner_of(*s) == dids[0]));
+        }
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
 }
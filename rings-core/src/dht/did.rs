@@ -1,5 +1,7 @@
 use std::cmp::Eq;
 use std::cmp::PartialEq;
+use std::fmt::Debug;
+use std::hash::Hash;
 use std::ops::Add;
 use std::ops::Deref;
 use std::ops::Neg;
@@ -7,73 +9,125 @@ use std::ops::Sub;
 use std::str::FromStr;
 
 use num_bigint::BigUint;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 use web3::contract::tokens::Tokenizable;
 use web3::types::H160;
+use web3::types::H256;
 
-/// Did is a finate Ring R(P) where P = 2^160
 use crate::ecc::HashStr;
 use crate::err::Error;
 use crate::err::Result;
 
+/// Identifier of a finate Ring R(P) where P = 2^BITS.
+/// The arithmetic of finger table and successor list is generic over it,
+/// so a routing table can be keyed by ids of any width.
+/// `PeerRing` and `VirtualNode` are not generic over it, they are keyed by `Did`,
+/// which is the address of node's account, and is also the key of storage and
+/// the destination of messages.
+pub trait RingId:
+    Copy
+    + Eq
+    + Ord
+    + Hash
+    + Debug
+    + Serialize
+    + DeserializeOwned
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Neg<Output = Self>
+    + From<BigUint>
+    + Into<BigUint>
+{
+    /// Width of id in bits
+    const BITS: u32;
+
+    /// Order of the ring, which is 2^BITS
+    fn modulus() -> BigUint {
+        BigUint::from(2u16).pow(Self::BITS)
+    }
+
+    /// 2^k on the ring, it's the distance from a node to its finger k
+    fn pow2(k: u32) -> Self {
+        Self::from(BigUint::from(2u16).pow(k))
+    }
+
+    /// Test x <- (a, b)
+    fn in_range(&self, id: &Self, a: &Self, b: &Self) -> bool {
+        // Test x > a && b > x
+        *self - *id > *a - *id && *b - *id > *self - *id
+    }
+
+    /// Transform Did to BiasDid
+    fn bias(&self, id: &Self) -> BiasId<Self> {
+        BiasId::new(id, self)
+    }
+}
+
+/// Did is a finate Ring R(P) where P = 2^160
 #[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Debug, Serialize, Deserialize, Hash)]
 pub struct Did(H160);
 
+/// Did256 is a finate Ring R(P) where P = 2^256, for the routing tables keyed by
+/// 256-bit hashes, such as ed25519 public keys or sha256 of contents.
+/// It's not an address of node, so `PeerRing` is not served on it.
+#[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Debug, Serialize, Deserialize, Hash)]
+pub struct Did256(H256);
+
 impl std::fmt::Display for Did {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.into_token())
     }
 }
 
+impl std::fmt::Display for Did256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
 // Bias Did is a special Did which set origin Did's idendity to bias
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize, Hash)]
-pub struct BiasId {
-    bias: Did,
-    did: Did,
+#[serde(bound = "I: RingId")]
+pub struct BiasId<I: RingId = Did> {
+    bias: I,
+    did: I,
 }
 
-impl BiasId {
-    pub fn new(bias: &Did, id: &Did) -> BiasId {
+impl<I: RingId> BiasId<I> {
+    pub fn new(bias: &I, id: &I) -> BiasId<I> {
         BiasId {
             bias: *bias,
             did: *id - *bias,
         }
     }
 
-    pub fn to_did(bid: &BiasId) -> Did {
+    pub fn to_did(bid: &BiasId<I>) -> I {
         bid.did + bid.bias
     }
 
-    pub fn pos(&self) -> Did {
+    pub fn pos(&self) -> I {
         self.did
     }
 }
 
-impl PartialOrd for BiasId {
+impl<I: RingId> PartialOrd for BiasId<I> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        if other.bias != self.bias {
-            let did: Did = other.into();
-            let bid = BiasId::new(&self.bias, &did);
-            self.did.partial_cmp(&bid.did)
-        } else {
-            self.did.partial_cmp(&other.did)
-        }
+        Some(self.cmp(other))
     }
 }
 
-impl PartialEq<Did> for BiasId {
-    fn eq(&self, rhs: &Did) -> bool {
-        let id: Did = self.into();
-        id == *rhs
+impl<I: RingId> PartialEq<I> for BiasId<I> {
+    fn eq(&self, rhs: &I) -> bool {
+        BiasId::to_did(self) == *rhs
     }
 }
 
-impl Ord for BiasId {
+impl<I: RingId> Ord for BiasId<I> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         if other.bias != self.bias {
-            let did: Did = other.into();
-            let bid = BiasId::new(&self.bias, &did);
+            let bid = BiasId::new(&self.bias, &BiasId::to_did(other));
             self.did.cmp(&bid.did)
         } else {
             self.did.cmp(&other.did)
@@ -81,18 +135,6 @@ impl Ord for BiasId {
     }
 }
 
-impl From<BiasId> for Did {
-    fn from(id: BiasId) -> Did {
-        BiasId::to_did(&id)
-    }
-}
-
-impl From<&BiasId> for Did {
-    fn from(id: &BiasId) -> Did {
-        BiasId::to_did(id)
-    }
-}
-
 impl TryFrom<HashStr> for Did {
     type Error = Error;
     fn try_from(s: HashStr) -> Result<Self> {
@@ -100,25 +142,12 @@ impl TryFrom<HashStr> for Did {
     }
 }
 
-impl Did {
-    // Test x <- (a, b)
-    pub fn in_range(&self, id: &Self, a: &Self, b: &Self) -> bool {
-        // Test x > a && b > x
-        *self - *id > *a - *id && *b - *id > *self - *id
-    }
-
-    // Transform Did to BiasDid
-    pub fn bias(&self, id: &Self) -> BiasId {
-        BiasId::new(id, self)
-    }
+pub trait SortRing<I: RingId> {
+    fn sort(&mut self, id: I);
 }
 
-pub trait SortRing {
-    fn sort(&mut self, id: Did);
-}
-
-impl SortRing for Vec<Did> {
-    fn sort(&mut self, id: Did) {
+impl<I: RingId> SortRing<I> for Vec<I> {
+    fn sort(&mut self, id: I) {
         self.sort_by(|a, b| {
             let (da, db) = (*a - id, *b - id);
             (da).partial_cmp(&db).unwrap()
@@ -126,72 +155,99 @@ impl SortRing for Vec<Did> {
     }
 }
 
-impl Deref for Did {
-    type Target = H160;
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
+/// Implement finate ring arithmetic for an id which wraps a fixed-size hash
+macro_rules! impl_ring_id {
+    ($id:ident, $hash:ty, $bits:expr) => {
+        impl RingId for $id {
+            const BITS: u32 = $bits;
+        }
 
-impl From<Did> for H160 {
-    fn from(a: Did) -> Self {
-        a.0.to_owned()
-    }
-}
+        impl Deref for $id {
+            type Target = $hash;
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
 
-impl From<Did> for BigUint {
-    fn from(did: Did) -> BigUint {
-        BigUint::from_bytes_be(did.as_bytes())
-    }
-}
+        impl From<$id> for $hash {
+            fn from(a: $id) -> Self {
+                a.0.to_owned()
+            }
+        }
 
-impl From<BigUint> for Did {
-    fn from(a: BigUint) -> Self {
-        let ff = a % (BigUint::from(2u16).pow(160));
-        let mut va: Vec<u8> = ff.to_bytes_be();
-        let mut res = vec![0u8; 20 - va.len()];
-        res.append(&mut va);
-        assert_eq!(res.len(), 20, "{:?}", res);
-        Self(H160::from_slice(&res))
-    }
-}
+        impl From<$hash> for $id {
+            fn from(addr: $hash) -> Self {
+                Self(addr)
+            }
+        }
 
-impl From<H160> for Did {
-    fn from(addr: H160) -> Self {
-        Self(addr)
-    }
-}
+        impl From<$id> for BigUint {
+            fn from(did: $id) -> BigUint {
+                BigUint::from_bytes_be(did.as_bytes())
+            }
+        }
 
-impl FromStr for Did {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self> {
-        Ok(Self(H160::from_str(s).map_err(|_| Error::BadCHexInCache)?))
-    }
-}
+        impl From<BigUint> for $id {
+            fn from(a: BigUint) -> Self {
+                const LEN: usize = $bits / 8;
+                let ff = a % <$id as RingId>::modulus();
+                let mut va: Vec<u8> = ff.to_bytes_be();
+                let mut res = vec![0u8; LEN - va.len()];
+                res.append(&mut va);
+                assert_eq!(res.len(), LEN, "{:?}", res);
+                Self(<$hash>::from_slice(&res))
+            }
+        }
 
-// impl Finate Ring For Did
-impl Neg for Did {
-    type Output = Self;
-    fn neg(self) -> Self {
-        let ret = BigUint::from(2u16).pow(160) - BigUint::from(self);
-        ret.into()
-    }
-}
+        impl From<BiasId<$id>> for $id {
+            fn from(id: BiasId<$id>) -> $id {
+                BiasId::to_did(&id)
+            }
+        }
 
-impl Add for Did {
-    type Output = Self;
-    fn add(self, rhs: Self) -> Self {
-        ((BigUint::from(self) + BigUint::from(rhs)) % (BigUint::from(2u16).pow(160))).into()
-    }
-}
+        impl From<&BiasId<$id>> for $id {
+            fn from(id: &BiasId<$id>) -> $id {
+                BiasId::to_did(id)
+            }
+        }
 
-impl Sub for Did {
-    type Output = Self;
-    fn sub(self, rhs: Self) -> Self {
-        self + (-rhs)
-    }
+        impl FromStr for $id {
+            type Err = Error;
+            fn from_str(s: &str) -> Result<Self> {
+                Ok(Self(
+                    <$hash>::from_str(s).map_err(|_| Error::BadCHexInCache)?,
+                ))
+            }
+        }
+
+        // impl Finate Ring For Did
+        impl Neg for $id {
+            type Output = Self;
+            fn neg(self) -> Self {
+                let ret = <$id as RingId>::modulus() - BigUint::from(self);
+                ret.into()
+            }
+        }
+
+        impl Add for $id {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                ((BigUint::from(self) + BigUint::from(rhs)) % <$id as RingId>::modulus()).into()
+            }
+        }
+
+        impl Sub for $id {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                self + (-rhs)
+            }
+        }
+    };
 }
 
+impl_ring_id!(Did, H160, 160);
+impl_ring_id!(Did256, H256, 256);
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -230,4 +286,34 @@ mod tests {
         v.sort(d);
         assert_eq!(v, vec![d, a, b, c]);
     }
+
+    fn test_ring_arithmetic<I: RingId>() {
+        let zero = I::from(BigUint::from(0u16));
+        let one = I::pow2(0);
+        let max = I::from(I::modulus() - 1u16);
+        assert_eq!(max + one, zero);
+        assert_eq!(zero - one, max);
+        assert_eq!(-one + one, zero);
+        assert_eq!(-zero, zero);
+        assert_eq!(I::pow2(I::BITS), zero);
+        assert!(I::pow2(I::BITS - 1) > zero);
+
+        // bias makes the ring start at given id
+        let half = I::pow2(I::BITS - 1);
+        assert_eq!(max.bias(&half).pos(), half - one);
+        assert!(one.bias(&half) > max.bias(&half));
+        // one is in (half, 2) which crosses zero
+        assert!(one.in_range(&half, &half, &I::pow2(1)));
+        assert!(!half.in_range(&zero, &max, &one));
+    }
+
+    #[test]
+    fn test_ring_arithmetic_160() {
+        test_ring_arithmetic::<Did>();
+    }
+
+    #[test]
+    fn test_ring_arithmetic_256() {
+        test_ring_arithmetic::<Did256>();
+    }
 }
@@ -0,0 +1,95 @@
+#![warn(missing_docs)]
+//! Failure detector of nodes in routing tables, which is fed by results of liveness probes.
+//! A node is suspected once it fails a probe, and considered dead after it
+//! fails `max_suspicion` probes in a row.
+use std::time::Duration;
+
+use crate::dht::Did;
+use crate::storage::MemStorage;
+
+/// A probe is failed if no `Pong` arrives in 3 seconds by default
+pub const DEFAULT_PROBE_TIMEOUT_MS: u64 = 3000;
+/// A node is considered dead after 3 failed probes in a row by default
+pub const DEFAULT_MAX_SUSPICION: u8 = 3;
+
+/// Count of consecutive failed probes of nodes.
+#[derive(Debug)]
+pub struct FailureDetector {
+    timeout_ms: u64,
+    max_suspicion: u8,
+    suspicion: MemStorage<Did, u8>,
+}
+
+impl Default for FailureDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROBE_TIMEOUT_MS, DEFAULT_MAX_SUSPICION)
+    }
+}
+
+impl FailureDetector {
+    /// Create a detector with probe timeout, and number of failed probes before a node is dead.
+    pub fn new(timeout_ms: u64, max_suspicion: u8) -> Self {
+        Self {
+            timeout_ms,
+            max_suspicion: max_suspicion.max(1),
+            suspicion: MemStorage::new(),
+        }
+    }
+
+    /// How long to wait for the answer of a probe
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    /// Number of failed probes in a row before a node is considered dead
+    pub fn max_suspicion(&self) -> u8 {
+        self.max_suspicion
+    }
+
+    /// Number of failed probes in a row of node
+    pub fn suspicion(&self, did: &Did) -> u8 {
+        self.suspicion.get(did).unwrap_or(0)
+    }
+
+    /// Record a failed probe of node, return true if the node is considered dead.
+    /// The suspicion of a dead node is reset, since it's going to be evicted.
+    pub fn suspect(&self, did: Did) -> bool {
+        let count = self.suspicion(&did).saturating_add(1);
+        if count >= self.max_suspicion {
+            self.reset(&did);
+            return true;
+        }
+        self.suspicion.set(&did, count);
+        false
+    }
+
+    /// Clear suspicion of node, such as it answers a probe or it's removed
+    pub fn reset(&self, did: &Did) {
+        self.suspicion.remove(did);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+
+    #[test]
+    fn test_failure_detector() {
+        let dids = gen_ordered_dids(2);
+        let detector = FailureDetector::new(100, 3);
+        assert_eq!(detector.timeout(), Duration::from_millis(100));
+
+        assert!(!detector.suspect(dids[0]));
+        assert!(!detector.suspect(dids[0]));
+        assert_eq!(detector.suspicion(&dids[0]), 2);
+        assert_eq!(detector.suspicion(&dids[1]), 0);
+
+        // an answered probe clears suspicion
+        detector.reset(&dids[0]);
+        assert!(!detector.suspect(dids[0]));
+        assert!(!detector.suspect(dids[0]));
+        assert!(detector.suspect(dids[0]));
+        assert_eq!(detector.suspicion(&dids[0]), 0);
+    }
+}
@@ -1,27 +1,28 @@
 #![warn(missing_docs)]
 use std::ops::Index;
 
-use num_bigint::BigUint;
 use serde::Deserialize;
 use serde::Serialize;
 
 use super::did::BiasId;
+use super::did::RingId;
 use crate::dht::Did;
 use crate::err::Result;
 
 /// Finger table of Chord DHT
-/// Ring's finger table is implemented with BiasRing
+/// Ring's finger table is implemented with BiasRing, it's generic over width of id
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct FingerTable {
-    id: Did,
+#[serde(bound = "I: RingId")]
+pub struct FingerTable<I: RingId = Did> {
+    id: I,
     size: usize,
-    finger: Vec<Option<Did>>,
+    finger: Vec<Option<I>>,
     pub(super) fix_finger_index: u8,
 }
 
-impl FingerTable {
-    /// builder
-    pub fn new(id: Did, size: usize) -> Self {
+impl<I: RingId> FingerTable<I> {
+    /// builder, a full finger table has `I::BITS` fingers
+    pub fn new(id: I, size: usize) -> Self {
         Self {
             id,
             size,
@@ -36,19 +37,19 @@ impl FingerTable {
     }
 
     /// Get first element from Finger Table
-    pub fn first(&self) -> Option<Did> {
+    pub fn first(&self) -> Option<I> {
         let ids = self
             .finger
             .iter()
             .filter(|x| x.is_some())
             .take(1)
             .map(|x| x.unwrap())
-            .collect::<Vec<Did>>();
+            .collect::<Vec<I>>();
         ids.first().copied()
     }
 
     /// getter
-    pub fn get(&self, index: usize) -> &Option<Did> {
+    pub fn get(&self, index: usize) -> &Option<I> {
         if index >= self.finger.len() {
             return &None;
         }
@@ -56,7 +57,7 @@ impl FingerTable {
     }
 
     /// setter
-    pub fn set(&mut self, index: usize, id: Did) {
+    pub fn set(&mut self, index: usize, id: I) {
         if index >= self.finger.len() {
             return;
         }
@@ -64,13 +65,13 @@ impl FingerTable {
     }
 
     /// setter for fix_finger_index
-    pub fn set_fix(&mut self, id: Did) {
+    pub fn set_fix(&mut self, id: I) {
         let index = self.fix_finger_index as usize;
         self.set(index, id)
     }
 
     /// remove a node from dht finger table
-    pub fn remove(&mut self, id: Did) {
+    pub fn remove(&mut self, id: I) {
         let indexes: Vec<usize> = self
             .finger
             .iter()
@@ -102,25 +103,57 @@ impl FingerTable {
     }
 
     /// Join FingerTable
-    pub fn join(&mut self, id: Did) {
-        let bid: BiasId = id.bias(&self.id);
+    pub fn join(&mut self, id: I) {
+        self.join_with_proximity(id, |_| None)
+    }
+
+    /// Number of fingers
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Start of the interval of finger k, which is n + 2^k
+    pub fn finger_pos(&self, k: usize) -> I {
+        self.id + I::pow2(k as u32)
+    }
+
+    /// Check if a node is in the interval of finger k, which is [n + 2^k, n + 2^(k+1)).
+    fn in_interval(&self, k: usize, id: I) -> bool {
+        let pos = id.bias(&self.id).pos();
+        pos >= I::pow2(k as u32) && (k + 1 >= self.size || pos < I::pow2(k as u32 + 1))
+    }
+
+    /// Proximity neighbour selection, while both nodes are in the interval of finger k,
+    /// the node with lower RTT is preferred. Otherwise the closer one is preferred.
+    fn prefer(&self, k: usize, id: I, v: I, rtt: &impl Fn(&I) -> Option<u128>) -> bool {
+        if self.in_interval(k, id) && self.in_interval(k, v) {
+            if let (Some(a), Some(b)) = (rtt(&id), rtt(&v)) {
+                return a < b;
+            }
+        }
+        id.bias(&self.id) < v.bias(&self.id)
+    }
+
+    /// Join FingerTable, and prefer the node with lower RTT in an interval of finger,
+    /// `rtt` returns RTT of a node if it's measured.
+    pub fn join_with_proximity(&mut self, id: I, rtt: impl Fn(&I) -> Option<u128>) {
+        let bid: BiasId<I> = id.bias(&self.id);
 
         for k in 0u32..self.size as u32 {
             // (n + 2^k) % 2^m >= n
             // pos >= id
-            // from n to n + 2^160
-            let pos = Did::from(BigUint::from(2u16).pow(k));
+            // from n to n + 2^BITS
+            let pos = I::pow2(k);
             // pos less than id
             if bid.pos() >= pos {
                 // if pos <= id - self.id {
                 match self.finger[k as usize] {
                     Some(v) => {
                         // for a existed value v
-                        // if id is more close to self.id than v
-                        if bid < v.bias(&self.id) {
-                            // if id < v || id > -v {
+                        // if id is more close to self.id than v,
+                        // or both in interval of k, and id is more close in latency
+                        if v != id && self.prefer(k as usize, id, v, &rtt) {
                             self.finger[k as usize] = Some(id);
-                            // if id is more close to successor
                         }
                     }
                     None => {
@@ -131,14 +164,29 @@ impl FingerTable {
         }
     }
 
+    /// setter for fix_finger_index, the current finger is kept
+    /// if it's in the same interval and has lower RTT
+    pub fn set_fix_with_proximity(&mut self, id: I, rtt: impl Fn(&I) -> Option<u128>) {
+        let index = self.fix_finger_index as usize;
+        let keep = match self.get(index) {
+            Some(v) if *v != id && self.in_interval(index, *v) && self.in_interval(index, id) => {
+                matches!((rtt(v), rtt(&id)), (Some(a), Some(b)) if a < b)
+            }
+            _ => false,
+        };
+        if !keep {
+            self.set(index, id)
+        }
+    }
+
     /// Check finger is contains some node
-    pub fn contains(&self, v: &Option<Did>) -> bool {
+    pub fn contains(&self, v: &Option<I>) -> bool {
         self.finger.contains(v)
     }
 
     /// closest_preceding_node
-    pub fn closest(&self, id: Did) -> Result<Did> {
-        let bid: BiasId = id.bias(&self.id);
+    pub fn closest(&self, id: I) -> Result<I> {
+        let bid: BiasId<I> = id.bias(&self.id);
         for i in (0..self.size).rev() {
             if let Some(v) = self.finger[i as usize] {
                 if v.bias(&self.id) < bid {
@@ -157,7 +205,7 @@ impl FingerTable {
     }
 
     /// get finger list
-    pub fn list(&self) -> &Vec<Option<Did>> {
+    pub fn list(&self) -> &Vec<Option<I>> {
         &self.finger
     }
 
@@ -167,13 +215,13 @@ impl FingerTable {
     }
 
     #[cfg(test)]
-    pub fn clone_finger(self) -> Vec<Option<Did>> {
+    pub fn clone_finger(self) -> Vec<Option<I>> {
         self.finger
     }
 }
 
-impl Index<usize> for FingerTable {
-    type Output = Option<Did>;
+impl<I: RingId> Index<usize> for FingerTable<I> {
+    type Output = Option<I>;
     fn index(&self, index: usize) -> &Self::Output {
         self.get(index)
     }
@@ -181,13 +229,20 @@ impl Index<usize> for FingerTable {
 
 #[cfg(test)]
 mod test {
+    use num_bigint::BigUint;
+
     use super::*;
+    use crate::dht::tests::gen_ordered_did256s;
     use crate::dht::tests::gen_ordered_dids;
+    use crate::dht::Did256;
 
     #[test]
     fn test_finger_table_get_set_remove() {
-        let dids = gen_ordered_dids(5);
+        finger_table_get_set_remove(gen_ordered_dids(5));
+        finger_table_get_set_remove(gen_ordered_did256s(5));
+    }
 
+    fn finger_table_get_set_remove<I: RingId>(dids: Vec<I>) {
         let mut table = FingerTable::new(dids[0], 3);
         println!("check finger len");
         assert_eq!(table.len(), 0);
@@ -305,7 +360,11 @@ mod test {
 
     #[test]
     fn test_finger_table_remove_then_fill() {
-        let dids = gen_ordered_dids(6);
+        finger_table_remove_then_fill(gen_ordered_dids(6));
+        finger_table_remove_then_fill(gen_ordered_did256s(6));
+    }
+
+    fn finger_table_remove_then_fill<I: RingId>(dids: Vec<I>) {
         let (did1, did2, did3, did4, did5) = (dids[1], dids[2], dids[3], dids[4], dids[5]);
 
         let mut table = FingerTable::new(dids[0], 5);
@@ -388,4 +447,64 @@ mod test {
             None
         ]);
     }
+
+    #[test]
+    fn test_finger_table_proximity_selection() {
+        finger_table_proximity_selection::<Did>();
+        finger_table_proximity_selection::<Did256>();
+    }
+
+    #[test]
+    fn test_finger_table_full_width() {
+        let dids = gen_ordered_dids(2);
+        let table = FingerTable::new(dids[0], Did::BITS as usize);
+        assert_eq!(table.size(), 160);
+        assert_eq!(table.finger_pos(159) + Did::pow2(159), dids[0]);
+
+        let dids = gen_ordered_did256s(2);
+        let mut table = FingerTable::new(dids[0], Did256::BITS as usize);
+        assert_eq!(table.size(), 256);
+        assert_eq!(table.finger_pos(255) + Did256::pow2(255), dids[0]);
+        // a node at n + 2^255 is only the last finger
+        table.join(table.finger_pos(255));
+        assert_eq!(table.len(), 256);
+        table.reset_finger();
+        table.join(table.finger_pos(255) - Did256::pow2(0));
+        assert_eq!(table[255], None);
+        assert_eq!(table.len(), 255);
+    }
+
+    fn finger_table_proximity_selection<I: RingId>() {
+        let did = |x: u32| I::from(BigUint::from(x));
+        // a and b are in interval of finger 10, which is [n + 1024, n + 2048)
+        let (n, a, b) = (did(0), did(1100), did(1500));
+        let rtt = |x: &I| match x {
+            x if *x == a => Some(100),
+            x if *x == b => Some(10),
+            _ => None,
+        };
+
+        let mut table = FingerTable::new(n, I::BITS as usize);
+        table.join(a);
+        table.join(b);
+        assert_eq!(table[10], Some(a));
+
+        // a is kept without measured RTT, since it's closer
+        table.join_with_proximity(b, |_| None);
+        assert_eq!(table[10], Some(a));
+
+        // b has lower RTT, and takes place of a in interval of finger 10
+        table.join_with_proximity(b, rtt);
+        assert_eq!(table[10], Some(b));
+        // for the lower fingers, a is still the closest one
+        assert_eq!(table[9], Some(a));
+        assert_eq!(table[0], Some(a));
+
+        // fixing finger keeps the one with lower RTT in the interval
+        table.fix_finger_index = 10;
+        table.set_fix_with_proximity(a, rtt);
+        assert_eq!(table[10], Some(b));
+        table.set_fix_with_proximity(a, |_| None);
+        assert_eq!(table[10], Some(a));
+    }
 }
@@ -0,0 +1,115 @@
+#![warn(missing_docs)]
+//! Round-trip time of nodes, which is used for proximity neighbour selection of finger table.
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::Did;
+use crate::storage::MemStorage;
+
+/// Statistics of lookup hops, the latency of a hop is the RTT of the node it's forwarded to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LookupHopStats {
+    /// number of hops forwarded by this node
+    pub hops: u64,
+    /// number of hops whose next node has a measured RTT
+    pub measured_hops: u64,
+    /// average RTT of measured hops in ms
+    pub avg_rtt_ms: u64,
+}
+
+/// Smoothed RTT of nodes, and latency statistics of lookup hops.
+#[derive(Debug)]
+pub struct LatencyTable {
+    rtt: MemStorage<Did, u128>,
+    hops: AtomicU64,
+    measured_hops: AtomicU64,
+    total_hop_rtt_ms: AtomicU64,
+}
+
+impl Default for LatencyTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyTable {
+    /// Create an empty table
+    pub fn new() -> Self {
+        Self {
+            rtt: MemStorage::new(),
+            hops: AtomicU64::new(0),
+            measured_hops: AtomicU64::new(0),
+            total_hop_rtt_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a RTT sample of node, it's smoothed like SRTT of TCP, see RFC 6298.
+    pub fn record(&self, did: Did, sample_ms: u128) {
+        let srtt = match self.rtt.get(&did) {
+            Some(srtt) => (srtt * 7 + sample_ms) / 8,
+            None => sample_ms,
+        };
+        self.rtt.set(&did, srtt);
+    }
+
+    /// Smoothed RTT of node in ms, None if it's never measured
+    pub fn get(&self, did: &Did) -> Option<u128> {
+        self.rtt.get(did)
+    }
+
+    /// Forget RTT of node, such as it's disconnected
+    pub fn remove(&self, did: &Did) {
+        self.rtt.remove(did);
+    }
+
+    /// Record a lookup hop which is forwarded to `next`
+    pub fn record_hop(&self, next: Did) {
+        self.hops.fetch_add(1, Ordering::Relaxed);
+        if let Some(rtt) = self.get(&next) {
+            self.measured_hops.fetch_add(1, Ordering::Relaxed);
+            self.total_hop_rtt_ms
+                .fetch_add(rtt.try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+        }
+    }
+
+    /// Statistics of lookup hops since the table is created
+    pub fn hop_stats(&self) -> LookupHopStats {
+        let measured_hops = self.measured_hops.load(Ordering::Relaxed);
+        let total = self.total_hop_rtt_ms.load(Ordering::Relaxed);
+        LookupHopStats {
+            hops: self.hops.load(Ordering::Relaxed),
+            measured_hops,
+            avg_rtt_ms: total.checked_div(measured_hops).unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+
+    #[test]
+    fn test_latency_table() {
+        let dids = gen_ordered_dids(2);
+        let table = LatencyTable::new();
+        assert_eq!(table.get(&dids[0]), None);
+
+        table.record(dids[0], 80);
+        assert_eq!(table.get(&dids[0]), Some(80));
+        // a sample is smoothed with previous one
+        table.record(dids[0], 160);
+        assert_eq!(table.get(&dids[0]), Some(90));
+
+        table.record_hop(dids[0]);
+        table.record_hop(dids[1]);
+        assert_eq!(table.hop_stats(), LookupHopStats {
+            hops: 2,
+            measured_hops: 1,
+            avg_rtt_ms: 90,
+        });
+    }
+}
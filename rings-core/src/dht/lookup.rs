@@ -0,0 +1,177 @@
+#![warn(missing_docs)]
+//! Iterative lookup of successor.
+//! Instead of forwarding `FindSuccessorSend` recursively, the origin node asks each hop
+//! for a single step, and drives the next query itself, so that it can apply timeout and
+//! retry on every hop, and keep a full trace of the path.
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::chord::PeerRing;
+use super::chord::PeerRingAction;
+use super::types::Chord;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+
+/// Result of a single step of iterative lookup, answered by a hop
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LookupStep {
+    /// The successor of target is found
+    Found(Did),
+    /// The closest preceding node of target, which should be asked next
+    Next(Did),
+}
+
+/// Trace of a finished iterative lookup
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LookupTrace {
+    /// Target of lookup
+    pub id: Did,
+    /// Successor of target
+    pub successor: Did,
+    /// Nodes which answered the lookup, in order, start with origin node
+    pub path: Vec<Did>,
+}
+
+/// State of an iterative lookup, which is driven by origin node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IterativeLookup {
+    id: Did,
+    path: Vec<Did>,
+    step: LookupStep,
+}
+
+impl IterativeLookup {
+    /// Target of lookup
+    pub fn id(&self) -> Did {
+        self.id
+    }
+
+    /// Nodes which answered the lookup so far
+    pub fn path(&self) -> &Vec<Did> {
+        &self.path
+    }
+
+    /// The node should be asked next, None if the lookup is finished
+    pub fn next_hop(&self) -> Option<Did> {
+        match self.step {
+            LookupStep::Next(n) => Some(n),
+            LookupStep::Found(_) => None,
+        }
+    }
+
+    /// Apply the step answered by current hop.
+    /// A hop which points to a node already in path makes no progress, and is rejected.
+    pub fn advance(&mut self, step: LookupStep) -> Result<()> {
+        let hop = self
+            .next_hop()
+            .ok_or(Error::LookupAlreadyFinished(self.id))?;
+        self.path.push(hop);
+        if let LookupStep::Next(n) = step {
+            if self.path.contains(&n) {
+                return Err(Error::LookupLoopDetected(n));
+            }
+        }
+        self.step = step;
+        Ok(())
+    }
+
+    /// Return trace of the lookup, if it is finished
+    pub fn trace(&self) -> Option<LookupTrace> {
+        match self.step {
+            LookupStep::Found(successor) => Some(LookupTrace {
+                id: self.id,
+                successor,
+                path: self.path.clone(),
+            }),
+            LookupStep::Next(_) => None,
+        }
+    }
+}
+
+impl PeerRing {
+    /// Answer a single step of iterative lookup, without forwarding
+    pub fn lookup_step(&self, id: Did) -> Result<LookupStep> {
+        match self.find_successor(id)? {
+            PeerRingAction::Some(n) => Ok(LookupStep::Found(n)),
+            PeerRingAction::RemoteAction(n, _) => Ok(LookupStep::Next(n)),
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+
+    /// Start an iterative lookup with local finger table
+    pub fn iterative_lookup(&self, id: Did) -> Result<IterativeLookup> {
+        let step = self.lookup_step(id)?;
+        let lookup = IterativeLookup {
+            id,
+            path: vec![self.id],
+            step,
+        };
+        match step {
+            LookupStep::Next(n) if n == self.id => Err(Error::LookupLoopDetected(n)),
+            _ => Ok(lookup),
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+    use crate::storage::PersistenceStorage;
+
+    #[tokio::test]
+    async fn test_iterative_lookup() -> Result<()> {
+        let dids = gen_ordered_dids(4);
+        let (a, b, c, d) = (dids[0], dids[1], dids[2], dids[3]);
+        let db_a = PersistenceStorage::new_with_path(PersistenceStorage::random_path("./tmp"))
+            .await
+            .unwrap();
+        let db_c = PersistenceStorage::new_with_path(PersistenceStorage::random_path("./tmp"))
+            .await
+            .unwrap();
+        let node_a = PeerRing::new_with_storage(a, 3, db_a);
+        let node_c = PeerRing::new_with_storage(c, 3, db_c);
+
+        // a only knows b and c, c knows d
+        node_a.join(b)?;
+        node_a.join(c)?;
+        node_c.join(d)?;
+
+        // d is in range (c, d], which is unknown by a
+        let mut lookup = node_a.iterative_lookup(d)?;
+        assert_eq!(lookup.next_hop(), Some(c));
+        assert_eq!(lookup.trace(), None);
+
+        lookup.advance(node_c.lookup_step(d)?)?;
+        assert_eq!(lookup.next_hop(), None);
+        assert_eq!(
+            lookup.trace(),
+            Some(LookupTrace {
+                id: d,
+                successor: d,
+                path: vec![a, c]
+            })
+        );
+        assert!(matches!(
+            lookup.advance(LookupStep::Found(d)),
+            Err(Error::LookupAlreadyFinished(_))
+        ));
+
+        // a hop pointing back to the path is rejected
+        let mut lookup = node_a.iterative_lookup(d)?;
+        assert!(matches!(
+            lookup.advance(LookupStep::Next(a)),
+            Err(Error::LookupLoopDetected(n)) if n == a
+        ));
+
+        // local successor is returned without any hop
+        let lookup = node_a.iterative_lookup(b)?;
+        assert_eq!(lookup.next_hop(), None);
+        assert_eq!(lookup.trace().unwrap().path, vec![a]);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+}
@@ -4,24 +4,51 @@
 
 mod did;
 pub use did::Did;
+pub use did::Did256;
+pub use did::RingId;
 mod chord;
 /// Finger table for Rings
 pub mod finger;
+mod lookup;
 mod successor;
 mod types;
 pub use chord::PeerRing;
 pub use chord::PeerRingAction;
 pub use chord::RemoteAction as PeerRingRemoteAction;
 pub use finger::FingerTable;
+mod failure_detector;
+pub use failure_detector::FailureDetector;
+mod latency;
+pub use latency::LatencyTable;
+pub use latency::LookupHopStats;
+pub use lookup::IterativeLookup;
+pub use lookup::LookupStep;
+pub use lookup::LookupTrace;
+/// Virtual positions of a node on the ring
+pub mod position;
 pub use types::Chord;
 pub use types::ChordStabilize;
 pub use types::ChordStorage;
 pub use types::SubRingManager;
+mod scan;
+pub use scan::VNodePage;
+pub use scan::VNodeRange;
+pub use scan::DEFAULT_SCAN_LIMIT;
+mod scheduler;
+pub use scheduler::StabilizeMetrics;
+pub use scheduler::StabilizeScheduler;
 mod stabilization;
 pub use stabilization::Stabilization;
 pub use stabilization::TStabilize;
+mod topology;
+pub use topology::DHTStatus;
+pub use topology::RingInconsistency;
+pub use topology::RingSnapshot;
+pub mod record;
 /// Implement SubRing with VNode
 pub mod subring;
+mod vector_clock;
+pub use vector_clock::VectorClock;
 /// VNode is a special node that only has virtual address
 pub mod vnode;
 
@@ -36,4 +63,13 @@ pub mod tests {
             .map(|x| x.address().into())
             .collect()
     }
+
+    pub fn gen_ordered_did256s(n: usize) -> Vec<Did256> {
+        let mut dids: Vec<Did256> =
+            std::iter::repeat_with(|| web3::types::H256::from(rand::random::<[u8; 32]>()).into())
+                .take(n)
+                .collect();
+        dids.sort();
+        dids
+    }
 }
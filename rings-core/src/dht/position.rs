@@ -0,0 +1,176 @@
+#![warn(missing_docs)]
+//! Virtual positions of a physical node on the ring.
+//! With a single position per node, key ranges are badly unbalanced in small networks.
+//! A node can host several positions derived from its Did, each keeps its own
+//! finger table, successor list and predecessor, and the node is responsible for
+//! the union of intervals of its positions.
+//! The number of positions is a ring-wide parameter, so positions of any known node
+//! can be derived locally, and no extra message is needed to learn them.
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use web3::signing::keccak256;
+use web3::types::H160;
+
+use super::did::BiasId;
+use super::did::RingId;
+use super::successor::Successor;
+use super::FingerTable;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+
+/// Derive `n` positions of a node, the first one is the Did itself,
+/// the k-th one is the last 20 bytes of keccak256(did || k).
+pub fn derive_positions(did: Did, n: u8) -> Vec<Did> {
+    let mut positions = vec![did];
+    for k in 1..n {
+        let hash = keccak256(&[did.as_bytes(), &[k]].concat());
+        positions.push(H160::from_slice(&hash[12..]).into());
+    }
+    positions
+}
+
+/// A position of local node on the ring, whose tables contain positions of other nodes.
+#[derive(Debug)]
+pub struct VirtualPosition {
+    /// id of position on the ring
+    pub id: Did,
+    /// finger table of the position
+    pub finger: Mutex<FingerTable>,
+    /// successor list of the position
+    pub successor: Mutex<Successor>,
+    /// predecessor of the position
+    pub predecessor: Mutex<Option<Did>>,
+}
+
+impl VirtualPosition {
+    /// Create a position with empty tables
+    pub fn new(id: Did, succ_max: u8) -> Self {
+        Self {
+            id,
+            finger: Mutex::new(FingerTable::new(id, Did::BITS as usize)),
+            successor: Mutex::new(Successor::new(id, succ_max)),
+            predecessor: Mutex::new(None),
+        }
+    }
+
+    /// Lock and return MutexGuard of Successor
+    pub fn lock_successor(&self) -> Result<MutexGuard<Successor>> {
+        self.successor.lock().map_err(|_| Error::DHTSyncLockError)
+    }
+
+    /// Lock and return MutexGuard of Finger Table
+    pub fn lock_finger(&self) -> Result<MutexGuard<FingerTable>> {
+        self.finger.lock().map_err(|_| Error::DHTSyncLockError)
+    }
+
+    /// Lock and return MutexGuard of Predecessor
+    pub fn lock_predecessor(&self) -> Result<MutexGuard<Option<Did>>> {
+        self.predecessor.lock().map_err(|_| Error::DHTSyncLockError)
+    }
+
+    /// Calculate Bias of the Did from this position
+    pub fn bias(&self, id: Did) -> BiasId {
+        BiasId::new(&self.id, &id)
+    }
+
+    /// Join a position of other node into tables
+    pub fn join(&self, id: Did) -> Result<()> {
+        if id == self.id {
+            return Ok(());
+        }
+        self.lock_finger()?.join(id);
+        self.lock_successor()?.update(id);
+        let mut predecessor = self.lock_predecessor()?;
+        match *predecessor {
+            // a closer predecessor is the one with larger bias
+            Some(pre) if self.bias(pre) >= self.bias(id) => {}
+            _ => *predecessor = Some(id),
+        }
+        Ok(())
+    }
+
+    /// A position of other node thinks it might be predecessor of this position,
+    /// return true if predecessor is changed.
+    pub fn notify(&self, id: Did) -> Result<bool> {
+        if id == self.id {
+            return Ok(false);
+        }
+        let mut predecessor = self.lock_predecessor()?;
+        match *predecessor {
+            Some(pre) if self.bias(pre) >= self.bias(id) => Ok(false),
+            _ => {
+                *predecessor = Some(id);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Move to next finger to fix, return the position on ring that the finger should succeed.
+    pub fn next_fix_finger(&self) -> Result<Did> {
+        let mut finger = self.lock_finger()?;
+        let index = (finger.fix_finger_index as usize + 1) % finger.size();
+        finger.fix_finger_index = index as u8;
+        Ok(finger.finger_pos(index))
+    }
+
+    /// Remove a position of other node from tables
+    pub fn remove(&self, id: Did) -> Result<()> {
+        let mut finger = self.lock_finger()?;
+        let mut successor = self.lock_successor()?;
+        finger.remove(id);
+        successor.remove(id);
+        if successor.is_none() {
+            if let Some(x) = finger.first() {
+                successor.update(x);
+            }
+        }
+        let mut predecessor = self.lock_predecessor()?;
+        if *predecessor == Some(id) {
+            *predecessor = None;
+        }
+        Ok(())
+    }
+
+    /// Test if id is in (self, successor], which the position is responsible for.
+    pub fn is_responsible(&self, id: Did) -> Result<bool> {
+        let successor = self.lock_successor()?;
+        Ok(successor.is_none() || self.bias(id) <= self.bias(successor.min()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+
+    #[test]
+    fn test_derive_positions() {
+        let dids = gen_ordered_dids(2);
+        let positions = derive_positions(dids[0], 4);
+        assert_eq!(positions.len(), 4);
+        assert_eq!(positions[0], dids[0]);
+        assert_eq!(positions, derive_positions(dids[0], 4));
+        assert_eq!(positions[..2], derive_positions(dids[0], 2));
+        assert_ne!(positions[1..], derive_positions(dids[1], 4)[1..]);
+        assert_eq!(derive_positions(dids[0], 0), vec![dids[0]]);
+    }
+
+    #[test]
+    fn test_position_notify() -> Result<()> {
+        let dids = gen_ordered_dids(3);
+        let position = VirtualPosition::new(dids[2], 3);
+        assert!(!position.notify(dids[2])?);
+        assert!(position.notify(dids[0])?);
+        // a closer predecessor takes the place
+        assert!(position.notify(dids[1])?);
+        assert!(!position.notify(dids[0])?);
+        assert_eq!(*position.lock_predecessor()?, Some(dids[1]));
+
+        let first = position.next_fix_finger()?;
+        assert_eq!(position.lock_finger()?.fix_finger_index, 1);
+        assert_eq!(first, dids[2] + Did::pow2(1));
+        Ok(())
+    }
+}
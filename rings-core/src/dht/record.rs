@@ -0,0 +1,172 @@
+#![warn(missing_docs)]
+//! Mutable record stored on DHT.
+//! Unlike `VNodeType::Data` which is addressed by it's content, a record is addressed by
+//! `sha1(owner:name)`, it can be replaced by it's owner with a greater sequence number.
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::vnode::VNodeType;
+use super::vnode::VirtualNode;
+use crate::dht::Did;
+use crate::ecc::HashStr;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::MessageVerification;
+use crate::session::SessionManager;
+
+/// A record signed by it's owner
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// owner of record, who signed it
+    pub owner: Did,
+    /// name of record, unique for an owner
+    pub name: String,
+    /// sequence number, a record can only be replaced by a greater one
+    pub seq: u64,
+    /// value of record, `None` means the record is deleted
+    pub value: Option<Vec<u8>>,
+    /// signature of owner
+    pub verification: MessageVerification,
+}
+
+impl Record {
+    /// Create a record signed by session of owner
+    pub fn new(
+        name: &str,
+        value: Option<Vec<u8>>,
+        seq: u64,
+        session_manager: &SessionManager,
+    ) -> Result<Self> {
+        let owner = session_manager.authorizer()?;
+        // ttl of verification is not used, a record is valid until it's replaced
+        let verification =
+            MessageVerification::new(&(owner, name, seq, &value), session_manager, 0)?;
+        Ok(Self {
+            owner,
+            name: name.to_owned(),
+            seq,
+            value,
+            verification,
+        })
+    }
+
+    /// Address of record, which is `sha1(owner:name)`
+    pub fn address(owner: &Did, name: &str) -> Result<Did> {
+        let address: HashStr = format!("{}:{}", owner, name).into();
+        Did::from_str(&address.inner())
+    }
+
+    /// Check if the record is deleted
+    pub fn is_deleted(&self) -> bool {
+        self.value.is_none()
+    }
+
+    /// Check if the record is signed by it's owner.
+    /// A record outlives the session signed it, so the session is only checked at the time of signing.
+    pub fn verify(&self) -> Result<()> {
+        let signed = (self.owner, self.name.as_str(), self.seq, &self.value);
+        if self.verification.session.auth.authorizer.did != self.owner
+            || !self.verification.verify_signed_at(&signed)
+        {
+            return Err(Error::InvalidRecordSignature);
+        }
+        Ok(())
+    }
+
+    /// Merge with a record written later, the one with greater seq wins.
+    /// Writing a same record again is allowed, since a record may be synced more than once.
+    pub fn merge(&self, other: &Self) -> Result<Self> {
+        if self == other || other.seq > self.seq {
+            Ok(other.clone())
+        } else {
+            Err(Error::StaleRecord(other.seq, self.seq))
+        }
+    }
+}
+
+impl TryFrom<Record> for VirtualNode {
+    type Error = Error;
+    fn try_from(record: Record) -> Result<Self> {
+        let address = Record::address(&record.owner, &record.name)?;
+        let data = serde_json::to_string(&record).map_err(|_| Error::SerializeToString)?;
+        Ok(Self {
+            address,
+            data: vec![data.into()],
+            kind: VNodeType::Record,
+            expires_at: None,
+        })
+    }
+}
+
+impl TryFrom<VirtualNode> for Record {
+    type Error = Error;
+    fn try_from(vnode: VirtualNode) -> Result<Self> {
+        match &vnode.kind {
+            VNodeType::Record => {
+                let decoded: String = vnode.data[0].decode()?;
+                let record: Record = serde_json::from_str(&decoded).map_err(Error::Deserialize)?;
+                // a record can only be stored at the address of it's owner and name
+                if Record::address(&record.owner, &record.name)? != vnode.address {
+                    return Err(Error::DidNotEqual);
+                }
+                Ok(record)
+            }
+            _ => Err(Error::InvalidVNodeType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_record_verify_and_merge() -> Result<()> {
+        let key = SecretKey::random();
+        let sm = SessionManager::new_with_seckey(&key, None)?;
+        let owner: Did = key.address().into();
+
+        let r1 = Record::new("profile", Some(b"v1".to_vec()), 1, &sm)?;
+        assert_eq!(r1.owner, owner);
+        r1.verify()?;
+        let vnode: VirtualNode = r1.clone().try_into()?;
+        assert_eq!(vnode.address, Record::address(&owner, "profile")?);
+        assert_eq!(Record::try_from(vnode.clone())?, r1);
+
+        // record cannot be moved to another address
+        let mut moved = vnode;
+        moved.address = Record::address(&owner, "other")?;
+        assert!(Record::try_from(moved).is_err());
+
+        // forged value or owner is rejected
+        let mut forged = r1.clone();
+        forged.value = Some(b"forged".to_vec());
+        assert!(matches!(
+            forged.verify(),
+            Err(Error::InvalidRecordSignature)
+        ));
+        let other = SessionManager::new_with_seckey(&SecretKey::random(), None)?;
+        let mut forged = Record::new("profile", Some(b"v1".to_vec()), 1, &other)?;
+        forged.owner = owner;
+        assert!(matches!(
+            forged.verify(),
+            Err(Error::InvalidRecordSignature)
+        ));
+
+        // greater seq wins, stale one is rejected
+        let r2 = Record::new("profile", Some(b"v2".to_vec()), 2, &sm)?;
+        assert_eq!(r1.merge(&r2)?, r2);
+        assert_eq!(r2.merge(&r2)?, r2);
+        assert!(matches!(r2.merge(&r1), Err(Error::StaleRecord(1, 2))));
+
+        // deletion is a record without value
+        let deleted = Record::new("profile", None, 3, &sm)?;
+        deleted.verify()?;
+        assert!(deleted.is_deleted());
+        assert_eq!(r2.merge(&deleted)?, deleted);
+        Ok(())
+    }
+}
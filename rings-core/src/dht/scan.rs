@@ -0,0 +1,182 @@
+#![warn(missing_docs)]
+//! Range scan of vnodes stored on DHT.
+//! A scan walks successors clockwise from `start` to `end`, each responsible node
+//! answers a page of it's stored vnodes in the range, and a cursor of the rest.
+use serde::Deserialize;
+use serde::Serialize;
+use web3::types::H160;
+
+use super::did::RingId;
+use super::vnode::VNodeType;
+use super::vnode::VirtualNode;
+use crate::dht::Did;
+
+/// Max number of vnodes in a page by default
+pub const DEFAULT_SCAN_LIMIT: usize = 100;
+
+/// Range of vnode addresses [start, end] clockwise on the ring,
+/// with an optional filter of vnode type, and max number of vnodes in a page.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VNodeRange {
+    /// first address of range
+    pub start: Did,
+    /// last address of range, it's included
+    pub end: Did,
+    /// only vnodes of this type are scanned if it's set
+    pub kind: Option<VNodeType>,
+    /// max number of vnodes in a page
+    pub limit: usize,
+}
+
+/// Vnodes found in a range, ordered by address from start of the range
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VNodePage {
+    /// found vnodes
+    pub data: Vec<VirtualNode>,
+    /// the rest of range to scan, None if the scan is finished
+    pub next: Option<VNodeRange>,
+}
+
+impl VNodeRange {
+    /// Range of [start, end] for vnodes of all types
+    pub fn new(start: Did, end: Did) -> Self {
+        Self {
+            start,
+            end,
+            kind: None,
+            limit: DEFAULT_SCAN_LIMIT,
+        }
+    }
+
+    /// Range of addresses which start with `prefix`, a prefix longer than a did is truncated.
+    pub fn prefix(prefix: &[u8]) -> Self {
+        let mut start = [0u8; 20];
+        let mut end = [0xffu8; 20];
+        let n = prefix.len().min(20);
+        start[..n].copy_from_slice(&prefix[..n]);
+        end[..n].copy_from_slice(&prefix[..n]);
+        Self::new(H160::from(start).into(), H160::from(end).into())
+    }
+
+    /// Range of the whole ring
+    pub fn all() -> Self {
+        Self::prefix(&[])
+    }
+
+    /// Only scan vnodes of type `kind`
+    pub fn with_kind(mut self, kind: VNodeType) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Set max number of vnodes in a page, at least one vnode is returned in a page.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// Test if id is in [start, end]
+    pub fn contains(&self, id: Did) -> bool {
+        id.bias(&self.start) <= self.end.bias(&self.start)
+    }
+
+    /// Test if vnode is in range and matches the type filter
+    pub fn matches(&self, vnode: &VirtualNode) -> bool {
+        self.contains(vnode.did()) && self.kind.as_ref().map_or(true, |k| *k == vnode.kind)
+    }
+
+    /// The rest of range after id, None if id is the end
+    pub fn after(&self, id: Did) -> Option<Self> {
+        if id == self.end {
+            return None;
+        }
+        Some(Self {
+            start: id + Did::pow2(0),
+            ..self.clone()
+        })
+    }
+
+    /// Make a page of vnodes stored by a node which is responsible for [start, boundary],
+    /// boundary is None if the node is responsible for the whole ring.
+    pub fn page(&self, stored: Vec<VirtualNode>, boundary: Option<Did>) -> VNodePage {
+        let within =
+            |id: Did| boundary.map_or(true, |b| id.bias(&self.start) <= b.bias(&self.start));
+        let mut data: Vec<VirtualNode> = stored
+            .into_iter()
+            .filter(|v| !v.is_expired() && self.matches(v) && within(v.did()))
+            .collect();
+        data.sort_by_key(|v| v.did().bias(&self.start));
+        let limit = self.limit.max(1);
+        let next = if data.len() > limit {
+            data.truncate(limit);
+            data.last().and_then(|v| self.after(v.did()))
+        } else {
+            match boundary {
+                Some(b) if self.contains(b) => self.after(b),
+                _ => None,
+            }
+        };
+        VNodePage { data, next }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+
+    fn vnode(did: Did, kind: VNodeType) -> VirtualNode {
+        VirtualNode {
+            address: did,
+            data: vec![],
+            kind,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_vnode_range_page() {
+        let d = gen_ordered_dids(5);
+        let stored = vec![
+            vnode(d[3], VNodeType::Data),
+            vnode(d[1], VNodeType::SubRing),
+            vnode(d[2], VNodeType::Data),
+            vnode(d[4], VNodeType::Data),
+        ];
+
+        // range wraps around the ring
+        let range = VNodeRange::new(d[4], d[0]);
+        assert!(range.contains(d[4]) && range.contains(d[0]));
+        assert!(!range.contains(d[1]));
+        let mut start = [0u8; 20];
+        start[0] = 0xab;
+        let prefixed = VNodeRange::prefix(&[0xab]);
+        assert_eq!(prefixed.start, H160::from(start).into());
+        assert!(prefixed.contains(H160::from([0xab; 20]).into()));
+        assert!(!prefixed.contains(H160::from([0xac; 20]).into()));
+        assert!(VNodeRange::all().contains(d[2]));
+
+        // a node is responsible for [d1, d3], the rest starts after d3
+        let range = VNodeRange::new(d[1], d[4]);
+        let page = range.page(stored.clone(), Some(d[3]));
+        let found: Vec<Did> = page.data.iter().map(|v| v.did()).collect();
+        assert_eq!(found, vec![d[1], d[2], d[3]]);
+        assert_eq!(page.next.unwrap().start, d[3] + Did::pow2(0));
+
+        // the page is full, the rest starts after the last one
+        let page = range.clone().with_limit(2).page(stored.clone(), Some(d[3]));
+        assert_eq!(page.data.len(), 2);
+        assert_eq!(page.next.unwrap().start, d[2] + Did::pow2(0));
+
+        // range ends in the interval of the node
+        let page = VNodeRange::new(d[1], d[2]).page(stored.clone(), Some(d[3]));
+        assert_eq!(page.data.len(), 2);
+        assert_eq!(page.next, None);
+
+        // filtered by type, and the node is responsible for the whole ring
+        let page = range.with_kind(VNodeType::Data).page(stored, None);
+        let found: Vec<Did> = page.data.iter().map(|v| v.did()).collect();
+        assert_eq!(found, vec![d[2], d[3], d[4]]);
+        assert_eq!(page.next, None);
+    }
+}
@@ -0,0 +1,186 @@
+#![warn(missing_docs)]
+//! Adaptive schedule of stabilization.
+//! Stabilization runs every `min_interval` while the ring is churning, that's the routing
+//! tables are changed since last round, such as a node joined or a connection closed.
+//! Otherwise the interval is doubled after each round, until it reaches `max_interval`.
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Stabilization runs every second while the ring is churning by default
+pub const DEFAULT_MIN_INTERVAL_MS: u64 = 1000;
+/// Number of fingers fixed in a round while the ring is churning by default
+pub const DEFAULT_MAX_FINGERS_PER_ROUND: usize = 8;
+
+/// Rounds and durations of stabilization
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StabilizeMetrics {
+    /// number of finished rounds
+    pub rounds: u64,
+    /// duration of last round in ms
+    pub last_round_ms: u64,
+    /// average duration of rounds in ms
+    pub avg_round_ms: u64,
+    /// current interval between rounds in ms
+    pub interval_ms: u64,
+}
+
+/// Decide when the next round of stabilization should run, and how many fingers it fixes.
+#[derive(Debug)]
+pub struct StabilizeScheduler {
+    min_interval_ms: u64,
+    max_interval_ms: u64,
+    max_fingers_per_round: usize,
+    interval_ms: AtomicU64,
+    /// timestamp in ms when last round is finished
+    last_round_at: AtomicU64,
+    /// version of routing tables which is seen by last round
+    seen_version: AtomicU64,
+    running: AtomicBool,
+    rounds: AtomicU64,
+    last_round_ms: AtomicU64,
+    total_round_ms: AtomicU64,
+}
+
+impl StabilizeScheduler {
+    /// Create a scheduler whose interval is between `min_interval_ms` and `max_interval_ms`.
+    /// It starts with the min interval, since a new node is joining the ring.
+    pub fn new(min_interval_ms: u64, max_interval_ms: u64) -> Self {
+        let min_interval_ms = min_interval_ms.min(max_interval_ms);
+        Self {
+            min_interval_ms,
+            max_interval_ms,
+            max_fingers_per_round: DEFAULT_MAX_FINGERS_PER_ROUND,
+            interval_ms: AtomicU64::new(min_interval_ms),
+            last_round_at: AtomicU64::new(0),
+            seen_version: AtomicU64::new(0),
+            running: AtomicBool::new(false),
+            rounds: AtomicU64::new(0),
+            last_round_ms: AtomicU64::new(0),
+            total_round_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Set number of fingers fixed in a round while the ring is churning
+    pub fn with_max_fingers_per_round(mut self, n: usize) -> Self {
+        self.max_fingers_per_round = n.max(1);
+        self
+    }
+
+    /// The interval while the ring is churning, the scheduler should be polled by it.
+    pub fn min_interval_ms(&self) -> u64 {
+        self.min_interval_ms
+    }
+
+    /// Test if the ring is churning, by version of routing tables.
+    pub fn is_churning(&self, version: u64) -> bool {
+        self.seen_version.load(Ordering::SeqCst) != version
+            || self.interval_ms.load(Ordering::SeqCst) <= self.min_interval_ms
+    }
+
+    /// Number of fingers to fix in a round, all of them are fixed in turn
+    /// by multiple rounds while the ring is stable.
+    pub fn fingers_per_round(&self, version: u64) -> usize {
+        if self.is_churning(version) {
+            self.max_fingers_per_round
+        } else {
+            1
+        }
+    }
+
+    /// Start a round if it's due, return false if it's not due or another round is running.
+    /// A change of routing tables makes the round due after min interval.
+    pub fn begin(&self, now_ms: u64, version: u64) -> bool {
+        let interval = if self.seen_version.load(Ordering::SeqCst) != version {
+            self.min_interval_ms
+        } else {
+            self.interval_ms.load(Ordering::SeqCst)
+        };
+        if now_ms < self.last_round_at.load(Ordering::SeqCst) + interval {
+            return false;
+        }
+        self.running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    /// Finish a round, the interval is reset to min if routing tables are changed since last
+    /// round, otherwise it's doubled.
+    pub fn finish(&self, started_ms: u64, finished_ms: u64, version: u64) {
+        let elapsed = finished_ms.saturating_sub(started_ms);
+        self.rounds.fetch_add(1, Ordering::SeqCst);
+        self.last_round_ms.store(elapsed, Ordering::SeqCst);
+        self.total_round_ms.fetch_add(elapsed, Ordering::SeqCst);
+        self.last_round_at.store(finished_ms, Ordering::SeqCst);
+
+        let changed = self.seen_version.swap(version, Ordering::SeqCst) != version;
+        let interval = if changed {
+            self.min_interval_ms
+        } else {
+            (self.interval_ms.load(Ordering::SeqCst) * 2)
+                .max(1)
+                .min(self.max_interval_ms)
+        };
+        self.interval_ms.store(interval, Ordering::SeqCst);
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    /// Rounds and durations since the scheduler is created
+    pub fn metrics(&self) -> StabilizeMetrics {
+        let rounds = self.rounds.load(Ordering::SeqCst);
+        StabilizeMetrics {
+            rounds,
+            last_round_ms: self.last_round_ms.load(Ordering::SeqCst),
+            avg_round_ms: self
+                .total_round_ms
+                .load(Ordering::SeqCst)
+                .checked_div(rounds)
+                .unwrap_or(0),
+            interval_ms: self.interval_ms.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduler_backoff_and_churn() {
+        let scheduler = StabilizeScheduler::new(1000, 8000).with_max_fingers_per_round(4);
+        assert!(scheduler.begin(1000, 0));
+        // only one round is running at the same time
+        assert!(!scheduler.begin(1000, 0));
+        assert_eq!(scheduler.fingers_per_round(0), 4);
+        scheduler.finish(1000, 1100, 0);
+
+        // the ring is stable, and the interval is doubled
+        assert_eq!(scheduler.metrics().interval_ms, 2000);
+        assert_eq!(scheduler.fingers_per_round(0), 1);
+        assert!(!scheduler.begin(2100, 0));
+        assert!(scheduler.begin(3100, 0));
+        scheduler.finish(3100, 3400, 0);
+        assert_eq!(scheduler.metrics().interval_ms, 4000);
+        assert!(scheduler.begin(7400, 0));
+        scheduler.finish(7400, 7500, 0);
+        assert!(scheduler.begin(15500, 0));
+        scheduler.finish(15500, 15500, 0);
+        // the interval is bounded
+        assert_eq!(scheduler.metrics().interval_ms, 8000);
+
+        // the ring is changed, stabilization runs after min interval and fixes more fingers
+        assert!(!scheduler.begin(16000, 1));
+        assert_eq!(scheduler.fingers_per_round(1), 4);
+        assert!(scheduler.begin(16500, 1));
+        scheduler.finish(16500, 16600, 1);
+        assert_eq!(scheduler.metrics(), StabilizeMetrics {
+            rounds: 5,
+            last_round_ms: 100,
+            avg_round_ms: 120,
+            interval_ms: 1000,
+        });
+    }
+}
@@ -1,25 +1,38 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use futures::future::join_all;
+use itertools::Itertools;
 
+use crate::dht::scheduler;
 use crate::dht::ChordStabilize;
+use crate::dht::Did;
 use crate::dht::PeerRing;
 use crate::dht::PeerRingAction;
 use crate::dht::PeerRingRemoteAction;
+use crate::dht::StabilizeMetrics;
+use crate::dht::StabilizeScheduler;
+use crate::err::Error;
 use crate::err::Result;
 use crate::message::FindSuccessorReportHandler;
 use crate::message::FindSuccessorSend;
 use crate::message::FindSuccessorThen;
 use crate::message::Message;
+use crate::message::MessagePayload;
 use crate::message::NotifyPredecessorSend;
 use crate::message::PayloadSender;
+use crate::message::Ping;
+use crate::message::TChordStorage;
 use crate::swarm::Swarm;
+use crate::transports::manager::TransportManager;
+use crate::utils;
 
 #[derive(Clone)]
 pub struct Stabilization {
     chord: Arc<PeerRing>,
     swarm: Arc<Swarm>,
     timeout: usize,
+    scheduler: Arc<StabilizeScheduler>,
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -29,39 +42,69 @@ pub trait TStabilize {
 }
 
 impl Stabilization {
+    /// Stabilization backs off to run every `timeout` seconds while the ring is stable,
+    /// see `StabilizeScheduler`.
     pub fn new(swarm: Arc<Swarm>, timeout: usize) -> Self {
         Self {
             chord: swarm.dht(),
             swarm,
             timeout,
+            scheduler: Arc::new(StabilizeScheduler::new(
+                scheduler::DEFAULT_MIN_INTERVAL_MS,
+                timeout as u64 * 1000,
+            )),
         }
     }
 
+    /// Replace the default scheduler
+    pub fn with_scheduler(mut self, scheduler: StabilizeScheduler) -> Self {
+        self.scheduler = Arc::new(scheduler);
+        self
+    }
+
     pub fn get_timeout(&self) -> usize {
         self.timeout
     }
 
+    /// Rounds and durations of stabilization
+    pub fn metrics(&self) -> StabilizeMetrics {
+        self.scheduler.metrics()
+    }
+
     async fn notify_predecessor(&self) -> Result<()> {
-        let (successor_min, successor_list) = {
-            let successor = self.chord.lock_successor()?;
-            (successor.min(), successor.list())
-        };
+        let mut successor_list = self.chord.lock_successor()?.list();
+        // owners of successors of virtual positions
+        for owner in self.chord.position_successors()? {
+            if !successor_list.contains(&owner) {
+                successor_list.push(owner);
+            }
+        }
 
         let msg = Message::NotifyPredecessorSend(NotifyPredecessorSend { id: self.chord.id });
-        if self.chord.id != successor_min {
-            for s in successor_list {
-                self.swarm
-                    .send_message(msg.clone(), s, self.swarm.did())
-                    .await?;
-            }
-            Ok(())
-        } else {
-            Ok(())
+        for s in successor_list.into_iter().filter(|s| *s != self.chord.id) {
+            self.swarm
+                .send_message(msg.clone(), s, self.swarm.did())
+                .await?;
         }
+        Ok(())
     }
 
+    /// Fix fingers in turn, more fingers are fixed in a round while the ring is churning.
     async fn fix_fingers(&self) -> Result<()> {
-        match self.chord.fix_fingers() {
+        let n = self
+            .scheduler
+            .fingers_per_round(self.chord.topology_version());
+        for _ in 0..n {
+            self.fix_finger(self.chord.fix_fingers()).await?;
+            for action in self.chord.fix_position_fingers()? {
+                self.fix_finger(Ok(action)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fix_finger(&self, action: Result<PeerRingAction>) -> Result<()> {
+        match action {
             Ok(action) => match action {
                 PeerRingAction::None => {
                     // tracing::debug!("wait to next round");
@@ -92,6 +135,120 @@ impl Stabilization {
         }
     }
 
+    async fn repair_replicas(&self) -> Result<()> {
+        let action = self.chord.replicate_all().await?;
+        self.swarm.storage_replicate(action).await
+    }
+
+    /// Probe a node by `Ping`, and wait for `Pong` in timeout of failure detector.
+    /// The RTT of node is measured from the time the ping is sent by local clock.
+    /// A node which fails `max_suspicion` probes in a row is disconnected, and it's
+    /// evicted from predecessor, successor and finger table.
+    async fn probe(&self, did: Did) -> Result<()> {
+        let detector = &self.chord.failure_detector;
+        let payload = MessagePayload::new_direct(
+            Message::Ping(Ping {
+                ts_ms: utils::get_epoch_ms(),
+            }),
+            self.swarm.session_manager(),
+            did,
+        )?;
+        let sent_at = utils::get_epoch_ms();
+        let result = self
+            .swarm
+            .send_payload_and_wait(payload, detector.timeout())
+            .await
+            .and_then(|report| match report.data {
+                Message::Pong(_) if report.origin() == did => Ok(()),
+                _ => Err(Error::InvalidMessage(format!(
+                    "unexpected report of ping: {}",
+                    report.data
+                ))),
+            });
+        match result {
+            Ok(()) => {
+                detector.reset(&did);
+                let rtt = utils::get_epoch_ms().saturating_sub(sent_at);
+                self.chord.record_rtt(did, rtt)
+            }
+            Err(e) => {
+                tracing::debug!("[stabilize] Probe of {:?} failed: {:?}", did, e);
+                if detector.suspect(did) {
+                    tracing::warn!(
+                        "[stabilize] Evict {:?} which failed {} probes",
+                        did,
+                        detector.max_suspicion()
+                    );
+                    self.swarm.disconnect(did).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Nodes need probing, which are predecessor, successors, fingers, and other connected nodes.
+    fn probe_targets(&self) -> Result<Vec<Did>> {
+        let mut dids = self.swarm.get_dids();
+        if let PeerRingAction::RemoteAction(p, PeerRingRemoteAction::CheckPredecessor) =
+            self.chord.check_predecessor()?
+        {
+            dids.push(p);
+        }
+        for action in self.chord.check_position_predecessors()? {
+            if let PeerRingAction::RemoteAction(p, PeerRingRemoteAction::CheckPredecessor) = action
+            {
+                dids.push(p);
+            }
+        }
+        dids.extend(self.chord.lock_successor()?.list());
+        dids.extend(self.chord.lock_finger()?.list().iter().flatten());
+        Ok(dids
+            .into_iter()
+            .filter(|did| *did != self.chord.id)
+            .unique()
+            .collect())
+    }
+
+    /// Check liveness of nodes in routing tables, and measure their RTT by the way,
+    /// the fingers are reselected by proximity when the `Pong` of a probe is received.
+    /// It's called periodically along with `stabilize`.
+    pub async fn probe_peers(&self) -> Result<()> {
+        let targets = self.probe_targets()?;
+        let results = join_all(targets.into_iter().map(|did| self.probe(did))).await;
+        for e in results.into_iter().filter_map(|r| r.err()) {
+            tracing::error!("[stabilize] Failed on evict node {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Remove expired vnodes and evict vnodes over storage quota
+    async fn sweep(&self) -> Result<()> {
+        self.chord.sweep().await
+    }
+
+    /// Run a round of stabilization and probing if it's due, return false if it's skipped.
+    /// It should be polled every `StabilizeScheduler::min_interval_ms`, the duration of
+    /// round counts both stabilization and probing.
+    pub async fn tick(&self) -> bool {
+        let started = utils::get_epoch_ms() as u64;
+        if !self.scheduler.begin(started, self.chord.topology_version()) {
+            return false;
+        }
+        self.stabilize()
+            .await
+            .unwrap_or_else(|e| tracing::error!("failed to stabilize {:?}", e));
+        self.probe_peers()
+            .await
+            .unwrap_or_else(|e| tracing::error!("failed to probe peers {:?}", e));
+        // evictions by probing are changes of topology in this round
+        self.scheduler.finish(
+            started,
+            utils::get_epoch_ms() as u64,
+            self.chord.topology_version(),
+        );
+        true
+    }
+
     pub async fn stabilize(&self) -> Result<()> {
         if let Err(e) = self.notify_predecessor().await {
             tracing::error!("[stabilize] Failed on notify predecessor {:?}", e);
@@ -99,6 +256,12 @@ impl Stabilization {
         if let Err(e) = self.fix_fingers().await {
             tracing::error!("[stabilize] Failed on fix_finger {:?}", e);
         }
+        if let Err(e) = self.repair_replicas().await {
+            tracing::error!("[stabilize] Failed on repair replicas {:?}", e);
+        }
+        if let Err(e) = self.sweep().await {
+            tracing::error!("[stabilize] Failed on sweep storage {:?}", e);
+        }
         Ok(())
     }
 }
@@ -121,13 +284,13 @@ mod stabilizer {
     impl TStabilize for Stabilization {
         async fn wait(self: Arc<Self>) {
             loop {
-                let timeout = Delay::new(Duration::from_secs(self.timeout as u64)).fuse();
+                let timeout =
+                    Delay::new(Duration::from_millis(self.scheduler.min_interval_ms())).fuse();
                 pin_mut!(timeout);
                 select! {
-                    _ = timeout => self
-                        .stabilize()
-                        .await
-                        .unwrap_or_else(|e| tracing::error!("failed to stabilize {:?}", e)),
+                    _ = timeout => {
+                        self.tick().await;
+                    }
                 }
             }
         }
@@ -148,17 +311,15 @@ mod stabilizer {
     #[async_trait(?Send)]
     impl TStabilize for Stabilization {
         async fn wait(self: Arc<Self>) {
+            let ttl = self.scheduler.min_interval_ms() as i32;
             let caller = Arc::clone(&self);
             let func = move || {
                 let caller = caller.clone();
                 spawn_local(Box::pin(async move {
-                    caller
-                        .stabilize()
-                        .await
-                        .unwrap_or_else(|e| tracing::error!("failed to stabilize {:?}", e));
+                    caller.tick().await;
                 }))
             };
-            poll!(func, 25000);
+            poll!(func, ttl);
         }
     }
 }
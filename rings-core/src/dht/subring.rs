@@ -1,4 +1,6 @@
 #![warn(missing_docs)]
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::str::FromStr;
 
 use async_trait::async_trait;
@@ -8,8 +10,10 @@ use serde::Serialize;
 use super::chord::PeerRing;
 use super::chord::PeerRingAction;
 use super::chord::RemoteAction;
+use super::did::RingId;
 use super::types::Chord;
 use super::types::SubRingManager;
+use super::vector_clock::VectorClock;
 use super::vnode::VNodeType;
 use super::vnode::VirtualNode;
 use super::FingerTable;
@@ -28,12 +32,28 @@ pub struct SubRing {
     pub name: String,
     /// did of subring, generate with hash(name)
     pub did: Did,
-    /// finger table
+    /// finger table of members, which is used for routing only,
+    /// it keeps at most one member in each interval
     pub finger: FingerTable,
-    /// admin of ring, for verify that a message is come from ring
+    /// all members of subring
+    #[serde(default)]
+    pub members: BTreeSet<Did>,
+    /// version of each did which ever joined, it's increased on every join and removal.
+    /// A removed did is kept here as tombstone, so the removal is not undone by
+    /// merging a stale replica which still has it.
+    #[serde(default)]
+    pub member_versions: BTreeMap<Did, u64>,
+    /// admin of ring, for verify that a message is come from ring,
+    /// only admin can remove other members
     pub admin: Option<Did>,
     /// creator
     pub creator: Did,
+    /// nonce of the last kick applied, see `KickSubRing`
+    #[serde(default)]
+    pub kick_nonce: u64,
+    /// version of membership, ticked by the node storing subring on each modification
+    #[serde(default)]
+    pub version: VectorClock,
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -45,7 +65,8 @@ impl SubRingManager<PeerRingAction> for PeerRing {
                 let id = id.to_owned();
                 if let Ok(subring) = self.get_subring(rid).await {
                     let mut sr = subring;
-                    sr.finger.join(id);
+                    sr.join(id);
+                    sr.version.tick(self.id);
                     self.store_subring(&sr).await?;
                 }
                 Ok(PeerRingAction::None)
@@ -58,6 +79,69 @@ impl SubRingManager<PeerRingAction> for PeerRing {
         }
     }
 
+    async fn leave_subring(&self, id: &Did, rid: &Did) -> Result<PeerRingAction> {
+        match self.find_successor(*rid) {
+            Ok(PeerRingAction::Some(_)) => {
+                if let Ok(subring) = self.get_subring(rid).await {
+                    let mut sr = subring;
+                    sr.remove(*id);
+                    sr.version.tick(self.id);
+                    self.store_subring(&sr).await?;
+                }
+                Ok(PeerRingAction::None)
+            }
+            Ok(PeerRingAction::RemoteAction(n, RemoteAction::FindSuccessor(_))) => Ok(
+                PeerRingAction::RemoteAction(n, RemoteAction::FindAndLeaveSubRing(*rid)),
+            ),
+            Ok(a) => Err(Error::PeerRingUnexpectedAction(a)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn kick_subring(
+        &self,
+        admin: &Did,
+        id: &Did,
+        rid: &Did,
+        nonce: u64,
+    ) -> Result<PeerRingAction> {
+        match self.find_successor(*rid) {
+            Ok(PeerRingAction::Some(_)) => {
+                let mut sr = self.get_subring(rid).await?;
+                if sr.admin != Some(*admin) {
+                    return Err(Error::SubRingPermissionDenied(*admin));
+                }
+                if nonce <= sr.kick_nonce {
+                    return Err(Error::StaleSubRingKick(nonce, sr.kick_nonce));
+                }
+                sr.kick_nonce = nonce;
+                sr.remove(*id);
+                sr.version.tick(self.id);
+                self.store_subring(&sr).await?;
+                Ok(PeerRingAction::None)
+            }
+            Ok(PeerRingAction::RemoteAction(n, RemoteAction::FindSuccessor(_))) => Ok(
+                PeerRingAction::RemoteAction(n, RemoteAction::FindAndKickSubRing(*rid)),
+            ),
+            Ok(a) => Err(Error::PeerRingUnexpectedAction(a)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list_subring(&self, rid: &Did) -> Result<PeerRingAction> {
+        match self.find_successor(*rid) {
+            Ok(PeerRingAction::Some(_)) => {
+                let vnode = self.get_subring(rid).await?.try_into()?;
+                Ok(PeerRingAction::SomeVNode(vnode))
+            }
+            Ok(PeerRingAction::RemoteAction(n, RemoteAction::FindSuccessor(_))) => Ok(
+                PeerRingAction::RemoteAction(n, RemoteAction::FindAndListSubRing(*rid)),
+            ),
+            Ok(a) => Err(Error::PeerRingUnexpectedAction(a)),
+            Err(e) => Err(e),
+        }
+    }
+
     async fn cloest_preceding_node_for_subring(&self, id: &Did, rid: &Did) -> Option<Result<Did>> {
         let id = id.to_owned();
         if let Ok(subring) = self.get_subring(rid).await {
@@ -73,10 +157,8 @@ impl SubRingManager<PeerRingAction> for PeerRing {
     }
 
     async fn store_subring(&self, subring: &SubRing) -> Result<()> {
-        let id = subring.did;
         let vn: VirtualNode = subring.clone().try_into()?;
-        self.storage.put(&id, &vn).await?;
-        Ok(())
+        self.put_vnode(&vn).await
     }
 
     async fn get_subring_by_name(&self, name: &str) -> Result<SubRing> {
@@ -113,19 +195,63 @@ impl SubRingManager<PeerRingAction> for PeerRing {
 }
 
 impl SubRing {
-    /// Create a new SubRing
+    /// Create a new SubRing, creator is the admin of it
     pub fn new(name: &str, creator: &Did) -> Result<Self> {
         let address: HashStr = name.to_owned().into();
         let did = Did::from_str(&address.inner())?;
         Ok(Self {
             name: name.to_owned(),
             did,
-            finger: FingerTable::new(did, 1),
-            admin: None,
+            finger: FingerTable::new(did, Did::BITS as usize),
+            members: BTreeSet::new(),
+            member_versions: BTreeMap::new(),
+            admin: Some(*creator),
             creator: *creator,
+            kick_nonce: 0,
+            version: VectorClock::new(),
         })
     }
 
+    /// All members of subring, ordered by did
+    pub fn members(&self) -> Vec<Did> {
+        self.members.iter().copied().collect()
+    }
+
+    /// Add a member, and join it to finger table for routing
+    pub fn join(&mut self, id: Did) {
+        *self.member_versions.entry(id).or_default() += 1;
+        self.members.insert(id);
+        self.finger.join(id);
+    }
+
+    /// Remove a member, and rebuild finger table with the rest,
+    /// so the intervals held by the removed one are taken by other members.
+    pub fn remove(&mut self, id: Did) {
+        if self.members.remove(&id) {
+            *self.member_versions.entry(id).or_default() += 1;
+            self.rebuild_finger();
+        }
+    }
+
+    /// State of a did as `(version, removed)`, or None if it's never seen.
+    /// Members stored by earlier versions have no version, and are taken as version 0.
+    fn member_state(&self, id: &Did) -> Option<(u64, bool)> {
+        let removed = !self.members.contains(id);
+        match self.member_versions.get(id) {
+            Some(v) => Some((*v, removed)),
+            None if !removed => Some((0, false)),
+            None => None,
+        }
+    }
+
+    fn rebuild_finger(&mut self) {
+        let mut finger = FingerTable::new(self.did, Did::BITS as usize);
+        for m in self.members.iter() {
+            finger.join(*m);
+        }
+        self.finger = finger;
+    }
+
     /// Create a SubRing from Ring
     pub fn from_ring(name: &str, ring: &PeerRing) -> Result<Self> {
         let address: HashStr = name.to_owned().into();
@@ -135,10 +261,47 @@ impl SubRing {
             name: name.to_owned(),
             did,
             finger: (*finger).clone(),
+            members: finger.list().iter().flatten().copied().collect(),
+            member_versions: BTreeMap::new(),
             admin: None,
             creator: ring.id,
+            kick_nonce: 0,
+            version: VectorClock::new(),
         })
     }
+
+    /// Merge with a replica of same subring, which is received from remote and not
+    /// authenticated, so only joins are taken from it: a member is added if its version is
+    /// greater than the local state of the did, and a stale replica can't undo a removal.
+    /// Admin and creator never change, a replica with other ones is refused. Removals and
+    /// kick nonce of a replica are never taken, as anyone can forge them; they are applied
+    /// only by the node storing subring, which checks the signed origin of the request.
+    pub fn merge(&mut self, other: &Self) -> Result<()> {
+        if self.did != other.did {
+            return Err(Error::DidNotEqual);
+        }
+        if self.admin != other.admin || self.creator != other.creator {
+            return Err(Error::SubRingReplicaMismatch);
+        }
+        let mut joined = false;
+        for did in other.members.iter() {
+            let version = other.member_versions.get(did).copied().unwrap_or(0);
+            match self.member_state(did) {
+                Some((v, _)) if v >= version => {}
+                _ => {
+                    self.member_versions.insert(*did, version);
+                    self.members.insert(*did);
+                    joined = true;
+                }
+            }
+        }
+        // keep finger table independent of merge order
+        if joined {
+            self.rebuild_finger();
+        }
+        self.version.merge(&other.version);
+        Ok(())
+    }
 }
 
 impl TryFrom<SubRing> for VirtualNode {
@@ -149,6 +312,7 @@ impl TryFrom<SubRing> for VirtualNode {
             address: ring.did,
             data: vec![data.into()],
             kind: VNodeType::SubRing,
+            expires_at: None,
         })
     }
 }
@@ -159,11 +323,251 @@ impl TryFrom<VirtualNode> for SubRing {
         match &vnode.kind {
             VNodeType::SubRing => {
                 let decoded: String = vnode.data[0].decode()?;
-                let subring: SubRing =
+                let mut subring: SubRing =
                     serde_json::from_str(&decoded).map_err(Error::Deserialize)?;
+                // members were kept only in finger table by earlier versions
+                if subring.members.is_empty() {
+                    subring.members = subring.finger.list().iter().flatten().copied().collect();
+                }
                 Ok(subring)
             }
             _ => Err(Error::InvalidVNodeType),
         }
     }
 }
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::dht::ChordStorage;
+    use crate::ecc::SecretKey;
+    use crate::storage::PersistenceStorage;
+
+    fn random_did() -> Did {
+        SecretKey::random().address().into()
+    }
+
+    fn seeded_did(rng: &mut StdRng) -> Did {
+        Did::from(num_bigint::BigUint::from_bytes_be(&rng.gen::<[u8; 20]>()))
+    }
+
+    #[test]
+    fn test_subring_concat() -> Result<()> {
+        let (writer1, writer2) = (random_did(), random_did());
+        let (member1, member2) = (random_did(), random_did());
+        let base = SubRing::new("test_subring_concat", &random_did())?;
+        let mut a = base.clone();
+        a.join(member1);
+        a.version.tick(writer1);
+        let mut b = base.clone();
+        b.join(member2);
+        b.version.tick(writer2);
+        let (vbase, va, vb): (VirtualNode, VirtualNode, VirtualNode) = (
+            base.clone().try_into()?,
+            a.clone().try_into()?,
+            b.clone().try_into()?,
+        );
+
+        // the newer version wins
+        assert_eq!(SubRing::try_from(VirtualNode::concat(&vbase, &va)?)?, a);
+        assert_eq!(SubRing::try_from(VirtualNode::concat(&va, &vbase)?)?, a);
+
+        // concurrent versions are united, whatever the order is
+        let ab: SubRing = VirtualNode::concat(&va, &vb)?.try_into()?;
+        let ba: SubRing = VirtualNode::concat(&vb, &va)?.try_into()?;
+        assert_eq!(ab, ba);
+        let mut expected = vec![member1, member2];
+        expected.sort();
+        assert_eq!(ab.members(), expected);
+        assert!(ab.version > a.version);
+        assert!(ab.version > b.version);
+
+        // a local removal is not undone by a stale replica
+        let mut c = ab.clone();
+        c.remove(member1);
+        c.version.tick(writer1);
+        assert_eq!(c.members(), vec![member2]);
+        let (vab, vc): (VirtualNode, VirtualNode) = (ab.try_into()?, c.clone().try_into()?);
+        assert_eq!(SubRing::try_from(VirtualNode::concat(&vc, &vab)?)?, c);
+
+        // a removal of replica is not taken, even if it's newer
+        let merged: SubRing = VirtualNode::concat(&vab, &vc)?.try_into()?;
+        assert_eq!(merged.members(), expected);
+        assert_eq!(merged.version, c.version);
+
+        // a local removal is kept against concurrent joins
+        let member3 = random_did();
+        let mut d = SubRing::try_from(vab.clone())?;
+        d.join(member3);
+        d.version.tick(writer2);
+        let vd: VirtualNode = d.try_into()?;
+        let cd: SubRing = VirtualNode::concat(&vc, &vd)?.try_into()?;
+        let mut expected = vec![member2, member3];
+        expected.sort();
+        assert_eq!(cd.members(), expected);
+
+        // replicas of different subrings cannot be merged
+        let other: VirtualNode = SubRing::new("test_subring_concat_other", &writer1)?.try_into()?;
+        assert!(VirtualNode::concat(&vab, &other).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_forged_subring_replica() -> Result<()> {
+        let (admin, writer) = (random_did(), random_did());
+        let (member1, member2) = (random_did(), random_did());
+        let mut subring = SubRing::new("test_forged_subring_replica", &admin)?;
+        subring.join(member1);
+        subring.join(member2);
+        subring.kick_nonce = 1;
+        subring.version.tick(writer);
+        let stored: VirtualNode = subring.clone().try_into()?;
+
+        // a replica which takes over admin is refused, even if it's version dominates
+        let forger = random_did();
+        let mut forged = subring.clone();
+        forged.admin = Some(forger);
+        forged.creator = forger;
+        forged.remove(member1);
+        for _ in 0..10 {
+            forged.version.tick(forger);
+        }
+        assert!(forged.version > subring.version);
+        let vforged: VirtualNode = forged.try_into()?;
+        assert!(matches!(
+            VirtualNode::concat(&stored, &vforged),
+            Err(Error::SubRingReplicaMismatch)
+        ));
+
+        // removals and kick nonce of a replica with same admin are not taken
+        let mut forged = subring.clone();
+        forged.remove(member1);
+        forged.kick_nonce = u64::MAX;
+        forged.version.tick(forger);
+        let vforged: VirtualNode = forged.clone().try_into()?;
+        let merged: SubRing = VirtualNode::concat(&stored, &vforged)?.try_into()?;
+        assert_eq!(merged.admin, Some(admin));
+        assert_eq!(merged.creator, admin);
+        assert_eq!(merged.kick_nonce, 1);
+        assert_eq!(merged.members(), subring.members());
+        assert_eq!(merged.version, forged.version);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subring_members_in_same_interval() -> Result<()> {
+        let mut subring = SubRing::new("test_subring_members_in_same_interval", &random_did())?;
+        // the neighbours fall in the same interval of finger table
+        let member1 = subring.did + Did::from(num_bigint::BigUint::from(16u16));
+        let member2 = member1 + Did::from(num_bigint::BigUint::from(1u16));
+        subring.join(member2);
+        subring.join(member1);
+        assert_eq!(subring.members(), {
+            let mut v = vec![member1, member2];
+            v.sort();
+            v
+        });
+        assert!(!subring.finger.contains(&Some(member2)));
+
+        // the interval is taken by the other one after removal
+        subring.remove(member1);
+        assert_eq!(subring.members(), vec![member2]);
+        assert!(subring.finger.contains(&Some(member2)));
+        Ok(())
+    }
+
+    /// Exchange replicas in random order, every node sends to every other once.
+    async fn exchange(nodes: &[PeerRing], rid: &Did, rng: &mut StdRng) -> Result<()> {
+        let mut pairs = vec![];
+        for i in 0..nodes.len() {
+            for j in 0..nodes.len() {
+                if i != j {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs.shuffle(rng);
+        for (i, j) in pairs {
+            let vnode: VirtualNode = nodes[i].get_subring(rid).await?.try_into()?;
+            nodes[j].store(vnode).await?;
+        }
+        Ok(())
+    }
+
+    /// All replicas are same, and return the membership.
+    async fn converged(nodes: &[PeerRing], rid: &Did) -> Result<BTreeSet<Did>> {
+        let merged = nodes[0].get_subring(rid).await?;
+        for node in nodes.iter() {
+            assert_eq!(node.get_subring(rid).await?, merged);
+        }
+        Ok(merged.members)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_join_subring_converge() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(20221017);
+        for round in 0..16 {
+            let name = format!("test_concurrent_join_subring_converge_{}", round);
+            let subring = SubRing::new(&name, &seeded_did(&mut rng))?;
+            let rid = subring.did;
+
+            // every node holds a replica of subring, and is not aware of others
+            let mut nodes = vec![];
+            for _ in 0..rng.gen_range(2..5) {
+                let path = PersistenceStorage::random_path("./tmp");
+                let db = PersistenceStorage::new_with_path(path.as_str()).await?;
+                let node = PeerRing::new_with_storage(seeded_did(&mut rng), 3, db);
+                node.store_subring(&subring).await?;
+                nodes.push(node);
+            }
+
+            // members join via random nodes
+            let mut expected = BTreeSet::new();
+            for _ in 0..rng.gen_range(1..10) {
+                let member = seeded_did(&mut rng);
+                let node = nodes.choose(&mut rng).unwrap();
+                assert_eq!(
+                    node.join_subring(&member, &rid).await?,
+                    PeerRingAction::None
+                );
+                expected.insert(member);
+            }
+            exchange(&nodes, &rid, &mut rng).await?;
+            assert_eq!(converged(&nodes, &rid).await?, expected);
+
+            // members leave via the node storing subring, concurrently with joins via others
+            let joined: Vec<Did> = expected.iter().copied().collect();
+            let count = rng.gen_range(1..=joined.len());
+            let leaving: Vec<Did> = joined.choose_multiple(&mut rng, count).copied().collect();
+            for member in leaving.iter() {
+                assert_eq!(
+                    nodes[0].leave_subring(member, &rid).await?,
+                    PeerRingAction::None
+                );
+                expected.remove(member);
+            }
+            for _ in 0..rng.gen_range(0..5) {
+                let member = seeded_did(&mut rng);
+                let node = nodes.choose(&mut rng).unwrap();
+                node.join_subring(&member, &rid).await?;
+                expected.insert(member);
+            }
+            exchange(&nodes, &rid, &mut rng).await?;
+
+            // removals are kept by the node storing subring, and not taken by other replicas
+            assert_eq!(nodes[0].get_subring(&rid).await?.members, expected);
+            let stale: BTreeSet<Did> = expected.iter().chain(leaving.iter()).copied().collect();
+            for node in nodes[1..].iter() {
+                assert_eq!(node.get_subring(&rid).await?.members, stale);
+            }
+        }
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+}
@@ -1,20 +1,21 @@
 //! Successor for Chord
+use crate::dht::did::RingId;
 use crate::dht::did::SortRing;
 use crate::dht::Did;
 
 #[derive(Debug, Clone)]
-pub struct Successor {
+pub struct Successor<I: RingId = Did> {
     /// self id
-    id: Did,
+    id: I,
     /// max successor
     max: u8,
     /// successor's list
-    successors: Vec<Did>,
+    successors: Vec<I>,
 }
 
-impl Successor {
+impl<I: RingId> Successor<I> {
     /// create a new Successor instance
-    pub fn new(id: Did, max: u8) -> Self {
+    pub fn new(id: I, max: u8) -> Self {
         Self {
             id,
             max,
@@ -22,11 +23,15 @@ impl Successor {
         }
     }
 
+    pub fn capacity(&self) -> u8 {
+        self.max
+    }
+
     pub fn is_none(&self) -> bool {
         self.successors.is_empty()
     }
 
-    pub fn min(&self) -> Did {
+    pub fn min(&self) -> I {
         if self.is_none() {
             self.id
         } else {
@@ -34,7 +39,7 @@ impl Successor {
         }
     }
 
-    pub fn max(&self) -> Did {
+    pub fn max(&self) -> I {
         if self.is_none() {
             self.id
         } else {
@@ -42,7 +47,7 @@ impl Successor {
         }
     }
 
-    pub fn update(&mut self, successor: Did) {
+    pub fn update(&mut self, successor: I) {
         if self.successors.contains(&successor) || successor == self.id {
             return;
         }
@@ -51,11 +56,11 @@ impl Successor {
         self.successors.truncate(self.max.into());
     }
 
-    pub fn list(&self) -> Vec<Did> {
+    pub fn list(&self) -> Vec<I> {
         self.successors.clone()
     }
 
-    pub fn remove(&mut self, id: Did) {
+    pub fn remove(&mut self, id: I) {
         self.successors.retain(|&v| v != id);
     }
 }
@@ -63,12 +68,16 @@ impl Successor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dht::tests::gen_ordered_did256s;
     use crate::dht::tests::gen_ordered_dids;
 
     #[test]
     fn test_successor_update() {
-        let dids = gen_ordered_dids(6);
+        successor_update(gen_ordered_dids(6));
+        successor_update(gen_ordered_did256s(6));
+    }
 
+    fn successor_update<I: RingId>(dids: Vec<I>) {
         let mut succ = Successor::new(dids[0], 3);
         assert!(succ.is_none());
 
@@ -90,8 +99,11 @@ mod tests {
 
     #[test]
     fn test_successor_remove() {
-        let dids = gen_ordered_dids(4);
+        successor_remove(gen_ordered_dids(4));
+        successor_remove(gen_ordered_did256s(4));
+    }
 
+    fn successor_remove<I: RingId>(dids: Vec<I>) {
         let mut succ = Successor::new(dids[0], 3);
         assert!(succ.is_none());
 
@@ -0,0 +1,305 @@
+#![warn(missing_docs)]
+//! Snapshot of chord state of nodes, and consistency checker of a ring
+//! which is reconstructed by walking successors from a seed node.
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::did::RingId;
+use super::latency::LookupHopStats;
+use super::scheduler::StabilizeMetrics;
+use crate::dht::Did;
+use crate::storage::CacheStats;
+
+/// Chord state of a node
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DHTStatus {
+    /// did of node
+    pub did: Did,
+    /// predecessor of node
+    pub predecessor: Option<Did>,
+    /// successor list of node
+    pub successors: Vec<Did>,
+    /// populated finger entries, with their index
+    pub fingers: Vec<(usize, Did)>,
+    /// index of the finger which is fixed in last round of stabilization
+    pub fix_finger_index: u8,
+    /// number of vnodes in storage
+    pub storage_count: u64,
+    /// bytes of vnode data in storage, cache and replicas
+    #[serde(default)]
+    pub storage_usage: usize,
+    /// counters of cache for vnodes fetched from remote
+    #[serde(default)]
+    pub cache: CacheStats,
+    /// latency of lookup hops forwarded by this node
+    #[serde(default)]
+    pub lookup_hops: LookupHopStats,
+    /// rounds and durations of stabilization, they are known by the process running
+    /// stabilization only, so it's None in the report of a remote node
+    #[serde(default)]
+    pub stabilization: Option<StabilizeMetrics>,
+}
+
+/// Inconsistency found in a ring
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RingInconsistency {
+    /// the status of node cannot be fetched
+    Unreachable(Did),
+    /// node has no successor
+    NoSuccessor(Did),
+    /// predecessor of the successor of node is not the node
+    BrokenPair {
+        /// the node
+        node: Did,
+        /// successor of the node
+        successor: Did,
+        /// predecessor of the successor
+        predecessor: Option<Did>,
+    },
+    /// a known node is in (node, successor), it's skipped by the successor pointer
+    Gap {
+        /// the node
+        node: Did,
+        /// successor of the node
+        successor: Did,
+        /// the known node which is skipped
+        skipped: Did,
+    },
+    /// walking successors doesn't go back to the seed node
+    NotClosed {
+        /// the last node which is walked
+        last: Did,
+    },
+}
+
+impl std::fmt::Display for RingInconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unreachable(did) => write!(f, "{} is unreachable", did),
+            Self::NoSuccessor(did) => write!(f, "{} has no successor", did),
+            Self::BrokenPair {
+                node,
+                successor,
+                predecessor: Some(p),
+            } => write!(
+                f,
+                "successor of {} is {}, but predecessor of {} is {}",
+                node, successor, successor, p
+            ),
+            Self::BrokenPair {
+                node,
+                successor,
+                predecessor: None,
+            } => write!(
+                f,
+                "successor of {} is {}, but {} has no predecessor",
+                node, successor, successor
+            ),
+            Self::Gap {
+                node,
+                successor,
+                skipped,
+            } => write!(
+                f,
+                "{} is between {} and it's successor {}",
+                skipped, node, successor
+            ),
+            Self::NotClosed { last } => write!(f, "ring is not closed after {}", last),
+        }
+    }
+}
+
+/// Ring reconstructed by walking successors from a seed node
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RingSnapshot {
+    /// status of nodes in order of walking, the first one is the seed
+    pub nodes: Vec<DHTStatus>,
+    /// nodes whose status cannot be fetched
+    pub unreachable: Vec<Did>,
+    /// walking successors goes back to the seed
+    pub closed: bool,
+}
+
+impl RingSnapshot {
+    /// The next node to walk, None if the walk is finished.
+    /// It marks the ring closed if the next one is the seed.
+    pub fn next(&mut self) -> Option<Did> {
+        let seed = self.nodes.first()?.did;
+        let next = *self.nodes.last()?.successors.first()?;
+        if next == seed {
+            self.closed = true;
+            return None;
+        }
+        if self.unreachable.contains(&next) || self.nodes.iter().any(|n| n.did == next) {
+            return None;
+        }
+        Some(next)
+    }
+
+    fn get(&self, did: Did) -> Option<&DHTStatus> {
+        self.nodes.iter().find(|n| n.did == did)
+    }
+
+    /// All dids which are mentioned by walked nodes
+    fn known(&self) -> HashSet<Did> {
+        let mut known = HashSet::new();
+        for n in self.nodes.iter() {
+            known.insert(n.did);
+            known.extend(n.predecessor);
+            known.extend(n.successors.iter().copied());
+            known.extend(n.fingers.iter().map(|(_, did)| *did));
+        }
+        known
+    }
+
+    /// Check consistency of successor and predecessor pointers
+    pub fn check(&self) -> Vec<RingInconsistency> {
+        let known = self.known();
+        let mut ret = vec![];
+        for node in self.nodes.iter() {
+            let successor = match node.successors.first() {
+                Some(s) => *s,
+                None => {
+                    ret.push(RingInconsistency::NoSuccessor(node.did));
+                    continue;
+                }
+            };
+            if let Some(s) = self.get(successor) {
+                if s.predecessor != Some(node.did) {
+                    ret.push(RingInconsistency::BrokenPair {
+                        node: node.did,
+                        successor,
+                        predecessor: s.predecessor,
+                    });
+                }
+            }
+            // the closest skipped node is reported
+            if let Some(skipped) = known
+                .iter()
+                .filter(|x| x.in_range(&node.did, &node.did, &successor))
+                .min_by_key(|x| x.bias(&node.did))
+            {
+                ret.push(RingInconsistency::Gap {
+                    node: node.did,
+                    successor,
+                    skipped: *skipped,
+                });
+            }
+        }
+        if !self.closed {
+            if let Some(last) = self.nodes.last() {
+                ret.push(RingInconsistency::NotClosed { last: last.did });
+            }
+        }
+        ret.extend(
+            self.unreachable
+                .iter()
+                .map(|did| RingInconsistency::Unreachable(*did)),
+        );
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+
+    fn status(did: Did, predecessor: Option<Did>, successor: Option<Did>) -> DHTStatus {
+        DHTStatus {
+            did,
+            predecessor,
+            successors: successor.into_iter().collect(),
+            fingers: successor.into_iter().map(|s| (0, s)).collect(),
+            fix_finger_index: 0,
+            storage_count: 0,
+            storage_usage: 0,
+            cache: CacheStats::default(),
+            lookup_hops: LookupHopStats::default(),
+            stabilization: None,
+        }
+    }
+
+    fn walk(statuses: &[DHTStatus]) -> RingSnapshot {
+        let mut snapshot = RingSnapshot {
+            nodes: vec![statuses[0].clone()],
+            ..Default::default()
+        };
+        while let Some(next) = snapshot.next() {
+            match statuses.iter().find(|s| s.did == next) {
+                Some(s) => snapshot.nodes.push(s.clone()),
+                None => snapshot.unreachable.push(next),
+            }
+        }
+        snapshot
+    }
+
+    #[test]
+    fn test_ring_snapshot_check() {
+        let d = gen_ordered_dids(4);
+
+        // d0 -> d1 -> d2 -> d0
+        let snapshot = walk(&[
+            status(d[0], Some(d[2]), Some(d[1])),
+            status(d[1], Some(d[0]), Some(d[2])),
+            status(d[2], Some(d[1]), Some(d[0])),
+        ]);
+        assert!(snapshot.closed);
+        assert_eq!(snapshot.nodes.len(), 3);
+        assert_eq!(snapshot.check(), vec![]);
+
+        // d1 thinks d3 is it's predecessor, and d3 is skipped by d2
+        let snapshot = walk(&[
+            status(d[0], Some(d[2]), Some(d[1])),
+            status(d[1], Some(d[3]), Some(d[2])),
+            status(d[2], Some(d[1]), Some(d[0])),
+        ]);
+        assert!(snapshot.closed);
+        assert_eq!(snapshot.check(), vec![
+            RingInconsistency::BrokenPair {
+                node: d[0],
+                successor: d[1],
+                predecessor: Some(d[3]),
+            },
+            RingInconsistency::Gap {
+                node: d[2],
+                successor: d[0],
+                skipped: d[3],
+            },
+        ]);
+
+        // d1 skips d2 which is known by d3
+        let snapshot = walk(&[
+            status(d[0], Some(d[3]), Some(d[1])),
+            status(d[1], Some(d[0]), Some(d[3])),
+            status(d[3], Some(d[2]), Some(d[0])),
+        ]);
+        assert_eq!(snapshot.check(), vec![
+            RingInconsistency::BrokenPair {
+                node: d[1],
+                successor: d[3],
+                predecessor: Some(d[2]),
+            },
+            RingInconsistency::Gap {
+                node: d[1],
+                successor: d[3],
+                skipped: d[2],
+            },
+        ]);
+
+        // d1 is unreachable, and d0 has no successor
+        let snapshot = walk(&[status(d[0], None, Some(d[1]))]);
+        assert!(!snapshot.closed);
+        assert_eq!(snapshot.check(), vec![
+            RingInconsistency::NotClosed { last: d[0] },
+            RingInconsistency::Unreachable(d[1]),
+        ]);
+        let snapshot = walk(&[status(d[0], None, None)]);
+        assert_eq!(snapshot.check(), vec![
+            RingInconsistency::NoSuccessor(d[0]),
+            RingInconsistency::NotClosed { last: d[0] },
+        ]);
+    }
+}
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 
 use super::did::Did;
+use super::scan::VNodeRange;
 use super::subring::SubRing;
 use super::vnode::VirtualNode;
 use crate::err::Result;
@@ -32,8 +33,11 @@ pub trait ChordStorage<A>: Chord<A> {
     async fn store(&self, peer: VirtualNode) -> Result<A>;
     /// Batch store
     async fn store_vec(&self, peer: Vec<VirtualNode>) -> Result<A>;
+    /// Scan a page of vnodes in range, which starts on the node responsible for
+    /// start of range, and continues on it's successors by cursor of the page
+    async fn scan(&self, range: &VNodeRange) -> Result<A>;
     /// When A Node's successor is updated, it should check the storage that
-    /// if exist some VNode's address is in (self.id, new_successor), then
+    /// if exist some VNode's address is out of (self.id, new_successor], then
     /// sync the data to the new successor
     async fn sync_with_successor(&self, new_successor: Did) -> Result<A>;
 }
@@ -68,6 +72,17 @@ pub trait SubRingManager<A>: ChordStorage<A> {
     /// And Noti closest preceding node that A is Joined
     async fn join_subring(&self, id: &Did, rid: &Did) -> Result<A>;
 
+    /// remove a node from subring, on it's own request
+    async fn leave_subring(&self, id: &Did, rid: &Did) -> Result<A>;
+
+    /// remove a node from subring, on request of `admin`, who should be verified by caller.
+    /// Only the admin of subring is permitted, and `nonce` should be greater than
+    /// the one of last kick, or the kick is rejected as replayed.
+    async fn kick_subring(&self, admin: &Did, id: &Did, rid: &Did, nonce: u64) -> Result<A>;
+
+    /// list members of subring, the subring is returned as VNode if it's stored locally
+    async fn list_subring(&self, rid: &Did) -> Result<A>;
+
     /// search a cloest preceding node
     async fn cloest_preceding_node_for_subring(&self, id: &Did, rid: &Did) -> Option<Result<Did>>;
 }
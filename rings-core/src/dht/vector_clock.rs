@@ -0,0 +1,96 @@
+#![warn(missing_docs)]
+//! Vector clock for versioning state which can be modified by more than one node,
+//! such as the finger table of a SubRing.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dht::Did;
+
+/// A vector clock records how many times each writer modified the state.
+/// Two clocks can be compared with `partial_cmp`, `None` means they are concurrent.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VectorClock(BTreeMap<Did, u64>);
+
+impl VectorClock {
+    /// Create an empty clock
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counter of a writer
+    pub fn get(&self, did: &Did) -> u64 {
+        self.0.get(did).copied().unwrap_or(0)
+    }
+
+    /// Record a modification made by writer
+    pub fn tick(&mut self, did: Did) {
+        *self.0.entry(did).or_insert(0) += 1;
+    }
+
+    /// Merge with another clock, by taking the max counter of each writer
+    pub fn merge(&mut self, other: &Self) {
+        for (did, counter) in other.0.iter() {
+            let c = self.0.entry(*did).or_insert(0);
+            *c = (*c).max(*counter);
+        }
+    }
+
+    /// Check if two clocks are concurrent, which means neither happened before the other
+    pub fn is_concurrent(&self, other: &Self) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+}
+
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let mut ord = Ordering::Equal;
+        for did in self.0.keys().chain(other.0.keys()) {
+            match (ord, self.get(did).cmp(&other.get(did))) {
+                (_, Ordering::Equal) => {}
+                (Ordering::Equal, o) => ord = o,
+                (a, b) if a != b => return None,
+                _ => {}
+            }
+        }
+        Some(ord)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+
+    #[test]
+    fn test_vector_clock_order() {
+        let dids = gen_ordered_dids(2);
+        let (a, b) = (dids[0], dids[1]);
+
+        let mut x = VectorClock::new();
+        let mut y = VectorClock::new();
+        assert_eq!(x.partial_cmp(&y), Some(Ordering::Equal));
+
+        x.tick(a);
+        assert!(x > y);
+        assert!(y < x);
+
+        y.tick(b);
+        assert!(x.is_concurrent(&y));
+        assert!(y.is_concurrent(&x));
+
+        let mut z = x.clone();
+        z.merge(&y);
+        assert!(z > x);
+        assert!(z > y);
+        assert_eq!((z.get(&a), z.get(&b)), (1, 1));
+
+        // merge is commutative and idempotent
+        let mut w = y.clone();
+        w.merge(&x);
+        w.merge(&x);
+        assert_eq!(z, w);
+    }
+}
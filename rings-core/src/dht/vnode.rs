@@ -6,6 +6,7 @@ use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::dht::record::Record;
 use crate::dht::subring::SubRing;
 use crate::dht::Did;
 use crate::ecc::HashStr;
@@ -14,6 +15,10 @@ use crate::err::Result;
 use crate::message::Encoded;
 use crate::message::Encoder;
 use crate::message::MessagePayload;
+use crate::utils;
+
+/// A relayed message is kept in mailbox of it's target in this period
+pub const MAILBOX_TTL_MS: u128 = 24 * 60 * 60 * 1000;
 
 /// VNode Types
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,6 +29,8 @@ pub enum VNodeType {
     SubRing,
     /// RelayMessage: A Relayed but unreach message, which is stored on it's successor
     RelayMessage,
+    /// Record: A mutable record signed by it's owner
+    Record,
 }
 
 /// A Virtual Node is a Node that dont have real network address.
@@ -31,6 +38,7 @@ pub enum VNodeType {
 /// For Encoded Data, it's sha1 of data, for a SubRing, it's sha1 of SubRing's name,
 /// and for the RelayedMessage, it's the target address of message plus 1 (for ensure that the message is
 /// sent to the successor of target), thus while target Node going online, it will sync from it's successor.
+/// The address is a 160-bit `Did` as node's, content keyed by wider hashes is mapped to it by sha1.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VirtualNode {
     /// address of vnode
@@ -39,6 +47,10 @@ pub struct VirtualNode {
     pub data: Vec<Encoded>,
     /// vnode type
     pub kind: VNodeType,
+    /// Timestamp in ms when the vnode is expired, it's set by publisher.
+    /// A vnode without expiration is kept until it's removed.
+    #[serde(default)]
+    pub expires_at: Option<u128>,
 }
 
 impl VirtualNode {
@@ -46,6 +58,46 @@ impl VirtualNode {
     pub fn did(&self) -> Did {
         self.address
     }
+
+    /// Address of mailbox of a node, which is the address of node plus 1.
+    /// Messages to an offline node are stored in it, by the predecessor of that node.
+    pub fn mailbox_address(did: Did) -> Did {
+        (BigUint::from(did) + BigUint::from(1u16)).into()
+    }
+
+    /// Set the vnode to be expired after `ttl_ms` from now.
+    pub fn with_ttl(mut self, ttl_ms: u128) -> Self {
+        self.expires_at = Some(utils::get_epoch_ms() + ttl_ms);
+        self
+    }
+
+    /// Check if the vnode is expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|ts| utils::get_epoch_ms() > ts)
+            .unwrap_or(false)
+    }
+
+    /// Size of vnode data in bytes, which is counted in storage quota.
+    pub fn size(&self) -> usize {
+        self.data.iter().map(|d| d.value().len()).sum()
+    }
+}
+
+/// The later expiration of two vnodes, `None` means never expired.
+fn later_expiration(a: Option<u128>, b: Option<u128>) -> Option<u128> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => None,
+    }
+}
+
+/// Check if a relayed message is out of `MAILBOX_TTL_MS`,
+/// the data which is not a message is never expired.
+fn is_expired_message(data: &Encoded) -> bool {
+    data.decode::<MessagePayload<serde_json::Value>>()
+        .map(|payload| payload.is_older_than(MAILBOX_TTL_MS))
+        .unwrap_or(false)
 }
 
 impl<T> TryFrom<MessagePayload<T>> for VirtualNode
@@ -53,12 +105,15 @@ where T: Serialize + DeserializeOwned
 {
     type Error = Error;
     fn try_from(msg: MessagePayload<T>) -> Result<Self> {
-        let address = BigUint::from(msg.addr) + BigUint::from(1u16);
+        let address = Self::mailbox_address(msg.relay.destination);
+        // the mailbox is reclaimed by sweep, once all messages in it are expired
+        let expires_at = Some(msg.origin_verification.ts_ms + MAILBOX_TTL_MS);
         let data = msg.encode()?;
         Ok(Self {
-            address: address.into(),
+            address,
             data: vec![data],
             kind: VNodeType::RelayMessage,
+            expires_at,
         })
     }
 }
@@ -71,6 +126,7 @@ impl TryFrom<Encoded> for VirtualNode {
             address: Did::from_str(&address.inner())?,
             data: vec![e],
             kind: VNodeType::Data,
+            expires_at: None,
         })
     }
 }
@@ -86,31 +142,55 @@ impl TryFrom<String> for VirtualNode {
 impl VirtualNode {
     /// concat data of a virtual Node
     /// We do not needs to check the type of VNode because two VNode with same address but
-    /// has different Type is incapable
+    /// has different Type is incapable.
+    /// The merged vnode is expired at the later expiration of them,
+    /// except a record, which takes the expiration of the newer one.
     pub fn concat(a: &Self, b: &Self) -> Result<Self> {
+        let expires_at = later_expiration(a.expires_at, b.expires_at);
         match &a.kind {
             VNodeType::RelayMessage => {
                 if a.address != b.address {
                     Err(Error::DidNotEqual)
                 } else {
+                    // a vnode may be synced or replicated more than once,
+                    // skip the data which is already exists
+                    let mut data = a.data.clone();
+                    for d in b.data.iter() {
+                        if !data.contains(d) {
+                            data.push(d.clone());
+                        }
+                    }
+                    data.retain(|d| !is_expired_message(d));
                     Ok(Self {
                         address: a.address,
-                        data: [&a.data[..], &b.data[..]].concat(),
+                        data,
                         kind: a.kind.clone(),
+                        expires_at,
                     })
                 }
             }
-            VNodeType::Data => Ok(a.clone()),
+            VNodeType::Data => Ok(Self {
+                expires_at,
+                ..a.clone()
+            }),
             VNodeType::SubRing => {
-                // if subring exists, just join creator to new subring
-                let decoded_a: String = a.data[0].decode()?;
-                let decoded_b: String = a.data[0].decode()?;
-                let mut subring_a: SubRing =
-                    serde_json::from_str(&decoded_a).map_err(Error::Deserialize)?;
-                let subring_b: SubRing =
-                    serde_json::from_str(&decoded_b).map_err(Error::Deserialize)?;
-                subring_a.finger.join(subring_b.creator);
-                subring_a.try_into()
+                // replicas of subring are merged by version, see `SubRing::merge`
+                let mut subring_a: SubRing = a.clone().try_into()?;
+                let subring_b: SubRing = b.clone().try_into()?;
+                subring_a.merge(&subring_b)?;
+                Ok(Self {
+                    expires_at,
+                    ..Self::try_from(subring_a)?
+                })
+            }
+            VNodeType::Record => {
+                // a stale record is rejected, see `Record::merge`
+                let record_a: Record = a.clone().try_into()?;
+                let record_b: Record = b.clone().try_into()?;
+                Ok(Self {
+                    expires_at: b.expires_at,
+                    ..Self::try_from(record_a.merge(&record_b)?)?
+                })
             }
         }
     }
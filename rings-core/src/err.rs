@@ -132,6 +132,18 @@ pub enum Error {
     #[error("PeerRing RWLock unlock failed")]
     PeerRingUnlockFailed,
 
+    #[error("Iterative lookup of {0} is already finished")]
+    LookupAlreadyFinished(crate::dht::Did),
+
+    #[error("Iterative lookup makes no progress, {0} is already in path")]
+    LookupLoopDetected(crate::dht::Did),
+
+    #[error("Iterative lookup timeout, no response from {0}")]
+    LookupTimeout(crate::dht::Did),
+
+    #[error("Request timeout, no report of transaction {0}")]
+    RequestTimeout(uuid::Uuid),
+
     #[error("Cannot seek did in swarm table, {0}")]
     SwarmMissDidInTable(crate::dht::Did),
 
@@ -165,6 +177,30 @@ pub enum Error {
     #[error("Invalid virtual node type")]
     InvalidVNodeType,
 
+    #[error("Permission denied, {0} is not admin of subring")]
+    SubRingPermissionDenied(crate::dht::Did),
+
+    #[error("Kick of subring is stale, nonce {0} is not greater than {1}")]
+    StaleSubRingKick(u64, u64),
+
+    #[error("Replica of subring has different admin or creator")]
+    SubRingReplicaMismatch,
+
+    #[error("Record is not signed by it's owner")]
+    InvalidRecordSignature,
+
+    #[error("Record is stale, seq {0} is not greater than {1}")]
+    StaleRecord(u64, u64),
+
+    #[error("Storage quota of {0} bytes is exceeded")]
+    StorageQuotaExceeded(usize),
+
+    #[error("Vnode {0} is rejected by storing node: {1}")]
+    VNodeRejected(crate::dht::Did, String),
+
+    #[error("Replica {0} is out of replica range of it's origin")]
+    ReplicaOutOfRange(crate::dht::Did),
+
     #[cfg(not(feature = "wasm"))]
     #[error("RTC new peer connection failed")]
     RTCPeerConnectionCreateFailed(#[source] webrtc::Error),
@@ -205,6 +241,12 @@ pub enum Error {
     #[error("DataChannel state not open")]
     RTCDataChannelStateNotOpen,
 
+    #[error("DataChannel chunk malformed")]
+    RTCDataChannelChunkMalformed,
+
+    #[error("DataChannel message too large, {0} bytes")]
+    RTCDataChannelMessageTooLarge(usize),
+
     #[cfg(not(feature = "wasm"))]
     #[error("RTC peer_connection add ice candidate error")]
     RTCPeerConnectionAddIceCandidateError(#[source] webrtc::Error),
@@ -297,6 +339,27 @@ pub enum Error {
 
     #[error("Message invalid: {0}")]
     InvalidMessage(String),
+
+    #[error("Invalid signature of payload")]
+    InvalidPayloadSignature,
+
+    #[error("Payload is expired")]
+    PayloadExpired,
+
+    #[error("Payload is signed {0}ms in the future, beyond tolerance of clock skew")]
+    PayloadFromFuture(u128),
+
+    #[error("Payload {0} is replayed")]
+    PayloadReplayed(uuid::Uuid),
+
+    #[error("Unsupported format of payload frame: {0:#x}")]
+    UnsupportedPayloadFormat(u8),
+
+    #[error("Stream is refused by {0:?}")]
+    StreamRefused(crate::dht::Did),
+
+    #[error("Stream not found: {0}")]
+    StreamNotFound(uuid::Uuid),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
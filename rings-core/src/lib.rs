@@ -63,6 +63,7 @@
 #![feature(async_closure)]
 #![feature(box_syntax)]
 #![feature(generators)]
+#![cfg_attr(test, feature(test))]
 pub mod channels;
 pub mod dht;
 pub mod ecc;
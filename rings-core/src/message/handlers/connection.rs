@@ -13,6 +13,8 @@ use crate::message::types::ConnectNodeReport;
 use crate::message::types::ConnectNodeSend;
 use crate::message::types::FindSuccessorReport;
 use crate::message::types::FindSuccessorSend;
+use crate::message::types::FindSuccessorStepReport;
+use crate::message::types::FindSuccessorStepSend;
 use crate::message::types::JoinDHT;
 use crate::message::types::Message;
 use crate::message::types::SyncVNodeWithSuccessor;
@@ -30,7 +32,16 @@ use crate::types::ice_transport::IceTrickleScheme;
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<LeaveDHT> for MessageHandler {
-    async fn handle(&self, _ctx: &MessagePayload<Message>, msg: &LeaveDHT) -> Result<()> {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &LeaveDHT) -> Result<()> {
+        // a node can only announce leaving of itself, except the local one
+        // which is emitted when a transport is closed
+        let origin = ctx.relay.origin();
+        if origin != self.dht.id && origin != msg.id {
+            return Err(Error::InvalidMessage(format!(
+                "{:?} cannot announce leaving of {:?}",
+                origin, msg.id
+            )));
+        }
         self.swarm.disconnect(msg.id).await
     }
 }
@@ -42,9 +53,30 @@ impl HandleMsg<JoinDHT> for MessageHandler {
         // here is two situation.
         // finger table just have no other node(beside next), it will be a `create` op
         // otherwise, it will be a `send` op
+        let successor = { self.dht.lock_successor()?.min() };
+        // messages may be kept in local mailbox before this node goes offline
+        if let Err(e) = self.replay_mailbox().await {
+            tracing::warn!("[JoinDHT] failed to replay mailbox: {:?}", e);
+        }
         match self.dht.join(msg.id)? {
             PeerRingAction::None => Ok(()),
             PeerRingAction::RemoteAction(next, PeerRingRemoteAction::FindSuccessor(id)) => {
+                // if successor is changed, the data out of (self, new_successor]
+                // should be handed over to the new successor
+                let new_successor = { self.dht.lock_successor()?.min() };
+                if new_successor != successor && new_successor == msg.id {
+                    if let PeerRingAction::RemoteAction(
+                        target,
+                        PeerRingRemoteAction::SyncVNodeWithSuccessor(data),
+                    ) = self.dht.sync_with_successor(msg.id).await?
+                    {
+                        self.send_direct_message(
+                            Message::SyncVNodeWithSuccessor(SyncVNodeWithSuccessor { data }),
+                            target,
+                        )
+                        .await?;
+                    }
+                }
                 // if there is only two nodes A, B, it may cause recursion
                 // A.successor == B
                 // B.successor == A
@@ -228,7 +260,7 @@ impl HandleMsg<FindSuccessorReport> for MessageHandler {
         }
 
         match &msg.handler {
-            FindSuccessorReportHandler::FixFingerTable => self.dht.lock_finger()?.set_fix(msg.id),
+            FindSuccessorReportHandler::FixFingerTable => self.dht.set_fix_finger(msg.id)?,
             FindSuccessorReportHandler::Connect => {
                 if self.swarm.get_and_check_transport(msg.id).await.is_none()
                     && msg.id != self.swarm.did()
@@ -257,6 +289,62 @@ impl HandleMsg<FindSuccessorReport> for MessageHandler {
     }
 }
 
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<FindSuccessorStepSend> for MessageHandler {
+    /// Answer a single step of iterative lookup, or relay the query along the route.
+    async fn handle(
+        &self,
+        ctx: &MessagePayload<Message>,
+        msg: &FindSuccessorStepSend,
+    ) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        if relay.destination != self.dht.id {
+            let next = msg
+                .route
+                .iter()
+                .skip_while(|&&n| n != self.dht.id)
+                .nth(1)
+                .copied()
+                .unwrap_or(relay.destination);
+            relay.relay(self.dht.id, Some(next))?;
+            return self.transpond_payload(ctx, relay).await;
+        }
+
+        relay.relay(self.dht.id, None)?;
+        self.send_report_message(
+            Message::FindSuccessorStepReport(FindSuccessorStepReport {
+                id: msg.id,
+                step: self.dht.lookup_step(msg.id)?,
+            }),
+            ctx.tx_id,
+            relay,
+        )
+        .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<FindSuccessorStepReport> for MessageHandler {
+    async fn handle(
+        &self,
+        ctx: &MessagePayload<Message>,
+        _msg: &FindSuccessorStepReport,
+    ) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+
+        // the step is taken by pending request of the lookup, see `Swarm::send_payload_and_wait`
+        Ok(())
+    }
+}
+
 #[cfg(not(feature = "wasm"))]
 #[cfg(test)]
 pub mod tests {
@@ -268,15 +356,53 @@ pub mod tests {
 
     use super::*;
     use crate::dht::Did;
+    use crate::dht::LookupStep;
+    use crate::dht::LookupTrace;
     use crate::ecc::tests::gen_ordered_keys;
     use crate::ecc::SecretKey;
     use crate::message::MessageHandler;
     use crate::swarm::Swarm;
     use crate::tests::default::prepare_node;
+    #[cfg(feature = "dummy")]
+    use crate::tests::default::simulator::SimConfig;
+    #[cfg(feature = "dummy")]
+    use crate::tests::default::simulator::Simulator;
     use crate::tests::manually_establish_connection;
     use crate::transports::manager::TransportManager;
     use crate::types::ice_transport::IceTransportInterface;
 
+    // Nodes join in every order, each one through the previous one, and the third one
+    // connects to the first one via DHT. Orders 1_2_3, 2_3_1 and 3_1_2 are clockwise,
+    // the others are anti-clockwise, see `test_triple_ordered_nodes_connection` and
+    // `test_triple_desc_ordered_nodes_connection` for messages of each order.
+    #[cfg(feature = "dummy")]
+    #[tokio::test]
+    async fn test_triple_nodes_connection_in_any_order() -> Result<()> {
+        let orders = [[0, 1, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0], [1, 0, 2], [
+            0, 2, 1,
+        ]];
+        for (seed, order) in orders.iter().enumerate() {
+            let mut sim = Simulator::new(SimConfig {
+                seed: seed as u64,
+                ..Default::default()
+            });
+            let mut keys: Vec<SecretKey> = (0..3).map(|_| sim.gen_key()).collect();
+            keys.sort_by_key(|k| k.address());
+
+            let mut bootstrap = None;
+            for i in order {
+                bootstrap = Some(sim.spawn_node_with_key(keys[*i], bootstrap).await?);
+            }
+            assert!(sim.stabilize_until_correct(10).await?, "order {:?}", order);
+            for did in sim.dids() {
+                assert_eq!(sim.node(did).unwrap().swarm.get_dids().len(), 2);
+            }
+            assert_eq!(sim.lookup_success_rate(16).await, 1.0);
+            sim.shutdown().await;
+        }
+        Ok(())
+    }
+
     // ndoe1.key < node2.key < node3.key
     //
     // Firstly, we connect node1 to node2, node2 to node3.
@@ -1044,4 +1170,86 @@ pub mod tests {
         assert!(t3_1.is_connected().await);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_find_successor_iterative() -> Result<()> {
+        let keys = gen_ordered_keys(4);
+        let (key1, key2, key3, key4) = (keys[0], keys[1], keys[2], keys[3]);
+        let (did1, dht1, swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, swarm2, node2, _path2) = prepare_node(key2).await;
+        let (did3, dht3, swarm3, node3, _path3) = prepare_node(key3).await;
+        let (did4, dht4, swarm4, _node4, _path4) = prepare_node(key4).await;
+
+        // build a chain of node1 -- node2 -- node3 -- node4,
+        // JoinDHT events are dropped to keep the chain
+        for (a, b) in [(&swarm1, &swarm2), (&swarm2, &swarm3), (&swarm3, &swarm4)] {
+            manually_establish_connection(a, b).await?;
+            assert!(a.poll_message().await.is_some());
+            assert!(b.poll_message().await.is_some());
+        }
+        dht1.join(did2)?;
+        dht2.join(did1)?;
+        dht2.join(did3)?;
+        dht3.join(did2)?;
+        dht3.join(did4)?;
+        dht4.join(did3)?;
+
+        let (trace, _) = tokio::join!(
+            swarm1.find_successor_iterative(did4, Duration::from_secs(5), 0),
+            async {
+                // node1 ask node2, node2 answer node3
+                let ev2 = node2.listen_once().await.unwrap();
+                assert_eq!(ev2.relay.path, vec![did1]);
+                assert!(matches!(
+                    ev2.data,
+                    Message::FindSuccessorStepSend(FindSuccessorStepSend{id, ref route}) if id == did4 && route.is_empty()
+                ));
+                let ev1 = node1.listen_once().await.unwrap();
+                assert_eq!(ev1.relay.path, vec![did1, did2]);
+                assert!(matches!(
+                    ev1.data,
+                    Message::FindSuccessorStepReport(FindSuccessorStepReport{id, step: LookupStep::Next(n)}) if id == did4 && n == did3
+                ));
+
+                // node1 is not connected to node3, so the query is relayed by node2
+                let ev2 = node2.listen_once().await.unwrap();
+                assert_eq!(ev2.relay.path, vec![did1]);
+                assert!(matches!(
+                    ev2.data,
+                    Message::FindSuccessorStepSend(FindSuccessorStepSend{id, ref route}) if id == did4 && route == &vec![did2]
+                ));
+                let ev3 = node3.listen_once().await.unwrap();
+                assert_eq!(ev3.relay.path, vec![did1, did2]);
+
+                // node3 answer node4 as successor, the report is relayed by node2
+                let ev2 = node2.listen_once().await.unwrap();
+                assert_eq!(ev2.relay.path, vec![did1, did2, did3]);
+                let ev1 = node1.listen_once().await.unwrap();
+                assert!(matches!(
+                    ev1.data,
+                    Message::FindSuccessorStepReport(FindSuccessorStepReport{id, step: LookupStep::Found(n)}) if id == did4 && n == did4
+                ));
+            }
+        );
+        assert_eq!(trace?, LookupTrace {
+            id: did4,
+            successor: did4,
+            path: vec![did1, did2, did3],
+        });
+        assert!(swarm1.pending_requests.is_empty());
+
+        // node2 is not listening, the query is retried once and then timeout
+        let trace = swarm1
+            .find_successor_iterative(did4, Duration::from_millis(500), 1)
+            .await;
+        assert!(matches!(trace, Err(Error::LookupTimeout(n)) if n == did2));
+        assert!(swarm1.pending_requests.is_empty());
+        for _ in 0..2 {
+            let ev2 = node2.listen_once().await.unwrap();
+            assert!(matches!(ev2.data, Message::FindSuccessorStepSend(_)));
+        }
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
 }
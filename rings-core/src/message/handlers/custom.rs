@@ -1,5 +1,8 @@
 use async_trait::async_trait;
 
+use crate::dht::vnode::VNodeType;
+use crate::dht::vnode::VirtualNode;
+use crate::dht::vnode::MAILBOX_TTL_MS;
 use crate::dht::Chord;
 use crate::dht::PeerRingAction;
 use crate::err::Error;
@@ -10,7 +13,10 @@ use crate::message::types::Message;
 use crate::message::HandleMsg;
 use crate::message::MessageHandler;
 use crate::message::MessagePayload;
+use crate::message::MessageRelay;
 use crate::message::PayloadSender;
+use crate::message::TChordStorage;
+use crate::storage::PersistenceStorageReadAndWrite;
 use crate::transports::manager::TransportManager;
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -26,19 +32,238 @@ impl HandleMsg<MaybeEncrypted<CustomMessage>> for MessageHandler {
         if self.dht.id != relay.destination {
             if self.swarm.get_transport(relay.destination).is_some() {
                 relay.relay(self.dht.id, Some(relay.destination))?;
-                return self.transpond_payload(ctx, relay).await;
+                return self.transpond_or_keep(ctx, relay).await;
             } else {
                 let next_node = match self.dht.find_successor(relay.destination)? {
+                    // destination is in range (self, successor] but not connected,
+                    // it's offline, keep the message in it's mailbox, see `replay_mailbox`
+                    PeerRingAction::Some(node) if node != relay.destination => {
+                        let vnode: VirtualNode = ctx.clone().try_into()?;
+                        return self.swarm.storage_store_nowait(vnode).await;
+                    }
                     PeerRingAction::Some(node) => Some(node),
                     PeerRingAction::RemoteAction(node, _) => Some(node),
                     _ => None,
                 }
                 .ok_or(Error::MessageHandlerMissNextNode)?;
                 relay.relay(self.dht.id, Some(next_node))?;
-                return self.transpond_payload(ctx, relay).await;
+                return self.transpond_or_keep(ctx, relay).await;
             }
         }
 
         Ok(())
     }
 }
+
+impl MessageHandler {
+    /// Relay a custom message, if it cannot be sent to next hop,
+    /// keep it in mailbox of destination, see `replay_mailbox`.
+    async fn transpond_or_keep(
+        &self,
+        ctx: &MessagePayload<Message>,
+        relay: MessageRelay,
+    ) -> Result<()> {
+        if let Err(e) = self.transpond_payload(ctx, relay).await {
+            tracing::warn!(
+                "failed to relay message {} to {:?}, keep it in mailbox: {}",
+                ctx.tx_id,
+                ctx.relay.destination,
+                e
+            );
+            let vnode: VirtualNode = ctx.clone().try_into()?;
+            return self.swarm.storage_store_nowait(vnode).await;
+        }
+        Ok(())
+    }
+
+    /// Replay messages in mailbox, which are stored by predecessor while this node is offline.
+    /// The mailbox is handed over to this node once it joins, see `sync_with_successor`.
+    /// Messages are verified as received ones, except that they are alive in `MAILBOX_TTL_MS`
+    /// regardless of their ttl. Delivered, invalid and expired messages are removed from mailbox.
+    pub async fn replay_mailbox(&self) -> Result<()> {
+        let address = VirtualNode::mailbox_address(self.dht.id);
+        let vnode: VirtualNode = match self.dht.storage.get(&address).await {
+            Ok(v) if v.kind == VNodeType::RelayMessage => v,
+            _ => return Ok(()),
+        };
+        let mut remains = vec![];
+        for data in vnode.data.iter() {
+            match data.decode::<MessagePayload<Message>>() {
+                Ok(payload) if payload.relay.destination == self.dht.id => {
+                    match self.verifier.check_stored(&payload, MAILBOX_TTL_MS) {
+                        Ok(()) => self.invoke_callback(&payload).await?,
+                        Err(e) => tracing::warn!(
+                            "[replay_mailbox] drop message {} in mailbox: {}",
+                            payload.tx_id,
+                            e
+                        ),
+                    }
+                }
+                // not a message to this node, keep it
+                _ => remains.push(data.clone()),
+            }
+        }
+        if remains.len() == vnode.data.len() {
+            Ok(())
+        } else if remains.is_empty() {
+            self.dht.remove_vnode(&address).await
+        } else {
+            self.dht
+                .put_vnode(&VirtualNode {
+                    data: remains,
+                    ..vnode
+                })
+                .await
+        }
+    }
+
+    /// Replay mailbox if it's stored to this node.
+    pub(crate) async fn replay_mailbox_if_stored(&self, vnodes: &[VirtualNode]) {
+        let address = VirtualNode::mailbox_address(self.dht.id);
+        if vnodes.iter().any(|v| v.did() == address) {
+            if let Err(e) = self.replay_mailbox().await {
+                tracing::warn!("[replay_mailbox] failed to replay mailbox: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+
+    use super::*;
+    use crate::ecc::tests::gen_ordered_keys;
+    use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
+    use crate::message::types::JoinDHT;
+    use crate::message::types::SyncVNodeWithSuccessor;
+    use crate::message::CallbackFn;
+    use crate::message::MessageCallback;
+    use crate::storage::PersistenceStorageOperation;
+    use crate::tests::default::prepare_node;
+    use crate::tests::manually_establish_connection;
+
+    #[derive(Clone, Default)]
+    struct MailboxCallback {
+        delivered: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    #[async_trait]
+    impl MessageCallback for MailboxCallback {
+        async fn custom_message(
+            &self,
+            handler: &MessageHandler,
+            _ctx: &MessagePayload<Message>,
+            msg: &MaybeEncrypted<CustomMessage>,
+        ) {
+            let msg = handler.decrypt_msg(msg).unwrap();
+            self.delivered.lock().await.push(msg.0);
+        }
+
+        async fn builtin_message(&self, _handler: &MessageHandler, _ctx: &MessagePayload<Message>) {
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mailbox() -> Result<()> {
+        let keys = gen_ordered_keys(3);
+        let (key1, key2, key3) = (keys[0], keys[1], keys[2]);
+        let (did1, dht1, swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, swarm2, _node2, _path2) = prepare_node(key2).await;
+        let (did3, _dht3, swarm3, node3, _path3) = prepare_node(key3).await;
+        let callback = MailboxCallback::default();
+        let cb: CallbackFn = Box::new(callback.clone());
+        let node2 = swarm2.create_message_handler(Some(cb), None);
+        test_only_two_nodes_establish_connection(&node1, &node3).await?;
+        let mailbox = VirtualNode::mailbox_address(did2);
+
+        // node2 is offline, and it's in range (node1, node3],
+        // so the message relayed by node1 is kept in mailbox of node2 on node1
+        swarm3
+            .send_message(
+                Message::custom("hello mailbox".as_bytes(), None)?,
+                did1,
+                did2,
+            )
+            .await?;
+        let ev = node1.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did3);
+        assert!(matches!(ev.data, Message::CustomMessage(_)));
+        assert_eq!(dht1.storage.get(&mailbox).await?.data.len(), 1);
+
+        // an expired message is dropped when it is merged into mailbox
+        let mut expired = MessagePayload::new_send(
+            Message::custom("expired".as_bytes(), None)?,
+            swarm3.session_manager(),
+            did1,
+            did2,
+        )?;
+        expired.origin_verification.ts_ms -= MAILBOX_TTL_MS + 1;
+        swarm1.storage_store(expired.try_into()?).await?;
+        assert_eq!(dht1.storage.get(&mailbox).await?.data.len(), 1);
+
+        // a forged message is kept, but dropped on replay
+        let mut forged = MessagePayload::new_send(
+            Message::custom("hello".as_bytes(), None)?,
+            swarm3.session_manager(),
+            did1,
+            did2,
+        )?;
+        forged.data = Message::custom("forged".as_bytes(), None)?;
+        swarm1.storage_store(forged.try_into()?).await?;
+        assert_eq!(dht1.storage.get(&mailbox).await?.data.len(), 2);
+
+        // node2 joins, and it's mailbox is handed over by node1
+        manually_establish_connection(&swarm1, &swarm2).await?;
+        let ev = node1.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::JoinDHT(JoinDHT{id}) if id == did2));
+        assert_eq!(dht1.storage.count().await?, 0);
+        let ev = node2.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::JoinDHT(JoinDHT{id}) if id == did1));
+        assert!(callback.delivered.lock().await.is_empty());
+
+        // node2 replays it's mailbox
+        let ev = node2.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did1);
+        assert!(
+            matches!(ev.data, Message::SyncVNodeWithSuccessor(SyncVNodeWithSuccessor{ref data}) if data[0].did() == mailbox)
+        );
+        assert_eq!(callback.delivered.lock().await.as_slice(), &[
+            "hello mailbox".as_bytes().to_vec()
+        ]);
+
+        // delivered messages are removed from mailbox
+        assert!(dht2.storage.get(&mailbox).await.is_err());
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_large_custom_message() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (_did1, _dht1, _swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, _dht2, _swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+
+        // larger than a chunk of data channel, it's split and reassembled by transports
+        let data: Vec<u8> = (0..256 * 1024).map(|_| rand::random::<u8>()).collect();
+        node1
+            .send_direct_message(Message::custom(&data, None)?, did2)
+            .await?;
+        let ev = node2.listen_once().await.unwrap();
+        match ev.data {
+            Message::CustomMessage(MaybeEncrypted::Plain(CustomMessage(msg))) => {
+                assert_eq!(msg, data)
+            }
+            _ => panic!("unexpected message {:?}", ev.data),
+        }
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+}
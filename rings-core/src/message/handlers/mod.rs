@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_recursion::async_recursion;
 use async_trait::async_trait;
@@ -9,6 +10,8 @@ use super::Message;
 use super::MessagePayload;
 use super::OriginVerificationGen;
 use super::PayloadSender;
+use super::PayloadVerifier;
+use super::PendingRequests;
 use crate::dht::Did;
 use crate::dht::PeerRing;
 use crate::err::Error;
@@ -20,10 +23,16 @@ use crate::swarm::Swarm;
 pub mod connection;
 /// Operator and Handler for CustomMessage
 pub mod custom;
+/// Handler for Ping and Pong, which measure RTT of connected nodes
+pub mod ping;
 /// Operator and handler for DHT stablization
 pub mod stabilization;
+/// Handler for query of chord state, which is used to inspect the ring
+pub mod status;
 /// Operator and Handler for Storage
 pub mod storage;
+/// Handler for frames of streams
+pub mod stream;
 /// Operator and Handler for SubRing
 pub mod subring;
 
@@ -67,6 +76,10 @@ pub struct MessageHandler {
     swarm: Arc<Swarm>,
     callback: Arc<Option<CallbackFn>>,
     validator: Arc<Option<ValidatorFn>>,
+    /// requests waiting for report, shared with swarm
+    pending: Arc<PendingRequests>,
+    /// verifier of received payloads, shared with swarm
+    verifier: Arc<PayloadVerifier>,
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -83,6 +96,8 @@ impl MessageHandler {
     ) -> Self {
         Self {
             dht: swarm.dht(),
+            pending: swarm.pending_requests(),
+            verifier: swarm.payload_verifier(),
             swarm,
             callback: Arc::new(callback),
             validator: Arc::new(validator),
@@ -113,6 +128,16 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Send `msg` to `destination`, and wait for the report of it.
+    pub async fn send_and_wait(
+        &self,
+        msg: Message,
+        destination: Did,
+        timeout: Duration,
+    ) -> Result<MessagePayload<Message>> {
+        self.swarm.send_and_wait(msg, destination, timeout).await
+    }
+
     pub fn decrypt_msg(&self, msg: &MaybeEncrypted<CustomMessage>) -> Result<CustomMessage> {
         let key = self.swarm.session_manager().session_key()?;
         let (decrypt_msg, _) = msg.to_owned().decrypt(key)?;
@@ -130,7 +155,7 @@ impl MessageHandler {
 
         self.validate(payload).await?;
 
-        match &payload.data {
+        let result = match &payload.data {
             Message::JoinDHT(ref msg) => self.handle(payload, msg).await,
             Message::LeaveDHT(ref msg) => self.handle(payload, msg).await,
             Message::ConnectNodeSend(ref msg) => self.handle(payload, msg).await,
@@ -138,12 +163,36 @@ impl MessageHandler {
             Message::AlreadyConnected(ref msg) => self.handle(payload, msg).await,
             Message::FindSuccessorSend(ref msg) => self.handle(payload, msg).await,
             Message::FindSuccessorReport(ref msg) => self.handle(payload, msg).await,
+            Message::FindSuccessorStepSend(ref msg) => self.handle(payload, msg).await,
+            Message::FindSuccessorStepReport(ref msg) => self.handle(payload, msg).await,
             Message::NotifyPredecessorSend(ref msg) => self.handle(payload, msg).await,
             Message::NotifyPredecessorReport(ref msg) => self.handle(payload, msg).await,
             Message::SearchVNode(ref msg) => self.handle(payload, msg).await,
             Message::FoundVNode(ref msg) => self.handle(payload, msg).await,
             Message::StoreVNode(ref msg) => self.handle(payload, msg).await,
+            Message::StoredVNode(ref msg) => self.handle(payload, msg).await,
+            Message::StoreVNodeRejected(ref msg) => self.handle(payload, msg).await,
+            Message::ReplicateVNode(ref msg) => self.handle(payload, msg).await,
+            Message::SearchVNodeReplica(ref msg) => self.handle(payload, msg).await,
+            Message::SyncVNodeWithSuccessor(ref msg) => self.handle(payload, msg).await,
+            Message::JoinSubRing(ref msg) => self.handle(payload, msg).await,
+            Message::LeaveSubRing(ref msg) => self.handle(payload, msg).await,
+            Message::KickSubRing(ref msg) => self.handle(payload, msg).await,
+            Message::ListSubRing(ref msg) => self.handle(payload, msg).await,
+            Message::SubRingMembers(ref msg) => self.handle(payload, msg).await,
+            Message::SubRingBroadcast(ref msg) => self.handle(payload, msg).await,
+            Message::SubRingMulticast(ref msg) => self.handle(payload, msg).await,
             Message::CustomMessage(ref msg) => self.handle(payload, msg).await,
+            Message::Ping(ref msg) => self.handle(payload, msg).await,
+            Message::Pong(ref msg) => self.handle(payload, msg).await,
+            Message::DHTStatusSend(ref msg) => self.handle(payload, msg).await,
+            Message::DHTStatusReport(ref msg) => self.handle(payload, msg).await,
+            Message::ScanVNode(ref msg) => self.handle(payload, msg).await,
+            Message::FoundVNodePage(ref msg) => self.handle(payload, msg).await,
+            Message::OpenStream(ref msg) => self.handle(payload, msg).await,
+            Message::StreamData(ref msg) => self.handle(payload, msg).await,
+            Message::StreamAck(ref msg) => self.handle(payload, msg).await,
+            Message::CloseStream(ref msg) => self.handle(payload, msg).await,
             Message::MultiCall(ref msg) => {
                 for message in msg.messages.iter().cloned() {
                     let payload = MessagePayload::new(
@@ -156,11 +205,11 @@ impl MessageHandler {
                 }
                 Ok(())
             }
-            x => Err(Error::MessageHandlerUnsupportMessageType(format!(
-                "{:?}",
-                x
-            ))),
-        }?;
+        };
+
+        // wake up the request waiting for this report, even if handler failed
+        self.pending.resolve(self.dht.id, payload);
+        result?;
 
         if let Err(e) = self.invoke_callback(payload).await {
             tracing::warn!("invoke callback error: {}", e);
@@ -169,12 +218,34 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Check a received payload by verifier of swarm, see `PayloadVerifier`.
+    /// Return false if it should be dropped without handling.
+    /// Formats advertised by the sender are learned from accepted payloads.
+    fn verify_payload(&self, payload: &MessagePayload<Message>) -> bool {
+        match self.verifier.check(payload) {
+            Ok(()) => {
+                self.swarm.learn_payload_format(payload);
+                true
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Drop payload {} from {:?}: {}",
+                    payload.tx_id,
+                    payload.addr,
+                    e
+                );
+                false
+            }
+        }
+    }
+
     /// This method is required because web-sys components is not `Send`
     /// which means a listening loop cannot running concurrency.
+    /// The polled payload is returned, even if it's dropped by verification.
     pub async fn listen_once(&self) -> Option<MessagePayload<Message>> {
         if let Some(payload) = self.swarm.poll_message().await {
-            if !payload.verify() {
-                tracing::error!("Cannot verify msg or it's expired: {:?}", payload);
+            if !self.verify_payload(&payload) {
+                return Some(payload);
             }
             if let Err(e) = self.handle_payload(&payload).await {
                 tracing::error!("Error in handle_message: {}", e);
@@ -205,27 +276,56 @@ impl PayloadSender<Message> for MessageHandler {
 #[cfg(not(feature = "wasm"))]
 mod listener {
     use std::sync::Arc;
+    use std::time::Duration;
 
     use async_trait::async_trait;
+    use futures::future::poll_fn;
+    use futures::future::FutureExt;
     use futures::pin_mut;
+    use futures::select;
     use futures::stream::StreamExt;
+    use futures_timer::Delay;
 
     use super::MessageHandler;
+    use crate::message::STREAM_TICK;
     use crate::types::message::MessageListener;
+    use crate::utils;
 
     #[async_trait]
     impl MessageListener for MessageHandler {
+        /// Handle received payloads, and drive byte streams every `STREAM_TICK`,
+        /// or once frames are written to them.
         async fn listen(self: Arc<Self>) {
             let payloads = self.swarm.iter_messages().await;
             pin_mut!(payloads);
-            while let Some(payload) = payloads.next().await {
-                if !payload.verify() {
-                    tracing::error!("Cannot verify msg or it's expired: {:?}", payload);
-                    continue;
+            let mut drive_at = utils::get_epoch_ms();
+            loop {
+                let now = utils::get_epoch_ms();
+                if now >= drive_at {
+                    self.swarm.drive_streams().await;
+                    drive_at = now + STREAM_TICK.as_millis();
                 }
-                if let Err(e) = self.handle_payload(&payload).await {
-                    tracing::error!("Error in handle_message: {}", e);
-                    continue;
+                let tick = Delay::new(Duration::from_millis((drive_at - now) as u64)).fuse();
+                pin_mut!(tick);
+                let outgoing = poll_fn(|cx| self.swarm.streams.poll_outgoing(cx)).fuse();
+                pin_mut!(outgoing);
+                select! {
+                    payload = payloads.next().fuse() => {
+                        let payload = match payload {
+                            Some(payload) => payload,
+                            None => break,
+                        };
+                        if !self.verify_payload(&payload) {
+                            continue;
+                        }
+                        if let Err(e) = self.handle_payload(&payload).await {
+                            tracing::error!("Error in handle_message: {}", e);
+                        }
+                    }
+                    _ = tick => {}
+                    _ = outgoing => {
+                        self.swarm.drive_streams().await;
+                    }
                 }
             }
         }
@@ -248,9 +348,14 @@ mod listener {
         async fn listen(self: Arc<Self>) {
             let handler = Arc::clone(&self);
             let func = move || {
-                let handler = handler.clone();
+                let h = handler.clone();
+                spawn_local(Box::pin(async move {
+                    h.listen_once().await;
+                }));
+                // byte streams are driven every round, see `Swarm::drive_streams`
+                let h = handler.clone();
                 spawn_local(Box::pin(async move {
-                    handler.listen_once().await;
+                    h.swarm.drive_streams().await;
                 }));
             };
             poll!(func, 1000);
@@ -267,8 +372,13 @@ pub mod tests {
 
     use super::*;
     use crate::dht::Did;
+    use crate::ecc::tests::gen_ordered_keys;
     use crate::ecc::SecretKey;
+    use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
+    use crate::message::types::Ping;
+    use crate::message::types::Pong;
     use crate::message::MessageHandler;
+    use crate::message::VerificationStats;
     use crate::tests::default::prepare_node;
     use crate::tests::manually_establish_connection;
     use crate::types::message::MessageListener;
@@ -384,4 +494,51 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_drop_forged_and_replayed_payloads() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (_did1, _dht1, swarm1, node1, _path1) = prepare_node(keys[0]).await;
+        let (did2, _dht2, swarm2, node2, _path2) = prepare_node(keys[1]).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        let accepted = swarm2.verification_stats().accepted;
+
+        let ping = |ts_ms| {
+            MessagePayload::new_direct(
+                Message::Ping(Ping { ts_ms }),
+                swarm1.session_manager(),
+                did2,
+            )
+        };
+        let payload = ping(1)?;
+        swarm1.do_send_payload(did2, payload.clone()).await?;
+        node2.listen_once().await.unwrap();
+
+        // replayed payload, and payload with forged signature are dropped
+        swarm1.do_send_payload(did2, payload.clone()).await?;
+        let ev = node2.listen_once().await.unwrap();
+        assert_eq!(ev.tx_id, payload.tx_id);
+        let mut forged = ping(2)?;
+        forged.data = Message::Ping(Ping { ts_ms: 3 });
+        swarm1.do_send_payload(did2, forged).await?;
+        node2.listen_once().await.unwrap();
+
+        // only the first ping and the last one are answered
+        swarm1.do_send_payload(did2, ping(4)?).await?;
+        node2.listen_once().await.unwrap();
+        for ts_ms in [1, 4] {
+            let ev = node1.listen_once().await.unwrap();
+            assert_eq!(ev.addr, did2);
+            assert!(matches!(ev.data, Message::Pong(Pong { ts_ms: t }) if t == ts_ms));
+        }
+        assert_eq!(swarm2.verification_stats(), VerificationStats {
+            accepted: accepted + 2,
+            invalid: 1,
+            replayed: 1,
+            ..Default::default()
+        });
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
 }
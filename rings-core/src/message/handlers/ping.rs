@@ -0,0 +1,89 @@
+use async_trait::async_trait;
+
+use crate::err::Result;
+use crate::message::types::Message;
+use crate::message::types::Ping;
+use crate::message::types::Pong;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<Ping> for MessageHandler {
+    /// Answer ping with the timestamp it carries, so the sender can measure RTT by it's own clock.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &Ping) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        self.send_report_message(Message::Pong(Pong { ts_ms: msg.ts_ms }), ctx.tx_id, relay)
+            .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<Pong> for MessageHandler {
+    /// A pong is consumed by the probe waiting for it, see `Stabilization::probe`,
+    /// the one which is not waited for is ignored.
+    async fn handle(&self, ctx: &MessagePayload<Message>, _: &Pong) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ecc::tests::gen_ordered_keys;
+    use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
+    use crate::tests::default::prepare_node;
+    use crate::utils;
+
+    #[tokio::test]
+    async fn test_unsolicited_pong_is_ignored() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (did1, dht1, _swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, _dht2, _swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        assert_eq!(dht1.latency.get(&did2), None);
+
+        // ping is answered with the timestamp it carries
+        let ts_ms = utils::get_epoch_ms();
+        node1
+            .send_direct_message(Message::Ping(Ping { ts_ms }), did2)
+            .await?;
+        let ev = node2.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did1);
+        assert!(matches!(ev.data, Message::Ping(_)));
+        let ev = node1.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did2);
+        assert!(matches!(ev.data, Message::Pong(Pong { ts_ms: t }) if t == ts_ms));
+
+        // but the pong is not waited for, so it's not a sample of RTT,
+        // neither is a pong with forged timestamp
+        node2
+            .send_direct_message(Message::Pong(Pong { ts_ms: 0 }), did1)
+            .await?;
+        let ev = node1.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::Pong(_)));
+        assert_eq!(dht1.latency.get(&did2), None);
+
+        // rtt of a disconnected node is forgotten
+        dht1.record_rtt(did2, 10)?;
+        assert_eq!(dht1.latency.get(&did2), Some(10));
+        dht1.remove(did2)?;
+        assert_eq!(dht1.latency.get(&did2), None);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+}
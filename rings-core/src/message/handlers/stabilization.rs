@@ -8,11 +8,13 @@ use crate::err::Result;
 use crate::message::types::Message;
 use crate::message::types::NotifyPredecessorReport;
 use crate::message::types::NotifyPredecessorSend;
+use crate::message::types::StoreVNode;
 use crate::message::types::SyncVNodeWithSuccessor;
 use crate::message::HandleMsg;
 use crate::message::MessageHandler;
 use crate::message::MessagePayload;
 use crate::message::PayloadSender;
+use crate::message::TChordStorage;
 use crate::transports::manager::TransportManager;
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -27,7 +29,29 @@ impl HandleMsg<NotifyPredecessorSend> for MessageHandler {
         let predecessor = { *self.dht.lock_predecessor()? };
 
         relay.relay(self.dht.id, None)?;
-        self.dht.notify(msg.id)?;
+        // predecessor is leaving the ring, and introduces its own predecessor to us,
+        // forget the leaving node so that the introduced one can be accepted
+        if predecessor == Some(relay.origin()) && msg.id != relay.origin() {
+            self.dht.remove(relay.origin())?;
+        }
+        if let Some(new_predecessor) = self.dht.notify(msg.id)? {
+            // the new predecessor is responsible for replicas in range (predecessor, self]
+            if let PeerRingAction::RemoteAction(
+                target,
+                PeerRingRemoteAction::PromoteReplica(data),
+            ) = self.dht.promote_replicas(new_predecessor)?
+            {
+                if self.swarm.get_and_check_transport(target).await.is_some() {
+                    self.send_direct_message(Message::StoreVNode(StoreVNode { data }), target)
+                        .await?;
+                } else {
+                    // the introduced predecessor may not be connected yet, route by DHT
+                    for vnode in data {
+                        self.swarm.storage_store_nowait(vnode).await?;
+                    }
+                }
+            }
+        }
         if let Some(id) = predecessor {
             if id != relay.origin() {
                 return self
@@ -48,9 +72,16 @@ impl HandleMsg<NotifyPredecessorSend> for MessageHandler {
 impl HandleMsg<NotifyPredecessorReport> for MessageHandler {
     async fn handle(
         &self,
-        _ctx: &MessagePayload<Message>,
+        ctx: &MessagePayload<Message>,
         msg: &NotifyPredecessorReport,
     ) -> Result<()> {
+        // a report which is not originated from us is sent by a leaving successor,
+        // which introduces its own successor to us, forget the leaving node
+        let origin = ctx.relay.origin();
+        let successor = { self.dht.lock_successor()?.min() };
+        if origin != self.dht.id && origin == successor && msg.id != origin {
+            self.dht.remove(origin)?;
+        }
         // if successor: predecessor is between (id, successor]
         // then update local successor
         if self.swarm.get_and_check_transport(msg.id).await.is_none() && msg.id != self.swarm.did()
@@ -90,6 +121,7 @@ mod test {
     use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
     use crate::swarm::Swarm;
     use crate::tests::default::prepare_node;
+    use crate::tests::default::prepare_node_with_failure_detector;
     use crate::tests::manually_establish_connection;
 
     #[tokio::test]
@@ -525,4 +557,67 @@ mod test {
     async fn run_stabilize_once(swarm: Arc<Swarm>) -> Result<()> {
         Stabilization::new(swarm, 5).stabilize().await
     }
+
+    #[tokio::test]
+    async fn test_probe_evicts_unresponsive_node() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (_did1, dht1, swarm1, node1, _path1) =
+            prepare_node_with_failure_detector(key1, 200, 2).await;
+        let (did2, _dht2, _swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        assert!(dht1.lock_successor()?.list().contains(&did2));
+        let stabilization = Stabilization::new(swarm1.clone(), 5);
+
+        // node2 answers the probe
+        let (probed, ping, pong) = tokio::join!(
+            stabilization.probe_peers(),
+            node2.listen_once(),
+            node1.listen_once()
+        );
+        probed?;
+        assert!(matches!(ping.unwrap().data, Message::Ping(_)));
+        assert!(matches!(pong.unwrap().data, Message::Pong(_)));
+        assert_eq!(dht1.failure_detector.suspicion(&did2), 0);
+        // rtt is measured by the probe
+        assert!(dht1.latency.get(&did2).is_some());
+
+        // node2 is suspected after it fails a probe
+        stabilization.probe_peers().await?;
+        assert_eq!(dht1.failure_detector.suspicion(&did2), 1);
+        assert!(dht1.lock_successor()?.list().contains(&did2));
+
+        // and it's evicted after it fails max_suspicion probes in a row
+        stabilization.probe_peers().await?;
+        assert!(!dht1.lock_successor()?.list().contains(&did2));
+        assert!(!dht1.lock_finger()?.contains(&Some(did2)));
+        assert_eq!(*dht1.lock_predecessor()?, None);
+        assert!(swarm1.get_transport(did2).is_none());
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_round_duration_counts_probing() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (_did1, _dht1, swarm1, node1, _path1) =
+            prepare_node_with_failure_detector(key1, 200, 3).await;
+        let (_did2, _dht2, _swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        let stabilization = Stabilization::new(swarm1.clone(), 5);
+
+        // node2 never answers, so the probe waits for timeout of failure detector
+        assert!(stabilization.tick().await);
+        let metrics = stabilization.metrics();
+        assert_eq!(metrics.rounds, 1);
+        assert!(metrics.last_round_ms >= 200);
+        // and next round is not due until min interval is passed after probing
+        assert!(!stabilization.tick().await);
+        assert_eq!(stabilization.metrics().rounds, 1);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
 }
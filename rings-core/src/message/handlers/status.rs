@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+
+use crate::dht::Chord;
+use crate::dht::PeerRingAction;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::types::DHTStatusReport;
+use crate::message::types::DHTStatusSend;
+use crate::message::types::Message;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::transports::manager::TransportManager;
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<DHTStatusSend> for MessageHandler {
+    /// Answer with chord state of this node, or forward the query to destination.
+    async fn handle(&self, ctx: &MessagePayload<Message>, _: &DHTStatusSend) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        if self.dht.id != relay.destination {
+            let next_node = if self.swarm.get_transport(relay.destination).is_some() {
+                relay.destination
+            } else {
+                match self.dht.find_successor(relay.destination)? {
+                    // destination is not on the ring, otherwise it's the successor
+                    PeerRingAction::Some(node) if node != relay.destination => {
+                        return Err(Error::MessageHandlerMissNextNode);
+                    }
+                    PeerRingAction::Some(node) => node,
+                    PeerRingAction::RemoteAction(node, _) => node,
+                    _ => return Err(Error::MessageHandlerMissNextNode),
+                }
+            };
+            relay.relay(self.dht.id, Some(next_node))?;
+            return self.transpond_payload(ctx, relay).await;
+        }
+
+        let status = self.dht.status().await?;
+        relay.relay(self.dht.id, None)?;
+        self.send_report_message(
+            Message::DHTStatusReport(DHTStatusReport { status }),
+            ctx.tx_id,
+            relay,
+        )
+        .await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<DHTStatusReport> for MessageHandler {
+    /// The report is delivered to the waiting request, see `PendingRequests`.
+    async fn handle(&self, ctx: &MessagePayload<Message>, _: &DHTStatusReport) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dht::RingSnapshot;
+    use crate::ecc::tests::gen_ordered_keys;
+    use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
+    use crate::tests::default::prepare_node;
+
+    #[tokio::test]
+    async fn test_query_dht_status() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (did1, dht1, _swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, _swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        *dht1.lock_predecessor()? = Some(did2);
+        *dht2.lock_predecessor()? = Some(did1);
+
+        node1
+            .send_direct_message(Message::DHTStatusSend(DHTStatusSend {}), did2)
+            .await?;
+        let ev = node2.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did1);
+        assert!(matches!(ev.data, Message::DHTStatusSend(_)));
+
+        let ev = node1.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did2);
+        let status = match ev.data {
+            Message::DHTStatusReport(DHTStatusReport { status }) => status,
+            _ => panic!("unexpected message {:?}", ev.data),
+        };
+        assert_eq!(status, dht2.status().await?);
+        assert_eq!(status.did, did2);
+        assert_eq!(status.predecessor, Some(did1));
+        assert_eq!(status.successors, vec![did1]);
+        assert_eq!(status.storage_count, 0);
+
+        // two nodes make a consistent ring
+        let mut snapshot = RingSnapshot {
+            nodes: vec![dht1.status().await?],
+            ..Default::default()
+        };
+        assert_eq!(snapshot.next(), Some(did2));
+        snapshot.nodes.push(status);
+        assert_eq!(snapshot.next(), None);
+        assert!(snapshot.closed);
+        assert_eq!(snapshot.check(), vec![]);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+}
@@ -1,21 +1,32 @@
 use async_trait::async_trait;
 
+use crate::dht::record::Record;
 use crate::dht::vnode::VirtualNode;
 use crate::dht::ChordStorage;
 use crate::dht::Did;
 use crate::dht::PeerRingAction;
 use crate::dht::PeerRingRemoteAction;
+use crate::dht::VNodePage;
+use crate::dht::VNodeRange;
 use crate::err::Error;
 use crate::err::Result;
 use crate::message::types::FoundVNode;
+use crate::message::types::FoundVNodePage;
 use crate::message::types::Message;
+use crate::message::types::ReplicateVNode;
+use crate::message::types::ScanVNode;
 use crate::message::types::SearchVNode;
+use crate::message::types::SearchVNodeReplica;
 use crate::message::types::StoreVNode;
+use crate::message::types::StoreVNodeRejected;
+use crate::message::types::StoredVNode;
 use crate::message::types::SyncVNodeWithSuccessor;
 use crate::message::HandleMsg;
 use crate::message::MessageHandler;
 use crate::message::MessagePayload;
+use crate::message::OriginVerificationGen;
 use crate::message::PayloadSender;
+use crate::message::DEFAULT_REQUEST_TIMEOUT;
 use crate::swarm::Swarm;
 
 /// TChordStorage should imply necessary method for DHT storage
@@ -24,10 +35,19 @@ use crate::swarm::Swarm;
 pub trait TChordStorage {
     /// check local cache of dht
     async fn storage_check_cache(&self, id: &Did) -> Option<VirtualNode>;
-    /// fetch virtual node from DHT
-    async fn storage_fetch(&self, id: &Did) -> Result<()>;
-    /// store virtual node on DHT
+    /// fetch virtual node from DHT, None if it's not found.
+    /// If it's not stored locally, the caller is blocked until the report is received,
+    /// for at most `DEFAULT_REQUEST_TIMEOUT`, and it fails with timeout after that.
+    async fn storage_fetch(&self, id: &Did) -> Result<Option<VirtualNode>>;
+    /// store virtual node on DHT.
+    /// If it's stored on remote, the caller is blocked until the report of storing node is
+    /// received, for at most `DEFAULT_REQUEST_TIMEOUT`, and it fails if the vnode is
+    /// refused, such as with `Error::StorageQuotaExceeded`.
     async fn storage_store(&self, vnode: VirtualNode) -> Result<()>;
+    /// copy vnodes to replica nodes, the actions are generated by `PeerRing`
+    async fn storage_replicate(&self, action: PeerRingAction) -> Result<()>;
+    /// scan virtual nodes in range, at most `range.limit` vnodes are returned in a page
+    async fn storage_scan(&self, range: VNodeRange) -> Result<VNodePage>;
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -39,26 +59,227 @@ impl TChordStorage for Swarm {
     }
 
     /// Fetch virtual node, if exist in localstoreage, copy it to the cache,
-    /// else Query Remote Node
-    async fn storage_fetch(&self, id: &Did) -> Result<()> {
+    /// else Query Remote Node and wait for `FoundVNode`, at most `DEFAULT_REQUEST_TIMEOUT`
+    /// for each request.
+    async fn storage_fetch(&self, id: &Did) -> Result<Option<VirtualNode>> {
         // If peer found that data is on it's localstore, copy it to the cache
-        match self.dht.lookup(id).await? {
+        let (next, msg) = match self.dht.lookup(id).await? {
             PeerRingAction::SomeVNode(v) => {
-                self.dht.cache(v);
-                Ok(())
+                self.dht.cache(v.clone());
+                return Ok(Some(v));
+            }
+            PeerRingAction::None => return Ok(None),
+            PeerRingAction::RemoteAction(next, PeerRingRemoteAction::FindVNodeReplica(id)) => {
+                (next, Message::SearchVNodeReplica(SearchVNodeReplica { id }))
             }
-            PeerRingAction::None => Ok(()),
             PeerRingAction::RemoteAction(next, _) => {
-                self.send_direct_message(Message::SearchVNode(SearchVNode { id: *id }), next)
-                    .await?;
+                (next, Message::SearchVNode(SearchVNode { id: *id }))
+            }
+            act => return Err(Error::PeerRingUnexpectedAction(act)),
+        };
+        let payload = MessagePayload::new_direct(msg, self.session_manager(), next)?;
+        let report = match self
+            .send_payload_and_wait(payload, DEFAULT_REQUEST_TIMEOUT)
+            .await
+        {
+            Ok(report) => report,
+            // next node may be down, search replica on the first node following id
+            Err(e) if self.dht.replication_factor > 0 => {
+                let holder = match self.dht.replica_holder(*id, &[next])? {
+                    Some(holder) => holder,
+                    None => return Err(e),
+                };
+                tracing::debug!("[storage_fetch] search replica on {:?}: {:?}", holder, e);
+                let payload = MessagePayload::new_direct(
+                    Message::SearchVNodeReplica(SearchVNodeReplica { id: *id }),
+                    self.session_manager(),
+                    holder,
+                )?;
+                self.send_payload_and_wait(payload, DEFAULT_REQUEST_TIMEOUT)
+                    .await?
+            }
+            Err(e) => return Err(e),
+        };
+        // FoundVNode is cached by it's handler before the report is resolved
+        match report.data {
+            Message::FoundVNode(FoundVNode { data }) => {
+                Ok(data.into_iter().find(|v| v.did() == *id))
+            }
+            msg => Err(Error::InvalidMessage(format!(
+                "unexpected report of fetching vnode: {}",
+                msg
+            ))),
+        }
+    }
+
+    /// Store VirtualNode, TryInto<VirtualNode> is implementated for alot of types,
+    /// and wait for `StoredVNode` or `StoreVNodeRejected` if it's stored on remote.
+    async fn storage_store(&self, vnode: VirtualNode) -> Result<()> {
+        match self.dht.store(vnode).await? {
+            PeerRingAction::None => Ok(()),
+            PeerRingAction::RemoteAction(target, PeerRingRemoteAction::FindAndStore(vnode)) => {
+                let payload = MessagePayload::new_direct(
+                    Message::StoreVNode(StoreVNode { data: vec![vnode] }),
+                    self.session_manager(),
+                    target,
+                )?;
+                match self
+                    .send_payload_and_wait(payload, DEFAULT_REQUEST_TIMEOUT)
+                    .await?
+                    .data
+                {
+                    Message::StoredVNode(_) => Ok(()),
+                    Message::StoreVNodeRejected(StoreVNodeRejected {
+                        quota: Some(quota), ..
+                    }) => Err(Error::StorageQuotaExceeded(quota)),
+                    Message::StoreVNodeRejected(StoreVNodeRejected { id, reason, .. }) => {
+                        Err(Error::VNodeRejected(id, reason))
+                    }
+                    msg => Err(Error::InvalidMessage(format!(
+                        "unexpected report of storing vnode: {}",
+                        msg
+                    ))),
+                }
+            }
+            act @ PeerRingAction::MultiActions(_) => self.storage_replicate(act).await,
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+
+    /// Send ReplicateVNode to replica nodes
+    async fn storage_replicate(&self, action: PeerRingAction) -> Result<()> {
+        match action {
+            PeerRingAction::None => Ok(()),
+            PeerRingAction::RemoteAction(target, PeerRingRemoteAction::ReplicateVNode(data)) => {
+                self.send_direct_message(
+                    Message::ReplicateVNode(ReplicateVNode { data, full: false }),
+                    target,
+                )
+                .await
+            }
+            PeerRingAction::RemoteAction(target, PeerRingRemoteAction::SyncReplica(data)) => {
+                self.send_direct_message(
+                    Message::ReplicateVNode(ReplicateVNode { data, full: true }),
+                    target,
+                )
+                .await
+            }
+            PeerRingAction::MultiActions(acts) => {
+                for act in acts {
+                    // a missing replica will be repaired by stabilization
+                    if let Err(e) = self.storage_replicate(act).await {
+                        tracing::warn!("[storage_replicate] failed to replicate vnode: {:?}", e);
+                    }
+                }
                 Ok(())
             }
             act => Err(Error::PeerRingUnexpectedAction(act)),
         }
     }
 
-    /// Store VirtualNode, TryInto<VirtualNode> is implementated for alot of types
-    async fn storage_store(&self, vnode: VirtualNode) -> Result<()> {
+    /// Scan vnodes on responsible nodes one by one, from start of range, until the page
+    /// is full or the range is finished. The next page is scanned with `next` of the page.
+    async fn storage_scan(&self, range: VNodeRange) -> Result<VNodePage> {
+        let limit = range.limit.max(1);
+        let mut page = VNodePage {
+            data: vec![],
+            next: Some(range),
+        };
+        while let Some(range) = page.next.take() {
+            if page.data.len() >= limit {
+                page.next = Some(VNodeRange { limit, ..range });
+                break;
+            }
+            let range = VNodeRange {
+                limit: limit - page.data.len(),
+                ..range
+            };
+            let found = match self.dht.scan(&range).await? {
+                PeerRingAction::SomeVNodePage(found) => found,
+                PeerRingAction::RemoteAction(next, PeerRingRemoteAction::ScanVNode(range)) => {
+                    let payload = MessagePayload::new_direct(
+                        Message::ScanVNode(ScanVNode { range }),
+                        self.session_manager(),
+                        next,
+                    )?;
+                    match self
+                        .send_payload_and_wait(payload, DEFAULT_REQUEST_TIMEOUT)
+                        .await?
+                        .data
+                    {
+                        Message::FoundVNodePage(FoundVNodePage { page }) => page,
+                        msg => {
+                            return Err(Error::InvalidMessage(format!(
+                                "unexpected report of scanning vnodes: {}",
+                                msg
+                            )))
+                        }
+                    }
+                }
+                act => return Err(Error::PeerRingUnexpectedAction(act)),
+            };
+            // the cursor should move forward in range, or the scan never ends
+            if let Some(next) = &found.next {
+                if !range.contains(next.start) || next.start == range.start {
+                    return Err(Error::InvalidMessage(format!(
+                        "cursor of scanning vnodes doesn't move forward: {}",
+                        next.start
+                    )));
+                }
+            }
+            // only the cursor is taken from remote, end and type filter of range are kept
+            page.data
+                .extend(found.data.into_iter().filter(|v| range.matches(v)));
+            page.next = found.next.map(|next| VNodeRange {
+                start: next.start,
+                limit,
+                ..range
+            });
+        }
+        Ok(page)
+    }
+}
+
+/// TRecordStorage should imply methods for mutable records on DHT, see `Record`
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+pub trait TRecordStorage {
+    /// put a record signed by this node, it replaces the previous one with same name
+    async fn put_record(&self, name: &str, value: Vec<u8>) -> Result<()>;
+    /// get record by owner and name, None if it's not found or deleted
+    async fn get_record(&self, owner: Did, name: &str) -> Result<Option<Record>>;
+    /// delete a record of this node
+    async fn delete_record(&self, name: &str) -> Result<()>;
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl TRecordStorage for Swarm {
+    async fn put_record(&self, name: &str, value: Vec<u8>) -> Result<()> {
+        self.write_record(name, Some(value)).await
+    }
+
+    async fn get_record(&self, owner: Did, name: &str) -> Result<Option<Record>> {
+        let address = Record::address(&owner, name)?;
+        match self.storage_fetch(&address).await? {
+            Some(vnode) => {
+                let record: Record = vnode.try_into()?;
+                record.verify()?;
+                Ok(Some(record).filter(|r| !r.is_deleted()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_record(&self, name: &str) -> Result<()> {
+        self.write_record(name, None).await
+    }
+}
+
+impl Swarm {
+    /// Store vnode without waiting for the report of storing node, it's used by handlers,
+    /// which should not block the listener that receives the report.
+    pub(crate) async fn storage_store_nowait(&self, vnode: VirtualNode) -> Result<()> {
         match self.dht.store(vnode).await? {
             PeerRingAction::None => Ok(()),
             PeerRingAction::RemoteAction(target, PeerRingRemoteAction::FindAndStore(vnode)) => {
@@ -66,12 +287,49 @@ impl TChordStorage for Swarm {
                     Message::StoreVNode(StoreVNode { data: vec![vnode] }),
                     target,
                 )
-                .await?;
-                Ok(())
+                .await
             }
+            act @ PeerRingAction::MultiActions(_) => self.storage_replicate(act).await,
             act => Err(Error::PeerRingUnexpectedAction(act)),
         }
     }
+
+    /// Sign and store a record, with the next seq of the stored one.
+    /// A deletion is stored as a record without value, so a stale write can still be rejected.
+    async fn write_record(&self, name: &str, value: Option<Vec<u8>>) -> Result<()> {
+        let owner = self.session_manager().authorizer()?;
+        let address = Record::address(&owner, name)?;
+        let seq = match self.storage_fetch(&address).await? {
+            Some(vnode) => Record::try_from(vnode)?.seq + 1,
+            None if value.is_none() => return Ok(()),
+            None => 1,
+        };
+        let record = Record::new(name, value, seq, self.session_manager())?;
+        self.storage_store(record.try_into()?).await
+    }
+}
+
+impl MessageHandler {
+    /// Search replica of id on node `next`, the report is correlated with the search request.
+    async fn search_replica(
+        &self,
+        ctx: &MessagePayload<Message>,
+        next: Did,
+        id: Did,
+    ) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        relay.relay(self.dht.id, Some(next))?;
+        relay.reset_destination(next)?;
+        let mut payload = MessagePayload::new(
+            Message::SearchVNodeReplica(SearchVNodeReplica { id }),
+            self.swarm.session_manager(),
+            OriginVerificationGen::Origin,
+            relay,
+        )?;
+        // report of replica should be correlated with the origin request
+        payload.tx_id = ctx.tx_id;
+        self.send_payload(payload).await
+    }
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -79,12 +337,22 @@ impl TChordStorage for Swarm {
 impl HandleMsg<SearchVNode> for MessageHandler {
     /// Search VNode via successor
     /// If a VNode is storead local, it will response immediately.
+    /// If a VNode is missing on the responsible node, search it in replicas.
+    /// If it's not found at all, response empty `FoundVNode`, so the origin stops waiting.
     async fn handle(&self, ctx: &MessagePayload<Message>, msg: &SearchVNode) -> Result<()> {
         let mut relay = ctx.relay.clone();
 
         match self.dht.lookup(&msg.id).await {
             Ok(action) => match action {
-                PeerRingAction::None => Ok(()),
+                PeerRingAction::None => {
+                    relay.relay(self.dht.id, None)?;
+                    self.send_report_message(
+                        Message::FoundVNode(FoundVNode { data: vec![] }),
+                        ctx.tx_id,
+                        relay,
+                    )
+                    .await
+                }
                 PeerRingAction::SomeVNode(v) => {
                     relay.relay(self.dht.id, None)?;
                     self.send_report_message(
@@ -94,9 +362,21 @@ impl HandleMsg<SearchVNode> for MessageHandler {
                     )
                     .await
                 }
+                PeerRingAction::RemoteAction(next, PeerRingRemoteAction::FindVNodeReplica(id)) => {
+                    self.search_replica(ctx, next, id).await
+                }
                 PeerRingAction::RemoteAction(next, _) => {
                     relay.relay(self.dht.id, Some(next))?;
-                    self.transpond_payload(ctx, relay).await
+                    match self.transpond_payload(ctx, relay).await {
+                        // next node may be down, search replica on the first node following id
+                        Err(e) if self.dht.replication_factor > 0 => {
+                            match self.dht.replica_holder(msg.id, &[next])? {
+                                Some(holder) => self.search_replica(ctx, holder, msg.id).await,
+                                None => Err(e),
+                            }
+                        }
+                        ret => ret,
+                    }
                 }
                 act => Err(Error::PeerRingUnexpectedAction(act)),
             },
@@ -105,6 +385,20 @@ impl HandleMsg<SearchVNode> for MessageHandler {
     }
 }
 
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<SearchVNodeReplica> for MessageHandler {
+    /// Search VNode in local replicas, response empty `FoundVNode` if it's missing.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &SearchVNodeReplica) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        let data = self.dht.fetch_replica(&msg.id).into_iter().collect();
+        relay.relay(self.dht.id, None)?;
+        self.send_report_message(Message::FoundVNode(FoundVNode { data }), ctx.tx_id, relay)
+            .await
+    }
+}
+
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<FoundVNode> for MessageHandler {
@@ -124,25 +418,143 @@ impl HandleMsg<FoundVNode> for MessageHandler {
     }
 }
 
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<ScanVNode> for MessageHandler {
+    /// Response a page of local vnodes in range, if this node is responsible for
+    /// start of range, otherwise forward the scan to it.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &ScanVNode) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        match self.dht.scan(&msg.range).await? {
+            PeerRingAction::SomeVNodePage(page) => {
+                relay.relay(self.dht.id, None)?;
+                self.send_report_message(
+                    Message::FoundVNodePage(FoundVNodePage { page }),
+                    ctx.tx_id,
+                    relay,
+                )
+                .await
+            }
+            PeerRingAction::RemoteAction(next, _) => {
+                relay.relay(self.dht.id, Some(next))?;
+                self.transpond_payload(ctx, relay).await
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<FoundVNodePage> for MessageHandler {
+    /// The page is delivered to the waiting scan, see `TChordStorage::storage_scan`.
+    async fn handle(&self, ctx: &MessagePayload<Message>, _: &FoundVNodePage) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        Ok(())
+    }
+}
+
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<StoreVNode> for MessageHandler {
+    /// Store vnodes or forward them to the responsible node.
+    /// The storing node always reports to the writer, `StoredVNode` once a vnode is stored,
+    /// or `StoreVNodeRejected` if it's refused, such as over storage quota.
     async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StoreVNode) -> Result<()> {
-        let virtual_peer = msg.data.clone();
-        for p in virtual_peer {
-            match self.dht.store(p).await {
-                Ok(action) => match action {
-                    PeerRingAction::None => Ok(()),
-                    PeerRingAction::RemoteAction(next, _) => {
-                        let mut relay = ctx.relay.clone();
-                        relay.reset_destination(next)?;
-                        relay.relay(self.dht.id, Some(next))?;
-                        self.transpond_payload(ctx, relay).await
-                    }
-                    act => Err(Error::PeerRingUnexpectedAction(act)),
-                },
+        for p in msg.data.iter().cloned() {
+            let id = p.did();
+            let stored = match self.dht.store(p).await {
+                Ok(PeerRingAction::None) => Ok(()),
+                Ok(PeerRingAction::RemoteAction(next, _)) => {
+                    let mut relay = ctx.relay.clone();
+                    relay.reset_destination(next)?;
+                    relay.relay(self.dht.id, Some(next))?;
+                    self.transpond_payload(ctx, relay).await?;
+                    continue;
+                }
+                Ok(act @ PeerRingAction::MultiActions(_)) => {
+                    self.swarm.storage_replicate(act).await
+                }
+                Ok(act) => Err(Error::PeerRingUnexpectedAction(act)),
                 Err(e) => Err(e),
-            }?;
+            };
+            let report = match stored {
+                Ok(()) => Message::StoredVNode(StoredVNode { id }),
+                Err(e) => {
+                    tracing::warn!("vnode {} is rejected: {}", id, e);
+                    Message::StoreVNodeRejected(StoreVNodeRejected {
+                        id,
+                        reason: e.to_string(),
+                        quota: match e {
+                            Error::StorageQuotaExceeded(quota) => Some(quota),
+                            _ => None,
+                        },
+                    })
+                }
+            };
+            let mut relay = ctx.relay.clone();
+            relay.relay(self.dht.id, None)?;
+            self.send_report_message(report, ctx.tx_id, relay).await?;
+        }
+        self.replay_mailbox_if_stored(&msg.data).await;
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<StoredVNode> for MessageHandler {
+    /// The report is delivered to the waiting `storage_store` by tx_id.
+    async fn handle(&self, ctx: &MessagePayload<Message>, _: &StoredVNode) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<StoreVNodeRejected> for MessageHandler {
+    /// The report is delivered to the waiting `storage_store` by tx_id, which fails with it.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StoreVNodeRejected) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+
+        tracing::debug!("vnode {} is rejected by storage: {}", msg.id, msg.reason);
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<ReplicateVNode> for MessageHandler {
+    /// Received replicas from predecessors, the previous replica will be overwritten.
+    /// A full copy replaces all replicas from the sender, and vnodes out of replica range
+    /// of the sender are dropped.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &ReplicateVNode) -> Result<()> {
+        let origin = ctx.origin();
+        if msg.full {
+            let keep: Vec<Did> = msg.data.iter().map(|v| v.did()).collect();
+            self.dht.prune_replicas(origin, &keep);
+        }
+        for data in msg.data.iter().cloned() {
+            if let Err(e) = self.dht.store_replica(origin, data).await {
+                tracing::warn!("drop replica from {:?}: {}", origin, e);
+            }
         }
         Ok(())
     }
@@ -151,30 +563,30 @@ impl HandleMsg<StoreVNode> for MessageHandler {
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<SyncVNodeWithSuccessor> for MessageHandler {
-    // received remote sync vnode request
+    /// Received vnodes handed over by predecessor.
+    /// If a vnode is already stored locally, `PeerRing::store` will merge them via
+    /// `VirtualNode::concat`, otherwise the vnode is forwarded to it's successor.
     async fn handle(
         &self,
         _ctx: &MessagePayload<Message>,
         msg: &SyncVNodeWithSuccessor,
     ) -> Result<()> {
         for data in msg.data.iter().cloned() {
-            // only simply store here
-            match self.dht.store(data).await {
-                Ok(PeerRingAction::None) => Ok(()),
-                Ok(PeerRingAction::RemoteAction(
-                    next,
-                    PeerRingRemoteAction::FindAndStore(peer),
-                )) => {
+            match self.dht.store(data).await? {
+                PeerRingAction::None => Ok(()),
+                PeerRingAction::RemoteAction(next, PeerRingRemoteAction::FindAndStore(peer)) => {
                     self.send_direct_message(
                         Message::StoreVNode(StoreVNode { data: vec![peer] }),
                         next,
                     )
                     .await
                 }
-                Ok(_) => unreachable!(),
-                Err(e) => Err(e),
+                act @ PeerRingAction::MultiActions(_) => self.swarm.storage_replicate(act).await,
+                act => Err(Error::PeerRingUnexpectedAction(act)),
             }?;
         }
+        // mailbox of this node is handed over after it joined
+        self.replay_mailbox_if_stored(&msg.data).await;
         Ok(())
     }
 }
@@ -182,11 +594,28 @@ impl HandleMsg<SyncVNodeWithSuccessor> for MessageHandler {
 #[cfg(not(feature = "wasm"))]
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
+    use num_bigint::BigUint;
+
     use super::*;
+    use crate::dht::subring::SubRing;
+    use crate::dht::vnode::VNodeType;
+    use crate::dht::RingId;
     use crate::ecc::tests::gen_ordered_keys;
+    use crate::ecc::SecretKey;
     use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
+    use crate::message::types::JoinDHT;
+    use crate::message::Encoder;
+    use crate::session::SessionManager;
     use crate::storage::PersistenceStorageOperation;
+    use crate::storage::PersistenceStorageReadAndWrite;
     use crate::tests::default::prepare_node;
+    use crate::tests::default::prepare_node_with_replication_factor;
+    use crate::tests::default::prepare_node_with_storage_quota;
+    use crate::tests::manually_establish_connection;
+    use crate::transports::manager::TransportManager;
+    use crate::types::message::MessageListener;
 
     #[tokio::test]
     async fn test_store_vnode() -> Result<()> {
@@ -208,25 +637,43 @@ mod test {
 
         // test remote store
         if vid.in_range(&did2, &did2, &did1) {
-            swarm1.storage_store(vnode.clone()).await.unwrap();
-            // if vnode in range [node2, node1]
-            // vnode should stored in node2
-            let ev = node2.listen_once().await.unwrap();
-            if let Message::StoreVNode(x) = ev.data {
-                assert_eq!(x.data[0].did(), vid);
-            } else {
-                panic!();
-            }
+            let (stored, _, _) = tokio::join!(
+                swarm1.storage_store(vnode.clone()),
+                async {
+                    // if vnode in range [node2, node1]
+                    // vnode should stored in node2
+                    let ev = node2.listen_once().await.unwrap();
+                    if let Message::StoreVNode(x) = ev.data {
+                        assert_eq!(x.data[0].did(), vid);
+                    } else {
+                        panic!();
+                    }
+                },
+                async {
+                    let ev = node1.listen_once().await.unwrap();
+                    assert!(matches!(ev.data, Message::StoredVNode(StoredVNode{id}) if id == vid));
+                }
+            );
+            stored?;
         } else {
-            swarm2.storage_store(vnode.clone()).await.unwrap();
-            // if vnode in range [node2, node1]
-            // vnode should stored in node1
-            let ev = node1.listen_once().await.unwrap();
-            if let Message::StoreVNode(x) = ev.data {
-                assert_eq!(x.data[0].did(), vid);
-            } else {
-                panic!();
-            }
+            let (stored, _, _) = tokio::join!(
+                swarm2.storage_store(vnode.clone()),
+                async {
+                    // if vnode in range [node2, node1]
+                    // vnode should stored in node1
+                    let ev = node1.listen_once().await.unwrap();
+                    if let Message::StoreVNode(x) = ev.data {
+                        assert_eq!(x.data[0].did(), vid);
+                    } else {
+                        panic!();
+                    }
+                },
+                async {
+                    let ev = node2.listen_once().await.unwrap();
+                    assert!(matches!(ev.data, Message::StoredVNode(StoredVNode{id}) if id == vid));
+                }
+            );
+            stored?;
         }
         assert!(swarm1.storage_check_cache(&vid).await.is_none());
         assert!(swarm2.storage_check_cache(&vid).await.is_none());
@@ -241,41 +688,379 @@ mod test {
         if vid.in_range(&did2, &did2, &did1) {
             // vid is in node 2
             println!("vid is on node 2 {:?}", &did2);
-            swarm1.storage_fetch(&vid).await.unwrap();
-            // it will send reqeust to node 2
-            let ev = node2.listen_once().await.unwrap();
-            // node 2 received search vnode request
-            if let Message::SearchVNode(x) = ev.data {
-                assert_eq!(x.id, vid);
-            } else {
-                panic!();
-            }
-            let ev = node1.listen_once().await.unwrap();
-            if let Message::FoundVNode(x) = ev.data {
-                assert_eq!(x.data[0].did(), vid);
-            } else {
-                panic!();
-            }
+            let (fetched, _, _) = tokio::join!(
+                swarm1.storage_fetch(&vid),
+                async {
+                    // it will send reqeust to node 2
+                    let ev = node2.listen_once().await.unwrap();
+                    // node 2 received search vnode request
+                    if let Message::SearchVNode(x) = ev.data {
+                        assert_eq!(x.id, vid);
+                    } else {
+                        panic!();
+                    }
+                },
+                async {
+                    let ev = node1.listen_once().await.unwrap();
+                    if let Message::FoundVNode(x) = ev.data {
+                        assert_eq!(x.data[0].did(), vid);
+                    } else {
+                        panic!();
+                    }
+                }
+            );
+            assert_eq!(fetched?, Some(vnode.clone()));
             assert!(swarm1.storage_check_cache(&vid).await.is_some());
         } else {
             // vid is in node 1
             println!("vid is on node 1 {:?}", &did1);
-            swarm2.storage_fetch(&vid).await.unwrap();
-            let ev = node1.listen_once().await.unwrap();
-            if let Message::SearchVNode(x) = ev.data {
-                assert_eq!(x.id, vid);
-            } else {
-                panic!();
+            let (fetched, _, _) = tokio::join!(
+                swarm2.storage_fetch(&vid),
+                async {
+                    let ev = node1.listen_once().await.unwrap();
+                    if let Message::SearchVNode(x) = ev.data {
+                        assert_eq!(x.id, vid);
+                    } else {
+                        panic!();
+                    }
+                },
+                async {
+                    let ev = node2.listen_once().await.unwrap();
+                    if let Message::FoundVNode(x) = ev.data {
+                        assert_eq!(x.data[0].did(), vid);
+                    } else {
+                        panic!();
+                    }
+                }
+            );
+            assert_eq!(fetched?, Some(vnode.clone()));
+            assert!(swarm2.storage_check_cache(&vid).await.is_some());
+        }
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_vnode_with_successor() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (did1, _dht1, _swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, _swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+
+        // vid is in range (node2, node1], so node2 is responsible for it
+        let vid = did2 + Did::from(BigUint::from(1u16));
+        let exists = VirtualNode {
+            address: vid,
+            data: vec!["message stored on node2".encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        let synced = VirtualNode {
+            address: vid,
+            data: vec!["message synced from node1".encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        assert_eq!(dht2.store(exists.clone()).await?, PeerRingAction::None);
+
+        node1
+            .send_direct_message(
+                Message::SyncVNodeWithSuccessor(SyncVNodeWithSuccessor {
+                    data: vec![synced.clone()],
+                }),
+                did2,
+            )
+            .await?;
+        let ev = node2.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did1);
+        assert!(matches!(ev.data, Message::SyncVNodeWithSuccessor(_)));
+
+        // synced vnode should be merged with the existing one
+        assert_eq!(dht2.storage.count().await?, 1);
+        assert_eq!(
+            dht2.storage.get(&vid).await?,
+            VirtualNode::concat(&exists, &synced)?
+        );
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_vnode_when_successor_changed() -> Result<()> {
+        let keys = gen_ordered_keys(3);
+        let (key1, key2, key3) = (keys[0], keys[1], keys[2]);
+        let (did1, dht1, swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, swarm2, node2, _path2) = prepare_node(key2).await;
+        let (did3, _dht3, _swarm3, node3, _path3) = prepare_node(key3).await;
+        test_only_two_nodes_establish_connection(&node1, &node3).await?;
+        assert_eq!(dht1.lock_successor()?.min(), did3);
+
+        // vid is in range (node2, node3], it's stored on node1 for now
+        let vid = did2 + Did::from(BigUint::from(1u16));
+        let vnode = VirtualNode {
+            address: vid,
+            data: vec!["message for node2".encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        assert_eq!(dht1.store(vnode.clone()).await?, PeerRingAction::None);
+        assert_eq!(dht1.storage.count().await?, 1);
+
+        // node2 joined, successor of node1 is changed to node2
+        manually_establish_connection(&swarm1, &swarm2).await?;
+        let ev = node1.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::JoinDHT(JoinDHT{id}) if id == did2));
+        assert_eq!(dht1.lock_successor()?.min(), did2);
+        assert_eq!(dht1.storage.count().await?, 0);
+
+        let ev = node2.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::JoinDHT(JoinDHT{id}) if id == did1));
+
+        // vnode is handed over to node2
+        let ev = node2.listen_once().await.unwrap();
+        assert_eq!(ev.addr, did1);
+        assert!(matches!(
+            ev.data,
+            Message::SyncVNodeWithSuccessor(SyncVNodeWithSuccessor{ref data}) if data == &vec![vnode.clone()]
+        ));
+        assert_eq!(dht2.storage.get(&vid).await?, vnode);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replicate_vnode() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (did1, dht1, swarm1, node1, _path1) =
+            prepare_node_with_replication_factor(key1, 1).await;
+        let (did2, dht2, _swarm2, node2, _path2) =
+            prepare_node_with_replication_factor(key2, 1).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+
+        // vid is in range (node2, node1], so node2 is responsible for it
+        let vid = did2 + Did::from(BigUint::from(1u16));
+        let vnode = VirtualNode {
+            address: vid,
+            data: vec!["replicated message".encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        let (stored, _, _) = tokio::join!(
+            swarm1.storage_store(vnode.clone()),
+            async {
+                let ev = node2.listen_once().await.unwrap();
+                assert!(matches!(ev.data, Message::StoreVNode(_)));
+            },
+            async {
+                // node2 copy vnode to it's successor node1, and then reports it's stored
+                let ev = node1.listen_once().await.unwrap();
+                assert_eq!(ev.addr, did2);
+                assert!(matches!(
+                    ev.data,
+                    Message::ReplicateVNode(ReplicateVNode{ref data, full: false}) if data == &vec![vnode.clone()]
+                ));
+                let ev = node1.listen_once().await.unwrap();
+                assert!(matches!(ev.data, Message::StoredVNode(StoredVNode{id}) if id == vid));
             }
-            let ev = node2.listen_once().await.unwrap();
-            if let Message::FoundVNode(x) = ev.data {
-                assert_eq!(x.data[0].did(), vid);
-            } else {
-                panic!();
+        );
+        stored?;
+        assert_eq!(dht2.storage.get(&vid).await?, vnode);
+        assert_eq!(dht1.storage.count().await?, 0);
+        assert_eq!(dht1.fetch_replica(&vid), Some(vnode.clone()));
+
+        // primary data is lost, lookup should fall back to replica on node1
+        dht2.remove_vnode(&vid).await?;
+        let (fetched, _, _) = tokio::join!(
+            swarm1.storage_fetch(&vid),
+            async {
+                let ev = node2.listen_once().await.unwrap();
+                assert!(matches!(ev.data, Message::SearchVNode(SearchVNode{id}) if id == vid));
+                let ev = node2.listen_once().await.unwrap();
+                assert!(matches!(ev.data, Message::FoundVNode(_)));
+            },
+            async {
+                let ev = node1.listen_once().await.unwrap();
+                assert!(
+                    matches!(ev.data, Message::SearchVNodeReplica(SearchVNodeReplica{id}) if id == vid)
+                );
+                assert_eq!(ev.relay.path, vec![did1, did2]);
+                let ev = node1.listen_once().await.unwrap();
+                assert!(
+                    matches!(ev.data, Message::FoundVNode(FoundVNode{ref data}) if data == &vec![vnode.clone()])
+                );
             }
-            assert!(swarm2.storage_check_cache(&vid).await.is_some());
+        );
+        assert_eq!(fetched?, Some(vnode.clone()));
+        assert_eq!(swarm1.storage_check_cache(&vid).await, Some(vnode.clone()));
+        assert!(swarm1.pending_requests().is_empty());
+
+        // vnode is missing on node1 and it's replica node2, an empty report is responsed
+        let missing = did1 + Did::from(BigUint::from(1u16));
+        let (fetched, _, _) = tokio::join!(
+            swarm1.storage_fetch(&missing),
+            async {
+                let ev = node2.listen_once().await.unwrap();
+                assert!(
+                    matches!(ev.data, Message::SearchVNodeReplica(SearchVNodeReplica{id}) if id == missing)
+                );
+            },
+            async {
+                let ev = node1.listen_once().await.unwrap();
+                assert!(
+                    matches!(ev.data, Message::FoundVNode(FoundVNode{ref data}) if data.is_empty())
+                );
+            }
+        );
+        assert_eq!(fetched?, None);
+
+        // primary node is unreachable, node1 will serve the replica itself
+        swarm1.disconnect(did2).await?;
+        assert!(swarm1.get_transport(did2).is_none());
+        assert_eq!(
+            dht1.lookup(&vid).await?,
+            PeerRingAction::SomeVNode(vnode.clone())
+        );
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_record() -> Result<()> {
+        let key = SecretKey::random();
+        let (did, dht, swarm, _node, _path) = prepare_node(key).await;
+        let address = Record::address(&did, "profile")?;
+
+        // node is alone, so records are stored locally
+        assert!(swarm.get_record(did, "profile").await?.is_none());
+        swarm.put_record("profile", b"v1".to_vec()).await?;
+        let r1 = swarm.get_record(did, "profile").await?.unwrap();
+        assert_eq!((r1.seq, r1.value.clone()), (1, Some(b"v1".to_vec())));
+        swarm.put_record("profile", b"v2".to_vec()).await?;
+        let r2 = swarm.get_record(did, "profile").await?.unwrap();
+        assert_eq!((r2.seq, r2.value.clone()), (2, Some(b"v2".to_vec())));
+
+        // stale record is rejected
+        assert!(matches!(
+            dht.store(r1.try_into()?).await,
+            Err(Error::StaleRecord(1, 2))
+        ));
+
+        // record signed by others is rejected
+        let other = SessionManager::new_with_seckey(&SecretKey::random(), None)?;
+        let mut forged = Record::new("profile", Some(b"forged".to_vec()), 3, &other)?;
+        forged.owner = did;
+        assert!(matches!(
+            dht.store(forged.try_into()?).await,
+            Err(Error::InvalidRecordSignature)
+        ));
+        assert_eq!(swarm.get_record(did, "profile").await?, Some(r2));
+
+        // deleted record is kept as a tombstone
+        swarm.delete_record("profile").await?;
+        assert!(swarm.get_record(did, "profile").await?.is_none());
+        let tombstone: Record = dht.storage.get(&address).await?.try_into()?;
+        assert_eq!(tombstone.seq, 3);
+        assert!(tombstone.is_deleted());
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_vnode_over_quota() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (_did1, _dht1, swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, _swarm2, node2, _path2) = prepare_node_with_storage_quota(key2, 0).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+
+        // vid is in range (node2, node1], so node2 is responsible for it
+        let vid = did2 + Did::from(BigUint::from(1u16));
+        let vnode = VirtualNode {
+            address: vid,
+            data: vec!["message over quota".encode()?],
+            kind: VNodeType::RelayMessage,
+            expires_at: None,
+        };
+        let (stored, _, _) = tokio::join!(
+            swarm1.storage_store(vnode),
+            async {
+                let ev = node2.listen_once().await.unwrap();
+                assert!(matches!(ev.data, Message::StoreVNode(_)));
+            },
+            async {
+                let ev = node1.listen_once().await.unwrap();
+                assert_eq!(ev.addr, did2);
+                assert!(matches!(
+                    ev.data,
+                    Message::StoreVNodeRejected(StoreVNodeRejected{id, ..}) if id == vid
+                ));
+            }
+        );
+        assert_eq!(dht2.storage.count().await?, 0);
+
+        // writer gets the error of storing node
+        assert!(matches!(stored, Err(Error::StorageQuotaExceeded(0))));
+        assert!(swarm1.pending_requests().is_empty());
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scan_vnodes() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (did1, dht1, swarm1, node1, _path1) = prepare_node(keys[0]).await;
+        let (_did2, dht2, _swarm2, node2, _path2) = prepare_node(keys[1]).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+
+        // vnodes are stored on their responsible nodes
+        let mut vnodes = (0..8)
+            .map(|i| VirtualNode::try_from(format!("scanned vnode {}", i)))
+            .collect::<Result<Vec<_>>>()?;
+        vnodes.push(SubRing::new("scanned subring", &did1)?.try_into()?);
+        for vnode in vnodes.iter().cloned() {
+            if dht1.store(vnode.clone()).await?.is_remote() {
+                dht2.store(vnode).await?;
+            }
+        }
+        vnodes.sort_by_key(|v| v.did());
+        assert_eq!(dht1.storage.count().await? + dht2.storage.count().await?, 9);
+
+        let listeners = [
+            tokio::spawn(async move { Arc::new(node1).listen().await }),
+            tokio::spawn(async move { Arc::new(node2).listen().await }),
+        ];
+
+        // scan the whole ring page by page, vnodes on both nodes are found in order
+        let mut scanned = vec![];
+        let mut next = Some(VNodeRange::all().with_limit(4));
+        while let Some(range) = next {
+            let page = swarm1.storage_scan(range).await?;
+            assert!(page.data.len() <= 4);
+            scanned.extend(page.data);
+            next = page.next;
         }
+        assert_eq!(scanned, vnodes);
 
+        // only subrings are scanned
+        let page = swarm1
+            .storage_scan(VNodeRange::all().with_kind(VNodeType::SubRing))
+            .await?;
+        assert_eq!(page.data.len(), 1);
+        assert_eq!(page.data[0].kind, VNodeType::SubRing);
+        assert_eq!(page.next, None);
+        assert!(swarm1.pending_requests().is_empty());
+
+        for listener in listeners {
+            listener.abort();
+        }
         tokio::fs::remove_dir_all("./tmp").await.ok();
         Ok(())
     }
@@ -0,0 +1,299 @@
+use async_trait::async_trait;
+
+use crate::dht::Chord;
+use crate::dht::PeerRingAction;
+use crate::err::Error;
+use crate::err::Result;
+use crate::message::stream::send_frame;
+use crate::message::types::CloseStream;
+use crate::message::types::Message;
+use crate::message::types::OpenStream;
+use crate::message::types::StreamAck;
+use crate::message::types::StreamData;
+use crate::message::HandleMsg;
+use crate::message::MessageHandler;
+use crate::message::MessagePayload;
+use crate::message::PayloadSender;
+use crate::message::RelayMethod;
+use crate::message::DEFAULT_STREAM_WINDOW;
+use crate::transports::manager::TransportManager;
+
+impl MessageHandler {
+    /// Pass a frame of stream on to it's destination, or the next node of a report.
+    /// Return false if the frame reaches this node.
+    async fn forward_stream_frame(&self, ctx: &MessagePayload<Message>) -> Result<bool> {
+        let mut relay = ctx.relay.clone();
+
+        if relay.method == RelayMethod::REPORT {
+            relay.relay(self.dht.id, None)?;
+            if relay.next_hop.is_some() {
+                self.transpond_payload(ctx, relay).await?;
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+        if self.dht.id == relay.destination {
+            return Ok(false);
+        }
+        let next_node = if self.swarm.get_transport(relay.destination).is_some() {
+            relay.destination
+        } else {
+            match self.dht.find_successor(relay.destination)? {
+                // destination is not on the ring, otherwise it's the successor
+                PeerRingAction::Some(node) if node != relay.destination => {
+                    return Err(Error::MessageHandlerMissNextNode);
+                }
+                PeerRingAction::Some(node) => node,
+                PeerRingAction::RemoteAction(node, _) => node,
+                _ => return Err(Error::MessageHandlerMissNextNode),
+            }
+        };
+        relay.relay(self.dht.id, Some(next_node))?;
+        self.transpond_payload(ctx, relay).await?;
+        Ok(true)
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<OpenStream> for MessageHandler {
+    /// Queue the stream to be accepted, and answer with window of it,
+    /// or refuse it if too many streams are waiting.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &OpenStream) -> Result<()> {
+        if self.forward_stream_frame(ctx).await? {
+            return Ok(());
+        }
+        let mut relay = ctx.relay.clone();
+        relay.relay(self.dht.id, None)?;
+
+        let origin = ctx.origin();
+        let report = match self.swarm.streams.incoming(
+            msg.stream_id,
+            origin,
+            msg.window,
+            ctx.origin_session_pubkey().ok(),
+        ) {
+            Ok(()) => Message::StreamAck(StreamAck {
+                stream_id: msg.stream_id,
+                ack: 0,
+                window: DEFAULT_STREAM_WINDOW,
+            }),
+            Err(e) => {
+                tracing::warn!("refuse stream {} from {:?}: {}", msg.stream_id, origin, e);
+                Message::CloseStream(CloseStream {
+                    stream_id: msg.stream_id,
+                    seq: 0,
+                })
+            }
+        };
+        self.send_report_message(report, ctx.tx_id, relay).await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<StreamData> for MessageHandler {
+    /// Take the frame, and acknowledge all frames received in order.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StreamData) -> Result<()> {
+        if self.forward_stream_frame(ctx).await? {
+            return Ok(());
+        }
+        let stream = self.swarm.streams.get(
+            &msg.stream_id,
+            ctx.origin(),
+            ctx.origin_session_pubkey().ok(),
+        )?;
+        let (ack, window) = stream.on_data(msg.seq, msg.data.clone());
+        let ack = Message::StreamAck(StreamAck {
+            stream_id: msg.stream_id,
+            ack,
+            window,
+        });
+        send_frame(&self.swarm, ack, stream.remote()).await
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<StreamAck> for MessageHandler {
+    /// A report of `OpenStream` or `CloseStream` is delivered to the waiting request,
+    /// others advance the window of stream.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &StreamAck) -> Result<()> {
+        if self.forward_stream_frame(ctx).await? || ctx.relay.method == RelayMethod::REPORT {
+            return Ok(());
+        }
+        let stream = self.swarm.streams.get(
+            &msg.stream_id,
+            ctx.origin(),
+            ctx.origin_session_pubkey().ok(),
+        )?;
+        stream.on_ack(msg.ack, msg.window);
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<CloseStream> for MessageHandler {
+    /// Remote sends no more frames, answer with the ack of stream.
+    /// A report of `OpenStream` means the stream is refused.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &CloseStream) -> Result<()> {
+        if self.forward_stream_frame(ctx).await? || ctx.relay.method == RelayMethod::REPORT {
+            return Ok(());
+        }
+        let mut relay = ctx.relay.clone();
+        relay.relay(self.dht.id, None)?;
+
+        let stream = self.swarm.streams.get(
+            &msg.stream_id,
+            ctx.origin(),
+            ctx.origin_session_pubkey().ok(),
+        )?;
+        let (ack, window) = stream.on_close(msg.seq);
+        self.send_report_message(
+            Message::StreamAck(StreamAck {
+                stream_id: msg.stream_id,
+                ack,
+                window,
+            }),
+            ctx.tx_id,
+            relay,
+        )
+        .await
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::io::AsyncReadExt;
+    use futures::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::ecc::tests::gen_ordered_keys;
+    use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
+    use crate::message::DEFAULT_STREAM_RTO;
+    use crate::message::MAX_STREAM_FRAME;
+    use crate::tests::default::prepare_node;
+    use crate::tests::default::prepare_node_with_stream_idle_timeout;
+    use crate::types::message::MessageListener;
+
+    #[tokio::test]
+    async fn test_byte_stream() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (_did1, _dht1, swarm1, node1, _path1) = prepare_node(keys[0]).await;
+        let (did2, _dht2, swarm2, node2, _path2) = prepare_node(keys[1]).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        let listeners = [
+            tokio::spawn(async move { Arc::new(node1).listen().await }),
+            tokio::spawn(async move { Arc::new(node2).listen().await }),
+        ];
+
+        // node2 echoes what it reads until node1 closes
+        let echo = tokio::spawn(async move {
+            let mut stream = swarm2.accept_stream().await;
+            let mut data = vec![];
+            stream.read_to_end(&mut data).await.unwrap();
+            stream.write_all(&data).await.unwrap();
+            stream.close().await.unwrap();
+            data.len()
+        });
+
+        // larger than window of frames, so the writer waits for acks
+        let data: Vec<u8> = (0..MAX_STREAM_FRAME * DEFAULT_STREAM_WINDOW as usize * 2)
+            .map(|_| rand::random::<u8>())
+            .collect();
+        let mut stream = swarm1.open_stream(did2).await?;
+        assert_eq!(stream.remote(), did2);
+        stream.write_all(&data).await.unwrap();
+        stream.close().await.unwrap();
+        assert_eq!(echo.await.unwrap(), data.len());
+
+        let mut echoed = vec![];
+        stream.read_to_end(&mut echoed).await.unwrap();
+        assert_eq!(echoed, data);
+
+        // a dropped stream is forgotten, frames of it are refused since then
+        drop(stream);
+        assert!(swarm1.streams.is_empty());
+
+        for listener in listeners {
+            listener.abort();
+        }
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_drop_byte_stream() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (_did1, _dht1, swarm1, node1, _path1) = prepare_node(keys[0]).await;
+        let (did2, _dht2, swarm2, node2, _path2) = prepare_node(keys[1]).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        let listeners = [
+            tokio::spawn(async move { Arc::new(node1).listen().await }),
+            tokio::spawn(async move { Arc::new(node2).listen().await }),
+        ];
+
+        let mut stream = swarm1.open_stream(did2).await?;
+        stream.write_all(b"hello").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut accepted = swarm2.accept_stream().await;
+
+        // remote reads to the end after the stream is dropped without closing
+        drop(stream);
+        let mut data = vec![];
+        tokio::time::timeout(DEFAULT_STREAM_RTO * 2, accepted.read_to_end(&mut data))
+            .await
+            .expect("stream is not closed by drop")
+            .unwrap();
+        assert_eq!(data, b"hello".to_vec());
+
+        for listener in listeners {
+            listener.abort();
+        }
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_byte_stream_expires() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (_did1, _dht1, swarm1, node1, _path1) =
+            prepare_node_with_stream_idle_timeout(keys[0], 600).await;
+        let (did2, _dht2, swarm2, node2, _path2) =
+            prepare_node_with_stream_idle_timeout(keys[1], 600).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+        let listeners = [
+            tokio::spawn(async move { Arc::new(node1).listen().await }),
+            tokio::spawn(async move { Arc::new(node2).listen().await }),
+        ];
+
+        // keepalive keeps the stream alive beyond idle timeout, until it's not accepted
+        // in idle timeout and forgotten by remote, then nothing is received
+        let mut stream = swarm1.open_stream(did2).await?;
+        assert_eq!(swarm2.streams.len(), 1);
+        let mut data = vec![];
+        let ret = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut data))
+            .await
+            .expect("stream is not expired");
+        assert_eq!(ret.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        assert!(swarm1.streams.is_empty());
+        assert!(swarm2.streams.is_empty());
+
+        // so does writing
+        assert_eq!(
+            stream.write_all(b"hello").await.unwrap_err().kind(),
+            std::io::ErrorKind::TimedOut
+        );
+
+        for listener in listeners {
+            listener.abort();
+        }
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+}
@@ -6,20 +6,42 @@ use async_trait::async_trait;
 use super::storage::TChordStorage;
 use crate::dht::subring::SubRing;
 use crate::dht::vnode::VirtualNode;
+use crate::dht::Chord;
 use crate::dht::Did;
 use crate::dht::PeerRingAction;
 use crate::dht::PeerRingRemoteAction as RemoteAction;
+use crate::dht::RingId;
 use crate::dht::SubRingManager;
 use crate::ecc::HashStr;
 use crate::err::Error;
 use crate::err::Result;
+use crate::message::types::CustomMessage;
 use crate::message::types::JoinSubRing;
+use crate::message::types::KickSubRing;
+use crate::message::types::LeaveSubRing;
+use crate::message::types::ListSubRing;
+use crate::message::types::MaybeEncrypted;
 use crate::message::types::Message;
+use crate::message::types::SubRingBroadcast;
+use crate::message::types::SubRingMembers;
+use crate::message::types::SubRingMulticast;
 use crate::message::HandleMsg;
 use crate::message::MessageHandler;
 use crate::message::MessagePayload;
 use crate::message::PayloadSender;
+use crate::message::DEFAULT_REQUEST_TIMEOUT;
 use crate::swarm::Swarm;
+use crate::transports::manager::TransportManager;
+use crate::utils;
+
+/// Number of subtrees which a subring broadcast is forwarded to, by each node
+pub const SUBRING_FANOUT: usize = 3;
+
+/// A subring broadcast is remembered in this period, to suppress duplication
+const SUBRING_BROADCAST_TTL_MS: u128 = 60 * 1000;
+
+/// A kick signed by admin is valid in this period
+const SUBRING_KICK_TTL_MS: usize = 60 * 1000;
 
 /// SubRingOperator should imply necessary operator for DHT SubRing
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -31,6 +53,16 @@ pub trait SubRingOperator {
     async fn subring_create(&self, name: &str) -> Result<()>;
     /// join a subring
     async fn subring_join(&self, name: &str) -> Result<()>;
+    /// leave a subring
+    async fn subring_leave(&self, name: &str) -> Result<()>;
+    /// remove a member from subring, only admin of the subring is permitted
+    async fn subring_kick(&self, name: &str, did: Did) -> Result<()>;
+    /// list members of subring
+    async fn subring_members(&self, name: &str) -> Result<Vec<Did>>;
+    /// Broadcast a message to every member of subring.
+    /// The message is routed to the node storing the subring, and then spread over
+    /// members as a tree, see `SUBRING_FANOUT`.
+    async fn subring_broadcast(&self, name: &str, msg: CustomMessage) -> Result<()>;
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -56,14 +88,261 @@ impl SubRingOperator for Swarm {
             Err(e) => Err(e),
         }
     }
+
+    async fn subring_leave(&self, name: &str) -> Result<()> {
+        let address: HashStr = name.to_owned().into();
+        let did = Did::from_str(&address.inner())?;
+        match self.dht.leave_subring(&self.dht.id, &did).await {
+            Ok(PeerRingAction::RemoteAction(next, RemoteAction::FindAndLeaveSubRing(rid))) => {
+                self.send_direct_message(Message::LeaveSubRing(LeaveSubRing { did: rid }), next)
+                    .await
+            }
+            Ok(PeerRingAction::None) => Ok(()),
+            Ok(act) => Err(Error::PeerRingUnexpectedAction(act)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn subring_kick(&self, name: &str, did: Did) -> Result<()> {
+        let address: HashStr = name.to_owned().into();
+        let rid = Did::from_str(&address.inner())?;
+        let msg = KickSubRing::new(rid, did, self.session_manager(), SUBRING_KICK_TTL_MS)?;
+        match self
+            .dht
+            .kick_subring(&self.dht.id, &did, &rid, msg.nonce)
+            .await
+        {
+            Ok(PeerRingAction::RemoteAction(next, RemoteAction::FindAndKickSubRing(_))) => {
+                self.send_direct_message(Message::KickSubRing(msg), next)
+                    .await
+            }
+            Ok(PeerRingAction::None) => Ok(()),
+            Ok(act) => Err(Error::PeerRingUnexpectedAction(act)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn subring_members(&self, name: &str) -> Result<Vec<Did>> {
+        let address: HashStr = name.to_owned().into();
+        let rid = Did::from_str(&address.inner())?;
+        match self.dht.list_subring(&rid).await? {
+            PeerRingAction::SomeVNode(v) => Ok(SubRing::try_from(v)?.members()),
+            PeerRingAction::RemoteAction(next, RemoteAction::FindAndListSubRing(rid)) => {
+                let payload = MessagePayload::new_direct(
+                    Message::ListSubRing(ListSubRing { did: rid }),
+                    self.session_manager(),
+                    next,
+                )?;
+                match self
+                    .send_payload_and_wait(payload, DEFAULT_REQUEST_TIMEOUT)
+                    .await?
+                    .data
+                {
+                    Message::SubRingMembers(SubRingMembers { members, .. }) => Ok(members),
+                    msg => Err(Error::InvalidMessage(format!(
+                        "unexpected report of listing subring: {}",
+                        msg
+                    ))),
+                }
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+
+    async fn subring_broadcast(&self, name: &str, msg: CustomMessage) -> Result<()> {
+        let address: HashStr = name.to_owned().into();
+        let rid = Did::from_str(&address.inner())?;
+        let data = MaybeEncrypted::Plain(msg);
+        match self.dht.find_successor(rid)? {
+            PeerRingAction::Some(_) => {
+                let tx_id = uuid::Uuid::new_v4();
+                self.mark_subring_broadcast(tx_id);
+                let members = self.subring_receivers(&rid, self.dht.id).await?;
+                self.subring_multicast(tx_id, rid, self.dht.id, &data, members)
+                    .await
+            }
+            PeerRingAction::RemoteAction(next, _) => {
+                let payload = MessagePayload::new_direct(
+                    Message::SubRingBroadcast(SubRingBroadcast { rid, data }),
+                    self.session_manager(),
+                    next,
+                )?;
+                self.mark_subring_broadcast(payload.tx_id);
+                self.send_payload(payload).await
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+}
+
+impl Swarm {
+    /// Remember a subring broadcast, return false if it's already seen.
+    pub(crate) fn mark_subring_broadcast(&self, tx_id: uuid::Uuid) -> bool {
+        let now = utils::get_epoch_ms();
+        self.subring_broadcasts
+            .retain(|_, ts| now < *ts + SUBRING_BROADCAST_TTL_MS);
+        self.subring_broadcasts.set(&tx_id, now).is_none()
+    }
+
+    /// Members of subring stored locally, except the origin of broadcast.
+    async fn subring_receivers(&self, rid: &Did, origin: Did) -> Result<Vec<Did>> {
+        let subring = self.dht.get_subring(rid).await?;
+        Ok(subring
+            .members()
+            .into_iter()
+            .filter(|m| *m != origin)
+            .collect())
+    }
+
+    /// Send a subring broadcast to subtrees of members, with the same tx_id.
+    /// A failed subtree is skipped, the others are still delivered.
+    async fn subring_multicast(
+        &self,
+        tx_id: uuid::Uuid,
+        rid: Did,
+        origin: Did,
+        data: &MaybeEncrypted<CustomMessage>,
+        members: Vec<Did>,
+    ) -> Result<()> {
+        for (head, subtree) in fan_out(self.dht.id, members, SUBRING_FANOUT) {
+            let next_hop = if self.get_transport(head).is_some() {
+                head
+            } else {
+                match self.dht.find_successor(head)? {
+                    PeerRingAction::Some(node) => node,
+                    PeerRingAction::RemoteAction(node, _) => node,
+                    act => return Err(Error::PeerRingUnexpectedAction(act)),
+                }
+            };
+            let mut payload = MessagePayload::new_send(
+                Message::SubRingMulticast(SubRingMulticast {
+                    rid,
+                    origin,
+                    members: subtree,
+                    data: data.clone(),
+                }),
+                self.session_manager(),
+                next_hop,
+                head,
+            )?;
+            payload.tx_id = tx_id;
+            if let Err(e) = self.send_payload(payload).await {
+                tracing::warn!("[subring_multicast] failed to send to {:?}: {:?}", head, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split members into at most `degree` subtrees, ordered by their distance from `current`.
+/// Each subtree is sent to it's first node, which forwards it to the rest.
+fn fan_out(current: Did, mut members: Vec<Did>, degree: usize) -> Vec<(Did, Vec<Did>)> {
+    members.retain(|m| *m != current);
+    members.sort_by_key(|m| m.bias(&current));
+    members.dedup();
+    if members.is_empty() {
+        return vec![];
+    }
+    let size = (members.len() + degree.max(1) - 1) / degree.max(1);
+    members
+        .chunks(size)
+        .map(|c| (c[0], c[1..].to_vec()))
+        .collect()
+}
+
+impl MessageHandler {
+    /// Deliver subring broadcast to application via callback.
+    async fn deliver_subring_broadcast(
+        &self,
+        ctx: &MessagePayload<Message>,
+        data: &MaybeEncrypted<CustomMessage>,
+    ) {
+        if let Some(ref cb) = *self.callback {
+            cb.custom_message(self, ctx, data).await
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<SubRingBroadcast> for MessageHandler {
+    /// Route broadcast to the node storing subring, which delivers it to members.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &SubRingBroadcast) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let origin = ctx.origin();
+        match self.dht.find_successor(msg.rid)? {
+            PeerRingAction::Some(_) => {
+                if !self.swarm.mark_subring_broadcast(ctx.tx_id) {
+                    return Ok(());
+                }
+                let mut members = self.swarm.subring_receivers(&msg.rid, origin).await?;
+                if members.contains(&self.dht.id) {
+                    members.retain(|m| *m != self.dht.id);
+                    self.deliver_subring_broadcast(ctx, &msg.data).await;
+                }
+                self.swarm
+                    .subring_multicast(ctx.tx_id, msg.rid, origin, &msg.data, members)
+                    .await
+            }
+            PeerRingAction::RemoteAction(next, _) => {
+                relay.relay(self.dht.id, Some(next))?;
+                relay.reset_destination(next)?;
+                self.transpond_payload(ctx, relay).await
+            }
+            act => Err(Error::PeerRingUnexpectedAction(act)),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<SubRingMulticast> for MessageHandler {
+    /// Deliver broadcast to local, and forward it to the subtree.
+    /// A broadcast is delivered only once, duplication is dropped by tx_id.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &SubRingMulticast) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        if self.dht.id != relay.destination {
+            let next_hop = if self.swarm.get_transport(relay.destination).is_some() {
+                relay.destination
+            } else {
+                match self.dht.find_successor(relay.destination)? {
+                    PeerRingAction::Some(node) => node,
+                    PeerRingAction::RemoteAction(node, _) => node,
+                    act => return Err(Error::PeerRingUnexpectedAction(act)),
+                }
+            };
+            relay.relay(self.dht.id, Some(next_hop))?;
+            return self.transpond_payload(ctx, relay).await;
+        }
+
+        if !self.swarm.mark_subring_broadcast(ctx.tx_id) {
+            tracing::debug!(
+                "[SubRingMulticast] drop duplicated broadcast {:?}",
+                ctx.tx_id
+            );
+            return Ok(());
+        }
+        self.swarm
+            .subring_multicast(
+                ctx.tx_id,
+                msg.rid,
+                msg.origin,
+                &msg.data,
+                msg.members.clone(),
+            )
+            .await?;
+        self.deliver_subring_broadcast(ctx, &msg.data).await;
+        Ok(())
+    }
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
 #[cfg_attr(not(feature = "wasm"), async_trait)]
 impl HandleMsg<JoinSubRing> for MessageHandler {
+    /// The member is the signed origin of payload, so a node cannot join others.
     async fn handle(&self, ctx: &MessagePayload<Message>, msg: &JoinSubRing) -> Result<()> {
         let mut relay = ctx.relay.clone();
-        let origin = relay.origin();
+        let origin = ctx.origin();
         match self.dht.join_subring(&origin, &msg.did).await {
             Ok(PeerRingAction::RemoteAction(next, RemoteAction::FindAndJoinSubRing(_))) => {
                 relay.relay(self.dht.id, Some(next))?;
@@ -76,3 +355,390 @@ impl HandleMsg<JoinSubRing> for MessageHandler {
         }
     }
 }
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<LeaveSubRing> for MessageHandler {
+    /// The member is the signed origin of payload, so a node cannot remove others.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &LeaveSubRing) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let origin = ctx.origin();
+        match self.dht.leave_subring(&origin, &msg.did).await {
+            Ok(PeerRingAction::RemoteAction(next, RemoteAction::FindAndLeaveSubRing(_))) => {
+                relay.relay(self.dht.id, Some(next))?;
+                relay.reset_destination(next)?;
+                self.transpond_payload(ctx, relay).await
+            }
+            Ok(PeerRingAction::None) => Ok(()),
+            Ok(act) => Err(Error::PeerRingUnexpectedAction(act)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<KickSubRing> for MessageHandler {
+    /// The signature of admin is verified before the kick is relayed or applied,
+    /// and the permission and nonce are checked by the node storing subring.
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &KickSubRing) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        let admin = msg.signer()?;
+        match self
+            .dht
+            .kick_subring(&admin, &msg.did, &msg.rid, msg.nonce)
+            .await
+        {
+            Ok(PeerRingAction::RemoteAction(next, RemoteAction::FindAndKickSubRing(_))) => {
+                relay.relay(self.dht.id, Some(next))?;
+                relay.reset_destination(next)?;
+                self.transpond_payload(ctx, relay).await
+            }
+            Ok(PeerRingAction::None) => Ok(()),
+            Ok(act) => Err(Error::PeerRingUnexpectedAction(act)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<ListSubRing> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, msg: &ListSubRing) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+        match self.dht.list_subring(&msg.did).await {
+            Ok(PeerRingAction::SomeVNode(v)) => {
+                let members = SubRing::try_from(v)?.members();
+                relay.relay(self.dht.id, None)?;
+                self.send_report_message(
+                    Message::SubRingMembers(SubRingMembers {
+                        did: msg.did,
+                        members,
+                    }),
+                    ctx.tx_id,
+                    relay,
+                )
+                .await
+            }
+            Ok(PeerRingAction::RemoteAction(next, RemoteAction::FindAndListSubRing(_))) => {
+                relay.relay(self.dht.id, Some(next))?;
+                relay.reset_destination(next)?;
+                self.transpond_payload(ctx, relay).await
+            }
+            Ok(act) => Err(Error::PeerRingUnexpectedAction(act)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", async_trait(?Send))]
+#[cfg_attr(not(feature = "wasm"), async_trait)]
+impl HandleMsg<SubRingMembers> for MessageHandler {
+    async fn handle(&self, ctx: &MessagePayload<Message>, _msg: &SubRingMembers) -> Result<()> {
+        let mut relay = ctx.relay.clone();
+
+        relay.relay(self.dht.id, None)?;
+        if relay.next_hop.is_some() {
+            return self.transpond_payload(ctx, relay).await;
+        }
+
+        // the members are taken by pending request, see `SubRingOperator::subring_members`
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::lock::Mutex;
+    use tokio::time::sleep;
+    use tokio::time::Duration;
+
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+    use crate::ecc::tests::gen_ordered_keys;
+    use crate::ecc::SecretKey;
+    use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
+    use crate::message::CallbackFn;
+    use crate::message::MessageCallback;
+    use crate::session::SessionManager;
+    use crate::tests::default::prepare_node;
+    use crate::tests::manually_establish_connection;
+    use crate::types::message::MessageListener;
+
+    #[derive(Clone, Default)]
+    struct SubRingCallback {
+        delivered: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    #[async_trait]
+    impl MessageCallback for SubRingCallback {
+        async fn custom_message(
+            &self,
+            handler: &MessageHandler,
+            _ctx: &MessagePayload<Message>,
+            msg: &MaybeEncrypted<CustomMessage>,
+        ) {
+            let msg = handler.decrypt_msg(msg).unwrap();
+            self.delivered.lock().await.push(msg.0);
+        }
+
+        async fn builtin_message(&self, _handler: &MessageHandler, _ctx: &MessagePayload<Message>) {
+        }
+    }
+
+    #[tokio::test]
+    async fn test_join_subring() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (did1, dht1, _swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, _swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+
+        let name = "test_join_subring";
+        let subring = SubRing::new(name, &did1)?;
+        let rid = subring.did;
+
+        // subring is stored on the node which responsible for it's address,
+        // the other one send JoinSubRing to it.
+        let (owner, owner_dht, joiner, joiner_did) = if rid.in_range(&did2, &did2, &did1) {
+            (&node2, &dht2, &node1, did1)
+        } else {
+            (&node1, &dht1, &node2, did2)
+        };
+        owner_dht.store_subring(&subring).await?;
+        assert!(!owner_dht
+            .get_subring(&rid)
+            .await?
+            .members
+            .contains(&joiner_did));
+
+        joiner
+            .send_direct_message(Message::JoinSubRing(JoinSubRing { did: rid }), owner.dht.id)
+            .await?;
+        let ev = owner.listen_once().await.unwrap();
+        assert_eq!(ev.addr, joiner_did);
+        assert!(matches!(ev.data, Message::JoinSubRing(JoinSubRing{did}) if did == rid));
+
+        assert!(owner_dht
+            .get_subring(&rid)
+            .await?
+            .members
+            .contains(&joiner_did));
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subring_membership() -> Result<()> {
+        let keys = gen_ordered_keys(2);
+        let (key1, key2) = (keys[0], keys[1]);
+        let (did1, dht1, swarm1, node1, _path1) = prepare_node(key1).await;
+        let (did2, dht2, swarm2, node2, _path2) = prepare_node(key2).await;
+        test_only_two_nodes_establish_connection(&node1, &node2).await?;
+
+        // subring is stored on owner, and it's admin is the other node
+        let name = "test_subring_membership";
+        let rid = SubRing::new(name, &did1)?.did;
+        let (owner, owner_dht, owner_swarm, owner_did, admin, admin_swarm, admin_did) =
+            if rid.in_range(&did2, &did2, &did1) {
+                (&node2, &dht2, &swarm2, did2, &node1, &swarm1, did1)
+            } else {
+                (&node1, &dht1, &swarm1, did1, &node2, &swarm2, did2)
+            };
+        owner_dht
+            .store_subring(&SubRing::new(name, &admin_did)?)
+            .await?;
+        owner_swarm.subring_join(name).await?;
+        assert_eq!(owner_swarm.subring_members(name).await?, vec![owner_did]);
+
+        // list members from remote
+        let (members, _, _) = tokio::join!(
+            admin_swarm.subring_members(name),
+            async {
+                let ev = owner.listen_once().await.unwrap();
+                assert!(matches!(ev.data, Message::ListSubRing(ListSubRing{did}) if did == rid));
+            },
+            async {
+                let ev = admin.listen_once().await.unwrap();
+                assert!(matches!(ev.data, Message::SubRingMembers(_)));
+            }
+        );
+        assert_eq!(members?, vec![owner_did]);
+
+        // owner is not admin, and cannot kick others
+        assert!(matches!(
+            owner_swarm.subring_kick(name, owner_did).await,
+            Err(Error::SubRingPermissionDenied(did)) if did == owner_did
+        ));
+
+        // a kick with forged target is rejected
+        let mut forged = KickSubRing::new(rid, admin_did, admin_swarm.session_manager(), 1000)?;
+        forged.did = owner_did;
+        admin
+            .send_direct_message(Message::KickSubRing(forged), owner_did)
+            .await?;
+        let ev = owner.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::KickSubRing(_)));
+        assert_eq!(owner_dht.get_subring(&rid).await?.members(), vec![
+            owner_did
+        ]);
+
+        // admin kicks owner from remote
+        admin_swarm.subring_kick(name, owner_did).await?;
+        let ev = owner.listen_once().await.unwrap();
+        let kick = match ev.data {
+            Message::KickSubRing(kick) if kick.did == owner_did => kick,
+            _ => panic!("unexpected message {:?}", ev.data),
+        };
+        assert!(owner_dht.get_subring(&rid).await?.members().is_empty());
+
+        // admin joins and leaves
+        admin_swarm.subring_join(name).await?;
+        let ev = owner.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::JoinSubRing(_)));
+        assert_eq!(owner_dht.get_subring(&rid).await?.members(), vec![
+            admin_did
+        ]);
+        admin_swarm.subring_leave(name).await?;
+        let ev = owner.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::LeaveSubRing(LeaveSubRing{did}) if did == rid));
+        assert!(owner_dht.get_subring(&rid).await?.members().is_empty());
+
+        // a replayed kick is rejected after owner joins again
+        owner_swarm.subring_join(name).await?;
+        admin
+            .send_direct_message(Message::KickSubRing(kick), owner_did)
+            .await?;
+        let ev = owner.listen_once().await.unwrap();
+        assert!(matches!(ev.data, Message::KickSubRing(_)));
+        assert_eq!(owner_dht.get_subring(&rid).await?.members(), vec![
+            owner_did
+        ]);
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_kick_subring_signature() -> Result<()> {
+        let key = SecretKey::random();
+        let did: Did = key.address().into();
+        let sm = SessionManager::new_with_seckey(&key, None)?;
+        let rid = SubRing::new("test_kick_subring_signature", &did)?.did;
+        let member: Did = SecretKey::random().address().into();
+
+        let kick = KickSubRing::new(rid, member, &sm, 1000)?;
+        assert_eq!(kick.signer()?, did);
+        assert!(KickSubRing::new(rid, member, &sm, 1000)?.nonce > kick.nonce);
+
+        let mut forged = kick.clone();
+        forged.rid = member;
+        assert!(forged.signer().is_err());
+
+        let mut forged = kick.clone();
+        forged.nonce += 1;
+        assert!(forged.signer().is_err());
+
+        let expired = KickSubRing::new(rid, member, &sm, 0)?;
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(expired.signer().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let dids = gen_ordered_dids(8);
+        let current = dids[3];
+
+        // members are ordered from current, and split into subtrees
+        let mut members = dids.clone();
+        members.reverse();
+        members.push(dids[5]);
+        let tree = fan_out(current, members, 3);
+        assert_eq!(tree, vec![
+            (dids[4], vec![dids[5], dids[6]]),
+            (dids[7], vec![dids[0], dids[1]]),
+            (dids[2], vec![]),
+        ]);
+
+        // every member is covered exactly once
+        let mut covered: Vec<Did> = tree
+            .into_iter()
+            .flat_map(|(head, subtree)| std::iter::once(head).chain(subtree))
+            .collect();
+        covered.sort();
+        let mut expected = dids.clone();
+        expected.remove(3);
+        assert_eq!(covered, expected);
+
+        assert_eq!(fan_out(current, vec![dids[0]], 3), vec![(dids[0], vec![])]);
+        assert!(fan_out(current, vec![current], 3).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subring_broadcast() -> Result<()> {
+        let keys = gen_ordered_keys(3);
+        let mut nodes = vec![];
+        for key in keys {
+            let (did, dht, swarm, _node, _path) = prepare_node(key).await;
+            let callback = SubRingCallback::default();
+            let cb: CallbackFn = Box::new(callback.clone());
+            let handler = swarm.create_message_handler(Some(cb), None);
+            nodes.push((did, dht, swarm, handler, callback));
+        }
+        manually_establish_connection(&nodes[0].2, &nodes[1].2).await?;
+        manually_establish_connection(&nodes[1].2, &nodes[2].2).await?;
+        manually_establish_connection(&nodes[0].2, &nodes[2].2).await?;
+        for (_, _, _, handler, _) in nodes.iter() {
+            let handler = Arc::new(handler.clone());
+            tokio::spawn(async move { handler.listen().await });
+        }
+        sleep(Duration::from_secs(3)).await;
+
+        // subring is stored on the node responsible for it, and every node joins it
+        let name = "test_subring_broadcast";
+        let rid = SubRing::new(name, &nodes[0].0)?.did;
+        let owner = nodes
+            .iter()
+            .position(|(_, dht, ..)| matches!(dht.find_successor(rid), Ok(PeerRingAction::Some(_))))
+            .unwrap();
+        let subring = SubRing::new(name, &nodes[owner].0)?;
+        nodes[owner].1.store_subring(&subring).await?;
+        for (_, _, swarm, ..) in nodes.iter() {
+            swarm.subring_join(name).await?;
+        }
+        sleep(Duration::from_secs(2)).await;
+        let members = nodes[owner].1.get_subring(&rid).await?.members();
+        assert!(!members.is_empty());
+
+        // broadcast from a node which is not the owner, it's routed to owner first
+        let sender = (owner + 1) % nodes.len();
+        nodes[sender]
+            .2
+            .subring_broadcast(name, CustomMessage("hello subring".as_bytes().to_vec()))
+            .await?;
+        sleep(Duration::from_secs(2)).await;
+
+        // every member except sender got the message exactly once
+        for (i, (did, _, _, _, callback)) in nodes.iter().enumerate() {
+            let delivered = callback.delivered.lock().await;
+            if i != sender && members.contains(did) {
+                assert_eq!(delivered.as_slice(), &["hello subring".as_bytes().to_vec()]);
+            } else {
+                assert!(delivered.is_empty());
+            }
+        }
+
+        // the broadcast is remembered by sender, a duplication will be dropped
+        let seen = nodes[sender].2.subring_broadcasts.keys();
+        assert_eq!(seen.len(), 1);
+        assert!(!nodes[sender].2.mark_subring_broadcast(seen[0]));
+
+        tokio::fs::remove_dir_all("./tmp").await.ok();
+        Ok(())
+    }
+}
@@ -8,13 +8,36 @@ pub use encoder::Encoder;
 mod payload;
 pub use payload::MessagePayload;
 pub use payload::OriginVerificationGen;
+pub use payload::PayloadFormat;
 pub use payload::PayloadSender;
 
+mod pending;
+pub use pending::PendingRequests;
+pub use pending::DEFAULT_REQUEST_TIMEOUT;
+
+mod stream;
+pub use stream::ByteStream;
+pub use stream::StreamManager;
+pub use stream::DEFAULT_STREAM_BACKLOG;
+pub use stream::DEFAULT_STREAM_IDLE_TIMEOUT;
+pub use stream::DEFAULT_STREAM_RTO;
+pub use stream::DEFAULT_STREAM_WINDOW;
+pub use stream::MAX_STREAM_FRAME;
+pub use stream::STREAM_TICK;
+
 mod types;
 pub use types::*;
 
+mod verifier;
+pub use verifier::PayloadVerifier;
+pub use verifier::VerificationStats;
+pub use verifier::DEFAULT_CLOCK_SKEW_MS;
+pub use verifier::DEFAULT_REPLAY_WINDOW;
+
 mod handlers;
 pub use handlers::storage::TChordStorage;
+pub use handlers::storage::TRecordStorage;
+pub use handlers::subring::SubRingOperator;
 pub use handlers::CallbackFn;
 pub use handlers::HandleMsg;
 pub use handlers::MessageCallback;
@@ -23,4 +46,5 @@ pub use handlers::ValidatorFn;
 
 mod protocols;
 pub use protocols::MessageRelay;
+pub use protocols::MessageVerification;
 pub use protocols::RelayMethod;
@@ -23,6 +23,66 @@ use crate::utils;
 
 const DEFAULT_TTL_MS: usize = 60 * 1000;
 
+/// Wire format of payloads sent over data channels.
+/// A frame of binary format starts with a header byte of it's format and version,
+/// whose high bit is set. It never appears at the start of a legacy frame, which is
+/// base58 text, so receivers can decode frames of both, see `MessagePayload::from_auto`.
+/// Legacy frames are sent to a peer until it advertises the binary format in `formats`
+/// of a payload, since nodes of older versions can't decode binary frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PayloadFormat {
+    /// base58 check encoded gzipped JSON without header, which is understood by all nodes
+    #[default]
+    Legacy = 0,
+    /// bincode, version 1, the origin verification is omitted if it's same as the verification
+    BincodeV1 = 0x81,
+}
+
+impl PayloadFormat {
+    /// Header byte of frames in this format, None for legacy frames
+    pub fn header(&self) -> Option<u8> {
+        match self {
+            Self::Legacy => None,
+            f => Some(*f as u8),
+        }
+    }
+
+    /// Format of a frame by it's first byte
+    pub fn from_header(byte: u8) -> Result<Self> {
+        match byte {
+            0x81 => Ok(Self::BincodeV1),
+            b if b & 0x80 != 0 => Err(Error::UnsupportedPayloadFormat(b)),
+            _ => Ok(Self::Legacy),
+        }
+    }
+}
+
+/// Body of a `PayloadFormat::BincodeV1` frame to send, see `BincodeFrame`.
+#[derive(Serialize)]
+struct BincodeFrameRef<'a, T> {
+    data: &'a T,
+    tx_id: &'a uuid::Uuid,
+    addr: &'a Did,
+    verification: &'a MessageVerification,
+    /// None if it's same as the verification, as it is for payloads which are not relayed
+    origin_verification: Option<&'a MessageVerification>,
+    relay: &'a MessageRelay,
+    formats: &'a [u8],
+}
+
+/// Body of a received `PayloadFormat::BincodeV1` frame.
+#[derive(Deserialize)]
+struct BincodeFrame<T> {
+    data: T,
+    tx_id: uuid::Uuid,
+    addr: Did,
+    verification: MessageVerification,
+    origin_verification: Option<MessageVerification>,
+    relay: MessageRelay,
+    formats: Vec<u8>,
+}
+
 pub enum OriginVerificationGen {
     Origin,
     Stick(MessageVerification),
@@ -36,6 +96,10 @@ pub struct MessagePayload<T> {
     pub verification: MessageVerification,
     pub origin_verification: MessageVerification,
     pub relay: MessageRelay,
+    /// Headers of binary formats which the sender of this hop can decode, and `CHUNK_HEADER`
+    /// if it reassembles chunk frames, it's set by each hop and not signed. Nodes of older versions ignore it, and never set it.
+    #[serde(default)]
+    pub formats: Vec<u8>,
 }
 
 impl<T> MessagePayload<T>
@@ -47,17 +111,16 @@ where T: Serialize + DeserializeOwned
         origin_verification_gen: OriginVerificationGen,
         relay: MessageRelay,
     ) -> Result<Self> {
-        let ts_ms = utils::get_epoch_ms();
-        let ttl_ms = DEFAULT_TTL_MS;
-        let msg = &MessageVerification::pack_msg(&data, ts_ms, ttl_ms)?;
         let tx_id = uuid::Uuid::new_v4();
         let addr = session_manager.authorizer()?;
-        let verification = MessageVerification {
-            session: session_manager.session()?,
-            sig: session_manager.sign(msg)?,
-            ttl_ms,
-            ts_ms,
-        };
+        // the origin signature is unique even for same data, which is the key of replay
+        // protection, see `PayloadVerifier`
+        let verification = MessageVerification::new_at(
+            &data,
+            session_manager,
+            DEFAULT_TTL_MS,
+            session_manager.next_ts_ms(),
+        )?;
 
         let origin_verification = match origin_verification_gen {
             OriginVerificationGen::Origin => verification.clone(),
@@ -71,6 +134,7 @@ where T: Serialize + DeserializeOwned
             verification,
             origin_verification,
             relay,
+            formats: vec![],
         })
     }
 
@@ -112,6 +176,12 @@ where T: Serialize + DeserializeOwned
             && now > self.origin_verification.ts_ms + self.origin_verification.ttl_ms as u128
     }
 
+    /// Check if the payload is created more than `ttl_ms` ago,
+    /// regardless of the ttl of it's verification.
+    pub fn is_older_than(&self, ttl_ms: u128) -> bool {
+        utils::get_epoch_ms() > self.origin_verification.ts_ms + ttl_ms
+    }
+
     pub fn verify(&self) -> bool {
         if self.is_expired() {
             return false;
@@ -120,6 +190,12 @@ where T: Serialize + DeserializeOwned
         self.verification.verify(&self.data) && self.origin_verification.verify(&self.data)
     }
 
+    /// Address of origin, which is signed by the origin verification,
+    /// unlike the path of relay which can be modified by any relay node.
+    pub fn origin(&self) -> Did {
+        self.origin_verification.session.auth.authorizer.did
+    }
+
     pub fn origin_session_pubkey(&self) -> Result<PublicKey> {
         self.origin_verification.session_pubkey(&self.data)
     }
@@ -151,11 +227,68 @@ where T: Serialize + DeserializeOwned
         serde_json::to_vec(self).map_err(Error::Serialize)
     }
 
+    /// Decode a frame of any supported format, or gzipped or plain JSON.
     pub fn from_auto(data: &[u8]) -> Result<Self> {
+        let header = *data.first().ok_or(Error::Decode)?;
+        if PayloadFormat::from_header(header)? == PayloadFormat::BincodeV1 {
+            return Self::from_bincode_frame(&data[1..]);
+        }
         if let Ok(m) = Self::from_gzipped(data) {
             return Ok(m);
         }
-        Self::from_json(data)
+        if let Ok(m) = Self::from_json(data) {
+            return Ok(m);
+        }
+        // a legacy frame
+        let encoded = Encoded::try_from(data.to_vec())?;
+        let v: Vec<u8> = encoded.decode()?;
+        Self::from_gzipped(&v).or_else(|_| Self::from_json(&v))
+    }
+
+    pub fn from_bincode(data: &[u8]) -> Result<Self> {
+        bincode::deserialize(data).map_err(Error::BincodeDeserialize)
+    }
+
+    pub fn to_bincode(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).map_err(Error::BincodeSerialize)
+    }
+
+    /// Frame to send over data channel in `format`
+    pub fn to_frame(&self, format: PayloadFormat) -> Result<Vec<u8>> {
+        match format.header() {
+            Some(header) => {
+                let body = BincodeFrameRef {
+                    data: &self.data,
+                    tx_id: &self.tx_id,
+                    addr: &self.addr,
+                    verification: &self.verification,
+                    origin_verification: Some(&self.origin_verification)
+                        .filter(|ov| **ov != self.verification),
+                    relay: &self.relay,
+                    formats: &self.formats,
+                };
+                let mut frame = vec![header];
+                bincode::serialize_into(&mut frame, &body).map_err(Error::BincodeSerialize)?;
+                Ok(frame)
+            }
+            None => Ok(self.encode()?.into()),
+        }
+    }
+
+    fn from_bincode_frame(data: &[u8]) -> Result<Self> {
+        let frame: BincodeFrame<T> =
+            bincode::deserialize(data).map_err(Error::BincodeDeserialize)?;
+        Ok(Self {
+            origin_verification: frame
+                .origin_verification
+                .unwrap_or_else(|| frame.verification.clone()),
+            data: frame.data,
+            tx_id: frame.tx_id,
+            addr: frame.addr,
+            verification: frame.verification,
+            relay: frame.relay,
+            formats: frame.formats,
+        })
     }
 }
 
@@ -226,18 +359,26 @@ where T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static
         .await
     }
 
+    /// Forward the payload with new relay, it's signed by this node again, and the origin
+    /// verification is kept.
+    /// The tx_id of every forwarded payload is kept, not only the lookups, so the report
+    /// of a request, which is answered with the tx_id of request on the destination, can be
+    /// correlated by `PendingRequests` on the origin node, however many hops it takes.
+    /// Thus a payload has same tx_id on all hops of it's path, and tx_id is no longer
+    /// unique per hop.
     async fn transpond_payload(
         &self,
         payload: &MessagePayload<T>,
         relay: MessageRelay,
     ) -> Result<()> {
-        self.send_payload(MessagePayload::new(
+        let mut pl = MessagePayload::new(
             payload.data.clone(),
             self.session_manager(),
             OriginVerificationGen::Stick(payload.origin_verification.clone()),
             relay,
-        )?)
-        .await
+        )?;
+        pl.tx_id = payload.tx_id;
+        self.send_payload(pl).await
     }
 }
 
@@ -310,4 +451,164 @@ pub mod test {
         let payload2: MessagePayload<TestData> = ungzip_encoded_payload.decode().unwrap();
         assert_eq!(payload, payload2);
     }
+
+    #[test]
+    fn test_payload_frame() {
+        let mut payload = new_test_payload();
+        payload.formats = vec![PayloadFormat::BincodeV1 as u8];
+
+        // payload which is relayed has different origin verification
+        let session = SessionManager::new_with_seckey(&SecretKey::random(), None).unwrap();
+        let relayed = MessagePayload::new(
+            payload.data.clone(),
+            &session,
+            OriginVerificationGen::Stick(payload.origin_verification.clone()),
+            payload.relay.clone(),
+        )
+        .unwrap();
+
+        for payload in [payload.clone(), relayed] {
+            for format in [PayloadFormat::Legacy, PayloadFormat::BincodeV1] {
+                let frame = payload.to_frame(format).unwrap();
+                assert_eq!(PayloadFormat::from_header(frame[0]).unwrap(), format);
+                let payload2: MessagePayload<TestData> = MessagePayload::from_auto(&frame).unwrap();
+                assert_eq!(payload, payload2);
+                assert!(payload2.verify());
+            }
+        }
+
+        // frame of unknown version is refused
+        let mut frame = payload.to_frame(PayloadFormat::BincodeV1).unwrap();
+        frame[0] = 0x82;
+        assert!(matches!(
+            MessagePayload::<TestData>::from_auto(&frame),
+            Err(Error::UnsupportedPayloadFormat(0x82))
+        ));
+    }
+
+    mod bench {
+        extern crate test;
+
+        use test::Bencher;
+
+        use super::*;
+        use crate::message::CustomMessage;
+        use crate::message::FindSuccessorReportHandler;
+        use crate::message::FindSuccessorSend;
+        use crate::message::FindSuccessorThen;
+        use crate::message::MaybeEncrypted;
+        use crate::message::Message;
+
+        fn new_payload(data: Message) -> MessagePayload<Message> {
+            let key = SecretKey::random();
+            let destination = SecretKey::random().address().into();
+            let session = SessionManager::new_with_seckey(&key, None).unwrap();
+            MessagePayload::new_direct(data, &session, destination).unwrap()
+        }
+
+        fn find_successor() -> MessagePayload<Message> {
+            new_payload(Message::FindSuccessorSend(FindSuccessorSend {
+                id: SecretKey::random().address().into(),
+                strict: false,
+                then: FindSuccessorThen::Report(FindSuccessorReportHandler::Connect),
+            }))
+        }
+
+        fn custom_message() -> MessagePayload<Message> {
+            let data = (0..1024).map(|_| rand::random::<u8>()).collect();
+            new_payload(Message::CustomMessage(MaybeEncrypted::Plain(
+                CustomMessage(data),
+            )))
+        }
+
+        #[test]
+        fn test_frame_size() {
+            // min percentage of bytes saved by binary frames
+            for (name, payload, min_saved) in [
+                ("FindSuccessorSend", find_successor(), 20),
+                ("CustomMessage(1KiB)", custom_message(), 30),
+            ] {
+                let legacy = payload.to_frame(PayloadFormat::Legacy).unwrap().len();
+                let bincode = payload.to_frame(PayloadFormat::BincodeV1).unwrap().len();
+                let saved = 100 - bincode * 100 / legacy;
+                assert!(
+                    saved >= min_saved,
+                    "{}: legacy {} bytes, bincode {} bytes, saved {}% < {}%",
+                    name,
+                    legacy,
+                    bincode,
+                    saved,
+                    min_saved
+                );
+            }
+        }
+
+        /// Frame of payload in format, which is checked to be decoded as the payload.
+        fn checked_frame(payload: &MessagePayload<Message>, format: PayloadFormat) -> Vec<u8> {
+            let frame = payload.to_frame(format).unwrap();
+            assert_eq!(PayloadFormat::from_header(frame[0]).unwrap(), format);
+            assert_eq!(&MessagePayload::from_auto(&frame).unwrap(), payload);
+            frame
+        }
+
+        fn encode(b: &mut Bencher, payload: MessagePayload<Message>, format: PayloadFormat) {
+            let frame = checked_frame(&payload, format);
+            b.bytes = frame.len() as u64;
+            b.iter(|| {
+                let f = payload.to_frame(format).unwrap();
+                assert_eq!(f.len(), frame.len());
+                f
+            });
+        }
+
+        fn decode(b: &mut Bencher, payload: MessagePayload<Message>, format: PayloadFormat) {
+            let frame = checked_frame(&payload, format);
+            b.bytes = frame.len() as u64;
+            b.iter(|| {
+                let p = MessagePayload::<Message>::from_auto(&frame).unwrap();
+                assert_eq!(p.tx_id, payload.tx_id);
+                p
+            });
+        }
+
+        #[bench]
+        fn bench_encode_find_successor_legacy(b: &mut Bencher) {
+            encode(b, find_successor(), PayloadFormat::Legacy)
+        }
+
+        #[bench]
+        fn bench_encode_find_successor_bincode(b: &mut Bencher) {
+            encode(b, find_successor(), PayloadFormat::BincodeV1)
+        }
+
+        #[bench]
+        fn bench_decode_find_successor_legacy(b: &mut Bencher) {
+            decode(b, find_successor(), PayloadFormat::Legacy)
+        }
+
+        #[bench]
+        fn bench_decode_find_successor_bincode(b: &mut Bencher) {
+            decode(b, find_successor(), PayloadFormat::BincodeV1)
+        }
+
+        #[bench]
+        fn bench_encode_custom_message_legacy(b: &mut Bencher) {
+            encode(b, custom_message(), PayloadFormat::Legacy)
+        }
+
+        #[bench]
+        fn bench_encode_custom_message_bincode(b: &mut Bencher) {
+            encode(b, custom_message(), PayloadFormat::BincodeV1)
+        }
+
+        #[bench]
+        fn bench_decode_custom_message_legacy(b: &mut Bencher) {
+            decode(b, custom_message(), PayloadFormat::Legacy)
+        }
+
+        #[bench]
+        fn bench_decode_custom_message_bincode(b: &mut Bencher) {
+            decode(b, custom_message(), PayloadFormat::BincodeV1)
+        }
+    }
 }
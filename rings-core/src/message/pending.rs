@@ -0,0 +1,144 @@
+#![warn(missing_docs)]
+//! Pending requests which are waiting for report, keyed by tx_id.
+//! A report reuses tx_id of the request via `MessagePayload::new_report`,
+//! so that it can be correlated with the request when it reaches the origin.
+use std::time::Duration;
+
+use dashmap::DashMap;
+use futures::channel::oneshot;
+use futures::future::select;
+use futures::future::Either;
+use futures_timer::Delay;
+
+use super::Message;
+use super::MessagePayload;
+use super::RelayMethod;
+use crate::dht::Did;
+use crate::err::Error;
+use crate::err::Result;
+
+/// Default timeout of waiting for a report
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Table of pending requests
+#[derive(Default)]
+pub struct PendingRequests {
+    table: DashMap<uuid::Uuid, oneshot::Sender<MessagePayload<Message>>>,
+}
+
+impl PendingRequests {
+    /// Create an empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of requests which are waiting for report
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Check if there is no request waiting for report
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Register a request, the returned receiver will get the report
+    pub fn register(&self, tx_id: uuid::Uuid) -> oneshot::Receiver<MessagePayload<Message>> {
+        let (sender, receiver) = oneshot::channel();
+        self.table.insert(tx_id, sender);
+        receiver
+    }
+
+    /// Forget a request, the report of it will be ignored
+    pub fn cancel(&self, tx_id: &uuid::Uuid) {
+        self.table.remove(tx_id);
+    }
+
+    /// Wake up the request which is waiting for the payload.
+    /// Only a report that reaches its destination can resolve a request.
+    /// Return true if a waiting request is found.
+    pub fn resolve(&self, current: Did, payload: &MessagePayload<Message>) -> bool {
+        if payload.relay.method != RelayMethod::REPORT || payload.relay.destination != current {
+            return false;
+        }
+        match self.table.remove(&payload.tx_id) {
+            Some((_, sender)) => {
+                if sender.send(payload.clone()).is_err() {
+                    tracing::debug!("[PendingRequests] request {:?} is dropped", payload.tx_id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wait for the report of a registered request, the request is cancelled on timeout.
+    pub async fn wait(
+        &self,
+        tx_id: uuid::Uuid,
+        receiver: oneshot::Receiver<MessagePayload<Message>>,
+        timeout: Duration,
+    ) -> Result<MessagePayload<Message>> {
+        match select(receiver, Delay::new(timeout)).await {
+            Either::Left((Ok(payload), _)) => Ok(payload),
+            _ => {
+                self.cancel(&tx_id);
+                Err(Error::RequestTimeout(tx_id))
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::JoinDHT;
+    use crate::session::SessionManager;
+
+    #[tokio::test]
+    async fn test_pending_requests() -> Result<()> {
+        let key1 = SecretKey::random();
+        let key2 = SecretKey::random();
+        let did1: Did = key1.address().into();
+        let did2: Did = key2.address().into();
+        let sm1 = SessionManager::new_with_seckey(&key1, None)?;
+        let sm2 = SessionManager::new_with_seckey(&key2, None)?;
+        let pending = PendingRequests::new();
+
+        let request =
+            MessagePayload::new_direct(Message::JoinDHT(JoinDHT { id: did1 }), &sm1, did2)?;
+        let mut relay = request.relay.clone();
+        relay.relay(did2, None)?;
+        let report = MessagePayload::new_report(
+            Message::JoinDHT(JoinDHT { id: did2 }),
+            request.tx_id,
+            &sm2,
+            &relay,
+        )?;
+
+        // a request is resolved only by a report reaching its origin
+        let receiver = pending.register(request.tx_id);
+        assert!(!pending.resolve(did1, &request));
+        assert!(!pending.resolve(did2, &report));
+        assert!(pending.resolve(did1, &report));
+        assert!(pending.is_empty());
+        let got = pending
+            .wait(request.tx_id, receiver, Duration::from_millis(100))
+            .await?;
+        assert_eq!(got.data, report.data);
+
+        // an unanswered request is cancelled on timeout
+        let receiver = pending.register(request.tx_id);
+        assert!(matches!(
+            pending
+                .wait(request.tx_id, receiver, Duration::from_millis(100))
+                .await,
+            Err(Error::RequestTimeout(id)) if id == request.tx_id
+        ));
+        assert!(pending.is_empty());
+        assert!(!pending.resolve(did1, &report));
+        Ok(())
+    }
+}
@@ -8,6 +8,8 @@ use crate::ecc::PublicKey;
 use crate::err::Error;
 use crate::err::Result;
 use crate::session::Session;
+use crate::session::SessionManager;
+use crate::utils;
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct MessageVerification {
@@ -18,14 +20,53 @@ pub struct MessageVerification {
 }
 
 impl MessageVerification {
+    /// Sign data with session key, the signature is valid in `ttl_ms`.
+    pub fn new<T>(data: &T, session_manager: &SessionManager, ttl_ms: usize) -> Result<Self>
+    where T: Serialize {
+        Self::new_at(data, session_manager, ttl_ms, utils::get_epoch_ms())
+    }
+
+    /// Sign data with session key at `ts_ms`, the signature is valid in `ttl_ms` after it.
+    pub fn new_at<T>(
+        data: &T,
+        session_manager: &SessionManager,
+        ttl_ms: usize,
+        ts_ms: u128,
+    ) -> Result<Self>
+    where
+        T: Serialize,
+    {
+        let msg = Self::pack_msg(data, ts_ms, ttl_ms)?;
+        Ok(Self {
+            session: session_manager.session()?,
+            sig: session_manager.sign(&msg)?,
+            ttl_ms,
+            ts_ms,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        utils::get_epoch_ms() > self.ts_ms + self.ttl_ms as u128
+    }
+
     pub fn verify<T>(&self, data: &T) -> bool
     where T: Serialize {
-        if !self.session.verify() {
-            return false;
-        }
+        self.session.verify() && self.verify_signature(data)
+    }
+
+    /// Verify data which outlives the session, such as a record on DHT.
+    /// The data is valid if it's signed by a session which is valid at the time of signing.
+    pub fn verify_signed_at<T>(&self, data: &T) -> bool
+    where T: Serialize {
+        self.ts_ms <= utils::get_epoch_ms()
+            && self.session.verify_at(self.ts_ms)
+            && self.verify_signature(data)
+    }
 
-        if let (Ok(did), Ok(msg)) = (self.session.did(), self.msg(data)) {
-            signers::default::verify(&msg, &did, &self.sig)
+    fn verify_signature<T>(&self, data: &T) -> bool
+    where T: Serialize {
+        if let Ok(msg) = self.msg(data) {
+            signers::default::verify(&msg, &self.session.auth.did, &self.sig)
         } else {
             false
         }
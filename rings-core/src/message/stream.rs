@@ -0,0 +1,813 @@
+#![warn(missing_docs)]
+//! Byte streams between two dids over the ring.
+//! A stream is opened by `OpenStream`. Bytes written to it are split into `StreamData`
+//! frames, which are sequenced, acknowledged by `StreamAck`, and retransmitted until
+//! they are acknowledged. A writer keeps no more unacknowledged frames than the window
+//! advertised by the reader.
+//! Streams are driven by the message listener, see `Swarm::drive_streams`, which sends
+//! frames written to streams, sends unacknowledged frames again after a timeout, and probes remote by keepalive frames if
+//! the window is closed or nothing is sent in a while. A stream expires if nothing is
+//! received from remote in idle timeout, and so does an incoming stream which is not
+//! accepted in it.
+//! Frames are sent directly if the remote is connected, otherwise they are relayed
+//! by nodes on the ring, as other messages are.
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use futures::future::poll_fn;
+use futures::io::AsyncRead;
+use futures::io::AsyncWrite;
+use futures::lock::Mutex as FuturesMutex;
+use futures::ready;
+use futures::Future;
+
+use super::CloseStream;
+use super::Message;
+use super::MessagePayload;
+use super::OpenStream;
+use super::PayloadSender;
+use super::StreamAck;
+use super::StreamData;
+use super::DEFAULT_REQUEST_TIMEOUT;
+use crate::dht::Chord;
+use crate::dht::Did;
+use crate::dht::PeerRingAction;
+use crate::ecc::PublicKey;
+use crate::err::Error;
+use crate::err::Result;
+use crate::swarm::Swarm;
+use crate::transports::manager::TransportManager;
+use crate::utils;
+
+/// Max size of data in a frame
+pub const MAX_STREAM_FRAME: usize = 16 * 1024;
+/// Frames a reader can take before they are read by default
+pub const DEFAULT_STREAM_WINDOW: u32 = 64;
+/// Timeout of acknowledgement, unacknowledged frames are sent again after it
+pub const DEFAULT_STREAM_RTO: Duration = Duration::from_secs(1);
+/// Max number of incoming streams waiting to be accepted by default
+pub const DEFAULT_STREAM_BACKLOG: usize = 64;
+/// A stream expires if nothing is received from remote in this time by default,
+/// and so does an incoming stream which is not accepted in it.
+pub const DEFAULT_STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Interval of driving streams by the message listener
+pub const STREAM_TICK: Duration = Duration::from_millis(250);
+/// Seq of keepalive frames, which is beyond any window,
+/// so they are dropped by remote and answered with an ack.
+const KEEPALIVE_SEQ: u64 = u64::MAX;
+
+#[cfg(not(feature = "wasm"))]
+type SendFuture = futures::future::BoxFuture<'static, Result<()>>;
+#[cfg(feature = "wasm")]
+type SendFuture = futures::future::LocalBoxFuture<'static, Result<()>>;
+
+/// Pick next hop of a message to destination, it's sent directly if destination is connected.
+pub(crate) fn next_hop(swarm: &Swarm, destination: Did) -> Result<Did> {
+    if swarm.get_transport(destination).is_some() {
+        return Ok(destination);
+    }
+    match swarm.dht.find_successor(destination)? {
+        PeerRingAction::Some(node) => Ok(node),
+        PeerRingAction::RemoteAction(node, _) => Ok(node),
+        _ => Err(Error::NoNextHop),
+    }
+}
+
+/// Send a frame of stream to destination
+pub(crate) async fn send_frame(swarm: &Swarm, msg: Message, destination: Did) -> Result<()> {
+    let next_hop = next_hop(swarm, destination)?;
+    swarm.send_message(msg, next_hop, destination).await
+}
+
+fn data_frame(stream_id: uuid::Uuid, seq: u64, data: Vec<u8>) -> Message {
+    Message::StreamData(StreamData {
+        stream_id,
+        seq,
+        data,
+    })
+}
+
+#[derive(Default)]
+struct StreamState {
+    /// seq of next frame to read
+    recv_next: u64,
+    out_of_order: BTreeMap<u64, Vec<u8>>,
+    readable: VecDeque<Vec<u8>>,
+    /// bytes of the first readable frame which are read
+    read_offset: usize,
+    /// seq after the last frame sent by remote, it's set when remote closed
+    fin: Option<u64>,
+    read_waker: Option<Waker>,
+    /// all frames before it are acknowledged by remote
+    acked: u64,
+    /// frames remote can take
+    window: u32,
+    /// sent frames which are not acknowledged yet
+    unacked: BTreeMap<u64, Vec<u8>>,
+    /// time to send unacknowledged frames again, or probe remote if the window is closed,
+    /// it's reset when frames are acknowledged
+    rto_at: u128,
+    /// time of the last frame received from remote
+    last_recv: u128,
+    /// time of the last frame sent to remote
+    last_sent: u128,
+    /// nothing is received from remote in idle timeout
+    expired: bool,
+    /// session pubkey of remote, which signed the opening or the acceptance of stream
+    remote_pubkey: Option<PublicKey>,
+    write_waker: Option<Waker>,
+}
+
+impl StreamState {
+    fn window(&self) -> u32 {
+        let taken = self.readable.len() + self.out_of_order.len();
+        DEFAULT_STREAM_WINDOW.saturating_sub(taken as u32)
+    }
+
+    fn wake(waker: &mut Option<Waker>) {
+        if let Some(w) = waker.take() {
+            w.wake()
+        }
+    }
+}
+
+/// State of a stream, which is shared by it's handle and message handler
+pub(crate) struct StreamShared {
+    id: uuid::Uuid,
+    remote: Did,
+    opened_at: u128,
+    state: Mutex<StreamState>,
+}
+
+impl StreamShared {
+    fn new(id: uuid::Uuid, remote: Did, window: u32) -> Self {
+        let now = utils::get_epoch_ms();
+        Self {
+            id,
+            remote,
+            opened_at: now,
+            state: Mutex::new(StreamState {
+                window,
+                last_recv: now,
+                last_sent: now,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub(crate) fn remote(&self) -> Did {
+        self.remote
+    }
+
+    fn set_remote_pubkey(&self, pubkey: Option<PublicKey>) {
+        self.state.lock().unwrap().remote_pubkey = pubkey;
+    }
+
+    /// Check if a frame signed by `pubkey` is from remote session of stream.
+    /// Any session of remote is taken before the stream is accepted.
+    fn is_signed_by(&self, pubkey: Option<PublicKey>) -> bool {
+        match self.state.lock().unwrap().remote_pubkey {
+            Some(remote_pubkey) => pubkey == Some(remote_pubkey),
+            None => true,
+        }
+    }
+
+    /// Take a frame from remote, return the ack and window to advertise.
+    /// Duplicated frames, frames beyond the window and oversized frames are dropped,
+    /// and out of order ones are kept in window.
+    pub(crate) fn on_data(&self, seq: u64, data: Vec<u8>) -> (u64, u32) {
+        let mut guard = self.state.lock().unwrap();
+        let st = &mut *guard;
+        st.last_recv = utils::get_epoch_ms();
+        let in_window = seq >= st.recv_next
+            && seq - st.recv_next < st.window() as u64
+            && st.fin.map_or(true, |f| seq < f);
+        if in_window && data.len() <= MAX_STREAM_FRAME {
+            st.out_of_order.insert(seq, data);
+            while let Some(data) = st.out_of_order.remove(&st.recv_next) {
+                if !data.is_empty() {
+                    st.readable.push_back(data);
+                }
+                st.recv_next += 1;
+            }
+            StreamState::wake(&mut st.read_waker);
+        }
+        (st.recv_next, st.window())
+    }
+
+    /// Take an ack from remote, acknowledged frames are dropped and the timer of
+    /// retransmission restarts.
+    pub(crate) fn on_ack(&self, ack: u64, window: u32) {
+        let mut st = self.state.lock().unwrap();
+        let now = utils::get_epoch_ms();
+        st.last_recv = now;
+        if ack > st.acked {
+            st.acked = ack;
+            st.unacked = st.unacked.split_off(&ack);
+            st.rto_at = now + DEFAULT_STREAM_RTO.as_millis();
+        }
+        st.window = window;
+        StreamState::wake(&mut st.write_waker);
+    }
+
+    /// Remote closed the stream after frame `seq`, return the ack and window to advertise.
+    pub(crate) fn on_close(&self, seq: u64) -> (u64, u32) {
+        let mut st = self.state.lock().unwrap();
+        st.last_recv = utils::get_epoch_ms();
+        st.fin = Some(seq);
+        StreamState::wake(&mut st.read_waker);
+        (st.recv_next, st.window())
+    }
+
+    /// Frames to send at `now`, which are unacknowledged frames after timeout of ack,
+    /// or a keepalive if the window is closed for a writer, or nothing is sent in a third
+    /// of `idle_timeout`. Return None if nothing is received from remote in `idle_timeout`.
+    fn due_frames(&self, now: u128, idle_timeout: u128) -> Option<Vec<Message>> {
+        let mut guard = self.state.lock().unwrap();
+        let st = &mut *guard;
+        if st.expired || now > st.last_recv + idle_timeout {
+            st.expired = true;
+            StreamState::wake(&mut st.read_waker);
+            StreamState::wake(&mut st.write_waker);
+            return None;
+        }
+        let rto_due = now >= st.rto_at;
+        let frames = if !st.unacked.is_empty() {
+            if !rto_due {
+                return Some(vec![]);
+            }
+            st.unacked
+                .iter()
+                .map(|(seq, data)| data_frame(self.id, *seq, data.clone()))
+                .collect()
+        } else if (rto_due && st.window == 0 && st.write_waker.is_some())
+            || now >= st.last_sent + idle_timeout / 3
+        {
+            vec![data_frame(self.id, KEEPALIVE_SEQ, vec![])]
+        } else {
+            return Some(vec![]);
+        };
+        st.rto_at = now + DEFAULT_STREAM_RTO.as_millis();
+        st.last_sent = now;
+        Some(frames)
+    }
+}
+
+/// Streams of a swarm, and incoming streams waiting to be accepted
+pub struct StreamManager {
+    streams: DashMap<uuid::Uuid, Arc<StreamShared>>,
+    backlog: usize,
+    idle_timeout: Duration,
+    /// streams opened by remote, in order of opening
+    incoming: Mutex<VecDeque<Arc<StreamShared>>>,
+    accept_waker: Mutex<Option<Waker>>,
+    /// streams are accepted one by one
+    acceptor: FuturesMutex<()>,
+    /// frames to send with remote of them, which are written to streams,
+    /// or `CloseStream` of dropped streams
+    outgoing: Mutex<Vec<(Did, Message)>>,
+    /// the message listener waiting for outgoing frames
+    drive_waker: Mutex<Option<Waker>>,
+}
+
+impl Default for StreamManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_STREAM_BACKLOG, DEFAULT_STREAM_IDLE_TIMEOUT)
+    }
+}
+
+impl StreamManager {
+    /// Create a manager which keeps at most `backlog` streams waiting to be accepted,
+    /// and expires streams which are idle or not accepted in `idle_timeout`.
+    pub fn new(backlog: usize, idle_timeout: Duration) -> Self {
+        Self {
+            streams: DashMap::new(),
+            backlog,
+            idle_timeout,
+            incoming: Mutex::new(VecDeque::new()),
+            accept_waker: Mutex::new(None),
+            acceptor: FuturesMutex::new(()),
+            outgoing: Mutex::new(vec![]),
+            drive_waker: Mutex::new(None),
+        }
+    }
+
+    /// Number of open streams
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Check if there is no open stream
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    pub(crate) fn register(&self, id: uuid::Uuid, remote: Did, window: u32) -> Arc<StreamShared> {
+        let stream = Arc::new(StreamShared::new(id, remote, window));
+        self.streams.insert(id, stream.clone());
+        stream
+    }
+
+    /// Register a stream opened by remote, it's refused if too many streams are waiting.
+    pub(crate) fn incoming(
+        &self,
+        id: uuid::Uuid,
+        remote: Did,
+        window: u32,
+        pubkey: Option<PublicKey>,
+    ) -> Result<()> {
+        if self.streams.contains_key(&id) {
+            return Err(Error::StreamRefused(remote));
+        }
+        let mut incoming = self.incoming.lock().unwrap();
+        self.expire_incoming(&mut incoming, utils::get_epoch_ms());
+        if incoming.len() >= self.backlog {
+            return Err(Error::StreamRefused(remote));
+        }
+        let stream = self.register(id, remote, window);
+        stream.set_remote_pubkey(pubkey);
+        incoming.push_back(stream);
+        StreamState::wake(&mut self.accept_waker.lock().unwrap());
+        Ok(())
+    }
+
+    /// Forget incoming streams which are not accepted in idle timeout,
+    /// frames of them are refused since then.
+    fn expire_incoming(&self, incoming: &mut VecDeque<Arc<StreamShared>>, now: u128) {
+        let timeout = self.idle_timeout.as_millis();
+        while incoming
+            .front()
+            .map_or(false, |s| now > s.opened_at + timeout)
+        {
+            if let Some(s) = incoming.pop_front() {
+                self.streams.remove(&s.id);
+            }
+        }
+    }
+
+    /// Wait for a stream opened by remote
+    pub(crate) async fn accept(&self) -> Arc<StreamShared> {
+        let _acceptor = self.acceptor.lock().await;
+        poll_fn(|cx| {
+            let mut incoming = self.incoming.lock().unwrap();
+            self.expire_incoming(&mut incoming, utils::get_epoch_ms());
+            match incoming.pop_front() {
+                Some(stream) => Poll::Ready(stream),
+                None => {
+                    *self.accept_waker.lock().unwrap() = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Get a stream which is opened with `remote`, a frame of it must be signed by the
+    /// session of remote which opened or accepted it.
+    pub(crate) fn get(
+        &self,
+        id: &uuid::Uuid,
+        remote: Did,
+        pubkey: Option<PublicKey>,
+    ) -> Result<Arc<StreamShared>> {
+        match self.streams.get(id) {
+            Some(s) if s.remote == remote && s.is_signed_by(pubkey) => Ok(s.clone()),
+            _ => Err(Error::StreamNotFound(*id)),
+        }
+    }
+
+    pub(crate) fn remove(&self, id: &uuid::Uuid) {
+        self.streams.remove(id);
+    }
+
+    /// Queue a frame to send, which is sent by `Swarm::drive_streams`.
+    fn send_later(&self, remote: Did, msg: Message) {
+        self.outgoing.lock().unwrap().push((remote, msg));
+        StreamState::wake(&mut self.drive_waker.lock().unwrap());
+    }
+
+    /// Ready if there are frames queued to send, see `Swarm::drive_streams`.
+    #[cfg(not(feature = "wasm"))]
+    pub(crate) fn poll_outgoing(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let outgoing = self.outgoing.lock().unwrap();
+        if !outgoing.is_empty() {
+            return Poll::Ready(());
+        }
+        *self.drive_waker.lock().unwrap() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Frames of all streams to send at `now` with their remote, see `StreamShared::due_frames`.
+    /// Expired streams are forgotten.
+    fn due_frames(&self, now: u128) -> Vec<(Did, Message)> {
+        let mut frames = std::mem::take(&mut *self.outgoing.lock().unwrap());
+        self.expire_incoming(&mut self.incoming.lock().unwrap(), now);
+        let idle_timeout = self.idle_timeout.as_millis();
+        let mut expired = vec![];
+        for stream in self.streams.iter() {
+            match stream.due_frames(now, idle_timeout) {
+                Some(due) => frames.extend(due.into_iter().map(|f| (stream.remote, f))),
+                None => expired.push(stream.id),
+            }
+        }
+        for id in expired {
+            tracing::info!("stream {} expired", id);
+            self.streams.remove(&id);
+        }
+        frames
+    }
+}
+
+/// A byte stream to a remote did, see `Swarm::open_stream` and `Swarm::accept_stream`.
+/// Closing it sends the rest bytes and tells remote no more bytes. Dropping it forgets
+/// the stream, so frames of it from remote are refused since then, and tells remote no
+/// more bytes if it's not closed, bytes which are not acknowledged yet may be lost.
+/// Reading or writing a stream which expired fails with `io::ErrorKind::TimedOut`.
+pub struct ByteStream {
+    shared: Arc<StreamShared>,
+    swarm: Arc<Swarm>,
+    next_seq: u64,
+    sending: Option<SendFuture>,
+    closed: bool,
+}
+
+fn io_error(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+impl ByteStream {
+    pub(crate) fn new(swarm: Arc<Swarm>, shared: Arc<StreamShared>) -> Self {
+        Self {
+            shared,
+            swarm,
+            next_seq: 0,
+            sending: None,
+            closed: false,
+        }
+    }
+
+    /// Id of stream
+    pub fn id(&self) -> uuid::Uuid {
+        self.shared.id
+    }
+
+    /// The remote did
+    pub fn remote(&self) -> Did {
+        self.shared.remote
+    }
+
+    /// Session pubkey of remote, data can be encrypted by it for remote
+    pub fn remote_pubkey(&self) -> Option<PublicKey> {
+        self.shared.state.lock().unwrap().remote_pubkey
+    }
+
+    /// Queue a frame to send, the message listener is woken to send it,
+    /// and so is a task on wasm.
+    fn send_later(&self, frame: Message) {
+        self.swarm.streams.send_later(self.shared.remote, frame);
+        #[cfg(feature = "wasm")]
+        {
+            let swarm = self.swarm.clone();
+            wasm_bindgen_futures::spawn_local(async move { swarm.drive_streams().await });
+        }
+    }
+
+    /// Drive `CloseStream` being sent
+    fn poll_sending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(f) = self.sending.as_mut() {
+            let ret = ready!(f.as_mut().poll(cx));
+            self.sending = None;
+            ret.map_err(io_error)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Wait for all frames to be acknowledged, they are sent again by `Swarm::drive_streams`.
+    fn poll_flush_frames(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_sending(cx))?;
+        let mut st = self.shared.state.lock().unwrap();
+        if st.unacked.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        if st.expired {
+            return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+        }
+        st.write_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for ByteStream {
+    fn drop(&mut self) {
+        self.swarm.streams.remove(&self.shared.id);
+        let expired = self.shared.state.lock().unwrap().expired;
+        if !self.closed && !expired {
+            let msg = Message::CloseStream(CloseStream {
+                stream_id: self.shared.id,
+                seq: self.next_seq,
+            });
+            self.send_later(msg);
+        }
+    }
+}
+
+impl AsyncRead for ByteStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut guard = self.shared.state.lock().unwrap();
+        let st = &mut *guard;
+        if let Some(frame) = st.readable.front() {
+            let data = &frame[st.read_offset..];
+            let n = data.len().min(buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            st.read_offset += n;
+            if st.read_offset == frame.len() {
+                st.readable.pop_front();
+                st.read_offset = 0;
+            }
+            return Poll::Ready(Ok(n));
+        }
+        if st.fin.map_or(false, |f| f <= st.recv_next) {
+            return Poll::Ready(Ok(0));
+        }
+        if st.expired {
+            return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+        }
+        st.read_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for ByteStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_sending(cx))?;
+        if this.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = buf.len().min(MAX_STREAM_FRAME);
+        let seq = this.next_seq;
+        {
+            let mut st = this.shared.state.lock().unwrap();
+            if st.expired {
+                return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+            }
+            // wait for acks, or the window is opened by an ack of keepalive
+            if st.unacked.len() >= st.window as usize {
+                st.write_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let now = utils::get_epoch_ms();
+            if st.unacked.is_empty() {
+                st.rto_at = now + DEFAULT_STREAM_RTO.as_millis();
+            }
+            st.last_sent = now;
+            st.unacked.insert(seq, buf[..n].to_vec());
+        }
+        this.next_seq += 1;
+        this.send_later(data_frame(this.shared.id, seq, buf[..n].to_vec()));
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_frames(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closed {
+            ready!(this.poll_flush_frames(cx))?;
+            this.closed = true;
+            let msg = Message::CloseStream(CloseStream {
+                stream_id: this.shared.id,
+                seq: this.next_seq,
+            });
+            let swarm = this.swarm.clone();
+            let remote = this.shared.remote;
+            this.sending = Some(Box::pin(async move {
+                let payload = MessagePayload::new_send(
+                    msg,
+                    swarm.session_manager(),
+                    next_hop(&swarm, remote)?,
+                    remote,
+                )?;
+                swarm
+                    .send_payload_and_wait(payload, DEFAULT_REQUEST_TIMEOUT)
+                    .await
+                    .map(|_| ())
+            }));
+        }
+        this.poll_sending(cx)
+    }
+}
+
+impl Swarm {
+    /// Open a byte stream to `did`, which is accepted by `Swarm::accept_stream` of it.
+    pub async fn open_stream(self: &Arc<Self>, did: Did) -> Result<ByteStream> {
+        let id = uuid::Uuid::new_v4();
+        // frames may arrive before the report of opening
+        let shared = self.streams.register(id, did, 0);
+        let msg = Message::OpenStream(OpenStream {
+            stream_id: id,
+            window: DEFAULT_STREAM_WINDOW,
+        });
+        let payload = next_hop(self, did)
+            .and_then(|hop| MessagePayload::new_send(msg, self.session_manager(), hop, did));
+        let ret = match payload {
+            Ok(payload) => {
+                self.send_payload_and_wait(payload, DEFAULT_REQUEST_TIMEOUT)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match ret {
+            Ok(report) => match &report.data {
+                Message::StreamAck(StreamAck { window, .. }) if report.origin() == did => {
+                    shared.set_remote_pubkey(report.origin_session_pubkey().ok());
+                    shared.on_ack(0, *window);
+                    Ok(ByteStream::new(self.clone(), shared))
+                }
+                _ => {
+                    self.streams.remove(&id);
+                    Err(Error::StreamRefused(did))
+                }
+            },
+            Err(e) => {
+                self.streams.remove(&id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Wait for a byte stream opened by remote
+    pub async fn accept_stream(self: &Arc<Self>) -> ByteStream {
+        let shared = self.streams.accept().await;
+        ByteStream::new(self.clone(), shared)
+    }
+
+    /// Send frames of streams which are due, such as frames written to streams,
+    /// retransmissions, keepalives and `CloseStream` of dropped streams, and forget
+    /// expired streams. It's called every `STREAM_TICK` by the message listener,
+    /// and once frames are written to streams.
+    pub async fn drive_streams(&self) {
+        for (remote, frame) in self.streams.due_frames(utils::get_epoch_ms()) {
+            if let Err(e) = send_frame(self, frame, remote).await {
+                tracing::debug!("failed to send frame of stream to {:?}: {}", remote, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+
+    #[test]
+    fn test_reassemble_frames_of_stream() {
+        let remote: Did = SecretKey::random().address().into();
+        let stream = StreamShared::new(uuid::Uuid::new_v4(), remote, 0);
+
+        // out of order frame is kept until the gap is filled
+        assert_eq!(
+            stream.on_data(1, b"world".to_vec()),
+            (0, DEFAULT_STREAM_WINDOW - 1)
+        );
+        assert_eq!(
+            stream.on_data(0, b"hello ".to_vec()),
+            (2, DEFAULT_STREAM_WINDOW - 2)
+        );
+        // duplicated, beyond window, and oversized
+        assert_eq!(stream.on_data(0, b"hello ".to_vec()).0, 2);
+        let window = DEFAULT_STREAM_WINDOW - 2;
+        assert_eq!(stream.on_data(2 + window as u64, vec![0]), (2, window));
+        assert_eq!(stream.on_data(1 << 20, vec![0]), (2, window));
+        assert_eq!(
+            stream.on_data(2, vec![0; MAX_STREAM_FRAME + 1]),
+            (2, window)
+        );
+
+        // frames after close are dropped
+        assert_eq!(stream.on_close(3), (2, DEFAULT_STREAM_WINDOW - 2));
+        assert_eq!(stream.on_data(3, b"!".to_vec()).0, 2);
+        assert_eq!(stream.on_data(2, vec![]), (3, DEFAULT_STREAM_WINDOW - 2));
+
+        let st = stream.state.lock().unwrap();
+        let data: Vec<u8> = st.readable.iter().flatten().copied().collect();
+        assert_eq!(data, b"hello world".to_vec());
+        assert_eq!(st.fin, Some(st.recv_next));
+    }
+
+    #[test]
+    fn test_retransmit_and_expire_stream() {
+        let remote: Did = SecretKey::random().address().into();
+        let stream = StreamShared::new(uuid::Uuid::new_v4(), remote, DEFAULT_STREAM_WINDOW);
+        let rto = DEFAULT_STREAM_RTO.as_millis();
+        let idle_timeout = 60 * rto;
+        let now = utils::get_epoch_ms();
+        {
+            let mut st = stream.state.lock().unwrap();
+            st.unacked.insert(0, b"hello".to_vec());
+            st.unacked.insert(1, b"world".to_vec());
+            st.rto_at = now + rto;
+        }
+
+        // unacknowledged frames are sent again after timeout of ack
+        assert_eq!(stream.due_frames(now, idle_timeout), Some(vec![]));
+        let due = stream.due_frames(now + rto, idle_timeout).unwrap();
+        assert_eq!(due, vec![
+            data_frame(stream.id, 0, b"hello".to_vec()),
+            data_frame(stream.id, 1, b"world".to_vec()),
+        ]);
+
+        // an ack restarts the timer
+        stream.on_ack(1, DEFAULT_STREAM_WINDOW);
+        let acked_at = stream.state.lock().unwrap().last_recv;
+        assert_eq!(
+            stream.due_frames(acked_at + rto - 1, idle_timeout),
+            Some(vec![])
+        );
+        let due = stream.due_frames(acked_at + rto, idle_timeout).unwrap();
+        assert_eq!(due, vec![data_frame(stream.id, 1, b"world".to_vec())]);
+
+        // remote is probed by keepalive if nothing is sent in a while
+        stream.on_ack(2, DEFAULT_STREAM_WINDOW);
+        let sent_at = stream.state.lock().unwrap().last_sent;
+        assert_eq!(
+            stream.due_frames(sent_at + idle_timeout / 3, idle_timeout),
+            Some(vec![data_frame(stream.id, KEEPALIVE_SEQ, vec![])])
+        );
+
+        // expired if nothing is received in idle timeout
+        assert_eq!(
+            stream.due_frames(acked_at + idle_timeout + rto + 1, idle_timeout),
+            None
+        );
+        assert!(stream.state.lock().unwrap().expired);
+    }
+
+    #[test]
+    fn test_expire_incoming_stream() {
+        let remote: Did = SecretKey::random().address().into();
+        let manager = StreamManager::new(1, Duration::from_millis(100));
+        let id = uuid::Uuid::new_v4();
+        manager
+            .incoming(id, remote, DEFAULT_STREAM_WINDOW, None)
+            .unwrap();
+
+        // backlog is full
+        let id2 = uuid::Uuid::new_v4();
+        assert!(manager
+            .incoming(id2, remote, DEFAULT_STREAM_WINDOW, None)
+            .is_err());
+
+        // not accepted in idle timeout
+        let opened_at = manager.get(&id, remote, None).unwrap().opened_at;
+        manager.due_frames(opened_at + 100);
+        assert_eq!(manager.len(), 1);
+        manager.due_frames(opened_at + 101);
+        assert!(manager.is_empty());
+        manager
+            .incoming(id2, remote, DEFAULT_STREAM_WINDOW, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_frames_of_stream_signed_by_remote_session() {
+        let key = SecretKey::random();
+        let remote: Did = key.address().into();
+        let manager = StreamManager::default();
+        let id = uuid::Uuid::new_v4();
+        manager
+            .incoming(id, remote, DEFAULT_STREAM_WINDOW, Some(key.pubkey()))
+            .unwrap();
+
+        assert!(manager.get(&id, remote, Some(key.pubkey())).is_ok());
+        // signed by another session, or from another did
+        let other = SecretKey::random();
+        assert!(manager.get(&id, remote, Some(other.pubkey())).is_err());
+        assert!(manager.get(&id, remote, None).is_err());
+        assert!(manager
+            .get(&id, other.address().into(), Some(key.pubkey()))
+            .is_err());
+    }
+}
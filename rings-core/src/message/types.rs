@@ -1,14 +1,24 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 
+use super::protocols::MessageVerification;
 use crate::dht::vnode::VirtualNode;
+use crate::dht::DHTStatus;
 use crate::dht::Did;
+use crate::dht::LookupStep;
+use crate::dht::VNodePage;
+use crate::dht::VNodeRange;
 use crate::ecc::elgamal;
 use crate::ecc::PublicKey;
 use crate::ecc::SecretKey;
 use crate::err::Error;
 use crate::err::Result;
+use crate::session::SessionManager;
+use crate::utils;
 
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
 pub struct ConnectNodeSend {
@@ -38,6 +48,19 @@ pub struct FindSuccessorReport {
     pub handler: FindSuccessorReportHandler,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FindSuccessorStepSend {
+    pub id: Did,
+    /// Nodes between origin and destination, which the query is relayed by
+    pub route: Vec<Did>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FindSuccessorStepReport {
+    pub id: Did,
+    pub step: LookupStep,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct NotifyPredecessorSend {
     pub id: Did,
@@ -73,6 +96,104 @@ pub struct StoreVNode {
     pub data: Vec<VirtualNode>,
 }
 
+/// Report of `StoreVNode`, which is sent by the storing node once the vnode is stored.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StoredVNode {
+    pub id: Did,
+}
+
+/// Report of `StoreVNode`, which is sent if the storing node refused the vnode,
+/// such as it's over storage quota.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StoreVNodeRejected {
+    pub id: Did,
+    pub reason: String,
+    /// storage quota of the storing node, if the vnode is refused for it
+    #[serde(default)]
+    pub quota: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReplicateVNode {
+    pub data: Vec<VirtualNode>,
+    /// replicas copied from the sender before are replaced by data
+    #[serde(default)]
+    pub full: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SearchVNodeReplica {
+    pub id: Did,
+}
+
+/// Scan vnodes in range, it's answered by `FoundVNodePage` from the node
+/// responsible for start of range
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ScanVNode {
+    pub range: VNodeRange,
+}
+
+/// Report of `ScanVNode`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FoundVNodePage {
+    pub page: VNodePage,
+}
+
+/// Probe a connected node, it's answered by `Pong` with same `ts_ms`.
+/// The sender measures RTT by the time it sends the ping, rather than the echoed `ts_ms`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Ping {
+    pub ts_ms: u128,
+}
+
+/// Report of `Ping`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Pong {
+    pub ts_ms: u128,
+}
+
+/// Query chord state of destination, it's answered by `DHTStatusReport`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DHTStatusSend {}
+
+/// Report of `DHTStatusSend`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DHTStatusReport {
+    pub status: DHTStatus,
+}
+
+/// Open a stream to destination, it's answered by `StreamAck` if it's accepted,
+/// or `CloseStream` if it's refused, see `Stream`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct OpenStream {
+    pub stream_id: uuid::Uuid,
+    /// frames the opener can receive before acknowledging
+    pub window: u32,
+}
+
+/// A frame of bytes in a stream, frames of a stream are sequenced from 0
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StreamData {
+    pub stream_id: uuid::Uuid,
+    pub seq: u64,
+    pub data: Vec<u8>,
+}
+
+/// Acknowledge all frames before `ack`, and advertise frames the receiver can take
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StreamAck {
+    pub stream_id: uuid::Uuid,
+    pub ack: u64,
+    pub window: u32,
+}
+
+/// No more frames from or after `seq` in a stream, it's answered by `StreamAck`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CloseStream {
+    pub stream_id: uuid::Uuid,
+    pub seq: u64,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct MultiCall {
     pub messages: Vec<Message>,
@@ -88,6 +209,53 @@ pub struct JoinSubRing {
     pub did: Did,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LeaveSubRing {
+    pub did: Did,
+}
+
+/// Remove a member from subring, it's signed by admin of the subring,
+/// and verified by the node storing the subring.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct KickSubRing {
+    pub rid: Did,
+    pub did: Did,
+    /// increasing number of kicks signed by admin, a kick with nonce not greater
+    /// than the last applied one of subring is rejected as replayed
+    pub nonce: u64,
+    /// signature of admin on `(rid, did, nonce)`
+    pub verification: MessageVerification,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ListSubRing {
+    pub did: Did,
+}
+
+/// Report of `ListSubRing`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SubRingMembers {
+    pub did: Did,
+    pub members: Vec<Did>,
+}
+
+/// Broadcast to members of a subring, it's routed to the node which stores the subring.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SubRingBroadcast {
+    pub rid: Did,
+    pub data: MaybeEncrypted<CustomMessage>,
+}
+
+/// Deliver a subring broadcast to receiver, which forwards it to `members`, as a subtree.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SubRingMulticast {
+    pub rid: Did,
+    /// the node which starts the broadcast
+    pub origin: Did,
+    pub members: Vec<Did>,
+    pub data: MaybeEncrypted<CustomMessage>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct CustomMessage(pub Vec<u8>);
 
@@ -124,14 +292,36 @@ pub enum Message {
     ConnectNodeReport(ConnectNodeReport),
     FindSuccessorSend(FindSuccessorSend),
     FindSuccessorReport(FindSuccessorReport),
+    FindSuccessorStepSend(FindSuccessorStepSend),
+    FindSuccessorStepReport(FindSuccessorStepReport),
     NotifyPredecessorSend(NotifyPredecessorSend),
     NotifyPredecessorReport(NotifyPredecessorReport),
     SearchVNode(SearchVNode),
     FoundVNode(FoundVNode),
     StoreVNode(StoreVNode),
+    StoreVNodeRejected(StoreVNodeRejected),
+    ReplicateVNode(ReplicateVNode),
+    SearchVNodeReplica(SearchVNodeReplica),
     SyncVNodeWithSuccessor(SyncVNodeWithSuccessor),
     JoinSubRing(JoinSubRing),
+    LeaveSubRing(LeaveSubRing),
+    KickSubRing(KickSubRing),
+    ListSubRing(ListSubRing),
+    SubRingMembers(SubRingMembers),
+    SubRingBroadcast(SubRingBroadcast),
+    SubRingMulticast(SubRingMulticast),
     CustomMessage(MaybeEncrypted<CustomMessage>),
+    Ping(Ping),
+    Pong(Pong),
+    DHTStatusSend(DHTStatusSend),
+    DHTStatusReport(DHTStatusReport),
+    ScanVNode(ScanVNode),
+    FoundVNodePage(FoundVNodePage),
+    OpenStream(OpenStream),
+    StreamData(StreamData),
+    StreamAck(StreamAck),
+    CloseStream(CloseStream),
+    StoredVNode(StoredVNode),
 }
 
 impl std::fmt::Display for Message {
@@ -148,6 +338,48 @@ impl Message {
     }
 }
 
+impl KickSubRing {
+    /// Sign a kick with session of admin, it expires in `ttl_ms`.
+    pub fn new(
+        rid: Did,
+        did: Did,
+        session_manager: &SessionManager,
+        ttl_ms: usize,
+    ) -> Result<Self> {
+        let nonce = Self::next_nonce();
+        let verification = MessageVerification::new(&(rid, did, nonce), session_manager, ttl_ms)?;
+        Ok(Self {
+            rid,
+            did,
+            nonce,
+            verification,
+        })
+    }
+
+    /// Nonce is the epoch in ms, and kept increasing in process, so a kick signed later
+    /// has a greater nonce, even after restart.
+    fn next_nonce() -> u64 {
+        static LAST: AtomicU64 = AtomicU64::new(0);
+        let now = utils::get_epoch_ms() as u64;
+        let next = |last: u64| std::cmp::max(last + 1, now);
+        // the closure never returns None
+        let last = LAST
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(next(last)))
+            .unwrap_or_default();
+        next(last)
+    }
+
+    /// Return the did which signed the kick, fail if signature is invalid or expired.
+    pub fn signer(&self) -> Result<Did> {
+        if self.verification.is_expired()
+            || !self.verification.verify(&(self.rid, self.did, self.nonce))
+        {
+            return Err(Error::VerifySignatureFailed);
+        }
+        Ok(self.verification.session.auth.authorizer.did)
+    }
+}
+
 impl<T> MaybeEncrypted<T>
 where T: Serialize + DeserializeOwned
 {
@@ -0,0 +1,351 @@
+#![warn(missing_docs)]
+//! Strict verification of received payloads.
+//! A payload is handled only if it's signatures are valid, it's not expired, it's not
+//! signed in the future beyond tolerance of clock skew, and it's not seen before.
+//! The age of a payload is told by it's origin verification, since the verification
+//! of hop is signed again by each relay. Payloads are remembered by the origin signature,
+//! which cannot be changed by relays, until they are expired, so a replayed payload is
+//! rejected within it's lifetime, and rejected as expired after that.
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use serde::Deserialize;
+use serde::Serialize;
+use sha1::Digest;
+use sha1::Sha1;
+
+use super::MessagePayload;
+use super::MessageVerification;
+use crate::err::Error;
+use crate::err::Result;
+use crate::storage::LruMemStorage;
+use crate::utils;
+
+/// Tolerance of clock skew between nodes by default
+pub const DEFAULT_CLOCK_SKEW_MS: u128 = 30 * 1000;
+/// Max number of payloads remembered for replay protection by default
+pub const DEFAULT_REPLAY_WINDOW: usize = 64 * 1024;
+
+/// Counters of verified payloads
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationStats {
+    /// payloads which pass verification
+    pub accepted: u64,
+    /// payloads with invalid signature
+    pub invalid: u64,
+    /// expired payloads
+    pub expired: u64,
+    /// payloads signed in the future
+    pub from_future: u64,
+    /// payloads which are seen before
+    pub replayed: u64,
+}
+
+/// Verifier of received payloads, which is shared by message handlers of a swarm
+pub struct PayloadVerifier {
+    clock_skew_ms: u128,
+    /// sha1 of origin signature of seen payloads, they are expired with payloads
+    seen: LruMemStorage<[u8; 20], ()>,
+    accepted: AtomicU64,
+    invalid: AtomicU64,
+    expired: AtomicU64,
+    from_future: AtomicU64,
+    replayed: AtomicU64,
+}
+
+impl Default for PayloadVerifier {
+    fn default() -> Self {
+        Self::new(DEFAULT_CLOCK_SKEW_MS, DEFAULT_REPLAY_WINDOW)
+    }
+}
+
+impl PayloadVerifier {
+    /// Create a verifier which tolerates `clock_skew_ms` of clock skew, and remembers
+    /// at most `replay_window` payloads. The least recently seen one is forgotten when
+    /// the window is full, so the window should cover payloads received in a ttl.
+    pub fn new(clock_skew_ms: u128, replay_window: usize) -> Self {
+        Self {
+            clock_skew_ms,
+            seen: LruMemStorage::new().with_capacity(replay_window),
+            accepted: AtomicU64::new(0),
+            invalid: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+            from_future: AtomicU64::new(0),
+            replayed: AtomicU64::new(0),
+        }
+    }
+
+    /// Tolerance of clock skew in ms
+    pub fn clock_skew_ms(&self) -> u128 {
+        self.clock_skew_ms
+    }
+
+    /// Counters of verified payloads
+    pub fn stats(&self) -> VerificationStats {
+        VerificationStats {
+            accepted: self.accepted.load(Ordering::SeqCst),
+            invalid: self.invalid.load(Ordering::SeqCst),
+            expired: self.expired.load(Ordering::SeqCst),
+            from_future: self.from_future.load(Ordering::SeqCst),
+            replayed: self.replayed.load(Ordering::SeqCst),
+        }
+    }
+
+    fn expires_at(&self, v: &MessageVerification) -> u128 {
+        v.ts_ms + v.ttl_ms as u128 + self.clock_skew_ms
+    }
+
+    /// Check a received payload, and remember it if it's accepted.
+    /// The rejected one is counted, and it should be dropped without handling.
+    pub fn check<T>(&self, payload: &MessagePayload<T>) -> Result<()>
+    where T: Serialize {
+        let ret = self.verify(payload);
+        self.count(&ret);
+        ret
+    }
+
+    /// Check a payload which is stored for `ttl_ms` before it's handled, such as a
+    /// message in mailbox. It's checked as a received one, except that it's alive in
+    /// `ttl_ms` since it's created, regardless of the ttl of it's verifications.
+    pub fn check_stored<T>(&self, payload: &MessagePayload<T>, ttl_ms: u128) -> Result<()>
+    where T: Serialize {
+        let ret = self.verify_stored(payload, ttl_ms);
+        self.count(&ret);
+        ret
+    }
+
+    fn count(&self, ret: &Result<()>) {
+        let counter = match ret {
+            Ok(()) => &self.accepted,
+            Err(Error::InvalidPayloadSignature) => &self.invalid,
+            Err(Error::PayloadExpired) => &self.expired,
+            Err(Error::PayloadFromFuture(_)) => &self.from_future,
+            Err(Error::PayloadReplayed(_)) => &self.replayed,
+            Err(_) => &self.invalid,
+        };
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn verify<T>(&self, payload: &MessagePayload<T>) -> Result<()>
+    where T: Serialize {
+        let (hop, origin) = (&payload.verification, &payload.origin_verification);
+        if !hop.verify(&payload.data) || !origin.verify(&payload.data) {
+            return Err(Error::InvalidPayloadSignature);
+        }
+
+        let now = utils::get_epoch_ms();
+        // a fresh verification of hop cannot renew an expired payload
+        let expires_at = self.expires_at(origin);
+        if now > expires_at {
+            return Err(Error::PayloadExpired);
+        }
+        let signed_at = hop.ts_ms.max(origin.ts_ms);
+        if signed_at > now + self.clock_skew_ms {
+            return Err(Error::PayloadFromFuture(signed_at - now));
+        }
+
+        self.remember(payload, expires_at)
+    }
+
+    fn verify_stored<T>(&self, payload: &MessagePayload<T>, ttl_ms: u128) -> Result<()>
+    where T: Serialize {
+        let (hop, origin) = (&payload.verification, &payload.origin_verification);
+        // the sessions may be expired while the payload is stored,
+        // they should be valid when the payload is signed
+        if !hop.verify_signed_at(&payload.data) || !origin.verify_signed_at(&payload.data) {
+            return Err(Error::InvalidPayloadSignature);
+        }
+        let expires_at = origin.ts_ms + ttl_ms;
+        if utils::get_epoch_ms() > expires_at {
+            return Err(Error::PayloadExpired);
+        }
+        self.remember(payload, expires_at)
+    }
+
+    /// Remember a payload until `expires_at`, fail if it's seen before.
+    /// The tx_id and hop verification are not part of the key, since they can be changed
+    /// by anyone who relays the payload. Origin signatures of distinct payloads differ,
+    /// even if they have same data, since the signed timestamp is strictly increasing in a
+    /// session, see `SessionManager::next_ts_ms`.
+    fn remember<T>(&self, payload: &MessagePayload<T>, expires_at: u128) -> Result<()> {
+        let mut digest = [0u8; 20];
+        digest.copy_from_slice(&Sha1::digest(&payload.origin_verification.sig));
+        if self
+            .seen
+            .set_with_expiration(&digest, (), Some(expires_at))
+            .is_some()
+        {
+            return Err(Error::PayloadReplayed(payload.tx_id));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecc::SecretKey;
+    use crate::message::payload::test::new_test_payload;
+    use crate::session::SessionManager;
+
+    /// Sign payload again with a verification signed at `ts_ms`
+    fn resign<T: Serialize>(
+        payload: &mut MessagePayload<T>,
+        session_manager: &SessionManager,
+        ts_ms: u128,
+        ttl_ms: usize,
+    ) -> Result<()> {
+        let msg = MessageVerification::pack_msg(&payload.data, ts_ms, ttl_ms)?;
+        payload.verification = MessageVerification {
+            session: session_manager.session()?,
+            sig: session_manager.sign(&msg)?,
+            ttl_ms,
+            ts_ms,
+        };
+        payload.origin_verification = payload.verification.clone();
+        Ok(())
+    }
+
+    /// Sign verification of hop again, as a relay does
+    fn resign_hop<T: Serialize>(
+        payload: &mut MessagePayload<T>,
+        session_manager: &SessionManager,
+    ) -> Result<()> {
+        let (ts_ms, ttl_ms) = (utils::get_epoch_ms(), payload.verification.ttl_ms);
+        let msg = MessageVerification::pack_msg(&payload.data, ts_ms, ttl_ms)?;
+        payload.verification = MessageVerification {
+            session: session_manager.session()?,
+            sig: session_manager.sign(&msg)?,
+            ttl_ms,
+            ts_ms,
+        };
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_forged_and_replayed_payloads() -> Result<()> {
+        let key = SecretKey::random();
+        let session_manager = SessionManager::new_with_seckey(&key, None)?;
+        let verifier = PayloadVerifier::new(1000, 16);
+
+        let payload = new_test_payload();
+        verifier.check(&payload)?;
+
+        // same payload is received again
+        assert!(matches!(
+            verifier.check(&payload),
+            Err(Error::PayloadReplayed(_))
+        ));
+
+        // signature is modified
+        let mut forged = new_test_payload();
+        forged.verification.sig[10] ^= 1;
+        assert!(matches!(
+            verifier.check(&forged),
+            Err(Error::InvalidPayloadSignature)
+        ));
+
+        // signed beyond tolerance of clock skew
+        let now = utils::get_epoch_ms();
+        let mut payload = new_test_payload();
+        resign(&mut payload, &session_manager, now + 500, 1000)?;
+        verifier.check(&payload)?;
+        resign(&mut payload, &session_manager, now + 60 * 1000, 1000)?;
+        assert!(matches!(
+            verifier.check(&payload),
+            Err(Error::PayloadFromFuture(_))
+        ));
+        resign(&mut payload, &session_manager, now - 2100, 1000)?;
+        assert!(matches!(
+            verifier.check(&payload),
+            Err(Error::PayloadExpired)
+        ));
+
+        assert_eq!(verifier.stats(), VerificationStats {
+            accepted: 2,
+            invalid: 1,
+            expired: 1,
+            from_future: 1,
+            replayed: 1,
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_accept_payloads_of_same_data() -> Result<()> {
+        let session_manager = SessionManager::new_with_seckey(&SecretKey::random(), None)?;
+        let destination = SecretKey::random().address().into();
+        let verifier = PayloadVerifier::new(1000, 16);
+
+        // identical payloads are signed in a burst, none of them is a replay
+        let data = new_test_payload().data;
+        for _ in 0..8 {
+            let payload = MessagePayload::new_direct(data.clone(), &session_manager, destination)?;
+            verifier.check(&payload)?;
+        }
+        assert_eq!(verifier.stats().accepted, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_payloads_renewed_by_relay() -> Result<()> {
+        let origin = SessionManager::new_with_seckey(&SecretKey::random(), None)?;
+        let relay = SessionManager::new_with_seckey(&SecretKey::random(), None)?;
+        let verifier = PayloadVerifier::new(1000, 16);
+
+        // a relay re-signs the hop and changes tx_id of a seen payload
+        let mut payload = new_test_payload();
+        resign(&mut payload, &origin, utils::get_epoch_ms(), 10 * 1000)?;
+        verifier.check(&payload)?;
+        resign_hop(&mut payload, &relay)?;
+        payload.tx_id = uuid::Uuid::new_v4();
+        assert!(matches!(
+            verifier.check(&payload),
+            Err(Error::PayloadReplayed(_))
+        ));
+
+        // a fresh hop doesn't renew an expired origin
+        let mut payload = new_test_payload();
+        resign(&mut payload, &origin, utils::get_epoch_ms() - 5000, 1000)?;
+        resign_hop(&mut payload, &relay)?;
+        assert!(matches!(
+            verifier.check(&payload),
+            Err(Error::PayloadExpired)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_stored_payloads() -> Result<()> {
+        let key = SecretKey::random();
+        let session_manager = SessionManager::new_with_seckey(&key, None)?;
+        let verifier = PayloadVerifier::new(0, 16);
+
+        // ttl of verification is passed while it's stored
+        let mut payload = new_test_payload();
+        resign(&mut payload, &session_manager, utils::get_epoch_ms(), 1)?;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(matches!(
+            verifier.check(&payload),
+            Err(Error::PayloadExpired)
+        ));
+        assert!(matches!(
+            verifier.check_stored(&payload, 1),
+            Err(Error::PayloadExpired)
+        ));
+        verifier.check_stored(&payload, 60 * 1000)?;
+        assert!(matches!(
+            verifier.check_stored(&payload, 60 * 1000),
+            Err(Error::PayloadReplayed(_))
+        ));
+
+        // signature is still verified
+        let mut forged = new_test_payload();
+        forged.origin_verification.sig[10] ^= 1;
+        assert!(matches!(
+            verifier.check_stored(&forged, 60 * 1000),
+            Err(Error::InvalidPayloadSignature)
+        ));
+        Ok(())
+    }
+}
@@ -33,6 +33,8 @@ pub use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 pub use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
 
 pub use crate::dht::vnode;
+pub use crate::message::SubRingOperator;
 pub use crate::message::TChordStorage;
+pub use crate::message::TRecordStorage;
 pub use crate::storage::PersistenceStorage;
 pub use crate::transports::Transport;
@@ -5,6 +5,8 @@
 //! - Then we can sign the auth message via some web3 provider like metamask or just with raw private key, and create the SessionManger with
 //! - SessionManager::new(sig, auth_info, temp_key)
 
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::RwLock;
 
@@ -67,12 +69,15 @@ pub struct SessionWithKey {
 #[derive(Debug)]
 pub struct SessionManager {
     inner: Arc<RwLock<SessionWithKey>>,
+    /// timestamp of the last signed message, see `next_ts_ms`
+    last_ts_ms: Arc<AtomicU64>,
 }
 
 impl Clone for SessionManager {
     fn clone(&self) -> Self {
         Self {
             inner: Arc::clone(&self.inner),
+            last_ts_ms: Arc::clone(&self.last_ts_ms),
         }
     }
 }
@@ -92,18 +97,29 @@ impl Session {
     }
 
     pub fn is_expired(&self) -> bool {
+        self.is_expired_at(utils::get_epoch_ms())
+    }
+
+    /// Check if session is expired at the time `ts_ms`
+    pub fn is_expired_at(&self, ts_ms: u128) -> bool {
         if let Ttl::Some(ttl_ms) = self.auth.ttl_ms {
-            let now = utils::get_epoch_ms();
-            now > self.auth.ts_ms + ttl_ms as u128
+            ts_ms > self.auth.ts_ms + ttl_ms as u128
         } else {
             false
         }
     }
 
     pub fn verify(&self) -> bool {
-        if self.is_expired() {
-            return false;
-        }
+        !self.is_expired() && self.verify_authorization()
+    }
+
+    /// Check if session is valid at the time `ts_ms`,
+    /// for verifying data which is signed in the past.
+    pub fn verify_at(&self, ts_ms: u128) -> bool {
+        ts_ms >= self.auth.ts_ms && !self.is_expired_at(ts_ms) && self.verify_authorization()
+    }
+
+    fn verify_authorization(&self) -> bool {
         if let Ok(auth_str) = self.auth.to_string() {
             match self.auth.signer {
                 Signer::DEFAULT => {
@@ -208,6 +224,7 @@ impl SessionManager {
 
         Self {
             inner: Arc::new(RwLock::new(inner)),
+            last_ts_ms: Arc::new(AtomicU64::new(0)),
         }
     }
 
@@ -256,6 +273,21 @@ impl SessionManager {
     pub fn authorizer(&self) -> Result<Did> {
         Ok(self.session()?.auth.authorizer.did)
     }
+
+    /// Timestamp in ms to sign a payload, it's strictly increasing, so payloads of same
+    /// data signed in the same ms still have different signatures, and are not taken as
+    /// replayed by receivers, see `PayloadVerifier`. It runs ahead of clock only in bursts
+    /// of more than one message per ms, which is covered by tolerance of clock skew.
+    pub fn next_ts_ms(&self) -> u128 {
+        let now = utils::get_epoch_ms() as u64;
+        let prev = self
+            .last_ts_ms
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                Some(now.max(last + 1))
+            })
+            .unwrap_or(now);
+        now.max(prev + 1) as u128
+    }
 }
 
 #[cfg(test)]
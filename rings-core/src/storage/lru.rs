@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::utils;
+
+/// Hit and miss counters of a `LruMemStorage`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug)]
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    /// timestamp in ms when the entry is expired
+    expires_at: Option<u128>,
+    /// slot of the entry used just before this one
+    prev: Option<usize>,
+    /// slot of the entry used just after this one
+    next: Option<usize>,
+}
+
+impl<K, V> Entry<K, V> {
+    fn is_expired(&self, now: u128) -> bool {
+        self.expires_at.map(|ts| now > ts).unwrap_or(false)
+    }
+}
+
+/// Entries are kept in slots, and linked in the order of access,
+/// so an entry is accessed, moved to the end, or evicted in O(1).
+#[derive(Debug)]
+struct Table<K, V> {
+    index: HashMap<K, usize>,
+    slots: Vec<Option<Entry<K, V>>>,
+    /// slots of removed entries, they are reused by new entries
+    free: Vec<usize>,
+    /// the least recently used entry
+    head: Option<usize>,
+    /// the most recently used entry
+    tail: Option<usize>,
+}
+
+impl<K, V> Default for Table<K, V> {
+    fn default() -> Self {
+        Self {
+            index: HashMap::new(),
+            slots: vec![],
+            free: vec![],
+            head: None,
+            tail: None,
+        }
+    }
+}
+
+impl<K, V> Table<K, V>
+where K: Copy + Eq + Hash
+{
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn slot(&self, i: usize) -> Option<&Entry<K, V>> {
+        self.slots.get(i).and_then(|e| e.as_ref())
+    }
+
+    fn slot_mut(&mut self, i: usize) -> Option<&mut Entry<K, V>> {
+        self.slots.get_mut(i).and_then(|e| e.as_mut())
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry<K, V>> {
+        self.slots.iter().flatten()
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = match self.slot(i) {
+            Some(e) => (e.prev, e.next),
+            None => return,
+        };
+        match prev.and_then(|p| self.slot_mut(p)) {
+            Some(p) => p.next = next,
+            None => self.head = next,
+        }
+        match next.and_then(|n| self.slot_mut(n)) {
+            Some(n) => n.prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_back(&mut self, i: usize) {
+        let tail = self.tail;
+        if let Some(e) = self.slot_mut(i) {
+            e.prev = tail;
+            e.next = None;
+        }
+        match tail.and_then(|t| self.slot_mut(t)) {
+            Some(t) => t.next = Some(i),
+            None => self.head = Some(i),
+        }
+        self.tail = Some(i);
+    }
+
+    /// Mark an entry as the most recently used.
+    fn touch(&mut self, i: usize) {
+        self.unlink(i);
+        self.push_back(i);
+    }
+
+    /// Insert or overwrite an entry as the most recently used, return the previous value.
+    fn insert(&mut self, key: K, value: V, expires_at: Option<u128>) -> Option<V> {
+        if let Some(i) = self.index.get(&key).copied() {
+            let prev = self.slot_mut(i).map(|e| {
+                e.expires_at = expires_at;
+                std::mem::replace(&mut e.value, value)
+            });
+            self.touch(i);
+            return prev;
+        }
+        let entry = Entry {
+            key,
+            value,
+            expires_at,
+            prev: None,
+            next: None,
+        };
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.slots[i] = Some(entry);
+                i
+            }
+            None => {
+                self.slots.push(Some(entry));
+                self.slots.len() - 1
+            }
+        };
+        self.index.insert(key, i);
+        self.push_back(i);
+        None
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Entry<K, V>> {
+        let i = self.index.remove(key)?;
+        self.unlink(i);
+        self.free.push(i);
+        self.slots[i].take()
+    }
+
+    /// Remove the least recently used entry.
+    fn pop_front(&mut self) -> Option<Entry<K, V>> {
+        let key = self.slot(self.head?)?.key;
+        self.remove(&key)
+    }
+}
+
+/// In-memory storage which keeps the order of access,
+/// thus the least recently used entry can be evicted first.
+/// It can be bounded by number of entries, and entries can be expired after a TTL.
+/// With a weigher, the total weight of entries is counted as they are written and removed.
+#[derive(Debug, Default)]
+pub struct LruMemStorage<K, V>
+where
+    K: Copy + Eq + Hash,
+    V: Clone,
+{
+    table: Mutex<Table<K, V>>,
+    capacity: Option<usize>,
+    ttl_ms: Option<u128>,
+    weigher: Option<fn(&V) -> usize>,
+    weight: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K, V> LruMemStorage<K, V>
+where
+    K: Copy + Eq + Hash,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            table: Mutex::new(Table::default()),
+            capacity: None,
+            ttl_ms: None,
+            weigher: None,
+            weight: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Keep at most `capacity` entries, the least recently used one is evicted when it's full.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Expire entries after `ttl_ms` since they are set.
+    pub fn with_ttl(mut self, ttl_ms: u128) -> Self {
+        self.ttl_ms = Some(ttl_ms);
+        self
+    }
+
+    /// Count weight of entries by weigher, such as size in bytes, see `weight`.
+    pub fn with_weigher(mut self, weigher: fn(&V) -> usize) -> Self {
+        self.weigher = Some(weigher);
+        self
+    }
+
+    /// Total weight of entries, it's 0 without weigher.
+    pub fn weight(&self) -> usize {
+        self.weight.load(Ordering::SeqCst)
+    }
+
+    fn weigh(&self, value: &V) -> usize {
+        self.weigher.map(|w| w(value)).unwrap_or(0)
+    }
+
+    /// Count an entry is removed
+    fn forget(&self, value: &V) {
+        self.weight.fetch_sub(self.weigh(value), Ordering::SeqCst);
+    }
+
+    /// The table is still consistent if a thread panics while holding it,
+    /// since it's never left half updated, so a poisoned lock is recovered.
+    fn lock(&self) -> MutexGuard<Table<K, V>> {
+        self.table.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get an entry, and mark it as the most recently used.
+    /// An expired entry is removed and counted as a miss.
+    pub fn get(&self, addr: &K) -> Option<V> {
+        let now = utils::get_epoch_ms();
+        let value = {
+            let mut table = self.lock();
+            let found = table.index.get(addr).copied();
+            match found.and_then(|i| table.slot(i).map(|e| (i, e.is_expired(now)))) {
+                Some((i, false)) => {
+                    table.touch(i);
+                    table.slot(i).map(|e| e.value.clone())
+                }
+                Some((_, true)) => {
+                    if let Some(entry) = table.remove(addr) {
+                        self.forget(&entry.value);
+                    }
+                    None
+                }
+                None => None,
+            }
+        };
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        value
+    }
+
+    pub fn set(&self, addr: &K, value: V) -> Option<V> {
+        self.set_with_expiration(addr, value, None)
+    }
+
+    /// Set an entry which is expired at `expires_at`, or the TTL of storage if it's earlier.
+    pub fn set_with_expiration(&self, addr: &K, value: V, expires_at: Option<u128>) -> Option<V> {
+        let ttl_expires_at = self.ttl_ms.map(|ttl| utils::get_epoch_ms() + ttl);
+        let expires_at = match (expires_at, ttl_expires_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.weight.fetch_add(self.weigh(&value), Ordering::SeqCst);
+        let mut table = self.lock();
+        let prev = table.insert(*addr, value, expires_at);
+        if let Some(v) = prev.as_ref() {
+            self.forget(v);
+        }
+        if let Some(capacity) = self.capacity {
+            while table.len() > capacity {
+                match table.pop_front() {
+                    Some(entry) => self.forget(&entry.value),
+                    None => break,
+                }
+            }
+        }
+        prev
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn keys(&self) -> Vec<K> {
+        self.lock().entries().map(|e| e.key).collect()
+    }
+
+    pub fn values(&self) -> Vec<V> {
+        self.lock().entries().map(|e| e.value.clone()).collect()
+    }
+
+    pub fn items(&self) -> Vec<(K, V)> {
+        self.lock()
+            .entries()
+            .map(|e| (e.key, e.value.clone()))
+            .collect()
+    }
+
+    pub fn retain(&self, mut f: impl FnMut(&K, &V) -> bool) {
+        self.retain_entries(|e| f(&e.key, &e.value))
+    }
+
+    pub fn remove(&self, addr: &K) -> Option<(K, V)> {
+        let entry = self.lock().remove(addr)?;
+        self.forget(&entry.value);
+        Some((entry.key, entry.value))
+    }
+
+    /// Remove all expired entries.
+    pub fn remove_expired(&self) {
+        let now = utils::get_epoch_ms();
+        self.retain_entries(|e| !e.is_expired(now))
+    }
+
+    fn retain_entries(&self, mut f: impl FnMut(&Entry<K, V>) -> bool) {
+        let mut table = self.lock();
+        let removed: Vec<K> = table.entries().filter(|e| !f(e)).map(|e| e.key).collect();
+        for key in removed {
+            if let Some(entry) = table.remove(&key) {
+                self.forget(&entry.value);
+            }
+        }
+    }
+
+    /// Remove and return the least recently used entry.
+    pub fn pop_lru(&self) -> Option<(K, V)> {
+        let entry = self.lock().pop_front()?;
+        self.forget(&entry.value);
+        Some((entry.key, entry.value))
+    }
+
+    /// Counters of `get` since the storage is created.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_memstorage_pop_least_recently_used() {
+        let store = LruMemStorage::<u8, String>::new();
+        store.set(&1, "a".into());
+        store.set(&2, "b".into());
+        store.set(&3, "c".into());
+
+        // 1 is accessed, so 2 becomes the least recently used
+        assert_eq!(store.get(&1), Some("a".into()));
+        assert_eq!(store.pop_lru(), Some((2, "b".into())));
+        assert_eq!(store.pop_lru(), Some((3, "c".into())));
+        assert_eq!(store.pop_lru(), Some((1, "a".into())));
+        assert_eq!(store.pop_lru(), None);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_lru_memstorage_capacity_and_ttl() {
+        let store = LruMemStorage::<u8, String>::new()
+            .with_capacity(2)
+            .with_ttl(60 * 1000);
+        store.set(&1, "a".into());
+        store.set(&2, "b".into());
+        assert_eq!(store.get(&1), Some("a".into()));
+
+        // 2 is evicted since it's the least recently used
+        store.set(&3, "c".into());
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&2), None);
+
+        // an entry can be expired earlier than TTL of storage
+        store.set_with_expiration(&3, "c".into(), Some(0));
+        assert_eq!(store.get(&3), None);
+        assert_eq!(store.len(), 1);
+
+        assert_eq!(store.stats(), CacheStats {
+            hits: 1,
+            misses: 2,
+            entries: 1
+        });
+    }
+
+    #[test]
+    fn test_lru_memstorage_weight() {
+        let store = LruMemStorage::<u8, String>::new()
+            .with_capacity(2)
+            .with_weigher(String::len);
+        store.set(&1, "a".into());
+        store.set(&2, "bb".into());
+        assert_eq!(store.weight(), 3);
+
+        // overwritten, evicted and removed entries are not counted
+        store.set(&1, "aaa".into());
+        assert_eq!(store.weight(), 5);
+        store.set(&3, "cccc".into());
+        assert_eq!(store.weight(), 7);
+        store.remove(&1);
+        assert_eq!(store.weight(), 4);
+        store.set_with_expiration(&4, "d".into(), Some(0));
+        store.remove_expired();
+        assert_eq!(store.weight(), 4);
+        store.retain(|_, _| false);
+        assert_eq!(store.weight(), 0);
+    }
+}
@@ -58,6 +58,10 @@ where
             .collect()
     }
 
+    pub fn retain(&self, f: impl FnMut(&K, &mut V) -> bool) {
+        self.table.retain(f)
+    }
+
     pub fn remove(&self, addr: &K) -> Option<(K, V)> {
         match self.get(addr) {
             Some(_) => self.table.remove(addr),
@@ -3,16 +3,21 @@ use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use async_stream::stream;
 use async_trait::async_trait;
 use futures::Stream;
+use itertools::Itertools;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use crate::channels::Channel;
 use crate::dht::Chord;
+use crate::dht::DHTStatus;
 use crate::dht::Did;
+use crate::dht::LookupStep;
+use crate::dht::LookupTrace;
 use crate::dht::PeerRing;
 use crate::dht::PeerRingAction;
 use crate::ecc::SecretKey;
@@ -20,18 +25,29 @@ use crate::err::Error;
 use crate::err::Result;
 use crate::message;
 use crate::message::CallbackFn;
-use crate::message::Decoder;
-use crate::message::Encoder;
 use crate::message::Message;
 use crate::message::MessageHandler;
 use crate::message::MessagePayload;
+use crate::message::PayloadFormat;
 use crate::message::PayloadSender;
+use crate::message::PayloadVerifier;
+use crate::message::PendingRequests;
+use crate::message::StreamManager;
 use crate::message::ValidatorFn;
+use crate::message::VerificationStats;
+use crate::message::DEFAULT_CLOCK_SKEW_MS;
+use crate::message::DEFAULT_REPLAY_WINDOW;
+use crate::message::DEFAULT_STREAM_BACKLOG;
+use crate::message::DEFAULT_STREAM_IDLE_TIMEOUT;
 use crate::prelude::RTCSdpType;
 use crate::session::SessionManager;
 use crate::session::Ttl;
 use crate::storage::MemStorage;
 use crate::storage::PersistenceStorage;
+use crate::storage::PersistenceStorageReadAndWrite;
+use crate::transports::chunk::CHUNK_HEADER;
+#[cfg(all(not(feature = "wasm"), feature = "dummy"))]
+use crate::transports::dummy::DummyTransportHub;
 use crate::transports::manager::TransportManager;
 use crate::transports::Transport;
 use crate::types::channel::Channel as ChannelTrait;
@@ -46,11 +62,22 @@ pub struct SwarmBuilder {
     external_address: Option<String>,
     dht_did: Option<Did>,
     dht_succ_max: u8,
+    dht_replication_factor: u8,
+    dht_storage_quota: Option<usize>,
+    dht_cache: Option<(usize, u128)>,
+    dht_failure_detector: Option<(u64, u8)>,
+    dht_virtual_positions: u8,
     dht_storage: PersistenceStorage,
     session_manager: Option<SessionManager>,
     session_ttl: Option<Ttl>,
+    payload_clock_skew_ms: u128,
+    payload_replay_window: usize,
+    payload_format: PayloadFormat,
+    stream_idle_timeout: Duration,
     /// support forward request to hidden services.
     hidden_service_port: Option<usize>,
+    #[cfg(all(not(feature = "wasm"), feature = "dummy"))]
+    dummy_hub: Arc<DummyTransportHub>,
 }
 
 impl SwarmBuilder {
@@ -67,10 +94,21 @@ impl SwarmBuilder {
             external_address: None,
             dht_did: None,
             dht_succ_max: 3,
+            dht_replication_factor: 0,
+            dht_storage_quota: None,
+            dht_cache: None,
+            dht_failure_detector: None,
+            dht_virtual_positions: 1,
             dht_storage,
             session_manager: None,
             session_ttl: None,
+            payload_clock_skew_ms: DEFAULT_CLOCK_SKEW_MS,
+            payload_replay_window: DEFAULT_REPLAY_WINDOW,
+            payload_format: PayloadFormat::BincodeV1,
+            stream_idle_timeout: DEFAULT_STREAM_IDLE_TIMEOUT,
             hidden_service_port: None,
+            #[cfg(all(not(feature = "wasm"), feature = "dummy"))]
+            dummy_hub: DummyTransportHub::global(),
         }
     }
 
@@ -84,6 +122,71 @@ impl SwarmBuilder {
         self
     }
 
+    pub fn dht_replication_factor(mut self, k: u8) -> Self {
+        self.dht_replication_factor = k;
+        self
+    }
+
+    /// Max bytes of vnode data kept by this node, writes over quota are refused.
+    pub fn dht_storage_quota(mut self, bytes: usize) -> Self {
+        self.dht_storage_quota = Some(bytes);
+        self
+    }
+
+    /// Max number of vnodes cached by this node, and how long they are cached.
+    pub fn dht_cache(mut self, capacity: usize, ttl_ms: u128) -> Self {
+        self.dht_cache = Some((capacity, ttl_ms));
+        self
+    }
+
+    /// Timeout of liveness probes, and number of failed probes in a row before a node is evicted.
+    pub fn dht_failure_detector(mut self, timeout_ms: u64, max_suspicion: u8) -> Self {
+        self.dht_failure_detector = Some((timeout_ms, max_suspicion));
+        self
+    }
+
+    /// Number of positions hosted by each node on the ring, it should be same on all nodes.
+    pub fn dht_virtual_positions(mut self, n: u8) -> Self {
+        self.dht_virtual_positions = n;
+        self
+    }
+
+    /// Tolerance of clock skew for timestamps of received payloads,
+    /// a payload signed later than `now + ms` is rejected.
+    pub fn payload_clock_skew(mut self, ms: u128) -> Self {
+        self.payload_clock_skew_ms = ms;
+        self
+    }
+
+    /// Max number of received payloads remembered to reject replayed ones.
+    pub fn payload_replay_window(mut self, size: usize) -> Self {
+        self.payload_replay_window = size;
+        self
+    }
+
+    /// Preferred wire format of sent payloads, payloads of all formats can be received.
+    /// It's advertised to peers, and used for a peer only after the peer advertises it too,
+    /// see `Swarm::peer_payload_format`. Set it to `PayloadFormat::Legacy` to always send
+    /// legacy frames.
+    pub fn payload_format(mut self, format: PayloadFormat) -> Self {
+        self.payload_format = format;
+        self
+    }
+
+    /// Byte streams expire if nothing is received from remote in `timeout_ms`,
+    /// and so do incoming streams which are not accepted in it.
+    pub fn stream_idle_timeout(mut self, timeout_ms: u64) -> Self {
+        self.stream_idle_timeout = Duration::from_millis(timeout_ms);
+        self
+    }
+
+    /// Hub which transports of the swarm are bound to, it's the global one by default.
+    #[cfg(all(not(feature = "wasm"), feature = "dummy"))]
+    pub fn dummy_hub(mut self, hub: Arc<DummyTransportHub>) -> Self {
+        self.dummy_hub = hub;
+        self
+    }
+
     pub fn external_address(mut self, external_address: Option<String>) -> Self {
         self.external_address = external_address;
         self
@@ -123,7 +226,18 @@ impl SwarmBuilder {
             .dht_did
             .ok_or_else(|| Error::SwarmBuildFailed("Should set session_manager or key".into()))?;
 
-        let dht = PeerRing::new_with_storage(dht_did, self.dht_succ_max, self.dht_storage);
+        let mut dht = PeerRing::new_with_storage(dht_did, self.dht_succ_max, self.dht_storage)
+            .with_replication_factor(self.dht_replication_factor)
+            .with_virtual_positions(self.dht_virtual_positions);
+        if let Some(quota) = self.dht_storage_quota {
+            dht = dht.with_storage_quota(quota);
+        }
+        if let Some((capacity, ttl_ms)) = self.dht_cache {
+            dht = dht.with_cache(capacity, ttl_ms);
+        }
+        if let Some((timeout_ms, max_suspicion)) = self.dht_failure_detector {
+            dht = dht.with_failure_detector(timeout_ms, max_suspicion);
+        }
 
         Ok(Swarm {
             pending_transports: Arc::new(Mutex::new(vec![])),
@@ -132,8 +246,22 @@ impl SwarmBuilder {
             ice_servers: self.ice_servers,
             external_address: self.external_address,
             dht: Arc::new(dht),
+            pending_requests: Arc::new(PendingRequests::new()),
+            payload_verifier: Arc::new(PayloadVerifier::new(
+                self.payload_clock_skew_ms,
+                self.payload_replay_window,
+            )),
+            payload_format: self.payload_format,
+            peer_formats: MemStorage::new(),
+            subring_broadcasts: MemStorage::new(),
+            streams: Arc::new(StreamManager::new(
+                DEFAULT_STREAM_BACKLOG,
+                self.stream_idle_timeout,
+            )),
             session_manager,
             hidden_service_port: self.hidden_service_port,
+            #[cfg(all(not(feature = "wasm"), feature = "dummy"))]
+            dummy_hub: self.dummy_hub,
         })
     }
 }
@@ -145,9 +273,23 @@ pub struct Swarm {
     pub(crate) transport_event_channel: Channel<Event>,
     pub(crate) external_address: Option<String>,
     pub(crate) dht: Arc<PeerRing>,
+    /// requests which are waiting for report, keyed by tx_id
+    pub(crate) pending_requests: Arc<PendingRequests>,
+    /// verifier of received payloads, shared by message handlers
+    pub(crate) payload_verifier: Arc<PayloadVerifier>,
+    /// preferred wire format of sent payloads, which is advertised to peers
+    pub(crate) payload_format: PayloadFormat,
+    /// preferred format of peers which advertised it, legacy frames are sent to others
+    pub(crate) peer_formats: MemStorage<Did, PayloadFormat>,
+    /// tx_id of subring broadcasts which are seen, with the time they are seen
+    pub(crate) subring_broadcasts: MemStorage<uuid::Uuid, u128>,
+    /// byte streams to remote dids, see `Swarm::open_stream`
+    pub(crate) streams: Arc<StreamManager>,
     /// support forward request to hidden services.
     pub hidden_service_port: Option<usize>,
     session_manager: SessionManager,
+    #[cfg(all(not(feature = "wasm"), feature = "dummy"))]
+    pub(crate) dummy_hub: Arc<DummyTransportHub>,
 }
 
 impl Swarm {
@@ -179,7 +321,7 @@ impl Swarm {
 
         match ev {
             Some(Event::DataChannelMessage(msg)) => {
-                let payload = MessagePayload::from_encoded(&msg.try_into()?)?;
+                let payload = MessagePayload::from_auto(&msg)?;
                 Ok(Some(payload))
             }
             Some(Event::RegisterTransport((did, id))) => {
@@ -328,6 +470,256 @@ impl Swarm {
         self.send_message(connect_msg, next_hop, did).await?;
         Ok(transport)
     }
+
+    /// Find successor of `id` iteratively. Instead of forwarding the query, each hop
+    /// answers its closest preceding node, and the next query is sent by local node.
+    /// A hop is retried `retries` times if it does not respond in `timeout`.
+    /// The returned trace contains every node which answered the lookup.
+    pub async fn find_successor_iterative(
+        &self,
+        id: Did,
+        timeout: Duration,
+        retries: u8,
+    ) -> Result<LookupTrace> {
+        let mut lookup = self.dht.iterative_lookup(id)?;
+        while let Some(hop) = lookup.next_hop() {
+            // the query is relayed by previous hops, since local node may not connect to the hop
+            let route: Vec<Did> = if self.get_and_check_transport(hop).await.is_some() {
+                vec![]
+            } else {
+                lookup.path().iter().skip(1).copied().collect()
+            };
+            let step = self
+                .query_lookup_step(id, hop, route, timeout, retries)
+                .await?;
+            tracing::debug!("[find_successor_iterative] {:?} answered {:?}", hop, step);
+            lookup.advance(step)?;
+        }
+        lookup.trace().ok_or(Error::LookupTimeout(id))
+    }
+
+    async fn query_lookup_step(
+        &self,
+        id: Did,
+        hop: Did,
+        route: Vec<Did>,
+        timeout: Duration,
+        retries: u8,
+    ) -> Result<LookupStep> {
+        let next_hop = route.first().copied().unwrap_or(hop);
+        for _ in 0..=retries {
+            let payload = MessagePayload::new_send(
+                Message::FindSuccessorStepSend(message::FindSuccessorStepSend {
+                    id,
+                    route: route.clone(),
+                }),
+                &self.session_manager,
+                next_hop,
+                hop,
+            )?;
+            match self.send_payload_and_wait(payload, timeout).await {
+                Ok(MessagePayload {
+                    data: Message::FindSuccessorStepReport(report),
+                    ..
+                }) => return Ok(report.step),
+                Ok(report) => {
+                    return Err(Error::InvalidMessage(format!(
+                        "unexpected report of lookup: {}",
+                        report.data
+                    )))
+                }
+                Err(e) => tracing::warn!("[query_lookup_step] no response from {:?}: {:?}", hop, e),
+            }
+        }
+        Err(Error::LookupTimeout(hop))
+    }
+
+    /// Chord state of a node, it's queried by `DHTStatusSend` if the node is remote.
+    pub async fn dht_status(&self, did: Did, timeout: Duration) -> Result<DHTStatus> {
+        if did == self.did() {
+            return self.dht.status().await;
+        }
+        match self
+            .send_and_wait(
+                Message::DHTStatusSend(message::DHTStatusSend {}),
+                did,
+                timeout,
+            )
+            .await?
+        {
+            MessagePayload {
+                data: Message::DHTStatusReport(report),
+                ..
+            } => Ok(report.status),
+            report => Err(Error::InvalidMessage(format!(
+                "unexpected report of dht status: {}",
+                report.data
+            ))),
+        }
+    }
+
+    /// Wire format of payloads sent to a peer. It's legacy until the peer advertises
+    /// the preferred format of this node, since older nodes can't decode binary frames.
+    pub fn peer_payload_format(&self, did: Did) -> PayloadFormat {
+        self.peer_formats.get(&did).unwrap_or_default()
+    }
+
+    /// Learn formats advertised by the sender of a verified payload, which is the signer
+    /// of hop verification rather than the unsigned `addr`. Large messages are split into
+    /// chunks for the sender only after it advertises `CHUNK_HEADER`.
+    pub(crate) fn learn_payload_format<T>(&self, payload: &MessagePayload<T>) {
+        let did = payload.verification.session.auth.authorizer.did;
+        if let Some(header) = self.payload_format.header() {
+            if payload.formats.contains(&header) {
+                self.peer_formats.set(&did, self.payload_format);
+            }
+        }
+        if payload.formats.contains(&CHUNK_HEADER) {
+            if let Some(transport) = self.get_transport(did) {
+                transport.enable_chunks();
+            }
+        }
+    }
+
+    /// Requests which are waiting for report.
+    pub fn pending_requests(&self) -> Arc<PendingRequests> {
+        self.pending_requests.clone()
+    }
+
+    /// Verifier of received payloads.
+    pub fn payload_verifier(&self) -> Arc<PayloadVerifier> {
+        self.payload_verifier.clone()
+    }
+
+    /// Counters of received payloads which are accepted or dropped by verification.
+    pub fn verification_stats(&self) -> VerificationStats {
+        self.payload_verifier.stats()
+    }
+
+    /// Send `msg` to `destination`, and wait for the report of it.
+    /// Return `Error::RequestTimeout` if no report arrives in `timeout`.
+    pub async fn send_and_wait(
+        &self,
+        msg: Message,
+        destination: Did,
+        timeout: Duration,
+    ) -> Result<MessagePayload<Message>> {
+        let next_hop = {
+            match self.dht.find_successor(destination)? {
+                PeerRingAction::Some(node) => Some(node),
+                PeerRingAction::RemoteAction(node, _) => Some(node),
+                _ => None,
+            }
+        }
+        .ok_or(Error::NoNextHop)?;
+        let payload = MessagePayload::new_send(msg, &self.session_manager, next_hop, destination)?;
+        self.send_payload_and_wait(payload, timeout).await
+    }
+
+    /// Send the payload, and wait for the report which has the same tx_id.
+    pub async fn send_payload_and_wait(
+        &self,
+        payload: MessagePayload<Message>,
+        timeout: Duration,
+    ) -> Result<MessagePayload<Message>> {
+        let tx_id = payload.tx_id;
+        let receiver = self.pending_requests.register(tx_id);
+        if let Err(e) = self.send_payload(payload).await {
+            self.pending_requests.cancel(&tx_id);
+            return Err(e);
+        }
+        self.pending_requests.wait(tx_id, receiver, timeout).await
+    }
+
+    /// Leave the ring gracefully, which takes four steps:
+    /// 1. push every vnode of local storage to successor, which keeps them as replicas;
+    /// 2. notify predecessor and successor to relink with each other, and then the
+    /// successor will hand over the replicas to its new predecessor;
+    /// 3. broadcast `LeaveDHT` to finger entries;
+    /// 4. close all transports.
+    /// A peer which is unreachable is skipped, the others are still notified.
+    pub async fn leave_ring(&self) -> Result<()> {
+        let id = self.did();
+        let successor = { self.dht.lock_successor()?.min() };
+        let predecessor = { *self.dht.lock_predecessor()? };
+        let fingers: Vec<Did> = {
+            self.dht
+                .lock_finger()?
+                .list()
+                .iter()
+                .flatten()
+                .copied()
+                .unique()
+                .collect()
+        };
+
+        if successor != id {
+            let data: Vec<_> = self
+                .dht
+                .storage
+                .get_all()
+                .await?
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect();
+            if !data.is_empty() {
+                if let Err(e) = self
+                    .send_direct_message(
+                        Message::ReplicateVNode(message::ReplicateVNode { data, full: false }),
+                        successor,
+                    )
+                    .await
+                {
+                    tracing::warn!(
+                        "[leave_ring] failed to hand off to {:?}: {:?}",
+                        successor,
+                        e
+                    );
+                }
+            }
+        }
+
+        if let Some(predecessor) = predecessor {
+            if successor != id && predecessor != successor {
+                let relinks = [
+                    (
+                        predecessor,
+                        Message::NotifyPredecessorReport(message::NotifyPredecessorReport {
+                            id: successor,
+                        }),
+                    ),
+                    (
+                        successor,
+                        Message::NotifyPredecessorSend(message::NotifyPredecessorSend {
+                            id: predecessor,
+                        }),
+                    ),
+                ];
+                for (did, msg) in relinks {
+                    if let Err(e) = self.send_direct_message(msg, did).await {
+                        tracing::warn!("[leave_ring] failed to relink {:?}: {:?}", did, e);
+                    }
+                }
+            }
+        }
+
+        for did in fingers {
+            if let Err(e) = self
+                .send_direct_message(Message::LeaveDHT(message::LeaveDHT { id }), did)
+                .await
+            {
+                tracing::warn!("[leave_ring] failed to notify {:?}: {:?}", did, e);
+            }
+        }
+
+        // an unreachable peer should not keep other transports open
+        for did in self.get_dids() {
+            if let Err(e) = self.disconnect(did).await {
+                tracing::warn!("[leave_ring] failed to disconnect {:?}: {:?}", did, e);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg_attr(feature = "wasm", async_trait(?Send))]
@@ -339,7 +731,7 @@ where T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static + fmt::Deb
         Swarm::session_manager(self)
     }
 
-    async fn do_send_payload(&self, did: Did, payload: MessagePayload<T>) -> Result<()> {
+    async fn do_send_payload(&self, did: Did, mut payload: MessagePayload<T>) -> Result<()> {
         #[cfg(test)]
         {
             println!("+++++++++++++++++++++++++++++++++");
@@ -358,7 +750,13 @@ where T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static + fmt::Deb
             payload.relay.next_hop,
             transport.id
         );
-        let data: Vec<u8> = payload.encode()?.into();
+        payload.formats = self
+            .payload_format
+            .header()
+            .into_iter()
+            .chain([CHUNK_HEADER])
+            .collect();
+        let data = payload.to_frame(self.peer_payload_format(did))?;
         transport.wait_for_data_channel_open().await?;
         transport.send_message(data.as_slice()).await
     }
@@ -4,15 +4,69 @@ use crate::dht::Did;
 use crate::dht::PeerRing;
 use crate::ecc::SecretKey;
 use crate::message::MessageHandler;
+use crate::message::PayloadFormat;
 use crate::storage::PersistenceStorage;
 use crate::swarm::Swarm;
 use crate::swarm::SwarmBuilder;
 
+#[cfg(feature = "dummy")]
+pub mod simulator;
 mod test_message_handler;
+#[cfg(feature = "dummy")]
+mod test_simulator;
 mod test_stabilize;
 
 pub async fn prepare_node(
     key: SecretKey,
+) -> (Did, Arc<PeerRing>, Arc<Swarm>, MessageHandler, String) {
+    prepare_node_with_replication_factor(key, 0).await
+}
+
+pub async fn prepare_node_with_replication_factor(
+    key: SecretKey,
+    replication_factor: u8,
+) -> (Did, Arc<PeerRing>, Arc<Swarm>, MessageHandler, String) {
+    prepare_node_with(key, |builder| {
+        builder.dht_replication_factor(replication_factor)
+    })
+    .await
+}
+
+pub async fn prepare_node_with_storage_quota(
+    key: SecretKey,
+    quota: usize,
+) -> (Did, Arc<PeerRing>, Arc<Swarm>, MessageHandler, String) {
+    prepare_node_with(key, |builder| builder.dht_storage_quota(quota)).await
+}
+
+pub async fn prepare_node_with_failure_detector(
+    key: SecretKey,
+    timeout_ms: u64,
+    max_suspicion: u8,
+) -> (Did, Arc<PeerRing>, Arc<Swarm>, MessageHandler, String) {
+    prepare_node_with(key, |builder| {
+        builder.dht_failure_detector(timeout_ms, max_suspicion)
+    })
+    .await
+}
+
+pub async fn prepare_node_with_payload_format(
+    key: SecretKey,
+    format: PayloadFormat,
+) -> (Did, Arc<PeerRing>, Arc<Swarm>, MessageHandler, String) {
+    prepare_node_with(key, |builder| builder.payload_format(format)).await
+}
+
+pub async fn prepare_node_with_stream_idle_timeout(
+    key: SecretKey,
+    timeout_ms: u64,
+) -> (Did, Arc<PeerRing>, Arc<Swarm>, MessageHandler, String) {
+    prepare_node_with(key, |builder| builder.stream_idle_timeout(timeout_ms)).await
+}
+
+async fn prepare_node_with(
+    key: SecretKey,
+    config: impl FnOnce(SwarmBuilder) -> SwarmBuilder,
 ) -> (Did, Arc<PeerRing>, Arc<Swarm>, MessageHandler, String) {
     let stun = "stun://stun.l.google.com:19302";
     let did = key.address().into();
@@ -21,7 +75,11 @@ pub async fn prepare_node(
         .await
         .unwrap();
 
-    let swarm = Arc::new(SwarmBuilder::new(stun, storage).key(key).build().unwrap());
+    let swarm = Arc::new(
+        config(SwarmBuilder::new(stun, storage).key(key))
+            .build()
+            .unwrap(),
+    );
     let dht = swarm.dht();
     let node = swarm.create_message_handler(None, None);
 
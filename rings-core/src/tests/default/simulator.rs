@@ -0,0 +1,362 @@
+//! In-process network simulator for multi-node tests.
+//! Nodes of a simulator are connected through it's own `DummyTransportHub`, so
+//! simulators are isolated from each other and can run in parallel.
+//! Keys of nodes, bootstrap nodes, targets of churn, lookups and link conditions
+//! are all decided by RNGs of a seed, and stabilization is driven by the simulator
+//! in rounds instead of timers. Messages in flight are delivered by the virtual clock
+//! of hub, which is advanced by the simulator only when nodes are idle, so deliveries
+//! are ordered by their due time rather than by the scheduling of threads.
+//! Timeouts of requests still run on the real clock, so a lost message costs real time.
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::Either;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand::SeedableRng;
+use tokio::task::JoinHandle;
+use web3::types::H160;
+
+use crate::dht::Did;
+use crate::dht::PeerRing;
+use crate::dht::RingSnapshot;
+use crate::dht::Stabilization;
+use crate::ecc::SecretKey;
+use crate::err::Result;
+use crate::storage::PersistenceStorage;
+use crate::swarm::Swarm;
+use crate::swarm::SwarmBuilder;
+use crate::tests::manually_establish_connection;
+use crate::transports::dummy::DummyTransportHub;
+use crate::transports::dummy::HubStats;
+use crate::types::message::MessageListener;
+
+/// Real time given to nodes to handle delivered messages before the clock of hub is advanced
+const STEP_MS: u64 = 2;
+
+/// Config of a simulator
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    /// seed of RNGs
+    pub seed: u64,
+    /// range of latency of each message in ms
+    pub latency_ms: (u64, u64),
+    /// probability that a message is dropped
+    pub loss_rate: f64,
+    /// max length of successor list of nodes
+    pub succ_max: u8,
+    /// virtual time to wait for messages in flight after each step
+    pub settle_ms: u64,
+    /// timeout of each hop of lookups
+    pub lookup_timeout_ms: u64,
+    /// retries of each hop of lookups
+    pub lookup_retries: u8,
+    /// timeout of liveness probes, and number of failed probes before a node is evicted
+    pub failure_detector: (u64, u8),
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            latency_ms: (0, 0),
+            loss_rate: 0.0,
+            succ_max: 3,
+            settle_ms: 200,
+            lookup_timeout_ms: 1000,
+            lookup_retries: 2,
+            failure_detector: (300, 2),
+        }
+    }
+}
+
+/// A step of scripted churn, target of the step is picked by RNG
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Churn {
+    /// a new node joins through a random node
+    Join,
+    /// a random node leaves the ring gracefully
+    Leave,
+    /// a random node crashes without notice
+    Crash,
+}
+
+/// A node running in simulator
+pub struct SimNode {
+    pub did: Did,
+    pub swarm: Arc<Swarm>,
+    pub dht: Arc<PeerRing>,
+    pub stabilization: Arc<Stabilization>,
+    listener: JoinHandle<()>,
+    path: String,
+}
+
+pub struct Simulator {
+    pub hub: Arc<DummyTransportHub>,
+    config: SimConfig,
+    rng: StdRng,
+    nodes: Vec<SimNode>,
+}
+
+impl Simulator {
+    pub fn new(config: SimConfig) -> Self {
+        let hub = DummyTransportHub::new(config.seed);
+        hub.set_latency(config.latency_ms.0, config.latency_ms.1);
+        hub.set_loss_rate(config.loss_rate);
+        Self {
+            hub: Arc::new(hub),
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            nodes: vec![],
+        }
+    }
+
+    /// Dids of running nodes, in order of the ring
+    pub fn dids(&self) -> Vec<Did> {
+        let mut dids: Vec<Did> = self.nodes.iter().map(|n| n.did).collect();
+        dids.sort();
+        dids
+    }
+
+    pub fn node(&self, did: Did) -> Option<&SimNode> {
+        self.nodes.iter().find(|n| n.did == did)
+    }
+
+    pub fn hub_stats(&self) -> HubStats {
+        self.hub.stats()
+    }
+
+    /// Generate a key by RNG of simulator
+    pub fn gen_key(&mut self) -> SecretKey {
+        libsecp256k1::SecretKey::random(&mut self.rng).into()
+    }
+
+    fn pick(&mut self) -> Option<Did> {
+        self.nodes.choose(&mut self.rng).map(|n| n.did)
+    }
+
+    /// Start a node with key, and join it to the ring through bootstrap node.
+    pub async fn spawn_node_with_key(
+        &mut self,
+        key: SecretKey,
+        bootstrap: Option<Did>,
+    ) -> Result<Did> {
+        let path = PersistenceStorage::random_path("./tmp");
+        let storage = PersistenceStorage::new_with_path(path.as_str()).await?;
+        let swarm = Arc::new(
+            SwarmBuilder::new("stun://stun.l.google.com:19302", storage)
+                .key(key)
+                .dht_succ_max(self.config.succ_max)
+                .dht_failure_detector(
+                    self.config.failure_detector.0,
+                    self.config.failure_detector.1,
+                )
+                .dummy_hub(self.hub.clone())
+                .build()?,
+        );
+        if let Some(node) = bootstrap.and_then(|did| self.node(did)) {
+            manually_establish_connection(&swarm, &node.swarm).await?;
+        }
+        let handler = Arc::new(swarm.create_message_handler(None, None));
+        let node = SimNode {
+            did: swarm.did(),
+            dht: swarm.dht(),
+            stabilization: Arc::new(Stabilization::new(swarm.clone(), 5)),
+            listener: tokio::spawn(async move { handler.listen().await }),
+            swarm,
+            path,
+        };
+        let did = node.did;
+        self.nodes.push(node);
+        self.settle().await;
+        Ok(did)
+    }
+
+    /// Start a node with a generated key, and join it through a random node
+    pub async fn spawn_node(&mut self) -> Result<Did> {
+        let key = self.gen_key();
+        let bootstrap = self.pick();
+        self.spawn_node_with_key(key, bootstrap).await
+    }
+
+    pub async fn spawn_nodes(&mut self, n: usize) -> Result<Vec<Did>> {
+        let mut dids = vec![];
+        for _ in 0..n {
+            dids.push(self.spawn_node().await?);
+        }
+        Ok(dids)
+    }
+
+    /// Stop a node without notice, messages to it are dropped since then
+    pub async fn crash(&mut self, did: Did) {
+        if let Some(i) = self.nodes.iter().position(|n| n.did == did) {
+            let node = self.nodes.remove(i);
+            node.listener.abort();
+            self.hub.crash(did);
+            tokio::fs::remove_dir_all(&node.path).await.ok();
+        }
+    }
+
+    /// Leave the ring gracefully, then stop the node
+    pub async fn leave(&mut self, did: Did) -> Result<()> {
+        if let Some(node) = self.node(did) {
+            self.drive(node.swarm.leave_ring()).await?;
+            self.settle().await;
+        }
+        self.crash(did).await;
+        Ok(())
+    }
+
+    /// Apply steps of churn in order
+    pub async fn churn(&mut self, script: &[Churn]) -> Result<()> {
+        for step in script {
+            match step {
+                Churn::Join => {
+                    self.spawn_node().await?;
+                }
+                Churn::Leave => {
+                    if let Some(did) = self.pick() {
+                        self.leave(did).await?;
+                    }
+                }
+                Churn::Crash => {
+                    if let Some(did) = self.pick() {
+                        self.crash(did).await;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Isolate nodes from others, see `DummyTransportHub::partition`
+    pub fn partition(&self, dids: &[Did]) {
+        self.hub.partition(dids)
+    }
+
+    pub fn heal(&self) {
+        self.hub.heal()
+    }
+
+    /// Deliver messages in flight by the clock of hub, until no message is due
+    /// in `settle_ms` of virtual time.
+    pub async fn settle(&self) {
+        let deadline = self.hub.now_ms() + self.config.settle_ms + self.config.latency_ms.1 * 4;
+        loop {
+            // nodes handle delivered messages, and may send more
+            tokio::time::sleep(Duration::from_millis(STEP_MS)).await;
+            match self.hub.next_due() {
+                Some(due) if due <= deadline => self.hub.advance_to_next(),
+                _ => break,
+            };
+        }
+    }
+
+    /// Wait for a future of nodes, such as a request waiting for response, while
+    /// messages in flight are delivered by the clock of hub.
+    pub async fn drive<T>(&self, fut: impl Future<Output = T>) -> T {
+        tokio::pin!(fut);
+        loop {
+            let step = tokio::time::sleep(Duration::from_millis(STEP_MS));
+            tokio::pin!(step);
+            match futures::future::select(fut.as_mut(), step).await {
+                Either::Left((output, _)) => return output,
+                Either::Right(_) => self.hub.advance_to_next(),
+            };
+        }
+    }
+
+    /// Run rounds of stabilization and probing on all nodes, in random order
+    pub async fn stabilize(&mut self, rounds: usize) -> Result<()> {
+        for _ in 0..rounds {
+            let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+            order.shuffle(&mut self.rng);
+            for i in order {
+                let stabilization = self.nodes[i].stabilization.clone();
+                self.drive(stabilization.stabilize()).await?;
+                self.drive(stabilization.probe_peers()).await?;
+            }
+            self.settle().await;
+        }
+        Ok(())
+    }
+
+    /// Run rounds of stabilization until the ring is correct, return false if it's
+    /// still incorrect after `max_rounds`.
+    pub async fn stabilize_until_correct(&mut self, max_rounds: usize) -> Result<bool> {
+        for _ in 0..max_rounds {
+            if self.is_ring_correct().await? {
+                return Ok(true);
+            }
+            self.stabilize(1).await?;
+        }
+        self.is_ring_correct().await
+    }
+
+    /// Walk successors from the first node, by status of nodes in simulator
+    pub async fn snapshot(&self) -> Result<RingSnapshot> {
+        let mut snapshot = RingSnapshot::default();
+        if let Some(node) = self.nodes.first() {
+            snapshot.nodes.push(node.dht.status().await?);
+        }
+        while let Some(next) = snapshot.next() {
+            match self.node(next) {
+                Some(node) => snapshot.nodes.push(node.dht.status().await?),
+                None => snapshot.unreachable.push(next),
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// The ring is correct if walking successors visits all nodes in order and
+    /// goes back to the start, and each successor points back as predecessor.
+    pub async fn is_ring_correct(&self) -> Result<bool> {
+        let snapshot = self.snapshot().await?;
+        let issues = snapshot.check();
+        for issue in issues.iter() {
+            tracing::warn!("ring inconsistency: {}", issue);
+        }
+        Ok(issues.is_empty() && snapshot.closed && snapshot.nodes.len() == self.nodes.len())
+    }
+
+    /// Expected successor of id, which is the first node at or after id on the ring
+    pub fn expected_successor(&self, id: Did) -> Option<Did> {
+        let dids = self.dids();
+        dids.iter()
+            .find(|did| **did >= id)
+            .or_else(|| dids.first())
+            .copied()
+    }
+
+    /// Look up random ids from random nodes iteratively, return the rate of lookups
+    /// which find the expected successor.
+    pub async fn lookup_success_rate(&mut self, samples: usize) -> f64 {
+        if samples == 0 || self.nodes.is_empty() {
+            return 0.0;
+        }
+        let timeout = Duration::from_millis(self.config.lookup_timeout_ms);
+        let mut succeeded = 0;
+        for _ in 0..samples {
+            let id: Did = H160::from(self.rng.gen::<[u8; 20]>()).into();
+            let origin = self.nodes.choose(&mut self.rng).unwrap().swarm.clone();
+            let trace = self
+                .drive(origin.find_successor_iterative(id, timeout, self.config.lookup_retries))
+                .await;
+            match trace {
+                Ok(t) if Some(t.successor) == self.expected_successor(id) => succeeded += 1,
+                Ok(t) => tracing::debug!("lookup of {:?} found {:?}", id, t.successor),
+                Err(e) => tracing::debug!("lookup of {:?} failed: {:?}", id, e),
+            }
+        }
+        succeeded as f64 / samples as f64
+    }
+
+    /// Stop all nodes and remove their storage
+    pub async fn shutdown(mut self) {
+        for did in self.dids() {
+            self.crash(did).await;
+        }
+    }
+}
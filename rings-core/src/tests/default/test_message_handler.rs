@@ -1,21 +1,28 @@
 use std::str::FromStr;
 use std::sync::Arc;
 
+use num_bigint::BigUint;
 use tokio::time::sleep;
 use tokio::time::Duration;
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 
 use super::prepare_node;
+use super::prepare_node_with_payload_format;
+use crate::dht::vnode::VNodeType;
 use crate::dht::vnode::VirtualNode;
+use crate::dht::ChordStorage;
+use crate::dht::Did;
 use crate::ecc::tests::gen_ordered_keys;
 use crate::ecc::SecretKey;
 use crate::err::Error;
 use crate::err::Result;
 use crate::message;
+use crate::message::handlers::connection::tests::test_only_two_nodes_establish_connection;
 use crate::message::Encoder;
 use crate::message::FindSuccessorReportHandler;
 use crate::message::FindSuccessorThen;
 use crate::message::Message;
+use crate::message::PayloadFormat;
 use crate::message::PayloadSender;
 use crate::storage::PersistenceStorageOperation;
 use crate::storage::PersistenceStorageReadAndWrite;
@@ -463,3 +470,91 @@ async fn test_handle_storage() -> Result<()> {
     tokio::fs::remove_dir_all("./tmp").await.ok();
     Ok(())
 }
+
+#[tokio::test]
+async fn test_leave_ring() -> Result<()> {
+    let keys = gen_ordered_keys(3);
+    let (key1, key2, key3) = (keys[0], keys[1], keys[2]);
+
+    let (did1, dht1, swarm1, handler1, _path1) = prepare_node(key1).await;
+    let (did2, dht2, swarm2, handler2, _path2) = prepare_node(key2).await;
+    let (did3, dht3, swarm3, handler3, _path3) = prepare_node(key3).await;
+
+    manually_establish_connection(&swarm1, &swarm2).await?;
+    manually_establish_connection(&swarm2, &swarm3).await?;
+    manually_establish_connection(&swarm1, &swarm3).await?;
+
+    tokio::select! {
+        _ = async {
+            futures::join!(
+                async { loop { Arc::new(handler1.clone()).listen().await; } },
+                async { loop { Arc::new(handler2.clone()).listen().await; } },
+                async { loop { Arc::new(handler3.clone()).listen().await; } },
+            )
+        } => { unreachable!(); }
+        _ = async {
+            sleep(Duration::from_millis(3000)).await;
+            // node1 -> node2 -> node3
+            //   ^                 |
+            //   |-----------------|
+            assert_eq!(dht1.lock_successor()?.min(), did2);
+            assert_eq!(dht2.lock_successor()?.min(), did3);
+            assert_eq!(dht3.lock_successor()?.min(), did1);
+            *dht2.lock_predecessor()? = Some(did1);
+            *dht3.lock_predecessor()? = Some(did2);
+
+            // vid is in range (node2, node3], so node2 is responsible for it
+            let vid = did2 + Did::from(BigUint::from(1u16));
+            let vnode = VirtualNode {
+                address: vid,
+                data: vec!["leaving message".encode()?],
+                kind: VNodeType::RelayMessage,
+                expires_at: None,
+            };
+            dht2.store(vnode.clone()).await?;
+            assert_eq!(dht2.storage.get(&vid).await?, vnode);
+
+            swarm2.leave_ring().await?;
+            sleep(Duration::from_millis(3000)).await;
+
+            // node1 and node3 are relinked, and node1 takes over the vnode
+            assert!(swarm2.get_dids().is_empty());
+            assert!(swarm1.get_transport(did2).is_none());
+            assert!(swarm3.get_transport(did2).is_none());
+            assert_eq!(dht1.lock_successor()?.list(), vec![did3]);
+            assert_eq!(*dht3.lock_predecessor()?, Some(did1));
+            assert_eq!(dht3.fetch_replica(&vid), Some(vnode.clone()));
+            assert_eq!(dht1.storage.get(&vid).await?, vnode);
+            Ok::<(), Error>(())
+        } => {}
+    }
+    tokio::fs::remove_dir_all("./tmp").await.ok();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_switch_payload_format_per_peer() -> Result<()> {
+    let (did1, _, swarm1, node1, _) = prepare_node(SecretKey::random()).await;
+    let (did2, _, swarm2, node2, _) = prepare_node(SecretKey::random()).await;
+
+    // legacy frames are sent until peer advertises binary format
+    assert_eq!(swarm1.peer_payload_format(did2), PayloadFormat::Legacy);
+    test_only_two_nodes_establish_connection(&node1, &node2).await?;
+    assert_eq!(swarm1.peer_payload_format(did2), PayloadFormat::BincodeV1);
+    assert_eq!(swarm2.peer_payload_format(did1), PayloadFormat::BincodeV1);
+
+    // node which prefers legacy frames never advertises binary format
+    let (did3, _, swarm3, node3, _) = prepare_node(SecretKey::random()).await;
+    let (did4, _, swarm4, node4, _) =
+        prepare_node_with_payload_format(SecretKey::random(), PayloadFormat::Legacy).await;
+    test_only_two_nodes_establish_connection(&node3, &node4).await?;
+    assert_eq!(swarm3.peer_payload_format(did4), PayloadFormat::Legacy);
+    assert_eq!(swarm4.peer_payload_format(did3), PayloadFormat::Legacy);
+
+    // format is learned again after reconnecting
+    swarm1.remove_transport(did2);
+    assert_eq!(swarm1.peer_payload_format(did2), PayloadFormat::Legacy);
+
+    tokio::fs::remove_dir_all("./tmp").await.ok();
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use crate::dht::Did;
+use crate::err::Result;
+use crate::tests::default::simulator::Churn;
+use crate::tests::default::simulator::SimConfig;
+use crate::tests::default::simulator::Simulator;
+use crate::transports::dummy::HubStats;
+
+#[tokio::test]
+async fn test_simulator_ring_and_lookup() -> Result<()> {
+    let mut sim = Simulator::new(SimConfig {
+        seed: 1,
+        ..Default::default()
+    });
+    sim.spawn_nodes(8).await?;
+    assert!(sim.stabilize_until_correct(10).await?);
+    assert_eq!(sim.lookup_success_rate(32).await, 1.0);
+    sim.shutdown().await;
+    Ok(())
+}
+
+/// Tables of nodes, and counters of hub, after a same script is run on a seed
+async fn run_script(seed: u64) -> Result<(Vec<(Did, Vec<Did>, Option<Did>)>, HubStats, f64)> {
+    let mut sim = Simulator::new(SimConfig {
+        seed,
+        latency_ms: (5, 30),
+        ..Default::default()
+    });
+    sim.spawn_nodes(5).await?;
+    sim.stabilize(3).await?;
+    let mut tables = vec![];
+    for did in sim.dids() {
+        let dht = &sim.node(did).unwrap().dht;
+        tables.push((did, dht.lock_successor()?.list(), *dht.lock_predecessor()?));
+    }
+    let stats = sim.hub_stats();
+    let rate = sim.lookup_success_rate(16).await;
+    sim.shutdown().await;
+    Ok((tables, stats, rate))
+}
+
+#[tokio::test]
+async fn test_simulator_same_seed_same_network() -> Result<()> {
+    let (tables, stats, rate) = run_script(2).await?;
+    assert_eq!(tables.len(), 5);
+    assert!(stats.delivered > 0);
+    assert_eq!((tables, stats, rate), run_script(2).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_simulator_with_latency_and_loss() -> Result<()> {
+    let mut sim = Simulator::new(SimConfig {
+        seed: 3,
+        latency_ms: (5, 30),
+        loss_rate: 0.01,
+        failure_detector: (1000, 5),
+        ..Default::default()
+    });
+    sim.spawn_nodes(6).await?;
+    assert!(sim.stabilize_until_correct(20).await?);
+    assert!(sim.lookup_success_rate(32).await >= 0.9);
+    assert!(sim.hub_stats().delivered > 0);
+    sim.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_simulator_partition_and_heal() -> Result<()> {
+    let mut sim = Simulator::new(SimConfig {
+        seed: 4,
+        lookup_retries: 0,
+        lookup_timeout_ms: 300,
+        ..Default::default()
+    });
+    let dids = sim.spawn_nodes(6).await?;
+    assert!(sim.stabilize_until_correct(10).await?);
+
+    // lookups across the partition are lost
+    sim.partition(&dids[..3]);
+    assert!(sim.lookup_success_rate(32).await < 1.0);
+    assert!(sim.hub_stats().dropped > 0);
+
+    sim.heal();
+    assert_eq!(sim.lookup_success_rate(32).await, 1.0);
+    sim.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_simulator_scripted_churn() -> Result<()> {
+    let mut sim = Simulator::new(SimConfig {
+        seed: 5,
+        ..Default::default()
+    });
+    sim.spawn_nodes(8).await?;
+    assert!(sim.stabilize_until_correct(10).await?);
+
+    sim.churn(&[
+        Churn::Join,
+        Churn::Crash,
+        Churn::Join,
+        Churn::Leave,
+        Churn::Crash,
+    ])
+    .await?;
+    assert_eq!(sim.dids().len(), 8);
+    assert!(sim.stabilize_until_correct(20).await?);
+    assert_eq!(sim.lookup_success_rate(32).await, 1.0);
+    sim.shutdown().await;
+    Ok(())
+}
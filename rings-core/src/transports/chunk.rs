@@ -0,0 +1,368 @@
+#![warn(missing_docs)]
+//! Fragmentation of large messages over data channels.
+//! SCTP of data channels limits the size of a message, so a message larger than a chunk
+//! is split into chunk frames by the sending transport, and reassembled by the remote one.
+//! A chunk frame starts with the header byte `CHUNK_HEADER`, which never appears at the
+//! start of a payload frame, so a message which fits in a chunk is sent as is.
+//! Nodes of older versions can't reassemble chunk frames, so messages are split only after
+//! the remote advertises `CHUNK_HEADER` in `formats` of a payload, see
+//! `Swarm::learn_payload_format`. Before that, a large message is sent as is, as older
+//! versions do, which may exceed the limit of data channel of browsers.
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use crate::err::Error;
+use crate::err::Result;
+use crate::utils;
+
+/// First byte of a chunk frame
+pub const CHUNK_HEADER: u8 = 0xc1;
+/// Header byte, message id (u64), sequence (u32) and total number (u32) of chunks
+const CHUNK_HEADER_LEN: usize = 1 + 8 + 4 + 4;
+/// Max size of a frame sent over data channel by default, which is safe for all browsers
+pub const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+/// Time to wait for the rest chunks of a message by default
+pub const DEFAULT_REASSEMBLY_TIMEOUT_MS: u128 = 30 * 1000;
+/// Max number of messages being reassembled at the same time by default
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+/// Max size of a reassembled message by default, which is above the largest payload
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+/// Max bytes of incomplete messages buffered by a codec by default
+pub const DEFAULT_MAX_BUFFERED: usize = 16 * 1024 * 1024;
+
+/// Config of chunking
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkConfig {
+    /// max size of a frame, including the header of chunk
+    pub chunk_size: usize,
+    /// an incomplete message is dropped after this timeout
+    pub timeout_ms: u128,
+    /// the oldest incomplete message is dropped when a new one comes beyond this limit
+    pub max_in_flight: usize,
+    /// max size of a message, larger ones are refused by both sides
+    pub max_message_size: usize,
+    /// max bytes of incomplete messages, the oldest ones are dropped beyond this limit
+    pub max_buffered: usize,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            timeout_ms: DEFAULT_REASSEMBLY_TIMEOUT_MS,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_buffered: DEFAULT_MAX_BUFFERED,
+        }
+    }
+}
+
+struct Chunk<'a> {
+    id: u64,
+    seq: u32,
+    total: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    fn parse(frame: &'a [u8]) -> Result<Self> {
+        if frame.len() <= CHUNK_HEADER_LEN || frame[0] != CHUNK_HEADER {
+            return Err(Error::RTCDataChannelChunkMalformed);
+        }
+        let chunk = Self {
+            id: u64::from_be_bytes(frame[1..9].try_into().unwrap()),
+            seq: u32::from_be_bytes(frame[9..13].try_into().unwrap()),
+            total: u32::from_be_bytes(frame[13..17].try_into().unwrap()),
+            data: &frame[CHUNK_HEADER_LEN..],
+        };
+        if chunk.seq >= chunk.total {
+            return Err(Error::RTCDataChannelChunkMalformed);
+        }
+        Ok(chunk)
+    }
+
+    fn to_frame(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(CHUNK_HEADER_LEN + self.data.len());
+        frame.push(CHUNK_HEADER);
+        frame.extend_from_slice(&self.id.to_be_bytes());
+        frame.extend_from_slice(&self.seq.to_be_bytes());
+        frame.extend_from_slice(&self.total.to_be_bytes());
+        frame.extend_from_slice(self.data);
+        frame
+    }
+}
+
+/// Received chunks of a message
+struct Reassembly {
+    total: u32,
+    chunks: BTreeMap<u32, Vec<u8>>,
+    size: usize,
+    started_at: u128,
+}
+
+/// Splits messages sent by a transport, and reassembles chunks received by it.
+/// Each transport has it's own codec, since ids of messages are only unique in a channel.
+pub struct ChunkCodec {
+    config: ChunkConfig,
+    /// the remote is known to reassemble chunks, see `enable_split`
+    split_enabled: AtomicBool,
+    next_id: AtomicU64,
+    buffers: Mutex<HashMap<u64, Reassembly>>,
+}
+
+impl Default for ChunkCodec {
+    fn default() -> Self {
+        Self::new(ChunkConfig::default())
+    }
+}
+
+impl ChunkCodec {
+    /// Create a codec with config
+    pub fn new(config: ChunkConfig) -> Self {
+        Self {
+            config,
+            split_enabled: AtomicBool::new(false),
+            next_id: AtomicU64::new(0),
+            buffers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Split messages from now on, it's called once the remote is known to reassemble them.
+    pub fn enable_split(&self) {
+        self.split_enabled.store(true, Ordering::SeqCst);
+    }
+
+    /// Split a message into frames to send, a message which fits in a chunk is not split,
+    /// and no message is split until `enable_split` is called.
+    pub fn split(&self, msg: &[u8]) -> Result<Vec<Vec<u8>>> {
+        if msg.len() <= self.config.chunk_size || !self.split_enabled.load(Ordering::SeqCst) {
+            return Ok(vec![msg.to_vec()]);
+        }
+        if msg.len() > self.config.max_message_size {
+            return Err(Error::RTCDataChannelMessageTooLarge(msg.len()));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let size = self
+            .config
+            .chunk_size
+            .saturating_sub(CHUNK_HEADER_LEN)
+            .max(1);
+        let total = ((msg.len() + size - 1) / size) as u32;
+        Ok(msg
+            .chunks(size)
+            .enumerate()
+            .map(|(seq, data)| {
+                Chunk {
+                    id,
+                    seq: seq as u32,
+                    total,
+                    data,
+                }
+                .to_frame()
+            })
+            .collect())
+    }
+
+    /// Feed a received frame, return the message if it's complete.
+    /// A frame which is not a chunk is returned as is.
+    pub fn feed(&self, frame: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        if frame.first() != Some(&CHUNK_HEADER) {
+            return Ok(Some(frame));
+        }
+        let chunk = Chunk::parse(&frame)?;
+        let now = utils::get_epoch_ms();
+        let mut buffers = self.buffers.lock().unwrap();
+
+        buffers.retain(|id, r| {
+            let alive = now <= r.started_at + self.config.timeout_ms;
+            if !alive {
+                tracing::warn!("drop message {} whose chunks are timeout", id);
+            }
+            alive
+        });
+        if !buffers.contains_key(&chunk.id) && buffers.len() >= self.config.max_in_flight {
+            let oldest = buffers
+                .iter()
+                .min_by_key(|(_, r)| r.started_at)
+                .map(|(id, _)| *id);
+            if let Some(id) = oldest {
+                tracing::warn!("drop message {} since too many messages are in flight", id);
+                buffers.remove(&id);
+            }
+        }
+
+        let r = buffers.entry(chunk.id).or_insert_with(|| Reassembly {
+            total: chunk.total,
+            chunks: BTreeMap::new(),
+            size: 0,
+            started_at: now,
+        });
+        if r.total != chunk.total {
+            buffers.remove(&chunk.id);
+            return Err(Error::RTCDataChannelChunkMalformed);
+        }
+        if !r.chunks.contains_key(&chunk.seq) {
+            r.size += chunk.data.len();
+            r.chunks.insert(chunk.seq, chunk.data.to_vec());
+        }
+        if r.size > self.config.max_message_size {
+            let size = r.size;
+            buffers.remove(&chunk.id);
+            return Err(Error::RTCDataChannelMessageTooLarge(size));
+        }
+        if r.chunks.len() >= r.total as usize {
+            let r = buffers.remove(&chunk.id).unwrap();
+            return Ok(Some(r.chunks.into_values().flatten().collect()));
+        }
+
+        // a peer can start many messages without finishing them, bound the buffered bytes
+        let mut buffered: usize = buffers.values().map(|r| r.size).sum();
+        while buffered > self.config.max_buffered {
+            let oldest = buffers
+                .iter()
+                .filter(|(id, _)| **id != chunk.id)
+                .min_by_key(|(_, r)| r.started_at)
+                .map(|(id, _)| *id);
+            match oldest.and_then(|id| buffers.remove(&id).map(|r| (id, r.size))) {
+                Some((id, size)) => {
+                    tracing::warn!("drop message {} since too many bytes are buffered", id);
+                    buffered -= size;
+                }
+                None => {
+                    buffers.remove(&chunk.id);
+                    return Err(Error::RTCDataChannelMessageTooLarge(buffered));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Number of messages being reassembled
+    pub fn in_flight(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codec(max_in_flight: usize, timeout_ms: u128) -> ChunkCodec {
+        let codec = ChunkCodec::new(ChunkConfig {
+            chunk_size: CHUNK_HEADER_LEN + 4,
+            timeout_ms,
+            max_in_flight,
+            max_message_size: 64,
+            max_buffered: 96,
+        });
+        codec.enable_split();
+        codec
+    }
+
+    #[test]
+    fn test_split_after_enabled() -> Result<()> {
+        let codec = ChunkCodec::new(ChunkConfig {
+            chunk_size: CHUNK_HEADER_LEN + 4,
+            ..Default::default()
+        });
+        // remote may not reassemble chunks, so the message is sent as is
+        let msg: Vec<u8> = (0..30).collect();
+        assert_eq!(codec.split(&msg)?, vec![msg.clone()]);
+
+        codec.enable_split();
+        assert_eq!(codec.split(&msg)?.len(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bound_buffered_bytes() -> Result<()> {
+        let sender = codec(8, 1000);
+        let receiver = codec(8, 1000);
+
+        // each message is within max size, but they are beyond the buffered limit together
+        let msg: Vec<u8> = (0..60).collect();
+        let (a, b) = (sender.split(&msg)?, sender.split(&msg)?);
+        assert_eq!(a.len(), 15);
+        for f in a[..14].iter() {
+            assert_eq!(receiver.feed(f.clone())?, None);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        for f in b[..14].iter() {
+            assert_eq!(receiver.feed(f.clone())?, None);
+        }
+        // the oldest message is dropped to make room for the newer one
+        assert_eq!(receiver.in_flight(), 1);
+        assert_eq!(receiver.feed(a[14].clone())?, None);
+        assert_eq!(receiver.feed(b[14].clone())?, Some(msg));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_and_reassemble() -> Result<()> {
+        let sender = codec(2, 1000);
+        let receiver = codec(2, 1000);
+
+        // small message is sent as is
+        let frames = sender.split(b"rings")?;
+        assert_eq!(frames, vec![b"rings".to_vec()]);
+        assert_eq!(receiver.feed(frames[0].clone())?, Some(b"rings".to_vec()));
+
+        // chunks arrive out of order, and duplicated
+        let msg: Vec<u8> = (0..30).collect();
+        let mut frames = sender.split(&msg)?;
+        assert_eq!(frames.len(), 8);
+        assert!(frames.iter().all(|f| f.len() <= CHUNK_HEADER_LEN + 4));
+        frames.reverse();
+        let last = frames.pop().unwrap();
+        for f in frames.iter().chain(frames.iter()) {
+            assert_eq!(receiver.feed(f.clone())?, None);
+        }
+        assert_eq!(receiver.in_flight(), 1);
+        assert_eq!(receiver.feed(last)?, Some(msg.clone()));
+        assert_eq!(receiver.in_flight(), 0);
+
+        // the oldest message is dropped beyond the limit of in-flight
+        let (a, b, c) = (
+            sender.split(&msg)?,
+            sender.split(&msg)?,
+            sender.split(&msg)?,
+        );
+        receiver.feed(a[0].clone())?;
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        receiver.feed(b[0].clone())?;
+        receiver.feed(c[0].clone())?;
+        assert_eq!(receiver.in_flight(), 2);
+        for f in a[1..].iter() {
+            assert_eq!(receiver.feed(f.clone())?, None);
+        }
+
+        // incomplete message is dropped after timeout
+        let receiver = codec(2, 0);
+        receiver.feed(a[0].clone())?;
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        for f in a[1..].iter() {
+            assert_eq!(receiver.feed(f.clone())?, None);
+        }
+
+        // malformed and oversized ones are refused
+        assert!(matches!(
+            sender.split(&[0u8; 65]),
+            Err(Error::RTCDataChannelMessageTooLarge(65))
+        ));
+        let mut bad = a[0].clone();
+        bad[9..13].copy_from_slice(&100u32.to_be_bytes());
+        assert!(matches!(
+            receiver.feed(bad),
+            Err(Error::RTCDataChannelChunkMalformed)
+        ));
+        assert!(matches!(
+            receiver.feed(vec![CHUNK_HEADER, 0]),
+            Err(Error::RTCDataChannelChunkMalformed)
+        ));
+        Ok(())
+    }
+}
@@ -32,6 +32,7 @@ use crate::message::Encoded;
 use crate::message::Encoder;
 use crate::message::MessagePayload;
 use crate::session::SessionManager;
+use crate::transports::chunk::ChunkCodec;
 use crate::transports::helper::Promise;
 use crate::transports::helper::TricklePayload;
 use crate::types::channel::Channel;
@@ -54,6 +55,7 @@ pub struct DefaultTransport {
     data_channel: Arc<FuturesMutex<Option<Arc<RTCDataChannel>>>>,
     event_sender: EventSender,
     public_key: Arc<AsyncRwLock<Option<PublicKey>>>,
+    chunks: Arc<ChunkCodec>,
 }
 
 impl PartialEq for DefaultTransport {
@@ -140,6 +142,7 @@ impl IceTransportInterface<Event, AcChannel<Event>> for DefaultTransport {
             pending_candidates: Arc::new(FuturesMutex::new(vec![])),
             data_channel: Arc::new(FuturesMutex::new(None)),
             public_key: Arc::new(AsyncRwLock::new(None)),
+            chunks: Arc::new(ChunkCodec::default()),
             event_sender,
         }
     }
@@ -235,26 +238,25 @@ impl IceTransportInterface<Event, AcChannel<Event>> for DefaultTransport {
     }
 
     async fn send_message(&self, msg: &[u8]) -> Result<()> {
-        let size = msg.len();
-        match self.get_data_channel().await {
-            Some(cnn) => match cnn.send(&Bytes::from(msg.to_vec())).await {
-                Ok(s) => {
-                    if !s == size {
-                        Err(Error::RTCDataChannelMessageIncomplete(s, size))
-                    } else {
-                        Ok(())
-                    }
-                }
+        let cnn = self
+            .get_data_channel()
+            .await
+            .ok_or(Error::RTCDataChannelNotReady)?;
+        for frame in self.chunks.split(msg)? {
+            let size = frame.len();
+            match cnn.send(&Bytes::from(frame)).await {
+                Ok(s) if s != size => return Err(Error::RTCDataChannelMessageIncomplete(s, size)),
+                Ok(_) => {}
                 Err(e) => {
                     if cnn.ready_state() != RTCDataChannelState::Open {
-                        Err(Error::RTCDataChannelStateNotOpen)
+                        return Err(Error::RTCDataChannelStateNotOpen);
                     } else {
-                        Err(Error::RTCDataChannelSendTextFailed(e))
+                        return Err(Error::RTCDataChannelSendTextFailed(e));
                     }
                 }
-            },
-            None => Err(Error::RTCDataChannelNotReady),
+            }
         }
+        Ok(())
     }
 }
 
@@ -327,16 +329,28 @@ impl IceTransportCallback for DefaultTransport {
 
     async fn on_data_channel(&self) -> Self::OnDataChannelHdlrFn {
         let event_sender = self.event_sender.clone();
+        let chunks = self.chunks.clone();
 
         box move |d: Arc<RTCDataChannel>| {
             let event_sender = event_sender.clone();
+            let chunks = chunks.clone();
             Box::pin(async move {
                 d.on_message(Box::new(move |msg: DataChannelMessage| {
                     tracing::debug!("Message from DataChannel: '{:?}'", msg);
                     let event_sender = event_sender.clone();
+                    let chunks = chunks.clone();
                     Box::pin(async move {
+                        // wait for the rest chunks if it's a part of message
+                        let msg = match chunks.feed(msg.data.to_vec()) {
+                            Ok(Some(msg)) => msg,
+                            Ok(None) => return,
+                            Err(e) => {
+                                tracing::warn!("Failed on reassemble msg, {:?}", e);
+                                return;
+                            }
+                        };
                         if event_sender
-                            .send(Event::DataChannelMessage(msg.data.to_vec()))
+                            .send(Event::DataChannelMessage(msg))
                             .await
                             .is_err()
                         {
@@ -491,6 +505,11 @@ impl DefaultTransport {
         }
     }
 
+    /// Split large messages into chunks, once the remote is known to reassemble them.
+    pub fn enable_chunks(&self) {
+        self.chunks.enable_split();
+    }
+
     pub async fn wait_for_data_channel_open(&self) -> Result<()> {
         match self.get_data_channel().await {
             Some(dc) => {
@@ -0,0 +1,424 @@
+#![warn(missing_docs)]
+//! Hub of dummy transports, which delivers messages between transports in process.
+//! Hubs are isolated from each other, so a test can run its own network in parallel
+//! with others. A hub simulates latency, packet loss and partitions of links.
+//! Each link between two nodes decides the loss and latency of it's messages by it's own
+//! RNG derived from the seed of hub, so the decisions of a link don't depend on how
+//! messages of other links interleave with it.
+//! Delayed messages wait on a virtual clock of the hub, which is moved forward by
+//! `DummyTransportHub::advance`, they are delivered in order of due time then sending.
+//! Chunks of a message are reassembled by the codec of remote transport when they are
+//! delivered, as a data channel does.
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+
+use crate::channels::Channel as AcChannel;
+use crate::dht::Did;
+use crate::transports::chunk::ChunkCodec;
+use crate::types::channel::Channel;
+use crate::types::channel::Event;
+
+type EventSender = <AcChannel<Event> as Channel<Event>>::Sender;
+
+lazy_static! {
+    static ref HUB: Arc<DummyTransportHub> = Arc::new(DummyTransportHub::default());
+}
+
+/// Counters of messages passed through a hub
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HubStats {
+    /// messages delivered to remote transports
+    pub delivered: u64,
+    /// messages dropped by packet loss, partitions or missing remote
+    pub dropped: u64,
+}
+
+/// Conditions of links, each message is delayed by a latency in `latency_ms`,
+/// and dropped by the probability `loss_rate`.
+#[derive(Debug)]
+struct LinkConditions {
+    latency_ms: (u64, u64),
+    loss_rate: f64,
+    seed: u64,
+    /// RNG of each link, it's derived from seed and dids of both ends
+    links: HashMap<(Did, Did), StdRng>,
+    /// RNG of messages whose ends are not registered yet
+    rng: StdRng,
+}
+
+impl LinkConditions {
+    fn rng(&mut self, link: Option<(Did, Did)>) -> &mut StdRng {
+        let seed = self.seed;
+        match link {
+            Some(link) => self
+                .links
+                .entry(link)
+                .or_insert_with(|| StdRng::seed_from_u64(link_seed(seed, link))),
+            None => &mut self.rng,
+        }
+    }
+}
+
+/// Seed of a link, which is mixed from seed of hub and dids of both ends by FNV-1a.
+fn link_seed(seed: u64, (from, to): (Did, Did)) -> u64 {
+    seed.to_be_bytes()
+        .iter()
+        .chain(from.as_bytes())
+        .chain(to.as_bytes())
+        .fold(0xcbf29ce484222325, |h, b| {
+            (h ^ *b as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Message waiting for it's latency, ordered by due time then sequence of sending.
+struct Delayed {
+    due: u64,
+    seq: u64,
+    sender: EventSender,
+    chunks: Option<Arc<ChunkCodec>>,
+    event: Event,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        (self.due, self.seq) == (other.due, other.seq)
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    // reversed, so the BinaryHeap pops the earliest one
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (other.due, other.seq).cmp(&(self.due, self.seq))
+    }
+}
+
+/// Send event to the remote transport, messages are reassembled by it's codec first.
+fn dispatch(sender: &EventSender, chunks: Option<&ChunkCodec>, event: Event) {
+    let event = match (event, chunks) {
+        (Event::DataChannelMessage(frame), Some(chunks)) => match chunks.feed(frame) {
+            Ok(Some(msg)) => Event::DataChannelMessage(msg),
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("Failed on reassemble msg, {:?}", e);
+                return;
+            }
+        },
+        (event, _) => event,
+    };
+    sender.try_send(event).ok();
+}
+
+/// Hub of dummy transports
+pub struct DummyTransportHub {
+    /// event sender of transports
+    pub senders: DashMap<uuid::Uuid, EventSender>,
+    /// local did of transports
+    pub dids: DashMap<uuid::Uuid, Did>,
+    /// chunk codec of transports, which reassembles received messages
+    pub chunks: DashMap<uuid::Uuid, Arc<ChunkCodec>>,
+    conditions: Mutex<LinkConditions>,
+    /// partition of nodes, nodes in different partitions cannot reach each other
+    partitions: DashMap<Did, u64>,
+    next_partition: AtomicU64,
+    /// messages waiting for their latency
+    queue: Mutex<BinaryHeap<Delayed>>,
+    /// virtual time of hub in ms
+    clock_ms: AtomicU64,
+    seq: AtomicU64,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Default for DummyTransportHub {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl DummyTransportHub {
+    /// Create an isolated hub, whose link conditions are decided by RNGs of `seed`.
+    /// There is no latency or packet loss by default.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            senders: DashMap::new(),
+            dids: DashMap::new(),
+            chunks: DashMap::new(),
+            conditions: Mutex::new(LinkConditions {
+                latency_ms: (0, 0),
+                loss_rate: 0.0,
+                seed,
+                links: HashMap::new(),
+                rng: StdRng::seed_from_u64(seed),
+            }),
+            partitions: DashMap::new(),
+            next_partition: AtomicU64::new(1),
+            queue: Mutex::new(BinaryHeap::new()),
+            clock_ms: AtomicU64::new(0),
+            seq: AtomicU64::new(0),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// The hub shared by transports which are not bound to a hub
+    pub fn global() -> Arc<Self> {
+        HUB.clone()
+    }
+
+    /// Delay each message by a random latency in [min_ms, max_ms].
+    /// Delayed messages are delivered only when the clock of hub is advanced.
+    pub fn set_latency(&self, min_ms: u64, max_ms: u64) {
+        self.conditions.lock().unwrap().latency_ms = (min_ms, max_ms.max(min_ms));
+    }
+
+    /// Drop each message by the probability `rate`
+    pub fn set_loss_rate(&self, rate: f64) {
+        self.conditions.lock().unwrap().loss_rate = rate.clamp(0.0, 1.0);
+    }
+
+    /// Isolate nodes from others, they can only reach nodes in the same partition.
+    pub fn partition(&self, dids: &[Did]) {
+        let p = self.next_partition.fetch_add(1, Ordering::SeqCst);
+        for did in dids {
+            self.partitions.insert(*did, p);
+        }
+    }
+
+    /// Remove all partitions
+    pub fn heal(&self) {
+        self.partitions.clear();
+    }
+
+    /// Test if messages from a node can reach another node
+    pub fn is_reachable(&self, from: Did, to: Did) -> bool {
+        let partition = |did| self.partitions.get(&did).map(|p| *p).unwrap_or(0);
+        partition(from) == partition(to)
+    }
+
+    /// Remove all transports of a node, messages to it are dropped since then,
+    /// as if the node is crashed.
+    pub fn crash(&self, did: Did) {
+        let ids: Vec<uuid::Uuid> = self
+            .dids
+            .iter()
+            .filter(|x| *x.value() == did)
+            .map(|x| *x.key())
+            .collect();
+        for id in ids {
+            self.senders.remove(&id);
+            self.dids.remove(&id);
+            self.chunks.remove(&id);
+        }
+    }
+
+    /// Counters of delivered and dropped messages
+    pub fn stats(&self) -> HubStats {
+        HubStats {
+            delivered: self.delivered.load(Ordering::SeqCst),
+            dropped: self.dropped.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Virtual time of hub in ms, it starts from 0
+    pub fn now_ms(&self) -> u64 {
+        self.clock_ms.load(Ordering::SeqCst)
+    }
+
+    /// Due time of the earliest delayed message, None if no message is in flight.
+    pub fn next_due(&self) -> Option<u64> {
+        self.queue.lock().unwrap().peek().map(|d| d.due)
+    }
+
+    /// Move the clock forward by `ms`, and deliver delayed messages which are due,
+    /// return the number of messages delivered.
+    pub fn advance(&self, ms: u64) -> usize {
+        let now = self.clock_ms.fetch_add(ms, Ordering::SeqCst) + ms;
+        let mut due = vec![];
+        {
+            let mut queue = self.queue.lock().unwrap();
+            while queue.peek().map(|d| d.due <= now).unwrap_or(false) {
+                due.extend(queue.pop());
+            }
+        }
+        let n = due.len();
+        for d in due {
+            dispatch(&d.sender, d.chunks.as_deref(), d.event);
+        }
+        n
+    }
+
+    /// Move the clock to the due time of next delayed message and deliver it,
+    /// along with others due at the same time. Return the number of messages delivered.
+    pub fn advance_to_next(&self) -> usize {
+        match self.next_due() {
+            Some(due) => self.advance(due.saturating_sub(self.now_ms())),
+            None => 0,
+        }
+    }
+
+    /// Deliver event from transport `from` to transport `to` under link conditions.
+    /// A message which cannot be delivered is dropped silently, as a lossy network does.
+    pub fn deliver(&self, from: uuid::Uuid, to: uuid::Uuid, event: Event) {
+        let sender = match self.senders.get(&to) {
+            Some(s) => s.clone(),
+            None => return self.drop_message(),
+        };
+        let chunks = self.chunks.get(&to).map(|c| c.clone());
+        let link = match (self.dids.get(&from), self.dids.get(&to)) {
+            (Some(a), Some(b)) => Some((*a, *b)),
+            _ => None,
+        };
+        if let Some((a, b)) = link {
+            if !self.is_reachable(a, b) {
+                return self.drop_message();
+            }
+        }
+
+        // None if the message is lost
+        let latency = {
+            let mut c = self.conditions.lock().unwrap();
+            let (loss_rate, (min, max)) = (c.loss_rate, c.latency_ms);
+            let rng = c.rng(link);
+            if loss_rate > 0.0 && rng.gen_bool(loss_rate) {
+                None
+            } else if max > 0 {
+                Some(rng.gen_range(min..=max))
+            } else {
+                Some(0)
+            }
+        };
+        let latency = match latency {
+            Some(latency) => latency,
+            None => return self.drop_message(),
+        };
+
+        self.delivered.fetch_add(1, Ordering::SeqCst);
+        if latency == 0 {
+            return dispatch(&sender, chunks.as_deref(), event);
+        }
+        self.queue.lock().unwrap().push(Delayed {
+            due: self.now_ms() + latency,
+            seq: self.seq.fetch_add(1, Ordering::SeqCst),
+            sender,
+            chunks,
+            event,
+        });
+    }
+
+    fn drop_message(&self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht::tests::gen_ordered_dids;
+
+    fn prepare_hub(hub: &DummyTransportHub, did: Did) -> (uuid::Uuid, AcChannel<Event>) {
+        let ch = AcChannel::new();
+        let id = uuid::Uuid::new_v4();
+        hub.senders.insert(id, ch.sender());
+        hub.dids.insert(id, did);
+        (id, ch)
+    }
+
+    #[tokio::test]
+    async fn test_hub_link_conditions() {
+        let dids = gen_ordered_dids(2);
+        let hub = DummyTransportHub::new(42);
+        let (id1, _ch1) = prepare_hub(&hub, dids[0]);
+        let (id2, ch2) = prepare_hub(&hub, dids[1]);
+        let msg = |i| Event::DataChannelMessage(vec![i]);
+
+        hub.deliver(id1, id2, msg(0));
+        assert_eq!(ch2.receiver().try_recv().unwrap(), msg(0));
+
+        hub.partition(&[dids[0]]);
+        assert!(!hub.is_reachable(dids[0], dids[1]));
+        hub.deliver(id1, id2, msg(1));
+        assert!(ch2.receiver().try_recv().is_err());
+        hub.heal();
+
+        // delayed messages are delivered by the clock of hub, in order of due time
+        hub.set_latency(50, 50);
+        hub.deliver(id1, id2, msg(2));
+        hub.set_latency(10, 10);
+        hub.deliver(id1, id2, msg(3));
+        assert!(ch2.receiver().try_recv().is_err());
+        assert_eq!(hub.next_due(), Some(10));
+        assert_eq!(hub.advance(9), 0);
+        assert_eq!(hub.advance_to_next(), 1);
+        assert_eq!(hub.now_ms(), 10);
+        assert_eq!(ch2.receiver().try_recv().unwrap(), msg(3));
+        assert_eq!(hub.advance(40), 1);
+        assert_eq!(ch2.receiver().try_recv().unwrap(), msg(2));
+        assert_eq!(hub.next_due(), None);
+        hub.set_latency(0, 0);
+
+        hub.set_loss_rate(1.0);
+        hub.deliver(id1, id2, msg(4));
+        assert!(ch2.receiver().try_recv().is_err());
+        hub.set_loss_rate(0.0);
+
+        hub.crash(dids[1]);
+        hub.deliver(id1, id2, msg(5));
+        assert_eq!(hub.stats(), HubStats {
+            delivered: 3,
+            dropped: 3,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_hub_same_seed_same_decisions() {
+        let dids = gen_ordered_dids(2);
+        // indexes of messages of link 1 -> 2 which are delivered, and their due time,
+        // messages of the reverse link are interleaved if `noisy`
+        let decide = |seed, noisy: bool| {
+            let hub = DummyTransportHub::new(seed);
+            let (id1, ch1) = prepare_hub(&hub, dids[0]);
+            let (id2, ch2) = prepare_hub(&hub, dids[1]);
+            hub.set_loss_rate(0.5);
+            hub.set_latency(1, 100);
+            for i in 0..64 {
+                hub.deliver(id1, id2, Event::DataChannelMessage(vec![i]));
+                if noisy {
+                    hub.deliver(id2, id1, Event::DataChannelMessage(vec![i]));
+                }
+            }
+            let mut received = vec![];
+            while hub.next_due().is_some() {
+                hub.advance_to_next();
+                while let Ok(ev) = ch2.receiver().try_recv() {
+                    received.push((hub.now_ms(), ev));
+                }
+                while ch1.receiver().try_recv().is_ok() {}
+            }
+            received
+        };
+        let received = decide(7, false);
+        assert!(!received.is_empty() && received.len() < 64);
+        assert_eq!(received, decide(7, false));
+        assert_eq!(received, decide(7, true));
+        assert_ne!(received, decide(8, false));
+    }
+}
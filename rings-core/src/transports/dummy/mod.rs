@@ -1,5 +1,8 @@
+pub mod hub;
 pub mod transport;
 
+pub use hub::DummyTransportHub;
+pub use hub::HubStats;
 pub use transport::DummyTransport;
 use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
 
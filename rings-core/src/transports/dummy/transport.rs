@@ -3,8 +3,6 @@ use std::sync::Mutex;
 
 use async_lock::RwLock as AsyncRwLock;
 use async_trait::async_trait;
-use dashmap::DashMap;
-use lazy_static::lazy_static;
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
 
@@ -17,6 +15,8 @@ use crate::message::Encoded;
 use crate::message::Encoder;
 use crate::message::MessagePayload;
 use crate::session::SessionManager;
+use crate::transports::chunk::ChunkCodec;
+use crate::transports::dummy::hub::DummyTransportHub;
 use crate::transports::helper::Promise;
 use crate::transports::helper::State;
 use crate::transports::helper::TricklePayload;
@@ -28,16 +28,6 @@ use crate::types::ice_transport::IceTrickleScheme;
 
 type EventSender = <AcChannel<Event> as Channel<Event>>::Sender;
 
-#[derive(Default)]
-pub struct DummyTransportHub {
-    pub senders: DashMap<uuid::Uuid, EventSender>,
-    pub dids: DashMap<uuid::Uuid, Did>,
-}
-
-lazy_static! {
-    static ref HUB: DummyTransportHub = DummyTransportHub::default();
-}
-
 #[derive(Clone)]
 pub struct DummyTransport {
     pub id: uuid::Uuid,
@@ -45,6 +35,8 @@ pub struct DummyTransport {
     event_sender: EventSender,
     ice_connection_state: Arc<Mutex<Option<RTCIceConnectionState>>>,
     public_key: Arc<AsyncRwLock<Option<PublicKey>>>,
+    hub: Arc<DummyTransportHub>,
+    chunks: Arc<ChunkCodec>,
 }
 
 impl PartialEq for DummyTransport {
@@ -58,13 +50,7 @@ impl IceTransportInterface<Event, AcChannel<Event>> for DummyTransport {
     type IceConnectionState = RTCIceConnectionState;
 
     fn new(event_sender: EventSender) -> Self {
-        Self {
-            id: uuid::Uuid::new_v4(),
-            remote_id: Arc::new(Mutex::new(None)),
-            event_sender,
-            ice_connection_state: Arc::new(Mutex::new(None)),
-            public_key: Arc::new(AsyncRwLock::new(None)),
-        }
+        Self::with_hub(event_sender, DummyTransportHub::global())
     }
 
     async fn start(
@@ -74,7 +60,8 @@ impl IceTransportInterface<Event, AcChannel<Event>> for DummyTransport {
     ) -> Result<&Self> {
         let mut ice_connection_state = self.ice_connection_state.lock().unwrap();
         *ice_connection_state = Some(RTCIceConnectionState::New);
-        HUB.senders.insert(self.id, self.event_sender.clone());
+        self.hub.senders.insert(self.id, self.event_sender.clone());
+        self.hub.chunks.insert(self.id, self.chunks.clone());
         Ok(self)
     }
 
@@ -124,10 +111,11 @@ impl IceTransportInterface<Event, AcChannel<Event>> for DummyTransport {
     }
 
     async fn send_message(&self, msg: &[u8]) -> Result<()> {
-        self.remote_sender()
-            .send(Event::DataChannelMessage(msg.to_vec()))
-            .await
-            .unwrap();
+        let remote_id = self.remote_id();
+        for frame in self.chunks.split(msg)? {
+            self.hub
+                .deliver(self.id, remote_id, Event::DataChannelMessage(frame));
+        }
         Ok(())
     }
 }
@@ -177,7 +165,7 @@ impl IceTrickleScheme for DummyTransport {
                 };
 
                 let local_did = self.pubkey().await.address().into();
-                HUB.dids.insert(self.id, local_did);
+                self.hub.dids.insert(self.id, local_did);
                 self.event_sender
                     .send(Event::RegisterTransport((local_did, self.id)))
                     .await
@@ -196,6 +184,19 @@ impl IceTrickleScheme for DummyTransport {
 }
 
 impl DummyTransport {
+    /// Create a transport which delivers messages through `hub`
+    pub fn with_hub(event_sender: EventSender, hub: Arc<DummyTransportHub>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            remote_id: Arc::new(Mutex::new(None)),
+            event_sender,
+            ice_connection_state: Arc::new(Mutex::new(None)),
+            public_key: Arc::new(AsyncRwLock::new(None)),
+            hub,
+            chunks: Arc::new(ChunkCodec::default()),
+        }
+    }
+
     pub async fn connect_success_promise(&self) -> Result<Promise> {
         let state = State {
             completed: true,
@@ -206,6 +207,11 @@ impl DummyTransport {
         Ok(promise)
     }
 
+    /// Split large messages into chunks, once the remote is known to reassemble them.
+    pub fn enable_chunks(&self) {
+        self.chunks.enable_split();
+    }
+
     pub async fn wait_for_data_channel_open(&self) -> Result<()> {
         Ok(())
     }
@@ -215,7 +221,7 @@ impl DummyTransport {
     }
 
     pub fn remote_sender(&self) -> EventSender {
-        HUB.senders.get(&self.remote_id()).unwrap().clone()
+        self.hub.senders.get(&self.remote_id()).unwrap().clone()
     }
 }
 
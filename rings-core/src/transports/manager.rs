@@ -32,6 +32,9 @@ impl TransportManager for Swarm {
 
     async fn new_transport(&self) -> Result<Self::Transport> {
         let event_sender = self.transport_event_channel.sender();
+        #[cfg(all(not(feature = "wasm"), feature = "dummy"))]
+        let mut ice_transport = Transport::with_hub(event_sender, self.dummy_hub.clone());
+        #[cfg(not(all(not(feature = "wasm"), feature = "dummy")))]
         let mut ice_transport = Transport::new(event_sender);
         ice_transport
             .start(self.ice_servers.clone(), self.external_address.clone())
@@ -99,6 +102,8 @@ impl TransportManager for Swarm {
     }
 
     fn remove_transport(&self, did: Did) -> Option<(Did, Self::Transport)> {
+        // peer may reconnect with another version
+        self.peer_formats.remove(&did);
         self.transports.remove(&did)
     }
 
@@ -12,5 +12,6 @@ pub use dummy::DummyTransport as Transport;
 #[cfg(feature = "wasm")]
 pub use wasm::WasmTransport as Transport;
 
+pub mod chunk;
 pub mod helper;
 pub mod manager;
@@ -36,6 +36,7 @@ use crate::message::Encoded;
 use crate::message::Encoder;
 use crate::message::MessagePayload;
 use crate::session::SessionManager;
+use crate::transports::chunk::ChunkCodec;
 use crate::transports::helper::Promise;
 use crate::transports::helper::TricklePayload;
 use crate::types::channel::Channel;
@@ -58,6 +59,7 @@ pub struct WasmTransport {
     channel: Option<Arc<RtcDataChannel>>,
     event_sender: EventSender,
     public_key: Arc<RwLock<Option<PublicKey>>>,
+    chunks: Arc<ChunkCodec>,
 }
 
 impl PartialEq for WasmTransport {
@@ -154,6 +156,7 @@ impl IceTransportInterface<Event, CbChannel<Event>> for WasmTransport {
             pending_candidates: Arc::new(Mutex::new(vec![])),
             channel: None,
             public_key: Arc::new(RwLock::new(None)),
+            chunks: Arc::new(ChunkCodec::default()),
             event_sender,
         }
     }
@@ -250,12 +253,15 @@ impl IceTransportInterface<Event, CbChannel<Event>> for WasmTransport {
     }
 
     async fn send_message(&self, msg: &[u8]) -> Result<()> {
-        match self.get_data_channel().await {
-            Some(cnn) => cnn
-                .send_with_u8_array(msg)
-                .map_err(|e| Error::RTCDataChannelSendTextFailed(format!("{:?}", e))),
-            None => Err(Error::RTCDataChannelNotReady),
+        let cnn = self
+            .get_data_channel()
+            .await
+            .ok_or(Error::RTCDataChannelNotReady)?;
+        for frame in self.chunks.split(msg)? {
+            cnn.send_with_u8_array(&frame)
+                .map_err(|e| Error::RTCDataChannelSendTextFailed(format!("{:?}", e)))?;
         }
+        Ok(())
     }
 }
 
@@ -348,15 +354,18 @@ impl IceTransportCallback for WasmTransport {
 
     async fn on_data_channel(&self) -> Self::OnDataChannelHdlrFn {
         let event_sender = self.event_sender.clone();
+        let chunks = self.chunks.clone();
 
         box move |ev: RtcDataChannelEvent| {
             tracing::debug!("channel open");
             let event_sender = Arc::clone(&event_sender);
+            let chunks = Arc::clone(&chunks);
             let ch = ev.channel();
             let on_message_cb = Closure::wrap(
                 (box move |ev: MessageEvent| {
                     let data = ev.data();
                     let event_sender = Arc::clone(&event_sender);
+                    let chunks = Arc::clone(&chunks);
                     spawn_local(async move {
                         let msg = if data.has_type::<web_sys::Blob>() {
                             let data: web_sys::Blob = data.clone().into();
@@ -376,6 +385,15 @@ impl IceTransportCallback for WasmTransport {
                         if msg.is_empty() {
                             return;
                         }
+                        // wait for the rest chunks if it's a part of message
+                        let msg = match chunks.feed(msg) {
+                            Ok(Some(msg)) => msg,
+                            Ok(None) => return,
+                            Err(e) => {
+                                tracing::warn!("Failed on reassemble msg, {:?}", e);
+                                return;
+                            }
+                        };
                         let event_sender = Arc::clone(&event_sender);
                         if let Err(e) =
                             CbChannel::send(&event_sender, Event::DataChannelMessage(msg)).await
@@ -543,6 +561,11 @@ impl IceTrickleScheme for WasmTransport {
 }
 
 impl WasmTransport {
+    /// Split large messages into chunks, once the remote is known to reassemble them.
+    pub fn enable_chunks(&self) {
+        self.chunks.enable_split();
+    }
+
     pub async fn wait_for_data_channel_open(&self) -> Result<()> {
         let dc = self.get_data_channel().await;
         match dc {
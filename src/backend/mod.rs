@@ -1,95 +1,503 @@
-use async_trait::async_trait;
+//! Backend services of a node, which are served over byte streams between dids.
+//! The tcp proxy tunnels tcp connections between nodes in sessions. A client listens on a
+//! local port, and each accepted connection is opened as a stream to the tcp proxy of a
+//! remote did, which connects to the proxied port. A stream starts with a frame naming the
+//! service, and bytes of the connection follow in frames, which are encrypted by session
+//! pubkey of the receiver, so nodes relaying the stream can't read them. A session ends
+//! when both sides are closed, or it's idle beyond the timeout.
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::io::AsyncReadExt as _;
+use futures::io::AsyncWriteExt as _;
+use futures::AsyncRead;
+use futures::AsyncWrite;
 use serde::Deserialize;
 use serde::Serialize;
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 
-use crate::prelude::rings_core::message::Message;
+use crate::error::Error;
+use crate::error::Result;
+use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::ecc::PublicKey;
+use crate::prelude::rings_core::message::ByteStream;
 use crate::prelude::*;
 
-#[derive(Deserialize, Serialize, Debug)]
+/// Max bytes read from a connection in a frame
+const TCP_READ_BUFFER: usize = 16 * 1024;
+/// Max size of an encrypted frame
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct BackendConfig {
     pub tcp_proxy: Option<TcpProxyConfig>,
+    /// local listeners which forward connections to the tcp proxy of remote dids
+    #[serde(default)]
+    pub tcp_tunnels: Vec<TcpTunnelConfig>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct TcpProxyConfig {
     pub port: u16,
+    /// a session is closed if no byte is sent or received in this time
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct TcpTunnelConfig {
+    /// local address to listen, such as `127.0.0.1:2222`
+    pub listen: String,
+    /// remote did which serves the tcp proxy
+    pub did: Did,
+    /// a session is closed if no byte is sent or received in this time
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+}
+
+fn default_idle_timeout() -> u64 {
+    300
 }
 
+/// Service of a stream opened to the backend, which is the first frame of the stream
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum BackendMessage {
-    TcpProxy(TcpProxyMessage),
+    /// Bytes of a tcp connection to the tcp proxy follow it
+    TcpProxy,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub enum TcpProxyMessage {
-    Write(Vec<u8>),
-    Read(Vec<u8>),
+/// A session of tcp proxy, which is counted until it's dropped
+struct Session(Arc<AtomicUsize>);
+
+impl Session {
+    fn new(sessions: &Arc<AtomicUsize>) -> Self {
+        sessions.fetch_add(1, Ordering::SeqCst);
+        Self(sessions.clone())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
+#[derive(Clone)]
 pub struct Backend {
-    tcp_proxy_port: Option<u16>,
+    tcp_proxy: Option<TcpProxyConfig>,
+    tcp_tunnels: Vec<TcpTunnelConfig>,
+    sessions: Arc<AtomicUsize>,
 }
 
 impl Backend {
     pub async fn new(config: BackendConfig) -> Self {
         Self {
-            tcp_proxy_port: config.tcp_proxy.map(|c| c.port),
+            tcp_proxy: config.tcp_proxy,
+            tcp_tunnels: config.tcp_tunnels,
+            sessions: Arc::new(AtomicUsize::new(0)),
         }
     }
-}
 
-#[async_trait]
-impl MessageCallback for Backend {
-    async fn custom_message(
+    /// Number of alive sessions
+    pub fn sessions(&self) -> usize {
+        self.sessions.load(Ordering::SeqCst)
+    }
+
+    /// Serve streams opened by remote, and listen on local addresses of tcp tunnels.
+    /// Streams are driven by the message listener of swarm, which should be running.
+    pub async fn serve(&self, swarm: Arc<Swarm>) {
+        futures::join!(self.serve_streams(swarm.clone()), self.serve_tunnels(swarm));
+    }
+
+    /// Accept streams opened by remote, each of them is served in a task.
+    async fn serve_streams(&self, swarm: Arc<Swarm>) {
+        loop {
+            let stream = swarm.accept_stream().await;
+            let backend = self.clone();
+            let swarm = swarm.clone();
+            tokio::spawn(async move {
+                let remote = stream.remote();
+                if let Err(e) = backend.serve_stream(&swarm, stream).await {
+                    tracing::warn!("failed to serve stream of {:?}: {}", remote, e);
+                }
+            });
+        }
+    }
+
+    /// Tunnel a stream to the proxied port, it's refused if tcp proxy is not served.
+    async fn serve_stream(&self, swarm: &Swarm, mut stream: ByteStream) -> Result<()> {
+        let key = swarm
+            .session_manager()
+            .session_key()
+            .map_err(Error::StreamError)?;
+        let service = read_frame(&mut stream, key).await?;
+        match service.map(|data| serde_json::from_slice(&data)) {
+            Some(Ok(BackendMessage::TcpProxy)) => {}
+            _ => return Err(Error::TunnelError("unknown service".to_string())),
+        }
+        let config = match &self.tcp_proxy {
+            Some(config) => config,
+            None => return Err(Error::TunnelError("tcp proxy is not served".to_string())),
+        };
+        let conn = TcpStream::connect(("127.0.0.1", config.port))
+            .await
+            .map_err(tunnel_error)?;
+        self.tunnel(key, stream, conn, Duration::from_secs(config.idle_timeout))
+            .await
+    }
+
+    /// Listen on local addresses of tcp tunnels, and forward accepted connections.
+    async fn serve_tunnels(&self, swarm: Arc<Swarm>) {
+        futures::future::join_all(
+            self.tcp_tunnels
+                .iter()
+                .map(|tunnel| self.serve_tunnel(swarm.clone(), tunnel.clone())),
+        )
+        .await;
+    }
+
+    async fn serve_tunnel(&self, swarm: Arc<Swarm>, tunnel: TcpTunnelConfig) {
+        let listener = match TcpListener::bind(&tunnel.listen).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!("failed to listen tcp tunnel on {}: {}", tunnel.listen, e);
+                return;
+            }
+        };
+        tracing::info!("tcp tunnel {} -> {:?}", tunnel.listen, tunnel.did);
+        loop {
+            let conn = match listener.accept().await {
+                Ok((conn, _)) => conn,
+                Err(e) => {
+                    tracing::warn!("failed to accept on {}: {}", tunnel.listen, e);
+                    continue;
+                }
+            };
+            let backend = self.clone();
+            let swarm = swarm.clone();
+            let tunnel = tunnel.clone();
+            tokio::spawn(async move {
+                if let Err(e) = backend.open_tunnel(&swarm, &tunnel, conn).await {
+                    tracing::warn!("failed to tunnel to {:?}: {}", tunnel.did, e);
+                }
+            });
+        }
+    }
+
+    /// Open a stream to the tcp proxy of remote, and tunnel the connection by it.
+    /// Bytes sent by remote before the connection is read are kept by the stream.
+    async fn open_tunnel(
         &self,
-        handler: &MessageHandler,
-        ctx: &MessagePayload<Message>,
-        msg: &MaybeEncrypted<CustomMessage>,
-    ) {
-        let mut relay = ctx.relay.clone();
-        relay.relay(relay.destination, None).unwrap();
-
-        if let Ok(CustomMessage(raw_msg)) = handler.decrypt_msg(msg) {
-            if let Ok(msg) = serde_json::from_slice(&raw_msg) {
-                match msg {
-                    BackendMessage::TcpProxy(msg) => match msg {
-                        TcpProxyMessage::Write(msg) => {
-                            if let Some(port) = self.tcp_proxy_port {
-                                let mut conn = TcpStream::connect(format!("127.0.0.1:{}", port))
-                                    .await
-                                    .unwrap();
-
-                                conn.write_all(&msg).await.unwrap();
-
-                                // 100k
-                                let mut buff: Vec<u8> = Vec::new();
-                                conn.read_to_end(&mut buff).await.unwrap();
-
-                                let resp = BackendMessage::TcpProxy(TcpProxyMessage::Read(buff));
-                                let resp_bytes = serde_json::to_vec(&resp).unwrap();
-                                let pubkey = ctx.origin_session_pubkey().unwrap();
-
-                                handler
-                                    .send_report_message(
-                                        Message::custom(&resp_bytes, Some(pubkey)).unwrap(),
-                                        ctx.tx_id,
-                                        relay,
-                                    )
-                                    .await
-                                    .unwrap();
-                            }
-                        }
-                        TcpProxyMessage::Read(msg) => {
-                            println!("TcpProxyMessage::Read: {:?}", msg);
-                        }
-                    },
+        swarm: &Arc<Swarm>,
+        tunnel: &TcpTunnelConfig,
+        conn: TcpStream,
+    ) -> Result<()> {
+        let key = swarm
+            .session_manager()
+            .session_key()
+            .map_err(Error::StreamError)?;
+        let mut stream = swarm
+            .open_stream(tunnel.did)
+            .await
+            .map_err(Error::StreamError)?;
+        let pubkey = remote_pubkey(&stream)?;
+        let service =
+            serde_json::to_vec(&BackendMessage::TcpProxy).map_err(|_| Error::JsonSerializeError)?;
+        write_frame(&mut stream, pubkey, &service).await?;
+        self.tunnel(key, stream, conn, Duration::from_secs(tunnel.idle_timeout))
+            .await
+    }
+
+    /// Exchange bytes of a connection with remote in encrypted frames of stream, until
+    /// both sides are closed, or no byte is sent or received in `idle_timeout`.
+    /// The stream and connection are closed when it's returned.
+    async fn tunnel(
+        &self,
+        key: SecretKey,
+        stream: ByteStream,
+        conn: TcpStream,
+        idle_timeout: Duration,
+    ) -> Result<()> {
+        let pubkey = remote_pubkey(&stream)?;
+        let id = stream.id();
+        let _session = Session::new(&self.sessions);
+        let last_active = Mutex::new(Instant::now());
+        let (mut stream_reader, mut stream_writer) = stream.split();
+        let (mut conn_reader, mut conn_writer) = conn.into_split();
+
+        let upstream = async {
+            let mut buf = vec![0u8; TCP_READ_BUFFER];
+            loop {
+                let n = conn_reader.read(&mut buf).await.map_err(tunnel_error)?;
+                if n == 0 {
+                    break;
                 }
+                *last_active.lock().unwrap() = Instant::now();
+                write_frame(&mut stream_writer, pubkey, &buf[..n]).await?;
+            }
+            stream_writer.close().await.map_err(tunnel_error)
+        };
+        let downstream = async {
+            while let Some(data) = read_frame(&mut stream_reader, key).await? {
+                *last_active.lock().unwrap() = Instant::now();
+                conn_writer.write_all(&data).await.map_err(tunnel_error)?;
+            }
+            conn_writer.shutdown().await.map_err(tunnel_error)
+        };
+        let idle = async {
+            loop {
+                let elapsed = last_active.lock().unwrap().elapsed();
+                if elapsed >= idle_timeout {
+                    break;
+                }
+                tokio::time::sleep(idle_timeout - elapsed).await;
+            }
+        };
+
+        tokio::select! {
+            ret = futures::future::try_join(upstream, downstream) => ret.map(|_| ()),
+            _ = idle => {
+                tracing::info!("session {} is idle, close it", id);
+                Ok(())
             }
         }
     }
+}
 
-    async fn builtin_message(&self, _handler: &MessageHandler, _ctx: &MessagePayload<Message>) {}
+fn tunnel_error(e: std::io::Error) -> Error {
+    Error::TunnelError(e.to_string())
+}
+
+fn remote_pubkey(stream: &ByteStream) -> Result<PublicKey> {
+    stream
+        .remote_pubkey()
+        .ok_or_else(|| Error::TunnelError("session pubkey of remote is unknown".to_string()))
+}
+
+/// Write bytes in a frame encrypted by `pubkey`, which is prefixed by it's length.
+async fn write_frame<W>(writer: &mut W, pubkey: PublicKey, data: &[u8]) -> Result<()>
+where W: AsyncWrite + Unpin {
+    let msg = MaybeEncrypted::new(CustomMessage(data.to_vec()), Some(pubkey))
+        .map_err(Error::StreamError)?;
+    // reserve the length prefix, so the frame is written to stream at once
+    let mut frame = vec![0u8; 4];
+    serde_json::to_writer(&mut frame, &msg).map_err(|_| Error::JsonSerializeError)?;
+    let len = (frame.len() - 4) as u32;
+    frame[..4].copy_from_slice(&len.to_be_bytes());
+    writer.write_all(&frame).await.map_err(tunnel_error)
+}
+
+/// Read bytes of a frame which is encrypted for `key`, None at the end of stream.
+/// Frames which are not encrypted are refused.
+async fn read_frame<R>(reader: &mut R, key: SecretKey) -> Result<Option<Vec<u8>>>
+where R: AsyncRead + Unpin {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(tunnel_error(e)),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(Error::TunnelError(format!(
+            "frame of {} bytes is too large",
+            len
+        )));
+    }
+    let mut frame = vec![0u8; len];
+    reader.read_exact(&mut frame).await.map_err(tunnel_error)?;
+    let msg: MaybeEncrypted<CustomMessage> =
+        serde_json::from_slice(&frame).map_err(|_| Error::JsonDeserializeError)?;
+    match msg.decrypt(key).map_err(Error::StreamError)? {
+        (CustomMessage(data), true) => Ok(Some(data)),
+        (_, false) => Err(Error::TunnelError("frame is not encrypted".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::time::sleep;
+
+    use super::*;
+    use crate::prelude::rings_core::dht::Stabilization;
+    use crate::prelude::rings_core::storage::PersistenceStorage;
+    use crate::prelude::rings_core::swarm::SwarmBuilder;
+    use crate::processor::Processor;
+
+    const GREETING: &[u8] = b"hello from server\n";
+
+    async fn new_processor() -> (Processor, String) {
+        let key = SecretKey::random();
+
+        let stun = "stun://stun.l.google.com:19302";
+        let path = PersistenceStorage::random_path("./tmp");
+        let storage = PersistenceStorage::new_with_path(path.as_str())
+            .await
+            .unwrap();
+
+        let swarm = Arc::new(SwarmBuilder::new(stun, storage).key(key).build().unwrap());
+        let stabilization = Arc::new(Stabilization::new(swarm.clone(), 200));
+        ((swarm, stabilization).into(), path)
+    }
+
+    /// Connect two processors, and run their message listeners.
+    async fn connect(p1: &Processor, p2: &Processor) {
+        let (transport_1, offer) = p1.create_offer().await.unwrap();
+        let (transport_2, answer) = p2.answer_offer(offer.as_str()).await.unwrap();
+        p1.accept_answer(transport_1.id.to_string().as_str(), answer.as_str())
+            .await
+            .unwrap();
+        transport_1
+            .connect_success_promise()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+        transport_2
+            .connect_success_promise()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+
+        for swarm in [p1.swarm.clone(), p2.swarm.clone()] {
+            let handler = Arc::new(swarm.create_message_handler(None, None));
+            tokio::spawn(async move { handler.listen().await });
+        }
+    }
+
+    async fn free_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    /// An echo server which greets first, before anything is received.
+    async fn echo_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    conn.write_all(GREETING).await.unwrap();
+                    let mut buf = vec![0u8; 1024];
+                    loop {
+                        let n = conn.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            break;
+                        }
+                        conn.write_all(&buf[..n]).await.unwrap();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    /// Serve an echo server by tcp proxy of p2, and a tunnel to it by p1.
+    async fn serve_backends(
+        p1: &Processor,
+        p2: &Processor,
+        idle_timeout: u64,
+    ) -> (Backend, Backend, String) {
+        let server = Backend::new(BackendConfig {
+            tcp_proxy: Some(TcpProxyConfig {
+                port: echo_server().await,
+                idle_timeout,
+            }),
+            tcp_tunnels: vec![],
+        })
+        .await;
+        let listen = format!("127.0.0.1:{}", free_port().await);
+        let client = Backend::new(BackendConfig {
+            tcp_proxy: None,
+            tcp_tunnels: vec![TcpTunnelConfig {
+                listen: listen.clone(),
+                did: p2.did(),
+                idle_timeout,
+            }],
+        })
+        .await;
+
+        for (backend, swarm) in [
+            (server.clone(), p2.swarm.clone()),
+            (client.clone(), p1.swarm.clone()),
+        ] {
+            tokio::spawn(async move { backend.serve(swarm).await });
+        }
+        (server, client, listen)
+    }
+
+    async fn connect_tunnel(listen: &str) -> TcpStream {
+        for _ in 0..50 {
+            if let Ok(conn) = TcpStream::connect(listen).await {
+                return conn;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        panic!("tunnel is not listening on {}", listen);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_tunnel_echo() {
+        let (p1, path1) = new_processor().await;
+        let (p2, path2) = new_processor().await;
+        connect(&p1, &p2).await;
+        let (server, client, listen) = serve_backends(&p1, &p2, 30).await;
+
+        let mut conn = connect_tunnel(&listen).await;
+        // The server speaks first, which is kept until the connection is read.
+        let mut greeting = vec![0u8; GREETING.len()];
+        conn.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, GREETING);
+
+        let data = (0..64 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+        let (mut reader, mut writer) = conn.into_split();
+        let sent = data.clone();
+        let send = tokio::spawn(async move { writer.write_all(&sent).await.unwrap() });
+        let mut echo = vec![0u8; data.len()];
+        reader.read_exact(&mut echo).await.unwrap();
+        send.await.unwrap();
+        assert_eq!(echo, data);
+        assert_eq!(client.sessions(), 1);
+        assert_eq!(server.sessions(), 1);
+
+        tokio::fs::remove_dir_all(path1).await.unwrap();
+        tokio::fs::remove_dir_all(path2).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tcp_tunnel_idle_timeout() {
+        let (p1, path1) = new_processor().await;
+        let (p2, path2) = new_processor().await;
+        connect(&p1, &p2).await;
+        let (server, client, listen) = serve_backends(&p1, &p2, 1).await;
+
+        let mut conn = connect_tunnel(&listen).await;
+        let mut greeting = vec![0u8; GREETING.len()];
+        conn.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, GREETING);
+        assert_eq!(client.sessions(), 1);
+
+        // Nothing is sent after greeting, so the session is closed after idle timeout.
+        let mut buf = vec![0u8; 16];
+        let n = tokio::time::timeout(Duration::from_secs(5), conn.read(&mut buf))
+            .await
+            .expect("session is not closed after idle timeout")
+            .unwrap();
+        assert_eq!(n, 0);
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(client.sessions(), 0);
+        assert_eq!(server.sessions(), 0);
+
+        tokio::fs::remove_dir_all(path1).await.unwrap();
+        tokio::fs::remove_dir_all(path2).await.unwrap();
+    }
 }
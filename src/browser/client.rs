@@ -446,6 +446,17 @@ impl Client {
         })
     }
 
+    /// broadcast custom message to members of a subring.
+    pub fn subring_broadcast(&self, name: String, msg: js_sys::Uint8Array) -> js_sys::Promise {
+        let p = self.processor.clone();
+        future_to_promise(async move {
+            p.subring_broadcast(name.as_str(), &msg.to_vec())
+                .await
+                .map_err(JsError::from)?;
+            Ok(JsValue::from_bool(true))
+        })
+    }
+
     /// get peer by address
     pub fn get_peer(&self, address: String, addr_type: Option<AddressType>) -> js_sys::Promise {
         let p = self.processor.clone();
@@ -523,8 +534,14 @@ impl Client {
         let p = self.processor.clone();
         future_to_promise(async move {
             let did = get_did(address.as_str(), addr_type.unwrap_or(AddressType::DEFAULT))?;
-            p.fetch(&did).await.map_err(JsError::from)?;
-            Ok(JsValue::null())
+            let v_node = p.fetch(&did).await.map_err(JsError::from)?;
+            if let Some(v) = v_node {
+                let wasm_vnode = VirtualNode::from(v);
+                let data = JsValue::from_serde(&wasm_vnode).map_err(JsError::from)?;
+                Ok(data)
+            } else {
+                Ok(JsValue::null())
+            }
         })
     }
 
@@ -538,6 +555,50 @@ impl Client {
         })
     }
 
+    /// put a record signed by this node to DHT
+    pub fn put_record(&self, name: String, value: js_sys::Uint8Array) -> js_sys::Promise {
+        let p = self.processor.clone();
+        future_to_promise(async move {
+            p.put_record(name.as_str(), &value.to_vec())
+                .await
+                .map_err(JsError::from)?;
+            Ok(JsValue::null())
+        })
+    }
+
+    /// get value of record by owner and name, null if it's not found or deleted
+    pub fn get_record(
+        &self,
+        owner: String,
+        name: String,
+        addr_type: Option<AddressType>,
+    ) -> js_sys::Promise {
+        let p = self.processor.clone();
+        future_to_promise(async move {
+            let did = get_did(owner.as_str(), addr_type.unwrap_or(AddressType::DEFAULT))?;
+            let value = p
+                .get_record(did, name.as_str())
+                .await
+                .map_err(JsError::from)?;
+            if let Some(v) = value {
+                Ok(js_sys::Uint8Array::from(v.as_slice()).into())
+            } else {
+                Ok(JsValue::null())
+            }
+        })
+    }
+
+    /// delete a record of this node from DHT
+    pub fn delete_record(&self, name: String) -> js_sys::Promise {
+        let p = self.processor.clone();
+        future_to_promise(async move {
+            p.delete_record(name.as_str())
+                .await
+                .map_err(JsError::from)?;
+            Ok(JsValue::null())
+        })
+    }
+
     pub fn request(&self, method: String, params: JsValue) -> js_sys::Promise {
         let meta = self.rpc_meta.clone();
         future_to_promise(async move {
@@ -776,6 +837,8 @@ pub enum VNodeType {
     SubRing,
     /// RelayMessage: A Relayed but unreach message, which is stored on it's successor
     RelayMessage,
+    /// Record: A mutable record signed by it's owner
+    Record,
 }
 
 impl From<vnode::VNodeType> for VNodeType {
@@ -784,6 +847,7 @@ impl From<vnode::VNodeType> for VNodeType {
             vnode::VNodeType::Data => Self::Data,
             vnode::VNodeType::SubRing => Self::SubRing,
             vnode::VNodeType::RelayMessage => Self::RelayMessage,
+            vnode::VNodeType::Record => Self::Record,
         }
     }
 }
@@ -10,6 +10,8 @@ use crate::jsonrpc::response::Peer;
 use crate::jsonrpc::response::TransportAndIce;
 use crate::jsonrpc_client::SimpleClient;
 use crate::prelude::reqwest;
+use crate::prelude::rings_core::dht::DHTStatus;
+use crate::prelude::rings_core::dht::RingSnapshot;
 use crate::seed::Seed;
 use crate::util::loader::ResourceLoader;
 
@@ -209,6 +211,165 @@ impl Client {
         ClientOutput::ok("Done.".into(), ())
     }
 
+    pub async fn leave(&self) -> Output<()> {
+        self.client
+            .call_method(Method::Leave.as_str(), Params::Array(vec![]))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok("Done.".into(), ())
+    }
+
+    pub async fn subring_leave(&self, name: &str) -> Output<()> {
+        let mut params = serde_json::Map::new();
+        params.insert("name".to_owned(), json!(name));
+        self.client
+            .call_method(Method::SubRingLeave.as_str(), Params::Map(params))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok("Done.".into(), ())
+    }
+
+    pub async fn subring_kick(&self, name: &str, did: &str) -> Output<()> {
+        let mut params = serde_json::Map::new();
+        params.insert("name".to_owned(), json!(name));
+        params.insert("did".to_owned(), json!(did));
+        self.client
+            .call_method(Method::SubRingKick.as_str(), Params::Map(params))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        ClientOutput::ok("Done.".into(), ())
+    }
+
+    pub async fn subring_members(&self, name: &str) -> Output<()> {
+        let mut params = serde_json::Map::new();
+        params.insert("name".to_owned(), json!(name));
+        let resp = self
+            .client
+            .call_method(Method::SubRingMembers.as_str(), Params::Map(params))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let members: Vec<String> =
+            serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut display = String::new();
+        display.push_str("Successful\n");
+        display.push_str("Did\n");
+        display.push_str(members.join("\n").as_str());
+        ClientOutput::ok(display, ())
+    }
+
+    pub async fn node_status(&self) -> Output<()> {
+        let status = self.dht_status(None).await?;
+        let mut display = String::new();
+        display.push_str("Successful\n");
+        display.push_str(format!("Did: {}\n", status.did).as_str());
+        display.push_str(
+            format!(
+                "Storage: {} vnodes, {} bytes in use\n",
+                status.storage_count, status.storage_usage
+            )
+            .as_str(),
+        );
+        display.push_str(
+            format!(
+                "Cache: {} hits, {} misses, {} entries\n",
+                status.cache.hits, status.cache.misses, status.cache.entries
+            )
+            .as_str(),
+        );
+        display.push_str(
+            format!(
+                "Lookup hops: {} forwarded, {} measured, {} ms average RTT\n",
+                status.lookup_hops.hops,
+                status.lookup_hops.measured_hops,
+                status.lookup_hops.avg_rtt_ms
+            )
+            .as_str(),
+        );
+        if let Some(stabilization) = status.stabilization {
+            display.push_str(
+                format!(
+                    "Stabilization: {} rounds, {} ms last, {} ms average, every {} ms",
+                    stabilization.rounds,
+                    stabilization.last_round_ms,
+                    stabilization.avg_round_ms,
+                    stabilization.interval_ms
+                )
+                .as_str(),
+            );
+        }
+        ClientOutput::ok(display, ())
+    }
+
+    async fn dht_status(&self, did: Option<&str>) -> anyhow::Result<DHTStatus> {
+        let params = did.map(|did| vec![json!(did)]).unwrap_or_default();
+        let resp = self
+            .client
+            .call_method(Method::DhtStatus.as_str(), Params::Array(params))
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        serde_json::from_value(resp).map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    pub async fn inspect_ring(&self, seed: Option<&str>, max_nodes: usize) -> Output<RingSnapshot> {
+        let mut snapshot = RingSnapshot {
+            nodes: vec![self.dht_status(seed).await?],
+            ..Default::default()
+        };
+        while snapshot.nodes.len() < max_nodes {
+            let next = match snapshot.next() {
+                Some(next) => next,
+                None => break,
+            };
+            match self.dht_status(Some(next.to_string().as_str())).await {
+                Ok(status) => snapshot.nodes.push(status),
+                Err(e) => {
+                    tracing::warn!("failed to fetch dht status of {}: {}", next, e);
+                    snapshot.unreachable.push(next);
+                }
+            }
+        }
+
+        let mut display = String::new();
+        display.push_str("Successful\n");
+        display.push_str("Did, Predecessor, Successors, Fingers, Storage, Cache\n");
+        for n in snapshot.nodes.iter() {
+            display.push_str(
+                format!(
+                    "{}, {}, {}, {}, {}, {}\n",
+                    n.did,
+                    n.predecessor
+                        .map(|p| p.to_string())
+                        .unwrap_or_else(|| "-".to_owned()),
+                    n.successors
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    n.fingers.len(),
+                    n.storage_count,
+                    n.cache.entries
+                )
+                .as_str(),
+            );
+        }
+        let issues = snapshot.check();
+        if issues.is_empty() {
+            display
+                .push_str(format!("Ring of {} nodes is consistent", snapshot.nodes.len()).as_str());
+        } else {
+            display.push_str(format!("Found {} inconsistencies\n", issues.len()).as_str());
+            display.push_str(
+                issues
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                    .as_str(),
+            );
+        }
+        ClientOutput::ok(display, snapshot)
+    }
+
     pub async fn send_message(&self, did: &str, text: &str) -> Output<()> {
         let mut params = serde_json::Map::new();
         params.insert("destination".to_owned(), json!(did));
@@ -50,6 +50,22 @@ pub enum Error {
     VNodeError(rings_core::err::Error),
     #[error("JsError: {0}")]
     JsError(String),
+    #[error("Leave ring error: {0}")]
+    LeaveRingError(rings_core::err::Error),
+    #[error("Find successor error: {0}")]
+    FindSuccessorError(rings_core::err::Error),
+    #[error("SubRing broadcast error: {0}")]
+    SubRingBroadcastError(rings_core::err::Error),
+    #[error("SubRing membership error: {0}")]
+    SubRingMembershipError(rings_core::err::Error),
+    #[error("Record error: {0}")]
+    RecordError(rings_core::err::Error),
+    #[error("DHT status error: {0}")]
+    DHTStatusError(rings_core::err::Error),
+    #[error("Byte stream error: {0}")]
+    StreamError(rings_core::err::Error),
+    #[error("Tunnel error: {0}")]
+    TunnelError(String),
 }
 
 impl Error {
@@ -78,6 +94,14 @@ impl Error {
             Error::NoPermission => 20,
             Error::VNodeError(_) => 21,
             Error::JsError(_) => 22,
+            Error::LeaveRingError(_) => 23,
+            Error::FindSuccessorError(_) => 24,
+            Error::SubRingBroadcastError(_) => 25,
+            Error::SubRingMembershipError(_) => 26,
+            Error::RecordError(_) => 27,
+            Error::DHTStatusError(_) => 28,
+            Error::StreamError(_) => 29,
+            Error::TunnelError(_) => 30,
         };
         -32000 - code
     }
@@ -27,6 +27,21 @@ pub enum Method {
     ListPendings,
     /// Close pending connect
     ClosePendingTransport,
+    /// Leave the ring gracefully
+    Leave,
+    /// Find successor of a did iteratively, with the path taken
+    FindSuccessor,
+    /// Broadcast custom message to members of a subring
+    SubRingBroadcast,
+    /// Leave a subring
+    SubRingLeave,
+    /// Remove a member from subring, by admin of the subring
+    SubRingKick,
+    /// List members of a subring
+    SubRingMembers,
+    /// Chord state and statistics of a node, such as predecessor, successors, fingers,
+    /// storage usage and counters of DHT cache
+    DhtStatus,
 }
 
 impl Method {
@@ -44,6 +59,13 @@ impl Method {
             Method::AcceptAnswer => "acceptAnswer",
             Method::ListPendings => "listPendings",
             Method::ClosePendingTransport => "closePendingTransport",
+            Method::Leave => "leave",
+            Method::FindSuccessor => "findSuccessor",
+            Method::SubRingBroadcast => "subringBroadcast",
+            Method::SubRingLeave => "subringLeave",
+            Method::SubRingKick => "subringKick",
+            Method::SubRingMembers => "subringMembers",
+            Method::DhtStatus => "dhtStatus",
         }
     }
 }
@@ -70,6 +92,13 @@ impl TryFrom<&str> for Method {
             "acceptAnswer" => Self::AcceptAnswer,
             "listPendings" => Self::ListPendings,
             "closePendingTransport" => Self::ClosePendingTransport,
+            "leave" => Self::Leave,
+            "findSuccessor" => Self::FindSuccessor,
+            "subringBroadcast" => Self::SubRingBroadcast,
+            "subringLeave" => Self::SubRingLeave,
+            "subringKick" => Self::SubRingKick,
+            "subringMembers" => Self::SubRingMembers,
+            "dhtStatus" => Self::DhtStatus,
             _ => return Err(Error::InvalidMethod),
         })
     }
@@ -7,6 +7,7 @@ use serde_json::Value as JsonValue;
 use crate::error::Error;
 use crate::error::Result;
 use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::dht::LookupTrace;
 use crate::prelude::rings_core::message::Encoded;
 use crate::prelude::rings_core::prelude::web3::contract::tokens::Tokenizable;
 use crate::prelude::rings_core::transports::Transport;
@@ -76,6 +77,24 @@ impl From<(&Arc<Transport>, Option<String>)> for TransportInfo {
     }
 }
 
+/// Path taken by an iterative lookup
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LookupPath {
+    pub id: String,
+    pub successor: String,
+    pub path: Vec<String>,
+}
+
+impl From<&LookupTrace> for LookupPath {
+    fn from(trace: &LookupTrace) -> Self {
+        Self {
+            id: trace.id.to_string(),
+            successor: trace.successor.to_string(),
+            path: trace.path.iter().map(|did| did.to_string()).collect(),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct TransportAndIce {
     pub transport_id: String,
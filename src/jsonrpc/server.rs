@@ -70,6 +70,13 @@ pub(crate) async fn build_handler(handler: &mut MetaIoHandler<RpcMeta>) {
         close_pending_transport,
     );
     handler.add_method_with_meta(Method::SendTo.as_str(), send_message);
+    handler.add_method_with_meta(Method::Leave.as_str(), leave);
+    handler.add_method_with_meta(Method::FindSuccessor.as_str(), find_successor);
+    handler.add_method_with_meta(Method::SubRingBroadcast.as_str(), subring_broadcast);
+    handler.add_method_with_meta(Method::SubRingLeave.as_str(), subring_leave);
+    handler.add_method_with_meta(Method::SubRingKick.as_str(), subring_kick);
+    handler.add_method_with_meta(Method::SubRingMembers.as_str(), subring_members);
+    handler.add_method_with_meta(Method::DhtStatus.as_str(), dht_status);
 }
 
 #[cfg(feature = "browser")]
@@ -87,6 +94,13 @@ pub async fn handle_request(method: Method, meta: RpcMeta, params: Params) -> Re
         Method::Disconnect => close_connection(params, meta).await,
         Method::ListPendings => list_pendings(params, meta).await,
         Method::ClosePendingTransport => close_pending_transport(params, meta).await,
+        Method::Leave => leave(params, meta).await,
+        Method::FindSuccessor => find_successor(params, meta).await,
+        Method::SubRingBroadcast => subring_broadcast(params, meta).await,
+        Method::SubRingLeave => subring_leave(params, meta).await,
+        Method::SubRingKick => subring_kick(params, meta).await,
+        Method::SubRingMembers => subring_members(params, meta).await,
+        Method::DhtStatus => dht_status(params, meta).await,
     }
 }
 
@@ -263,3 +277,132 @@ async fn send_message(params: Params, meta: RpcMeta) -> Result<Value> {
         .await?;
     Ok(serde_json::json!({}))
 }
+
+/// Handle leave ring
+async fn leave(_params: Params, meta: RpcMeta) -> Result<Value> {
+    meta.require_authed()?;
+    meta.processor.leave().await?;
+    Ok(serde_json::json!({}))
+}
+
+/// Handle find successor iteratively, params: [did, timeout_ms?, retries?]
+async fn find_successor(params: Params, meta: RpcMeta) -> Result<Value> {
+    meta.require_authed()?;
+    let params: Vec<Value> = params.parse()?;
+    let did = params
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let did = Did::from_str(did).map_err(|_| Error::from(ServerError::InvalidDid))?;
+    let timeout_ms = match params.get(1) {
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?,
+        None => processor::DEFAULT_LOOKUP_TIMEOUT_MS,
+    };
+    let retries = match params.get(2) {
+        Some(v) => v
+            .as_u64()
+            .and_then(|x| u8::try_from(x).ok())
+            .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?,
+        None => processor::DEFAULT_LOOKUP_RETRIES,
+    };
+    let trace = meta
+        .processor
+        .find_successor(did, timeout_ms, retries)
+        .await?;
+    serde_json::to_value(response::LookupPath::from(&trace))
+        .map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
+
+/// Handle broadcast message to members of a subring
+async fn subring_broadcast(params: Params, meta: RpcMeta) -> Result<Value> {
+    meta.require_authed()?;
+    let params: serde_json::Map<String, Value> = params.parse()?;
+    let name = params
+        .get("name")
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
+        .as_str()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let text = params
+        .get("text")
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
+        .as_str()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    meta.processor
+        .subring_broadcast(name, text.as_bytes())
+        .await?;
+    Ok(serde_json::json!({}))
+}
+
+/// Handle leave subring, params: {name}
+async fn subring_leave(params: Params, meta: RpcMeta) -> Result<Value> {
+    meta.require_authed()?;
+    let params: serde_json::Map<String, Value> = params.parse()?;
+    let name = params
+        .get("name")
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
+        .as_str()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    meta.processor.subring_leave(name).await?;
+    Ok(serde_json::json!({}))
+}
+
+/// Handle kick member from subring by admin, params: {name, did}
+async fn subring_kick(params: Params, meta: RpcMeta) -> Result<Value> {
+    meta.require_authed()?;
+    let params: serde_json::Map<String, Value> = params.parse()?;
+    let name = params
+        .get("name")
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
+        .as_str()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let did = params
+        .get("did")
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
+        .as_str()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let did = Did::from_str(did).map_err(|_| Error::from(ServerError::InvalidDid))?;
+    meta.processor.subring_kick(name, did).await?;
+    Ok(serde_json::json!({}))
+}
+
+/// Handle list members of subring, params: {name}
+async fn subring_members(params: Params, meta: RpcMeta) -> Result<Value> {
+    meta.require_authed()?;
+    let params: serde_json::Map<String, Value> = params.parse()?;
+    let name = params
+        .get("name")
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?
+        .as_str()
+        .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+    let members = meta.processor.subring_members(name).await?;
+    Ok(serde_json::json!(members
+        .iter()
+        .map(|did| did.to_string())
+        .collect::<Vec<_>>()))
+}
+
+/// Handle chord state and statistics of a node, params: [did?, timeout_ms?],
+/// it's local node if did is omitted
+async fn dht_status(params: Params, meta: RpcMeta) -> Result<Value> {
+    meta.require_authed()?;
+    let params: Vec<Value> = params.parse()?;
+    let did = match params.first() {
+        Some(v) => {
+            let did = v
+                .as_str()
+                .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
+            Did::from_str(did).map_err(|_| Error::from(ServerError::InvalidDid))?
+        }
+        None => meta.processor.did(),
+    };
+    let timeout_ms = match params.get(1) {
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| Error::new(ErrorCode::InvalidParams))?,
+        None => processor::DEFAULT_LOOKUP_TIMEOUT_MS,
+    };
+    let status = meta.processor.dht_status(did, timeout_ms).await?;
+    serde_json::to_value(status).map_err(|_| Error::from(ServerError::JsonSerializeError))
+}
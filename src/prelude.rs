@@ -32,7 +32,9 @@ pub use self::rings_core::prelude::web3;
 pub use self::rings_core::prelude::web_sys;
 pub use self::rings_core::prelude::PersistenceStorage;
 pub use self::rings_core::prelude::RTCIceConnectionState;
+pub use self::rings_core::prelude::SubRingOperator;
 pub use self::rings_core::prelude::TChordStorage;
+pub use self::rings_core::prelude::TRecordStorage;
 pub use self::rings_core::session::Session;
 pub use self::rings_core::session::SessionManager;
 pub use self::rings_core::session::Signer;
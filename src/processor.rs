@@ -2,6 +2,7 @@
 //! Processor of rings-node jsonrpc-server.
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(feature = "node")]
 use jsonrpc_core::Metadata;
@@ -12,8 +13,11 @@ use crate::error::Result;
 use crate::jsonrpc::method;
 use crate::jsonrpc::response::TransportAndIce;
 use crate::jsonrpc_client::SimpleClient;
+use crate::prelude::rings_core::dht::DHTStatus;
 use crate::prelude::rings_core::dht::Did;
+use crate::prelude::rings_core::dht::LookupTrace;
 use crate::prelude::rings_core::dht::Stabilization;
+use crate::prelude::rings_core::dht::StabilizeMetrics;
 use crate::prelude::rings_core::ecc::PublicKey;
 use crate::prelude::rings_core::ecc::SecretKey;
 use crate::prelude::rings_core::message::Encoded;
@@ -31,7 +35,15 @@ use crate::prelude::rings_core::types::ice_transport::IceTransportInterface;
 use crate::prelude::rings_core::types::ice_transport::IceTrickleScheme;
 use crate::prelude::vnode;
 use crate::prelude::web3::signing::keccak256;
+use crate::prelude::CustomMessage;
+use crate::prelude::SubRingOperator;
 use crate::prelude::TChordStorage;
+use crate::prelude::TRecordStorage;
+
+/// Default timeout of each hop of iterative lookup
+pub const DEFAULT_LOOKUP_TIMEOUT_MS: u64 = 3000;
+/// Default retries of each hop of iterative lookup
+pub const DEFAULT_LOOKUP_RETRIES: u8 = 2;
 
 /// Processor for rings-node jsonrpc server
 #[derive(Clone)]
@@ -302,6 +314,43 @@ impl Processor {
         Ok(())
     }
 
+    /// Leave the ring gracefully, stored data will be handed off to successor.
+    pub async fn leave(&self) -> Result<()> {
+        self.swarm.leave_ring().await.map_err(Error::LeaveRingError)
+    }
+
+    /// Rounds and durations of stabilization.
+    pub fn stabilize_metrics(&self) -> StabilizeMetrics {
+        self.stabilization.metrics()
+    }
+
+    /// Chord state of a node, it's queried through the ring if the node is remote.
+    /// Metrics of stabilization are included for local node.
+    pub async fn dht_status(&self, did: Did, timeout_ms: u64) -> Result<DHTStatus> {
+        let mut status = self
+            .swarm
+            .dht_status(did, Duration::from_millis(timeout_ms))
+            .await
+            .map_err(Error::DHTStatusError)?;
+        if did == self.did() {
+            status.stabilization = Some(self.stabilize_metrics());
+        }
+        Ok(status)
+    }
+
+    /// Find successor of a did iteratively, return the trace of lookup.
+    pub async fn find_successor(
+        &self,
+        did: Did,
+        timeout_ms: u64,
+        retries: u8,
+    ) -> Result<LookupTrace> {
+        self.swarm
+            .find_successor_iterative(did, Duration::from_millis(timeout_ms), retries)
+            .await
+            .map_err(Error::FindSuccessorError)
+    }
+
     /// List all pending transport.
     pub async fn list_pendings(&self) -> Result<Vec<Arc<Transport>>> {
         let pendings = self
@@ -349,13 +398,46 @@ impl Processor {
             .map_err(Error::SendMessage)
     }
 
+    /// Broadcast custom message to every member of a subring.
+    pub async fn subring_broadcast(&self, name: &str, msg: &[u8]) -> Result<()> {
+        tracing::info!("subring_broadcast, name: {}, text: {:?}", name, msg);
+        self.swarm
+            .subring_broadcast(name, CustomMessage(msg.to_vec()))
+            .await
+            .map_err(Error::SubRingBroadcastError)
+    }
+
+    /// Leave a subring.
+    pub async fn subring_leave(&self, name: &str) -> Result<()> {
+        self.swarm
+            .subring_leave(name)
+            .await
+            .map_err(Error::SubRingMembershipError)
+    }
+
+    /// Remove a member from subring, only admin of the subring is permitted.
+    pub async fn subring_kick(&self, name: &str, did: Did) -> Result<()> {
+        self.swarm
+            .subring_kick(name, did)
+            .await
+            .map_err(Error::SubRingMembershipError)
+    }
+
+    /// List members of a subring.
+    pub async fn subring_members(&self, name: &str) -> Result<Vec<Did>> {
+        self.swarm
+            .subring_members(name)
+            .await
+            .map_err(Error::SubRingMembershipError)
+    }
+
     /// check local cache of dht
     pub async fn check_cache(&self, id: &Did) -> Option<vnode::VirtualNode> {
         self.swarm.storage_check_cache(id).await
     }
 
-    /// fetch virtual node from DHT
-    pub async fn fetch(&self, id: &Did) -> Result<()> {
+    /// fetch virtual node from DHT, None if it's not found
+    pub async fn fetch(&self, id: &Did) -> Result<Option<vnode::VirtualNode>> {
         self.swarm
             .storage_fetch(id)
             .await
@@ -369,6 +451,32 @@ impl Processor {
             .await
             .map_err(error::Error::VNodeError)
     }
+
+    /// put a record signed by this node to DHT, it replaces the previous one with same name
+    pub async fn put_record(&self, name: &str, value: &[u8]) -> Result<()> {
+        self.swarm
+            .put_record(name, value.to_vec())
+            .await
+            .map_err(error::Error::RecordError)
+    }
+
+    /// get value of record by owner and name, None if it's not found or deleted
+    pub async fn get_record(&self, owner: Did, name: &str) -> Result<Option<Vec<u8>>> {
+        let record = self
+            .swarm
+            .get_record(owner, name)
+            .await
+            .map_err(error::Error::RecordError)?;
+        Ok(record.and_then(|r| r.value))
+    }
+
+    /// delete a record of this node from DHT
+    pub async fn delete_record(&self, name: &str) -> Result<()> {
+        self.swarm
+            .delete_record(name)
+            .await
+            .map_err(error::Error::RecordError)
+    }
 }
 
 /// Peer struct